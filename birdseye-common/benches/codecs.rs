@@ -0,0 +1,75 @@
+use birdseye_common::backend::MonitorMessage;
+use birdseye_common::codec::Codec;
+use birdseye_common::screen::Frame;
+use birdseye_common::{Process, Session, User};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+/// A process list about the size of a freshly booted windows machine
+fn process_list() -> Vec<Process> {
+    let user = User::new("student").with_id("S-1-5-21-3623811015-3361044348-30300820-1013");
+
+    (0..300)
+        .map(|pid| {
+            Process::new(pid, &format!("process-{pid}.exe"), &user)
+                .with_parent_pid(4)
+                .with_exe(&format!("C:\\Program Files\\Example\\process-{pid}.exe"))
+                .with_cmd(&[
+                    format!("C:\\Program Files\\Example\\process-{pid}.exe"),
+                    "--type=renderer".into(),
+                ])
+                .with_start_time(1_656_000_000 + pid as u64)
+                .with_usage(0.5, 64 * 1024 * 1024)
+                .with_session(Session::new(1, None))
+        })
+        .collect()
+}
+
+/// A 720p BGR0 frame with some noise so compression doesn't get too lucky
+fn frame() -> MonitorMessage {
+    let (width, height) = (1280, 720);
+    let data = (0..width * height * 4)
+        .map(|i| (i * 31 % 251) as u8)
+        .collect::<Vec<u8>>();
+
+    MonitorMessage::Frame(Frame::new(width, height, data))
+}
+
+fn bench_codecs(c: &mut Criterion) {
+    let processes = process_list();
+    let frame = frame();
+
+    let mut group = c.benchmark_group("encode");
+    for codec in Codec::ALL {
+        let size = codec.encode(&processes).unwrap().len();
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::new("processes", codec), &codec, |b, codec| {
+            b.iter(|| codec.encode(black_box(&processes)).unwrap())
+        });
+
+        let size = codec.encode(&frame).unwrap().len();
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::new("frame", codec), &codec, |b, codec| {
+            b.iter(|| codec.encode(black_box(&frame)).unwrap())
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("decode");
+    for codec in Codec::ALL {
+        let bytes = codec.encode(&processes).unwrap();
+        group.throughput(Throughput::Bytes(bytes.len() as u64));
+        group.bench_with_input(BenchmarkId::new("processes", codec), &bytes, |b, bytes| {
+            b.iter(|| codec.decode::<Vec<Process>>(black_box(bytes)).unwrap())
+        });
+
+        let bytes = codec.encode(&frame).unwrap();
+        group.throughput(Throughput::Bytes(bytes.len() as u64));
+        group.bench_with_input(BenchmarkId::new("frame", codec), &bytes, |b, bytes| {
+            b.iter(|| codec.decode::<MonitorMessage>(black_box(bytes)).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_codecs);
+criterion_main!(benches);
@@ -0,0 +1,58 @@
+//! Types only used by the server's REST api under `/api/v1`, everything else it sends is the same
+//! type the dashboard gets over the WebSocket
+
+use crate::machine::Presence;
+use crate::timetable::Timetable;
+use crate::{Machine, MachineId, RoomId};
+use serde::{Deserialize, Serialize};
+
+/// A machine, whether it is connected and the room it is in
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct MachineStatus {
+    pub machine: Machine,
+    pub presence: Presence,
+    pub room: Option<RoomId>,
+}
+
+/// A room and the machines in it
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct RoomInfo {
+    pub id: RoomId,
+    pub machines: Vec<MachineId>,
+}
+
+/// Where to put the periods read from an iCalendar file
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[cfg_attr(
+    feature = "openapi",
+    derive(utoipa::IntoParams),
+    into_params(parameter_in = Query)
+)]
+pub struct ImportQuery {
+    /// Put every period in this room rather than the room in each event's location
+    pub room: Option<RoomId>,
+}
+
+/// The size of a screen thumbnail
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[cfg_attr(
+    feature = "openapi",
+    derive(utoipa::IntoParams),
+    into_params(parameter_in = Query)
+)]
+pub struct ThumbnailQuery {
+    /// How wide the thumbnail should be in pixels, the server uses the closest width it is
+    /// configured to make
+    pub width: Option<u32>,
+}
+
+/// The timetable after an import
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ImportResult {
+    pub timetable: Timetable,
+    /// Events that couldn't be turned into a period or holiday, such as ones without a room
+    pub skipped: usize,
+}
@@ -0,0 +1,73 @@
+//! Types used by the dashboard to log in to the server
+
+use crate::{MachineId, RoomId};
+use serde::{Deserialize, Serialize};
+
+/// Body of a `POST /login` request
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct LoginRequest {
+    pub username: String,
+    pub password: String,
+}
+
+/// The teacher account a session belongs to, returned by `POST /login` and `GET /session`
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct AccountInfo {
+    username: String,
+    admin: bool,
+    rooms: Vec<RoomId>,
+}
+
+impl<'a> AccountInfo {
+    pub fn username(&'a self) -> &'a str {
+        &self.username
+    }
+
+    /// Admins are able to manage other accounts
+    pub fn is_admin(&self) -> bool {
+        self.admin
+    }
+
+    /// The rooms the account has been assigned to, admins can see every room whether or not they
+    /// have been assigned to it
+    pub fn rooms(&'a self) -> &'a [RoomId] {
+        &self.rooms
+    }
+
+    pub fn new(username: &str, admin: bool, rooms: Vec<RoomId>) -> Self {
+        Self {
+            username: username.to_string(),
+            admin,
+            rooms,
+        }
+    }
+}
+
+/// Body of a `POST /accounts` request, used by admins to create accounts for teachers
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct NewAccount {
+    pub username: String,
+    pub password: String,
+    #[serde(default)]
+    pub admin: bool,
+    /// The rooms the teacher teaches in
+    #[serde(default)]
+    pub rooms: Vec<RoomId>,
+}
+
+/// A one-time token a monitor can use to get a client certificate, returned by
+/// `POST /enrollment/tokens`
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct EnrollmentToken {
+    pub token: String,
+    /// When the token stops working, in seconds since the unix epoch
+    pub expires: u64,
+}
+
+/// Body of a `POST /enrollment/revoke` request
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct RevokeRequest {
+    pub machine: MachineId,
+}
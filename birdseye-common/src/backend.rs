@@ -0,0 +1,126 @@
+//! Messages sent over the link between `birdseye-monitor` and `birdseye-server`
+
+use crate::command::{Command, CommandError, RequestId};
+use crate::handshake::Hello;
+use crate::policy::{Rule, Violation};
+use crate::screen::{Demand, Frame, Packet};
+use crate::sync::{ProcessSync, Restriction};
+use crate::{Machine, MachineId, Process};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// How often a monitor sends [`MonitorMessage::Heartbeat`], the server treats a monitor that has
+/// sent nothing for three times this long as gone
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Messages sent from a monitor to the server
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub enum MonitorMessage {
+    /// The first message a version 1 monitor sends after connecting, newer monitors send
+    /// [`MonitorMessage::Hello`] instead. Version 1 is older than
+    /// [`MIN_PROTOCOL_VERSION`](crate::handshake::MIN_PROTOCOL_VERSION), so this is only read to
+    /// turn the monitor away
+    Register { version: u32, hostname: String },
+    /// Sent periodically so the server knows the monitor is still alive
+    Heartbeat,
+    /// A process has started on the monitor's machine, only sent by monitors older than protocol
+    /// version 4, newer monitors send [`MonitorMessage::Processes`]
+    ProcessStarted(Process),
+    /// A process has stopped on the monitor's machine, only sent by monitors older than protocol
+    /// version 4
+    ProcessStopped(Process),
+    /// A frame captured from the monitor's screen, only sent with protocol version 10, newer
+    /// monitors send [`MonitorMessage::Packet`]. Monitors skip frames rather than queue them when
+    /// the link can't keep up
+    Frame(Frame),
+    /// The result of a [`Command`] sent by the server
+    CommandResult {
+        id: RequestId,
+        result: CommandResult,
+    },
+    /// The first message a monitor sends after connecting, the server will reply with
+    /// [`ServerMessage::Hello`] or [`ServerMessage::Rejected`]
+    Hello { hello: Hello, hostname: String },
+    /// Sent straight after the handshake, the monitor is not considered registered until the
+    /// server has received this
+    Machine(Machine),
+    /// A snapshot of the machine's processes, or a change to them. A snapshot is sent after
+    /// registering and whenever the server asks for one with [`ServerMessage::ResyncProcesses`]
+    Processes(ProcessSync),
+    /// A process broke one of the rules from [`ServerMessage::Policy`]
+    PolicyViolation(Violation),
+    /// Every process the monitor has suspended or throttled, sent after registering and whenever
+    /// it changes. Only sent from protocol version 9
+    Restrictions(Vec<Restriction>),
+    /// A frame captured from the monitor's screen and encoded, only sent from protocol version 11
+    Packet(Packet),
+}
+
+/// Messages sent from the server to a monitor
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub enum ServerMessage {
+    /// Told version 1 monitors they had been registered. Version 1 is no longer supported so this
+    /// is never sent, it is kept so the messages after it keep their bincode tags
+    Registered,
+    /// The server has rejected the monitor, the link will be closed after this is sent
+    Rejected(String),
+    /// A command for the monitor to run, the monitor must reply with
+    /// [`MonitorMessage::CommandResult`] using the same id
+    Command { id: RequestId, command: Command },
+    /// The server's side of the handshake, sent in reply to [`MonitorMessage::Hello`]
+    Hello(Hello),
+    /// The server missed a process update, the monitor should send a new snapshot
+    ResyncProcesses,
+    /// The rules the monitor should enforce, replacing any it had before. Sent after the monitor
+    /// registers and whenever the rules change, only from protocol version 7
+    Policy(Vec<Rule>),
+    /// How much of the monitor's screen is needed, sent after the monitor registers and whenever
+    /// it changes. Only sent from protocol version 13, older servers need every frame
+    Demand(Demand),
+}
+
+/// The outcome of a [`Command`]
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub enum CommandResult {
+    Ok,
+    /// Sent by older monitors, newer monitors send [`CommandResult::Error`]
+    Err(String),
+    Error(CommandError),
+}
+
+impl From<CommandResult> for Result<(), CommandError> {
+    fn from(result: CommandResult) -> Self {
+        match result {
+            CommandResult::Ok => Ok(()),
+            CommandResult::Err(reason) => Err(CommandError::Failed(reason)),
+            CommandResult::Error(err) => Err(err),
+        }
+    }
+}
+
+impl From<Result<(), CommandError>> for CommandResult {
+    fn from(result: Result<(), CommandError>) -> Self {
+        match result {
+            Ok(()) => CommandResult::Ok,
+            Err(err) => CommandResult::Error(err),
+        }
+    }
+}
+
+/// Body of a `POST /enroll` request, sent by a monitor without a client certificate to swap a
+/// one-time enrollment token for one
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct EnrollRequest {
+    pub token: String,
+    /// The machine the certificate is for, this becomes the certificate's common name
+    pub machine: MachineId,
+    /// PEM encoded certificate signing request, the private key never leaves the monitor
+    pub csr: String,
+}
+
+/// Reply to a successful [`EnrollRequest`]
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct EnrollResponse {
+    /// PEM encoded client certificate
+    pub certificate: String,
+}
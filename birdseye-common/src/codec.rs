@@ -0,0 +1,97 @@
+//! Wire formats that messages can be encoded with
+//!
+//! The codec for a connection is picked during the websocket handshake using the
+//! `Sec-WebSocket-Protocol` header, clients offer the subprotocols they support (e.g.
+//! `birdseye.json`) and the server picks the first one it understands. Connections that don't
+//! offer a subprotocol use [`Codec::Bincode`] so older clients keep working.
+//!
+//! JSON is useful for debugging with browser devtools or `websocat --protocol birdseye.json`,
+//! bincode should be used everywhere else.
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+/// A format for encoding messages sent over a websocket
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Codec {
+    /// Compact binary encoding, used in production
+    #[default]
+    Bincode,
+    /// Human readable encoding, sent as text frames
+    Json,
+    /// Self describing binary encoding, useful for clients not written in rust
+    MessagePack,
+}
+
+impl Codec {
+    /// Every supported codec. Which one a connection uses is up to the order the client offers them
+    /// in, see [`Codec::negotiate`]
+    pub const ALL: [Codec; 3] = [Codec::Bincode, Codec::MessagePack, Codec::Json];
+
+    /// The websocket subprotocol used to ask for this codec
+    pub fn subprotocol(&self) -> &'static str {
+        match self {
+            Codec::Bincode => "birdseye.bincode",
+            Codec::Json => "birdseye.json",
+            Codec::MessagePack => "birdseye.msgpack",
+        }
+    }
+
+    pub fn from_subprotocol(subprotocol: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|codec| codec.subprotocol() == subprotocol.trim())
+    }
+
+    /// Pick a codec from the value of a `Sec-WebSocket-Protocol` header, the first subprotocol
+    /// offered that we understand wins
+    pub fn negotiate(offered: &str) -> Option<Self> {
+        offered.split(',').find_map(Self::from_subprotocol)
+    }
+
+    /// Whether messages encoded with this codec should be sent as text frames
+    pub fn is_text(&self) -> bool {
+        matches!(self, Codec::Json)
+    }
+
+    pub fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, CodecError> {
+        match self {
+            Codec::Bincode => bincode::serialize(value).map_err(CodecError::new),
+            Codec::Json => serde_json::to_vec(value).map_err(CodecError::new),
+            Codec::MessagePack => rmp_serde::to_vec_named(value).map_err(CodecError::new),
+        }
+    }
+
+    pub fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, CodecError> {
+        match self {
+            Codec::Bincode => bincode::deserialize(bytes).map_err(CodecError::new),
+            Codec::Json => serde_json::from_slice(bytes).map_err(CodecError::new),
+            Codec::MessagePack => rmp_serde::from_slice(bytes).map_err(CodecError::new),
+        }
+    }
+}
+
+impl Display for Codec {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.subprotocol())
+    }
+}
+
+/// An error encoding or decoding a message
+#[derive(Debug, Clone)]
+pub struct CodecError(String);
+
+impl CodecError {
+    fn new(err: impl Display) -> Self {
+        Self(err.to_string())
+    }
+}
+
+impl Display for CodecError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for CodecError {}
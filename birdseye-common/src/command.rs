@@ -0,0 +1,92 @@
+//! Commands that the dashboard can ask a monitor to run, and the errors they can fail with
+
+use crate::MachineId;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+/// Id used to match a response to the request that caused it, ids are picked by whoever sends the
+/// request and only have to be unique per connection
+pub type RequestId = u64;
+
+/// Actions that can be run on a monitor
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub enum Command {
+    /// Kill the process with the given pid
+    KillProcess { pid: u32 },
+    /// Blank the screen and block input
+    Lock,
+    /// Undo [`Command::Lock`]
+    Unlock,
+    /// Show a message on the screen
+    ShowMessage(String),
+    /// Ask a process to exit, killing it if it is still running after a few seconds. `start_time`
+    /// is the one from its [`Process`](crate::Process) so a new process given the same pid isn't
+    /// killed instead, and `tree` kills all of its children along with it. Only understood by
+    /// monitors from protocol version 8
+    Kill {
+        pid: u32,
+        start_time: u64,
+        tree: bool,
+    },
+    /// Pause a process without losing anything it was doing, only understood by monitors from
+    /// protocol version 9
+    Suspend { pid: u32, start_time: u64 },
+    /// Undo [`Command::Suspend`] and [`Command::Throttle`], only understood by monitors from
+    /// protocol version 9
+    Resume { pid: u32, start_time: u64 },
+    /// Let a process use at most `percent` of one CPU, between 1 and 100. Only understood by
+    /// monitors from protocol version 9
+    Throttle {
+        pid: u32,
+        start_time: u64,
+        percent: u8,
+    },
+}
+
+impl Command {
+    /// The first protocol version monitors understand the command on
+    pub fn min_version(&self) -> u32 {
+        match self {
+            Command::Kill { .. } => 8,
+            Command::Suspend { .. } | Command::Resume { .. } | Command::Throttle { .. } => 9,
+            _ => 1,
+        }
+    }
+}
+
+/// Reasons a [`Command`] can fail
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub enum CommandError {
+    /// No machine with the given id has ever registered
+    UnknownMachine(MachineId),
+    /// The machine is known but its monitor is not connected
+    MachineOffline(MachineId),
+    /// The monitor did not reply in time, the command may or may not have run
+    Timeout,
+    /// The monitor does not support the command
+    Unsupported,
+    /// No process with the given pid is running
+    ProcessNotFound(u32),
+    /// The command was refused, contains the reason
+    PermissionDenied(String),
+    /// The command was attempted but failed, contains the reason
+    Failed(String),
+}
+
+impl Display for CommandError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CommandError::UnknownMachine(id) => write!(f, "unknown machine {id}"),
+            CommandError::MachineOffline(id) => write!(f, "machine {id} is offline"),
+            CommandError::Timeout => write!(f, "the machine did not respond in time"),
+            CommandError::Unsupported => write!(f, "the machine does not support this command"),
+            CommandError::ProcessNotFound(pid) => write!(f, "no process with pid {pid}"),
+            CommandError::PermissionDenied(reason) => write!(f, "permission denied: {reason}"),
+            CommandError::Failed(reason) => write!(f, "command failed: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for CommandError {}
@@ -1,6 +1,92 @@
+use crate::command::{Command, CommandError, RequestId};
+use crate::handshake::Hello;
+use crate::machine::Presence;
+use crate::policy::Violation;
+use crate::screen::{Frame, Packet};
+use crate::sync::{ProcessSync, Restriction};
+use crate::{Machine, MachineId, RoomId};
 use serde::{Deserialize, Serialize};
 
-#[derive(Deserialize, Serialize, Debug)]
+/// Something a dashboard can subscribe to, subscribing to a room is the same as subscribing to
+/// every machine in it
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Topic {
+    Machine(MachineId),
+    Room(RoomId),
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub enum WsMessage {
-    Hello(String),
+    /// The first message sent by both the dashboard and the server, the server replies with its
+    /// own hello or [`WsMessage::Rejected`]
+    Hello(Hello),
+    /// The server refused the connection, the reason is meant to be shown to the user
+    Rejected(String),
+    /// Sent by the dashboard to run a command on a machine, the server replies with
+    /// [`WsMessage::Ack`] once the command has been passed on and [`WsMessage::Response`] once it
+    /// has finished
+    Request {
+        id: RequestId,
+        machine: MachineId,
+        command: Command,
+    },
+    /// The server has passed the request with the given id on to the machine
+    Ack(RequestId),
+    /// The outcome of the request with the given id
+    Response {
+        id: RequestId,
+        result: Result<(), CommandError>,
+    },
+    /// Sent by dashboards older than protocol version 5 to start receiving the process list of a
+    /// machine, newer dashboards use [`WsMessage::Subscribe`]
+    SubscribeProcesses(MachineId),
+    /// Sent by dashboards older than protocol version 5, newer dashboards use
+    /// [`WsMessage::Unsubscribe`]
+    UnsubscribeProcesses(MachineId),
+    /// A snapshot of, or change to, the process list of a machine
+    Processes {
+        machine: MachineId,
+        sync: ProcessSync,
+    },
+    /// Sent by the dashboard to start receiving events for a topic, the server replies with a
+    /// [`WsMessage::Machine`] and a process snapshot for every machine in the topic
+    Subscribe(Topic),
+    /// Stop receiving events for a topic
+    Unsubscribe(Topic),
+    /// Sent by the dashboard after it missed a process update, the server replies with a new
+    /// snapshot
+    ResyncProcesses(MachineId),
+    /// A machine has connected or disconnected, or its inventory has changed
+    Machine {
+        machine: Machine,
+        presence: Presence,
+    },
+    /// The dashboard asked to subscribe to a topic its account can't see, only sent from protocol
+    /// version 6
+    SubscribeDenied(Topic),
+    /// A process on a machine the dashboard is subscribed to broke a blacklist rule, only sent
+    /// from protocol version 7
+    PolicyViolation {
+        machine: MachineId,
+        violation: Violation,
+    },
+    /// Every process on a machine the dashboard is subscribed to that is suspended or throttled,
+    /// sent after subscribing and whenever it changes. Only sent from protocol version 9
+    Restrictions {
+        machine: MachineId,
+        restrictions: Vec<Restriction>,
+    },
+    /// Sent by the dashboard to start receiving [`WsMessage::Frame`] from a machine, only from
+    /// protocol version 10. Machines outside of the teacher's rooms are ignored
+    WatchScreen(MachineId),
+    /// Stop receiving frames from a machine
+    UnwatchScreen(MachineId),
+    /// The latest frame from the screen of a machine being watched. Frames are sent no faster than
+    /// the dashboard can take them, any that arrive while it is still receiving the last one are
+    /// skipped. Only sent with protocol version 10, and only for machines sending raw frames,
+    /// newer dashboards are sent [`WsMessage::Packet`]
+    Frame { machine: MachineId, frame: Frame },
+    /// The latest encoded frame from the screen of a machine being watched, sent the same way as
+    /// [`WsMessage::Frame`] from protocol version 11
+    Packet { machine: MachineId, packet: Packet },
 }
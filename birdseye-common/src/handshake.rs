@@ -0,0 +1,192 @@
+//! The hello exchanged by both ends of every BirdsEye connection, used to agree on a protocol
+//! version and find out what the other side is able to do
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fmt::{Display, Formatter};
+
+/// The protocol version spoken by this build of `birdseye-common`
+///
+/// # History
+/// | Version | Changes                                                                   |
+/// |---------|---------------------------------------------------------------------------|
+/// | 1       | Monitors register with a hostname                                         |
+/// | 2       | Hello with capabilities exchanged by both sides                           |
+/// | 3       | Monitors send their [`Machine`](crate::Machine) inventory after the hello |
+/// | 4       | Process lists are synced with snapshots and sequenced updates             |
+/// | 5       | Dashboards subscribe to machines and rooms rather than process lists      |
+/// | 6       | Dashboards are told when a subscription is refused                        |
+/// | 7       | Monitors enforce application blacklists pushed by the server              |
+/// | 8       | Monitors kill processes gracefully, optionally along with their children  |
+/// | 9       | Monitors suspend and throttle processes, and report which ones they have  |
+/// | 10      | Monitors stream their screen, relayed to the dashboards watching it       |
+/// | 11      | Screens are streamed as encoded packets rather than raw frames            |
+/// | 12      | Screens can be streamed as tiles, sending only the parts that change      |
+/// | 13      | Servers tell monitors how much of their screen is needed                  |
+/// | 14      | Screens can be streamed as H.264 video                                    |
+pub const PROTOCOL_VERSION: u32 = 14;
+
+/// The oldest protocol version this build of `birdseye-common` can still talk to. Versions 1 and 2
+/// sent [`Process`](crate::Process)es without the fields added in version 3, which bincode lays out
+/// by position, so they can't be decoded any more
+pub const MIN_PROTOCOL_VERSION: u32 = 3;
+
+/// Something a peer is able to do, sent in a [`Hello`] so the other side knows what it can ask for
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Capability {
+    /// The peer can capture its screen and send frames
+    CaptureScreen,
+    /// The peer can kill processes when asked
+    KillProcesses,
+    /// The peer has more than one display attached
+    MultiDisplay,
+    /// A capability added in a newer protocol version that this build does not know about
+    #[serde(other)]
+    Unknown,
+}
+
+/// The first message sent by both sides of a connection
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Hello {
+    version: u32,
+    min_version: u32,
+    agent: String,
+    capabilities: BTreeSet<Capability>,
+}
+
+impl<'a> Hello {
+    /// Create a hello for this build, `agent` should identify the program sending it, for example
+    /// `birdseye-monitor/0.1.0`
+    pub fn new(agent: &str, capabilities: impl IntoIterator<Item = Capability>) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            min_version: MIN_PROTOCOL_VERSION,
+            agent: agent.to_string(),
+            capabilities: capabilities.into_iter().collect(),
+        }
+    }
+
+    /// Create the hello that a version 1 monitor implicitly sent, these could not advertise any
+    /// capabilities. Only used to tell them they are too old, as version 1 is older than
+    /// [`MIN_PROTOCOL_VERSION`]
+    pub fn legacy(agent: &str) -> Self {
+        Self {
+            version: 1,
+            min_version: 1,
+            agent: agent.to_string(),
+            capabilities: BTreeSet::new(),
+        }
+    }
+
+    pub fn version(&'a self) -> u32 {
+        self.version
+    }
+
+    pub fn min_version(&'a self) -> u32 {
+        self.min_version
+    }
+
+    pub fn agent(&'a self) -> &'a str {
+        &self.agent
+    }
+
+    pub fn capabilities(&'a self) -> &'a BTreeSet<Capability> {
+        &self.capabilities
+    }
+
+    /// Work out which protocol version to use when talking to the sender of `remote`, the highest
+    /// version that both sides support is picked. Peers that only speak versions older than
+    /// [`MIN_PROTOCOL_VERSION`] are refused
+    pub fn negotiate(&self, remote: &Hello) -> Result<Negotiated, HandshakeError> {
+        let version = self.version.min(remote.version);
+
+        if version < self.min_version {
+            return Err(HandshakeError::PeerTooOld {
+                peer_version: remote.version,
+                min_version: self.min_version,
+            });
+        }
+
+        if version < remote.min_version {
+            return Err(HandshakeError::PeerTooNew {
+                peer_min_version: remote.min_version,
+                version: self.version,
+            });
+        }
+
+        Ok(Negotiated {
+            version,
+            agent: remote.agent.clone(),
+            capabilities: remote
+                .capabilities
+                .iter()
+                .filter(|cap| **cap != Capability::Unknown)
+                .copied()
+                .collect(),
+        })
+    }
+}
+
+/// The outcome of a successful handshake, describes the peer on the other side of the connection
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Negotiated {
+    version: u32,
+    agent: String,
+    capabilities: BTreeSet<Capability>,
+}
+
+impl<'a> Negotiated {
+    /// The protocol version both sides have agreed to use
+    pub fn version(&'a self) -> u32 {
+        self.version
+    }
+
+    /// The agent string sent by the peer
+    pub fn agent(&'a self) -> &'a str {
+        &self.agent
+    }
+
+    /// Whether the peer said it was able to do `capability`
+    pub fn has(&self, capability: Capability) -> bool {
+        self.capabilities.contains(&capability)
+    }
+
+    pub fn capabilities(&'a self) -> &'a BTreeSet<Capability> {
+        &self.capabilities
+    }
+}
+
+/// Reasons a handshake can fail
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub enum HandshakeError {
+    /// The peer only speaks protocol versions older than we support
+    PeerTooOld { peer_version: u32, min_version: u32 },
+    /// The peer requires a protocol version newer than we speak
+    PeerTooNew { peer_min_version: u32, version: u32 },
+    /// The peer sent something other than a hello as its first message
+    ExpectedHello,
+}
+
+impl Display for HandshakeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HandshakeError::PeerTooOld {
+                peer_version,
+                min_version,
+            } => write!(
+                f,
+                "protocol version {peer_version} is no longer supported, please update to at least version {min_version}"
+            ),
+            HandshakeError::PeerTooNew {
+                peer_min_version,
+                version,
+            } => write!(
+                f,
+                "peer requires protocol version {peer_min_version} but only version {version} is supported, please update"
+            ),
+            HandshakeError::ExpectedHello => write!(f, "expected a hello as the first message"),
+        }
+    }
+}
+
+impl std::error::Error for HandshakeError {}
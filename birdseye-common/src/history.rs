@@ -0,0 +1,109 @@
+//! Things that happened to machines, kept by the server so they can be looked back on
+
+use crate::policy::Violation;
+use crate::{MachineId, Process, RoomId};
+use serde::{Deserialize, Serialize};
+
+/// What happened
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub enum EventKind {
+    /// The machine's monitor connected to the server
+    Connected,
+    /// The machine's monitor disconnected from the server
+    Disconnected,
+    /// A process started, or was already running when the server first heard about it
+    ProcessStarted(Process),
+    /// A process stopped
+    ProcessStopped(Process),
+    /// A process broke a blacklist rule
+    PolicyViolation(Violation),
+}
+
+impl EventKind {
+    /// The process the event is about, if any
+    pub fn process(&self) -> Option<&Process> {
+        match self {
+            EventKind::ProcessStarted(process) | EventKind::ProcessStopped(process) => {
+                Some(process)
+            }
+            EventKind::PolicyViolation(violation) => Some(violation.process()),
+            EventKind::Connected | EventKind::Disconnected => None,
+        }
+    }
+}
+
+/// Something that happened on a machine
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Event {
+    /// When it happened, in seconds since the unix epoch
+    time: u64,
+    machine: MachineId,
+    kind: EventKind,
+}
+
+impl<'a> Event {
+    pub fn time(&'a self) -> u64 {
+        self.time
+    }
+
+    pub fn machine(&'a self) -> &'a MachineId {
+        &self.machine
+    }
+
+    pub fn kind(&'a self) -> &'a EventKind {
+        &self.kind
+    }
+
+    pub fn new(time: u64, machine: &MachineId, kind: EventKind) -> Self {
+        Self {
+            time,
+            machine: machine.clone(),
+            kind,
+        }
+    }
+}
+
+/// Query string of a `GET /history` request, every field is optional and they are all combined
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[cfg_attr(
+    feature = "openapi",
+    derive(utoipa::IntoParams),
+    into_params(parameter_in = Query)
+)]
+pub struct HistoryQuery {
+    pub machine: Option<MachineId>,
+    /// Only events from machines in this room
+    pub room: Option<RoomId>,
+    /// Only processes run by this user
+    pub user: Option<String>,
+    /// Only processes with a name containing this, ignoring case
+    pub process: Option<String>,
+    /// Start of the time range in seconds since the unix epoch. Processes that were started before
+    /// this and were still running at this time are included too
+    pub from: Option<u64>,
+    /// End of the time range in seconds since the unix epoch
+    pub to: Option<u64>,
+    /// Only events older than this id, pass [`HistoryPage::next`] here to get the next page
+    pub before: Option<u64>,
+    /// Most events to return
+    pub limit: Option<u32>,
+}
+
+/// An event along with the id it was stored under
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct HistoryEntry {
+    pub id: u64,
+    pub event: Event,
+}
+
+/// Reply to a `GET /history` request, events are newest first
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct HistoryPage {
+    pub events: Vec<HistoryEntry>,
+    /// The `before` to use for the next page, `None` if this is the last page
+    pub next: Option<u64>,
+}
@@ -1,32 +1,96 @@
+#[cfg(feature = "frontend")]
+pub mod api;
+#[cfg(feature = "frontend")]
+pub mod auth;
 #[cfg(feature = "backend")]
 pub mod backend;
+pub mod codec;
+pub mod command;
 #[cfg(feature = "frontend")]
 pub mod frontend;
+pub mod handshake;
+#[cfg(feature = "frontend")]
+pub mod history;
+pub mod machine;
+pub mod policy;
+pub mod room;
+pub mod screen;
+pub mod sync;
+pub mod timetable;
 
-use serde::Serialize;
+pub use machine::{Machine, MachineId};
+pub use room::RoomId;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct User {
     name: String,
+    id: Option<String>,
 }
 
 impl User {
-    pub fn name<'a>(&'a self) -> &'a str {
+    pub fn name(&self) -> &str {
         self.name.as_str()
     }
 
+    /// The platform specific id of the user, this is the uid on linux and the SID on windows
+    pub fn id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+
     pub fn new(name: &str) -> Self {
         Self {
             name: name.to_string(),
+            id: None,
+        }
+    }
+
+    pub fn with_id(mut self, id: &str) -> Self {
+        self.id = Some(id.to_string());
+        self
+    }
+}
+
+/// The login session a process belongs to
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Session {
+    id: u32,
+    tty: Option<String>,
+}
+
+impl<'a> Session {
+    pub fn id(&'a self) -> u32 {
+        self.id
+    }
+
+    /// The terminal the process is attached to, if any, e.g. `/dev/pts/0`
+    pub fn tty(&'a self) -> Option<&'a str> {
+        self.tty.as_deref()
+    }
+
+    pub fn new(id: u32, tty: Option<&str>) -> Self {
+        Self {
+            id,
+            tty: tty.map(str::to_string),
         }
     }
 }
 
 /// Serializable process struct to describe process used inside of crate
-#[derive(Clone, Serialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct Process {
     pid: u32,
+    parent_pid: Option<u32>,
     name: String,
+    exe: Option<String>,
+    cmd: Vec<String>,
+    start_time: u64,
+    cpu_usage: f32,
+    memory: u64,
+    session: Option<Session>,
     user: User,
 }
 
@@ -35,10 +99,43 @@ impl<'a> Process {
         &self.pid
     }
 
+    pub fn parent_pid(&'a self) -> Option<u32> {
+        self.parent_pid
+    }
+
     pub fn name(&'a self) -> &'a str {
         &self.name
     }
 
+    /// The path to the executable of the process
+    pub fn exe(&'a self) -> Option<&'a str> {
+        self.exe.as_deref()
+    }
+
+    /// The command line the process was started with, including the program name
+    pub fn cmd(&'a self) -> &'a [String] {
+        &self.cmd
+    }
+
+    /// The time the process was started, in seconds since the unix epoch
+    pub fn start_time(&'a self) -> u64 {
+        self.start_time
+    }
+
+    /// CPU usage of the process as a percentage, this can go above 100% on multi core machines
+    pub fn cpu_usage(&'a self) -> f32 {
+        self.cpu_usage
+    }
+
+    /// Memory used by the process in bytes
+    pub fn memory(&'a self) -> u64 {
+        self.memory
+    }
+
+    pub fn session(&'a self) -> Option<&'a Session> {
+        self.session.as_ref()
+    }
+
     pub fn user(&'a self) -> &'a User {
         &self.user
     }
@@ -46,8 +143,46 @@ impl<'a> Process {
     pub fn new(pid: u32, name: &str, user: &User) -> Self {
         Self {
             pid,
+            parent_pid: None,
             name: name.to_string(),
+            exe: None,
+            cmd: vec![],
+            start_time: 0,
+            cpu_usage: 0.0,
+            memory: 0,
+            session: None,
             user: user.clone(),
         }
     }
+
+    pub fn with_parent_pid(mut self, parent_pid: u32) -> Self {
+        self.parent_pid = Some(parent_pid);
+        self
+    }
+
+    pub fn with_exe(mut self, exe: &str) -> Self {
+        self.exe = Some(exe.to_string());
+        self
+    }
+
+    pub fn with_cmd(mut self, cmd: &[String]) -> Self {
+        self.cmd = cmd.to_vec();
+        self
+    }
+
+    pub fn with_start_time(mut self, start_time: u64) -> Self {
+        self.start_time = start_time;
+        self
+    }
+
+    pub fn with_usage(mut self, cpu_usage: f32, memory: u64) -> Self {
+        self.cpu_usage = cpu_usage;
+        self.memory = memory;
+        self
+    }
+
+    pub fn with_session(mut self, session: Session) -> Self {
+        self.session = Some(session);
+        self
+    }
 }
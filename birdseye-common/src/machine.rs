@@ -0,0 +1,183 @@
+//! Everything used to identify and describe a student's computer
+
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display as FmtDisplay, Formatter};
+use std::net::IpAddr;
+
+/// A stable id for a machine, this stays the same across reboots and reinstalls of the monitor and
+/// is used by the server and dashboard as the key for everything related to the machine
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(transparent)]
+pub struct MachineId(String);
+
+impl MachineId {
+    pub fn new(id: &str) -> Self {
+        Self(id.to_string())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FmtDisplay for MachineId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// A network interface on a machine
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct NetworkInterface {
+    name: String,
+    mac: Option<String>,
+    #[cfg_attr(feature = "openapi", schema(value_type = Vec<String>))]
+    addresses: Vec<IpAddr>,
+}
+
+impl<'a> NetworkInterface {
+    pub fn name(&'a self) -> &'a str {
+        &self.name
+    }
+
+    /// The MAC address of the interface, formatted as `aa:bb:cc:dd:ee:ff`
+    pub fn mac(&'a self) -> Option<&'a str> {
+        self.mac.as_deref()
+    }
+
+    pub fn addresses(&'a self) -> &'a [IpAddr] {
+        &self.addresses
+    }
+
+    pub fn new(name: &str, mac: Option<&str>, addresses: Vec<IpAddr>) -> Self {
+        Self {
+            name: name.to_string(),
+            mac: mac.map(str::to_string),
+            addresses,
+        }
+    }
+}
+
+/// A display attached to a machine
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Display {
+    index: u32,
+    width: u32,
+    height: u32,
+}
+
+impl Display {
+    /// The index of the display, the primary display is always 0
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn new(index: u32, width: u32, height: u32) -> Self {
+        Self {
+            index,
+            width,
+            height,
+        }
+    }
+}
+
+/// Inventory information about a machine running `birdseye-monitor`, sent when the monitor
+/// registers with the server
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Machine {
+    id: MachineId,
+    hostname: String,
+    os: String,
+    os_version: String,
+    interfaces: Vec<NetworkInterface>,
+    displays: Vec<Display>,
+    monitor_version: String,
+}
+
+impl<'a> Machine {
+    pub fn id(&'a self) -> &'a MachineId {
+        &self.id
+    }
+
+    pub fn hostname(&'a self) -> &'a str {
+        &self.hostname
+    }
+
+    /// The name of the operating system, e.g. `Windows` or `Ubuntu`
+    pub fn os(&'a self) -> &'a str {
+        &self.os
+    }
+
+    pub fn os_version(&'a self) -> &'a str {
+        &self.os_version
+    }
+
+    pub fn interfaces(&'a self) -> &'a [NetworkInterface] {
+        &self.interfaces
+    }
+
+    pub fn displays(&'a self) -> &'a [Display] {
+        &self.displays
+    }
+
+    /// The version of `birdseye-monitor` running on the machine
+    pub fn monitor_version(&'a self) -> &'a str {
+        &self.monitor_version
+    }
+
+    pub fn new(id: MachineId, hostname: &str, monitor_version: &str) -> Self {
+        Self {
+            id,
+            hostname: hostname.to_string(),
+            os: String::new(),
+            os_version: String::new(),
+            interfaces: vec![],
+            displays: vec![],
+            monitor_version: monitor_version.to_string(),
+        }
+    }
+
+    pub fn with_os(mut self, os: &str, os_version: &str) -> Self {
+        self.os = os.to_string();
+        self.os_version = os_version.to_string();
+        self
+    }
+
+    pub fn with_interfaces(mut self, interfaces: Vec<NetworkInterface>) -> Self {
+        self.interfaces = interfaces;
+        self
+    }
+
+    pub fn with_displays(mut self, displays: Vec<Display>) -> Self {
+        self.displays = displays;
+        self
+    }
+}
+
+/// Whether a machine's monitor is currently connected to the server
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub enum Presence {
+    /// Connected since the given time, in seconds since the unix epoch
+    Online { since: u64 },
+    /// Disconnected, last heard from at the given time, in seconds since the unix epoch
+    Offline { last_seen: u64 },
+}
+
+impl Presence {
+    pub fn is_online(&self) -> bool {
+        matches!(self, Presence::Online { .. })
+    }
+}
@@ -0,0 +1,354 @@
+//! Rules for blacklisting applications
+//!
+//! Rules are managed on the server, which sends each monitor the rules for the room its machine is
+//! in. The monitor compiles them into a [`CompiledPolicy`] once and checks every process it sees
+//! start against it.
+
+use crate::command::CommandError;
+use crate::{Process, RoomId};
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+/// Id of a [`Rule`], picked by the server
+pub type RuleId = u64;
+
+/// How a process name or exe path is matched
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub enum Pattern {
+    /// A shell style glob matching the whole string, ignoring case. `*` matches anything, `?`
+    /// matches a single character
+    Glob(String),
+    /// A regular expression, this only has to match part of the string
+    Regex(String),
+}
+
+/// What to do with a process that matches a rule, see [`Action::severity`] for how drastic each
+/// one is
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub enum Action {
+    /// Let it run but tell the dashboard
+    Alert,
+    /// Pause the process
+    Suspend,
+    /// Kill the process
+    Kill,
+    /// Let the process use at most the given percentage of one CPU, between 1 and 100. Only
+    /// understood by monitors from protocol version 9, older ones are sent [`Action::Alert`]
+    /// instead
+    Throttle(u8),
+}
+
+impl Action {
+    /// How drastic the action is, when a process breaks more than one rule the most drastic
+    /// action is taken
+    pub fn severity(&self) -> u8 {
+        match self {
+            Action::Alert => 0,
+            Action::Throttle(_) => 1,
+            Action::Suspend => 2,
+            Action::Kill => 3,
+        }
+    }
+}
+
+impl Display for Action {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Action::Alert => write!(f, "alert"),
+            Action::Suspend => write!(f, "suspend"),
+            Action::Kill => write!(f, "kill"),
+            Action::Throttle(percent) => write!(f, "throttle to {percent}%"),
+        }
+    }
+}
+
+/// A blacklisted application, a process matches if it matches every condition that is set
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Rule {
+    /// Ignored when creating a rule
+    #[serde(default)]
+    #[cfg_attr(feature = "openapi", schema(value_type = u64))]
+    id: RuleId,
+    name: String,
+    /// Matched against the process name
+    process: Option<Pattern>,
+    /// Matched against the full path of the process's executable
+    exe: Option<Pattern>,
+    /// Only processes run by this user
+    user: Option<String>,
+    /// Only machines in these rooms, every machine if empty
+    #[serde(default)]
+    rooms: Vec<RoomId>,
+    action: Action,
+}
+
+impl<'a> Rule {
+    pub fn id(&'a self) -> RuleId {
+        self.id
+    }
+
+    pub fn name(&'a self) -> &'a str {
+        &self.name
+    }
+
+    pub fn process(&'a self) -> Option<&'a Pattern> {
+        self.process.as_ref()
+    }
+
+    pub fn exe(&'a self) -> Option<&'a Pattern> {
+        self.exe.as_ref()
+    }
+
+    pub fn user(&'a self) -> Option<&'a str> {
+        self.user.as_deref()
+    }
+
+    pub fn rooms(&'a self) -> &'a [RoomId] {
+        &self.rooms
+    }
+
+    pub fn action(&'a self) -> Action {
+        self.action
+    }
+
+    /// Whether the rule applies to a machine in the given room
+    pub fn applies_to(&self, room: Option<&RoomId>) -> bool {
+        self.rooms.is_empty() || room.map(|room| self.rooms.contains(room)) == Some(true)
+    }
+
+    pub fn new(id: RuleId, name: &str, action: Action) -> Self {
+        Self {
+            id,
+            name: name.to_string(),
+            process: None,
+            exe: None,
+            user: None,
+            rooms: vec![],
+            action,
+        }
+    }
+
+    pub fn with_id(mut self, id: RuleId) -> Self {
+        self.id = id;
+        self
+    }
+
+    pub fn with_process(mut self, process: Pattern) -> Self {
+        self.process = Some(process);
+        self
+    }
+
+    pub fn with_exe(mut self, exe: Pattern) -> Self {
+        self.exe = Some(exe);
+        self
+    }
+
+    pub fn with_user(mut self, user: &str) -> Self {
+        self.user = Some(user.to_string());
+        self
+    }
+
+    pub fn with_rooms(mut self, rooms: Vec<RoomId>) -> Self {
+        self.rooms = rooms;
+        self
+    }
+
+    pub fn with_action(mut self, action: Action) -> Self {
+        self.action = action;
+        self
+    }
+}
+
+/// Reasons a rule can't be compiled
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub enum PolicyError {
+    /// The rule has neither a process nor an exe pattern, so it would match every process
+    NoPattern(String),
+    /// A pattern could not be compiled, contains the rule's name and the reason
+    InvalidPattern(String, String),
+    /// The rule throttles processes to less than 1% or more than 100% of a CPU, contains the
+    /// rule's name
+    InvalidThrottle(String),
+}
+
+impl Display for PolicyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PolicyError::NoPattern(rule) => {
+                write!(f, "rule {rule} needs a process or exe pattern")
+            }
+            PolicyError::InvalidPattern(rule, reason) => {
+                write!(f, "rule {rule} has an invalid pattern: {reason}")
+            }
+            PolicyError::InvalidThrottle(rule) => {
+                write!(f, "rule {rule} has to throttle to between 1% and 100%")
+            }
+        }
+    }
+}
+
+impl std::error::Error for PolicyError {}
+
+/// Turn a glob into an anchored, case insensitive regular expression
+#[cfg(feature = "backend")]
+fn glob_to_regex(glob: &str) -> String {
+    let mut regex = String::from("(?i)^");
+    let mut buf = [0; 4];
+
+    for c in glob.chars() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            c => regex.push_str(&regex::escape(c.encode_utf8(&mut buf))),
+        }
+    }
+
+    regex.push('$');
+    regex
+}
+
+#[cfg(feature = "backend")]
+fn compile_pattern(
+    rule: &Rule,
+    pattern: Option<&Pattern>,
+) -> Result<Option<regex::Regex>, PolicyError> {
+    let source = match pattern {
+        Some(Pattern::Glob(glob)) => glob_to_regex(glob),
+        Some(Pattern::Regex(regex)) => regex.clone(),
+        None => return Ok(None),
+    };
+
+    regex::Regex::new(&source)
+        .map(Some)
+        .map_err(|ex| PolicyError::InvalidPattern(rule.name.clone(), ex.to_string()))
+}
+
+#[cfg(feature = "backend")]
+struct CompiledRule {
+    rule: Rule,
+    process: Option<regex::Regex>,
+    exe: Option<regex::Regex>,
+}
+
+#[cfg(feature = "backend")]
+impl CompiledRule {
+    fn matches(&self, process: &Process) -> bool {
+        let name = self
+            .process
+            .as_ref()
+            .map(|regex| regex.is_match(process.name()))
+            .unwrap_or(true);
+        // A process without a known exe can't match an exe pattern
+        let exe = self
+            .exe
+            .as_ref()
+            .map(|regex| process.exe().map(|exe| regex.is_match(exe)) == Some(true))
+            .unwrap_or(true);
+        let user = self
+            .rule
+            .user
+            .as_deref()
+            .map(|user| user == process.user().name())
+            .unwrap_or(true);
+
+        name && exe && user
+    }
+}
+
+/// A set of rules with their patterns compiled, ready to check processes against
+#[cfg(feature = "backend")]
+#[derive(Default)]
+pub struct CompiledPolicy {
+    rules: Vec<CompiledRule>,
+}
+
+#[cfg(feature = "backend")]
+impl CompiledPolicy {
+    /// Compile every rule, failing on the first one that can't be. The rules' rooms are not
+    /// checked here, only pass in the rules for the machine the policy is used on
+    pub fn compile(rules: Vec<Rule>) -> Result<Self, PolicyError> {
+        let rules = rules
+            .into_iter()
+            .map(|rule| {
+                if rule.process.is_none() && rule.exe.is_none() {
+                    return Err(PolicyError::NoPattern(rule.name.clone()));
+                }
+                if let Action::Throttle(0 | 101..) = rule.action {
+                    return Err(PolicyError::InvalidThrottle(rule.name.clone()));
+                }
+
+                Ok(CompiledRule {
+                    process: compile_pattern(&rule, rule.process.as_ref())?,
+                    exe: compile_pattern(&rule, rule.exe.as_ref())?,
+                    rule,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { rules })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Get the rule a process breaks, if it breaks more than one the rule with the most drastic
+    /// action wins
+    pub fn check(&self, process: &Process) -> Option<&Rule> {
+        self.rules
+            .iter()
+            .filter(|rule| rule.matches(process))
+            .map(|rule| &rule.rule)
+            .max_by_key(|rule| rule.action.severity())
+    }
+}
+
+/// A process that broke a rule, sent by the monitor after the rule's action was carried out
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Violation {
+    #[cfg_attr(feature = "openapi", schema(value_type = u64))]
+    rule: RuleId,
+    /// The rule's name when it was broken
+    name: String,
+    action: Action,
+    process: Process,
+    /// Why the action couldn't be carried out, if it couldn't
+    error: Option<CommandError>,
+}
+
+impl<'a> Violation {
+    pub fn rule(&'a self) -> RuleId {
+        self.rule
+    }
+
+    pub fn name(&'a self) -> &'a str {
+        &self.name
+    }
+
+    pub fn action(&'a self) -> Action {
+        self.action
+    }
+
+    pub fn process(&'a self) -> &'a Process {
+        &self.process
+    }
+
+    pub fn error(&'a self) -> Option<&'a CommandError> {
+        self.error.as_ref()
+    }
+
+    pub fn new(rule: &Rule, process: Process, error: Option<CommandError>) -> Self {
+        Self {
+            rule: rule.id,
+            name: rule.name.clone(),
+            action: rule.action,
+            process,
+            error,
+        }
+    }
+}
@@ -0,0 +1,26 @@
+//! Rooms are used to group machines, e.g. all the computers in one classroom
+
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+/// The id of a room, this is the name used for the room in the server's config
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(transparent)]
+pub struct RoomId(String);
+
+impl RoomId {
+    pub fn new(id: &str) -> Self {
+        Self(id.to_string())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Display for RoomId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
@@ -0,0 +1,95 @@
+//! Encoding frames for a stream while keeping to a bitrate
+
+use super::jpeg::encode_jpeg;
+use super::{Encoding, Frame, Packet};
+
+/// The quality the first frame is encoded with
+const START_QUALITY: u8 = 50;
+
+/// The lowest quality used, screens become unreadable below this
+const MIN_QUALITY: u8 = 5;
+
+/// The highest quality used, anything more makes frames much bigger for little difference
+const MAX_QUALITY: u8 = 90;
+
+/// Encodes the frames of a stream, one at a time and in order
+pub trait Encoder: Send {
+    fn encode(&mut self, frame: &Frame) -> Packet;
+
+    /// How many frames a second will be encoded from now on, for encoders that share a bitrate
+    /// between them
+    fn set_frame_rate(&mut self, _frame_rate: u32) {}
+}
+
+/// Sends frames as they are, only for networks fast enough to take them
+pub struct RawEncoder;
+
+impl Encoder for RawEncoder {
+    fn encode(&mut self, frame: &Frame) -> Packet {
+        Packet::from(frame.clone())
+    }
+}
+
+/// Motion JPEG, every frame is its own JPEG so any of them can be shown without the ones before.
+/// The quality is raised or lowered after each frame to keep to the bitrate, as one frame of a
+/// screen is usually much the same size as the last
+pub struct JpegEncoder {
+    bitrate: u32,
+    frame_rate: u32,
+    quality: u8,
+}
+
+impl JpegEncoder {
+    /// `bitrate` is in bits a second, `frame_rate` is how many frames a second will be encoded
+    pub fn new(bitrate: u32, frame_rate: u32) -> Self {
+        Self {
+            bitrate,
+            frame_rate: frame_rate.max(1),
+            quality: START_QUALITY,
+        }
+    }
+
+    /// The quality the next frame will be encoded with
+    pub fn quality(&self) -> u8 {
+        self.quality
+    }
+
+    /// How many bytes each frame can take up
+    pub fn budget(&self) -> usize {
+        (self.bitrate / 8 / self.frame_rate) as usize
+    }
+}
+
+impl Encoder for JpegEncoder {
+    fn encode(&mut self, frame: &Frame) -> Packet {
+        let (width, height) = match (u16::try_from(frame.width()), u16::try_from(frame.height())) {
+            (Ok(width), Ok(height)) if width > 0 && height > 0 => (width, height),
+            // Too big or small for a JPEG
+            _ => return Packet::from(frame.clone()),
+        };
+
+        let jpeg = match encode_jpeg(width, height, &frame.to_rgb(), self.quality) {
+            Ok(jpeg) => jpeg,
+            // Not enough pixels for its size
+            Err(_) => return Packet::from(frame.clone()),
+        };
+
+        // Move faster when far from the budget
+        let budget = self.budget();
+        let step = match jpeg.len() > budget * 2 || jpeg.len() < budget / 4 {
+            true => 10,
+            false => 2,
+        };
+        if jpeg.len() > budget {
+            self.quality = self.quality.saturating_sub(step).max(MIN_QUALITY);
+        } else if jpeg.len() < budget * 3 / 4 {
+            self.quality = (self.quality + step).min(MAX_QUALITY);
+        }
+
+        Packet::new(Encoding::Jpeg, frame.width(), frame.height(), jpeg)
+    }
+
+    fn set_frame_rate(&mut self, frame_rate: u32) {
+        self.frame_rate = frame_rate.max(1);
+    }
+}
@@ -0,0 +1,355 @@
+//! H.264 video, which only sends how the screen moved since the last frame so uses far less of
+//! the network than tiles or JPEGs while a screen is busy
+//!
+//! Frames are encoded with OpenH264 at a fixed bitrate, and every [`KEYFRAME_INTERVAL`] seconds of
+//! frames is a keyframe. Packets have the same header as [tiles](super::tiles) packets, so they
+//! are sequenced, merged with [`Packet::followed_by`] and kept in a [`Snapshot`](super::Snapshot)
+//! the same way. A merged packet holds every frame since the keyframe it starts with, which the
+//! keyframe interval keeps from growing too long.
+//!
+//! H.264 can only encode frames with an even width and height, so odd frames are padded by
+//! repeating their last row or column and cropped again when decoded.
+//!
+//! Encoding and decoding need the `h264` feature, which builds OpenH264 from source. Browsers
+//! decode the frames themselves, see [`frames`] and [`codec`].
+//!
+//! # Format
+//! Packets with [`Encoding::H264`] hold a header followed by each frame, numbers are little endian
+//! | Bytes  | Description                                                   |
+//! |--------|---------------------------------------------------------------|
+//! | 1      | 1 if the first frame is a keyframe, 0 for a delta             |
+//! | 8      | The sequence of the frame a delta applies to                  |
+//! | 8      | The sequence of the last frame                                |
+//! | 4      | How many frames there are                                     |
+//! | 4      | For each frame, the length of its NAL units                   |
+//! | length | For each frame, its NAL units in Annex B format               |
+
+use super::tiles::{TileHeader, HEADER};
+use super::{DecodeError, Encoding, Packet};
+use bytes::Bytes;
+
+#[cfg(feature = "h264")]
+pub(super) use codec::decode_keyframe;
+#[cfg(feature = "h264")]
+pub use codec::H264Encoder;
+
+/// How many seconds apart keyframes are
+pub const KEYFRAME_INTERVAL: u32 = 5;
+
+/// The frames in a packet, sharing the packet's data
+#[derive(Clone)]
+pub(super) struct Frames {
+    pub(super) header: TileHeader,
+    width: u32,
+    height: u32,
+    pub(super) frames: Vec<Bytes>,
+}
+
+impl Frames {
+    pub(super) fn read(packet: &Packet) -> Result<Self, DecodeError> {
+        if packet.encoding() != Encoding::H264 {
+            return Err(DecodeError::Corrupt("not an H.264 packet"));
+        }
+
+        packet.check_size()?;
+        let header = TileHeader::read(packet)?;
+        let data = packet.data();
+        let count = u32::from_le_bytes(data[17..HEADER].try_into().unwrap());
+
+        let mut frames = vec![];
+        let mut position = HEADER;
+        for _ in 0..count {
+            let length = data
+                .get(position..position + 4)
+                .ok_or(DecodeError::Truncated)?;
+            let length = u32::from_le_bytes(length.try_into().unwrap()) as usize;
+            position += 4;
+
+            if data.len() < position + length {
+                return Err(DecodeError::Truncated);
+            }
+            frames.push(data.slice(position..position + length));
+            position += length;
+        }
+
+        Ok(Self {
+            header,
+            width: packet.width(),
+            height: packet.height(),
+            frames,
+        })
+    }
+
+    pub(super) fn write(&self) -> Packet {
+        let length = self
+            .frames
+            .iter()
+            .map(|frame| frame.len() + 4)
+            .sum::<usize>();
+        let mut data = Vec::with_capacity(HEADER + length);
+
+        data.push(self.header.keyframe() as u8);
+        data.extend_from_slice(&self.header.base().to_le_bytes());
+        data.extend_from_slice(&self.header.sequence().to_le_bytes());
+        data.extend_from_slice(&(self.frames.len() as u32).to_le_bytes());
+        for frame in &self.frames {
+            data.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+            data.extend_from_slice(frame);
+        }
+
+        Packet::new(Encoding::H264, self.width, self.height, data)
+    }
+
+    /// Play `later` after these frames
+    pub(super) fn merge(&mut self, later: Frames) -> Result<(), DecodeError> {
+        if (later.width, later.height) != (self.width, self.height)
+            || later.header.base() != self.header.sequence()
+        {
+            return Err(DecodeError::MissingKeyframe);
+        }
+
+        self.header = TileHeader::new(
+            self.header.keyframe(),
+            self.header.base(),
+            later.header.sequence(),
+        );
+        self.frames.extend(later.frames);
+        Ok(())
+    }
+}
+
+/// Split a packet into its frames, each of which is one access unit in Annex B format
+pub fn frames(packet: &Packet) -> Result<Vec<Bytes>, DecodeError> {
+    Ok(Frames::read(packet)?.frames)
+}
+
+/// The NAL units in an Annex B stream, without their start codes
+struct NalUnits<'a> {
+    rest: &'a [u8],
+}
+
+impl<'a> Iterator for NalUnits<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<&'a [u8]> {
+        // Skip past the next start code, which is two or more zeros and then a one
+        let start = self
+            .rest
+            .windows(3)
+            .position(|window| window == [0, 0, 1])?
+            + 3;
+        let rest = &self.rest[start..];
+        let end = rest
+            .windows(3)
+            .position(|window| window == [0, 0, 1] || window == [0, 0, 0])
+            .unwrap_or(rest.len());
+
+        self.rest = &rest[end..];
+        Some(&rest[..end])
+    }
+}
+
+/// The WebCodecs codec string for a keyframe, e.g. `avc1.42c01f`, read from the profile and level
+/// in its sequence parameter set. `None` for deltas, which don't have one
+pub fn codec(packet: &Packet) -> Option<String> {
+    let first = Frames::read(packet).ok()?.frames.into_iter().next()?;
+    let mut nal_units = NalUnits { rest: &first };
+    let sps = nal_units.find(|nal| nal.first().is_some_and(|header| header & 0x1f == 7))?;
+
+    match sps.get(1..4)? {
+        [profile, constraints, level] => {
+            Some(format!("avc1.{profile:02x}{constraints:02x}{level:02x}"))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(feature = "h264")]
+mod codec {
+    use super::super::{DecodeError, Encoder, Frame, Packet};
+    use super::{Frames, TileHeader, KEYFRAME_INTERVAL};
+    use openh264::decoder::{DecodedYUV, Decoder};
+    use openh264::encoder::{
+        BitRate, Encoder as OpenH264Encoder, EncoderConfig, FrameRate, FrameType, RateControlMode,
+        UsageType,
+    };
+    use openh264::formats::{BgraSliceU8, YUVBuffer, YUVSource};
+    use openh264::OpenH264API;
+
+    /// Encodes frames as H.264, see the [module docs](super)
+    pub struct H264Encoder {
+        bitrate: u32,
+        frame_rate: u32,
+        /// Made when the first frame is encoded, and again after the frame rate changes or
+        /// encoding fails so the stream starts over with a keyframe
+        encoder: Option<OpenH264Encoder>,
+        /// How many frames have been encoded since the last keyframe
+        since_keyframe: u32,
+        sequence: u64,
+    }
+
+    impl H264Encoder {
+        /// `bitrate` is in bits a second, `frame_rate` is how many frames a second will be encoded
+        pub fn new(bitrate: u32, frame_rate: u32) -> Self {
+            Self {
+                bitrate,
+                frame_rate: frame_rate.max(1),
+                encoder: None,
+                since_keyframe: 0,
+                sequence: 0,
+            }
+        }
+
+        fn encoder(&mut self) -> Result<&mut OpenH264Encoder, openh264::Error> {
+            if self.encoder.is_none() {
+                let config = EncoderConfig::new()
+                    .bitrate(BitRate::from_bps(self.bitrate))
+                    .max_frame_rate(FrameRate::from_hz(self.frame_rate as f32))
+                    .rate_control_mode(RateControlMode::Bitrate)
+                    .usage_type(UsageType::ScreenContentRealTime);
+                let encoder = OpenH264Encoder::with_api_config(OpenH264API::from_source(), config)?;
+                self.encoder = Some(encoder);
+            }
+
+            Ok(self.encoder.as_mut().unwrap())
+        }
+
+        /// Encode a frame into its NAL units, along with whether it is a keyframe. Frames the
+        /// encoder skips to keep to the bitrate have no NAL units
+        fn encode_frame(&mut self, frame: &Frame) -> Result<(bool, Vec<u8>), openh264::Error> {
+            let force = self.since_keyframe >= self.frame_rate * KEYFRAME_INTERVAL;
+            let yuv = YUVBuffer::from_bgra8_source(BgraSliceU8::new(
+                &padded(frame),
+                (even(frame.width()) as usize, even(frame.height()) as usize),
+            ));
+
+            let encoder = self.encoder()?;
+            if force {
+                encoder.force_intra_frame();
+            }
+            let stream = encoder.encode(&yuv)?;
+
+            Ok((
+                matches!(stream.frame_type(), FrameType::IDR),
+                stream.to_vec(),
+            ))
+        }
+    }
+
+    impl Encoder for H264Encoder {
+        fn encode(&mut self, frame: &Frame) -> Packet {
+            let (keyframe, nal_units) = match self.encode_frame(frame) {
+                Ok(encoded) => encoded,
+                // Bigger than H.264 allows, send it as it is and start again with a keyframe
+                Err(_) => {
+                    self.encoder = None;
+                    return Packet::from(frame.clone());
+                }
+            };
+
+            match keyframe {
+                true => self.since_keyframe = 1,
+                false => self.since_keyframe += 1,
+            }
+
+            // A skipped frame doesn't change anything, so keeps the same sequence
+            let base = self.sequence;
+            let frames = match nal_units.is_empty() {
+                true => vec![],
+                false => {
+                    self.sequence += 1;
+                    vec![nal_units.into()]
+                }
+            };
+
+            Frames {
+                header: TileHeader::new(keyframe, base, self.sequence),
+                width: frame.width(),
+                height: frame.height(),
+                frames,
+            }
+            .write()
+        }
+
+        fn set_frame_rate(&mut self, frame_rate: u32) {
+            let frame_rate = frame_rate.max(1);
+            if frame_rate != self.frame_rate {
+                self.frame_rate = frame_rate;
+                self.encoder = None;
+            }
+        }
+    }
+
+    /// Round a size up to the even size H.264 needs
+    fn even(size: u32) -> u32 {
+        size + size % 2
+    }
+
+    /// The frame's pixels, with the last column and row repeated if it is an odd size
+    fn padded(frame: &Frame) -> Vec<u8> {
+        let (width, height) = (frame.width() as usize, frame.height() as usize);
+        let row = even(frame.width()) as usize * 4;
+
+        let mut data = Vec::with_capacity(row * even(frame.height()) as usize);
+        for source in frame.data().chunks_exact(width * 4) {
+            data.extend_from_slice(source);
+            if width % 2 == 1 {
+                data.extend_from_slice(&source[source.len() - 4..]);
+            }
+        }
+        if height % 2 == 1 {
+            data.extend_from_within(data.len() - row..);
+        }
+
+        data
+    }
+
+    /// The pixels of a decoded picture, cropped to `width` and `height`
+    fn crop(picture: &DecodedYUV, width: u32, height: u32) -> Result<Frame, DecodeError> {
+        let (picture_width, picture_height) = picture.dimensions();
+        if (picture_width as u32, picture_height as u32) != (even(width), even(height)) {
+            return Err(DecodeError::Corrupt(
+                "H.264 is a different size to the frame",
+            ));
+        }
+
+        let mut rgb = vec![0; picture_width * picture_height * 3];
+        picture.write_rgb8(&mut rgb);
+
+        let rgb: Vec<u8> = rgb
+            .chunks_exact(picture_width * 3)
+            .take(height as usize)
+            .flat_map(|row| &row[..width as usize * 3])
+            .copied()
+            .collect();
+        Ok(Frame::from_rgb(width, height, &rgb))
+    }
+
+    /// Play every frame of a packet that starts with a keyframe, and return the last one
+    pub fn decode_keyframe(packet: &Packet) -> Result<Frame, DecodeError> {
+        let frames = Frames::read(packet)?;
+        if !frames.header.keyframe() {
+            return Err(DecodeError::MissingKeyframe);
+        }
+        let (last, before) = frames
+            .frames
+            .split_last()
+            .ok_or(DecodeError::Corrupt("keyframe has no frames"))?;
+
+        let corrupt = |_| DecodeError::Corrupt("not valid H.264");
+        let mut decoder = Decoder::new()
+            .map_err(|_| DecodeError::Unsupported("H.264 decoder could not be started"))?;
+        for frame in before {
+            decoder.decode(frame).map_err(corrupt)?;
+        }
+
+        let (width, height) = (packet.width(), packet.height());
+        if let Some(picture) = decoder.decode(last).map_err(corrupt)? {
+            return crop(&picture, width, height);
+        }
+        match decoder.flush_remaining().map_err(corrupt)?.last() {
+            Some(picture) => crop(picture, width, height),
+            None => Err(DecodeError::Truncated),
+        }
+    }
+}
@@ -0,0 +1,47 @@
+//! Baseline JPEGs for streaming screens and making thumbnails, using the `jpeg-encoder` and
+//! `jpeg-decoder` crates
+//!
+//! Images are encoded without chroma subsampling, which keeps coloured text on a screen readable.
+//! Any JPEG the decoder understands can be read back, as long as it is in colour.
+
+use super::{DecodeError, MAX_SIZE};
+use jpeg_decoder::{Decoder, Error, PixelFormat};
+use jpeg_encoder::{ColorType, Encoder, EncodingError};
+use std::io;
+
+/// Encode tightly packed RGB pixels as a JPEG, `quality` is between 1 and 100. Fails if the image
+/// is empty or there aren't enough pixels to fill it
+pub fn encode_jpeg(
+    width: u16,
+    height: u16,
+    rgb: &[u8],
+    quality: u8,
+) -> Result<Vec<u8>, EncodingError> {
+    let mut jpeg = vec![];
+    Encoder::new(&mut jpeg, quality.clamp(1, 100)).encode(rgb, width, height, ColorType::Rgb)?;
+
+    Ok(jpeg)
+}
+
+/// Decode a JPEG back into its size and tightly packed RGB pixels. JPEGs wider or taller than
+/// [`MAX_SIZE`] are refused before anything is allocated for their pixels
+pub fn decode_jpeg(jpeg: &[u8]) -> Result<(u16, u16, Vec<u8>), DecodeError> {
+    let error = |ex| match ex {
+        Error::Io(ex) if ex.kind() == io::ErrorKind::UnexpectedEof => DecodeError::Truncated,
+        Error::Unsupported(_) => DecodeError::Unsupported("JPEG feature"),
+        _ => DecodeError::Corrupt("not a valid JPEG"),
+    };
+
+    let mut decoder = Decoder::new(jpeg);
+    decoder.read_info().map_err(error)?;
+    let info = decoder.info().ok_or(DecodeError::Truncated)?;
+    if info.width as u32 > MAX_SIZE || info.height as u32 > MAX_SIZE {
+        return Err(DecodeError::TooBig);
+    }
+    if info.pixel_format != PixelFormat::RGB24 {
+        return Err(DecodeError::Unsupported("only colour JPEGs can be decoded"));
+    }
+
+    let rgb = decoder.decode().map_err(error)?;
+    Ok((info.width, info.height, rgb))
+}
@@ -0,0 +1,268 @@
+//! Frames captured from a machine's screen, streamed by monitors to the server and relayed on to
+//! the dashboards watching the machine
+//!
+//! Raw frames are far too big to send over a classroom network, so monitors encode them into
+//! [`Packet`]s first, see [`Encoder`].
+
+mod encoder;
+pub mod h264;
+pub mod jpeg;
+pub mod png;
+pub mod tiles;
+
+pub use encoder::{Encoder, JpegEncoder, RawEncoder};
+#[cfg(feature = "h264")]
+pub use h264::H264Encoder;
+pub use tiles::{Snapshot, TileDecoder, TileEncoder};
+
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+/// The widest and tallest a frame can be. Packets claiming to be bigger are refused before
+/// anything is allocated for their pixels, so a broken monitor can't run out the server's memory
+pub const MAX_SIZE: u32 = 8192;
+
+/// A single raw frame captured from a display, stored as tightly packed BGR0 pixels
+///
+/// The pixels are reference counted, so cloning a frame to hand it to every dashboard watching
+/// doesn't copy them.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    width: u32,
+    height: u32,
+    data: Bytes,
+}
+
+impl<'a> Frame {
+    pub fn width(&'a self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&'a self) -> u32 {
+        self.height
+    }
+
+    pub fn data(&'a self) -> &'a [u8] {
+        &self.data
+    }
+
+    pub fn new(width: u32, height: u32, data: impl Into<Bytes>) -> Self {
+        Self {
+            width,
+            height,
+            data: data.into(),
+        }
+    }
+
+    /// Copy a frame out of a buffer whose rows are `stride` bytes long, as screen capture APIs pad
+    /// the end of each row
+    pub fn from_padded(width: u32, height: u32, stride: usize, buffer: &[u8]) -> Self {
+        let row = width as usize * 4;
+        let mut data = Vec::with_capacity(row * height as usize);
+        for padded in buffer.chunks(stride).take(height as usize) {
+            data.extend_from_slice(&padded[..row]);
+        }
+
+        Self::new(width, height, data)
+    }
+
+    /// Make a frame from tightly packed RGB pixels
+    pub fn from_rgb(width: u32, height: u32, rgb: &[u8]) -> Self {
+        let data: Vec<u8> = rgb
+            .chunks_exact(3)
+            .flat_map(|pixel| [pixel[2], pixel[1], pixel[0], 0])
+            .collect();
+
+        Self::new(width, height, data)
+    }
+
+    /// The pixels as tightly packed RGB, which is what encoders want
+    pub fn to_rgb(&self) -> Vec<u8> {
+        self.data
+            .chunks_exact(4)
+            .flat_map(|pixel| [pixel[2], pixel[1], pixel[0]])
+            .collect()
+    }
+
+    /// Shrink the frame to `width` pixels wide keeping its aspect ratio, averaging the pixels that
+    /// end up in each one. Frames are never made bigger, and have to be at least 1x1 with all
+    /// their pixels
+    pub fn downscale(&self, width: u32) -> Frame {
+        let (source_width, source_height) = (self.width as usize, self.height as usize);
+        let width = (width as usize).clamp(1, source_width);
+        let height = (source_height * width / source_width).max(1);
+
+        let mut data = Vec::with_capacity(width * height * 4);
+        for y in 0..height {
+            let (top, bottom) = span(y, height, source_height);
+            for x in 0..width {
+                let (left, right) = span(x, width, source_width);
+
+                let mut sum = [0u32; 3];
+                for row in top..bottom {
+                    let start = (row * source_width + left) * 4;
+                    let end = (row * source_width + right) * 4;
+                    for pixel in self.data[start..end].chunks_exact(4) {
+                        sum[0] += pixel[0] as u32;
+                        sum[1] += pixel[1] as u32;
+                        sum[2] += pixel[2] as u32;
+                    }
+                }
+
+                let count = ((bottom - top) * (right - left)).max(1) as u32;
+                data.extend(sum.map(|channel| (channel / count) as u8));
+                data.push(0);
+            }
+        }
+
+        Frame::new(width as u32, height as u32, data)
+    }
+}
+
+/// The source pixels that end up in pixel `i` of `size` when shrinking from `source`, always at
+/// least one
+fn span(i: usize, size: usize, source: usize) -> (usize, usize) {
+    let start = i * source / size;
+    let end = ((i + 1) * source / size).max(start + 1);
+
+    (start, end)
+}
+
+/// How much of a machine's screen the server needs, so monitors only capture as much as is being
+/// looked at
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Demand {
+    /// Nobody is looking, nothing is captured
+    None,
+    /// Only thumbnails are being shown, so a frame a second no more than `width` pixels wide
+    Thumbnails { width: u32 },
+    /// A teacher has the machine open, every frame at full size
+    Full,
+}
+
+/// How the pixels in a [`Packet`] are encoded
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// Tightly packed BGR0 pixels, the same as a [`Frame`]
+    Raw,
+    /// A baseline JPEG, see [`jpeg`]
+    Jpeg,
+    /// Only the tiles of the screen that changed, see [`tiles`]
+    Tiles,
+    /// H.264 video, see [`h264`]
+    H264,
+}
+
+/// A frame encoded to be sent over the network. Raw and JPEG packets can be decoded on their own,
+/// tiles and H.264 packets other than keyframes need the packets before them
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Packet {
+    encoding: Encoding,
+    width: u32,
+    height: u32,
+    data: Bytes,
+}
+
+impl<'a> Packet {
+    pub fn encoding(&'a self) -> Encoding {
+        self.encoding
+    }
+
+    pub fn width(&'a self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&'a self) -> u32 {
+        self.height
+    }
+
+    pub fn data(&'a self) -> &'a Bytes {
+        &self.data
+    }
+
+    pub fn new(encoding: Encoding, width: u32, height: u32, data: impl Into<Bytes>) -> Self {
+        Self {
+            encoding,
+            width,
+            height,
+            data: data.into(),
+        }
+    }
+
+    /// Check the packet's size is one a frame can be, at least 1x1 and no more than
+    /// [`MAX_SIZE`] either way
+    pub fn check_size(&self) -> Result<(), DecodeError> {
+        if self.width == 0 || self.height == 0 {
+            return Err(DecodeError::Corrupt("empty frame"));
+        }
+        if self.width > MAX_SIZE || self.height > MAX_SIZE {
+            return Err(DecodeError::TooBig);
+        }
+
+        Ok(())
+    }
+
+    /// Get the frame back out of the packet, which has to be a keyframe
+    pub fn decode(&self) -> Result<Frame, DecodeError> {
+        self.check_size()?;
+        let pixels = self.width as u64 * self.height as u64;
+
+        match self.encoding {
+            Encoding::Raw if self.data.len() as u64 == pixels * 4 => {
+                Ok(Frame::new(self.width, self.height, self.data.clone()))
+            }
+            Encoding::Raw => Err(DecodeError::Corrupt("wrong number of pixels")),
+            Encoding::Jpeg => {
+                let (width, height, rgb) = jpeg::decode_jpeg(&self.data)?;
+                if (width as u32, height as u32) != (self.width, self.height) {
+                    return Err(DecodeError::Corrupt(
+                        "JPEG is a different size to the frame",
+                    ));
+                }
+
+                Ok(Frame::from_rgb(self.width, self.height, &rgb))
+            }
+            Encoding::Tiles => tiles::decode_keyframe(self),
+            #[cfg(feature = "h264")]
+            Encoding::H264 => h264::decode_keyframe(self),
+            #[cfg(not(feature = "h264"))]
+            Encoding::H264 => Err(DecodeError::Unsupported("H.264 needs the h264 feature")),
+        }
+    }
+}
+
+impl From<Frame> for Packet {
+    fn from(frame: Frame) -> Self {
+        Self::new(Encoding::Raw, frame.width, frame.height, frame.data)
+    }
+}
+
+/// Why a [`Packet`] couldn't be decoded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The data ends before the whole frame has been read
+    Truncated,
+    /// The data isn't what its encoding says it is
+    Corrupt(&'static str),
+    /// The data uses a feature the decoder doesn't understand
+    Unsupported(&'static str),
+    /// The packet only has what changed, and the packets before it haven't been seen
+    MissingKeyframe,
+    /// The frame is wider or taller than [`MAX_SIZE`]
+    TooBig,
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::Truncated => f.write_str("frame ends early"),
+            DecodeError::Corrupt(reason) => write!(f, "corrupt frame, {reason}"),
+            DecodeError::Unsupported(feature) => write!(f, "unsupported frame, {feature}"),
+            DecodeError::MissingKeyframe => f.write_str("frame needs the frames before it"),
+            DecodeError::TooBig => write!(f, "frame is bigger than {MAX_SIZE}x{MAX_SIZE}"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
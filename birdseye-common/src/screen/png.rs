@@ -0,0 +1,54 @@
+//! PNGs for the tiles of a [`TileEncoder`](super::TileEncoder), 8 bit RGB using the `png` crate
+//!
+//! Each row is filtered with whichever filter leaves the smallest bytes before the whole image is
+//! deflated. Screens are mostly flat colours and text, which these filters shrink to almost
+//! nothing.
+
+use super::DecodeError;
+use png::{AdaptiveFilterType, BitDepth, ColorType, Decoder, DecodingError, EncodingError};
+use std::io;
+
+/// Encode tightly packed RGB pixels as a PNG, fails if there aren't enough pixels to fill it
+pub fn encode_png(width: u32, height: u32, rgb: &[u8]) -> Result<Vec<u8>, EncodingError> {
+    let mut png = vec![];
+    let mut encoder = png::Encoder::new(&mut png, width, height);
+    encoder.set_color(ColorType::Rgb);
+    encoder.set_depth(BitDepth::Eight);
+    encoder.set_adaptive_filter(AdaptiveFilterType::Adaptive);
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(rgb)?;
+    writer.finish()?;
+
+    Ok(png)
+}
+
+/// Decode an 8 bit RGB PNG that should be `width` by `height` back into tightly packed RGB
+/// pixels. The size is checked before anything is allocated for the pixels, so a PNG claiming
+/// to be huge can't use up all the memory
+pub fn decode_png(png: &[u8], width: u32, height: u32) -> Result<Vec<u8>, DecodeError> {
+    let error = |ex: DecodingError| match ex {
+        DecodingError::IoError(ex) if ex.kind() == io::ErrorKind::UnexpectedEof => {
+            DecodeError::Truncated
+        }
+        DecodingError::LimitsExceeded => DecodeError::TooBig,
+        _ => DecodeError::Corrupt("not a valid PNG"),
+    };
+
+    let mut reader = Decoder::new(png).read_info().map_err(error)?;
+    let info = reader.info();
+    if (info.width, info.height) != (width, height) {
+        return Err(DecodeError::Corrupt("PNG is the wrong size"));
+    }
+    if (info.color_type, info.bit_depth) != (ColorType::Rgb, BitDepth::Eight) {
+        return Err(DecodeError::Unsupported(
+            "only 8 bit RGB PNGs can be decoded",
+        ));
+    }
+
+    let mut rgb = vec![0; reader.output_buffer_size()];
+    let frame = reader.next_frame(&mut rgb).map_err(error)?;
+    rgb.truncate(frame.buffer_size());
+
+    Ok(rgb)
+}
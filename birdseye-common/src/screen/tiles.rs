@@ -0,0 +1,471 @@
+//! A codec for mostly still desktops, only the parts of the screen that change are sent
+//!
+//! Frames are split into [`TILE_SIZE`] square tiles and each tile is hashed, only the tiles whose
+//! hash changed since the last frame are sent, each as a PNG so text stays sharp. Keyframes have
+//! every tile.
+//!
+//! Every packet has a sequence number, and a delta says which sequence it applies on top of (its
+//! base). Deltas can be merged with [`Packet::followed_by`], so anything that has to skip frames
+//! can send everything that changed across them instead, and kept in a [`Snapshot`], so anyone who
+//! starts watching partway can be sent the whole screen as a keyframe.
+//!
+//! # Format
+//! Packets with [`Encoding::Tiles`] hold a header followed by each tile, numbers are little endian
+//! | Bytes  | Description                                                   |
+//! |--------|---------------------------------------------------------------|
+//! | 1      | 1 for a keyframe, 0 for a delta                               |
+//! | 8      | The sequence of the frame a delta applies to                  |
+//! | 8      | The sequence of this frame                                    |
+//! | 4      | How many tiles there are                                      |
+//! | 4      | For each tile, its index counting along each row of tiles     |
+//! | 4      | For each tile, the length of its PNG                          |
+//! | length | For each tile, the PNG                                        |
+
+use super::h264::Frames;
+use super::png::{decode_png, encode_png};
+use super::{DecodeError, Encoder, Encoding, Frame, Packet};
+use bytes::Bytes;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+
+/// The width and height of a tile, tiles on the right and bottom edges can be smaller
+pub const TILE_SIZE: u32 = 64;
+
+/// The length of the header before the tiles
+pub(super) const HEADER: usize = 21;
+
+/// The header of a packet with [`Encoding::Tiles`], which [`Encoding::H264`] packets start with too
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TileHeader {
+    keyframe: bool,
+    base: u64,
+    sequence: u64,
+}
+
+impl TileHeader {
+    pub(super) fn new(keyframe: bool, base: u64, sequence: u64) -> Self {
+        Self {
+            keyframe,
+            base,
+            sequence,
+        }
+    }
+
+    /// Whether the packet can be shown without the ones before it, so has every tile
+    pub fn keyframe(&self) -> bool {
+        self.keyframe
+    }
+
+    /// The sequence of the frame a delta changes
+    pub fn base(&self) -> u64 {
+        self.base
+    }
+
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    pub fn read(packet: &Packet) -> Result<Self, DecodeError> {
+        if !matches!(packet.encoding(), Encoding::Tiles | Encoding::H264) {
+            return Err(DecodeError::Corrupt("not a tiles packet"));
+        }
+
+        let data = packet.data();
+        let header = data.get(..HEADER).ok_or(DecodeError::Truncated)?;
+        Ok(Self {
+            keyframe: header[0] == 1,
+            base: u64::from_le_bytes(header[1..9].try_into().unwrap()),
+            sequence: u64::from_le_bytes(header[9..17].try_into().unwrap()),
+        })
+    }
+}
+
+/// The tiles in a packet by index, sharing the packet's data
+#[derive(Clone)]
+struct Tiles {
+    header: TileHeader,
+    width: u32,
+    height: u32,
+    tiles: BTreeMap<u32, Bytes>,
+}
+
+impl Tiles {
+    fn read(packet: &Packet) -> Result<Self, DecodeError> {
+        if packet.encoding() != Encoding::Tiles {
+            return Err(DecodeError::Corrupt("not a tiles packet"));
+        }
+
+        packet.check_size()?;
+        let header = TileHeader::read(packet)?;
+        let data = packet.data();
+        let count = u32::from_le_bytes(data[17..HEADER].try_into().unwrap());
+        let (columns, rows) = grid(packet.width(), packet.height());
+
+        let mut tiles = BTreeMap::new();
+        let mut position = HEADER;
+        for _ in 0..count {
+            let numbers = data
+                .get(position..position + 8)
+                .ok_or(DecodeError::Truncated)?;
+            let index = u32::from_le_bytes(numbers[..4].try_into().unwrap());
+            let length = u32::from_le_bytes(numbers[4..].try_into().unwrap()) as usize;
+            position += 8;
+
+            if index as u64 >= columns as u64 * rows as u64 {
+                return Err(DecodeError::Corrupt("tile is outside of the frame"));
+            }
+            if data.len() < position + length {
+                return Err(DecodeError::Truncated);
+            }
+            tiles.insert(index, data.slice(position..position + length));
+            position += length;
+        }
+
+        Ok(Self {
+            header,
+            width: packet.width(),
+            height: packet.height(),
+            tiles,
+        })
+    }
+
+    fn write(&self) -> Packet {
+        let length = self.tiles.values().map(|png| png.len() + 8).sum::<usize>();
+        let mut data = Vec::with_capacity(HEADER + length);
+
+        data.push(self.header.keyframe as u8);
+        data.extend_from_slice(&self.header.base.to_le_bytes());
+        data.extend_from_slice(&self.header.sequence.to_le_bytes());
+        data.extend_from_slice(&(self.tiles.len() as u32).to_le_bytes());
+        for (index, png) in &self.tiles {
+            data.extend_from_slice(&index.to_le_bytes());
+            data.extend_from_slice(&(png.len() as u32).to_le_bytes());
+            data.extend_from_slice(png);
+        }
+
+        Packet::new(Encoding::Tiles, self.width, self.height, data)
+    }
+
+    /// Apply `later` on top of these tiles
+    fn merge(&mut self, later: Tiles) -> Result<(), DecodeError> {
+        if (later.width, later.height) != (self.width, self.height)
+            || later.header.base != self.header.sequence
+        {
+            return Err(DecodeError::MissingKeyframe);
+        }
+
+        self.header.sequence = later.header.sequence;
+        self.tiles.extend(later.tiles);
+        Ok(())
+    }
+}
+
+/// How many columns and rows of tiles a frame is split into
+fn grid(width: u32, height: u32) -> (u32, u32) {
+    (
+        (width as u64).div_ceil(TILE_SIZE as u64) as u32,
+        (height as u64).div_ceil(TILE_SIZE as u64) as u32,
+    )
+}
+
+/// The position and size of a tile
+fn bounds(index: u32, width: u32, height: u32) -> (u32, u32, u32, u32) {
+    let columns = grid(width, height).0;
+    let (x, y) = (index % columns * TILE_SIZE, index / columns * TILE_SIZE);
+
+    (x, y, TILE_SIZE.min(width - x), TILE_SIZE.min(height - y))
+}
+
+impl Packet {
+    /// Whether the packet can be shown without the ones before it
+    pub fn is_keyframe(&self) -> bool {
+        match self.encoding {
+            Encoding::Tiles | Encoding::H264 => {
+                TileHeader::read(self).is_ok_and(|header| header.keyframe())
+            }
+            Encoding::Raw | Encoding::Jpeg => true,
+        }
+    }
+
+    /// A packet that makes the change of this one and then `later`. Packets that don't depend on
+    /// the ones before them replace it, deltas have to start where this one finished
+    pub fn followed_by(&self, later: &Packet) -> Result<Packet, DecodeError> {
+        if later.is_keyframe() {
+            return Ok(later.clone());
+        }
+
+        match self.encoding {
+            Encoding::Tiles => {
+                let mut tiles = Tiles::read(self)?;
+                tiles.merge(Tiles::read(later)?)?;
+                Ok(tiles.write())
+            }
+            Encoding::H264 => {
+                let mut frames = Frames::read(self)?;
+                frames.merge(Frames::read(later)?)?;
+                Ok(frames.write())
+            }
+            Encoding::Raw | Encoding::Jpeg => Err(DecodeError::MissingKeyframe),
+        }
+    }
+}
+
+/// Encodes frames as tiles, see the [module docs](self)
+#[derive(Default)]
+pub struct TileEncoder {
+    width: u32,
+    height: u32,
+    /// The hash of every tile in the last frame
+    hashes: Vec<u64>,
+    sequence: u64,
+    keyframe: bool,
+}
+
+impl TileEncoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Send every tile with the next frame
+    pub fn request_keyframe(&mut self) {
+        self.keyframe = true;
+    }
+}
+
+impl Encoder for TileEncoder {
+    fn encode(&mut self, frame: &Frame) -> Packet {
+        let (width, height) = (frame.width(), frame.height());
+        let (columns, rows) = grid(width, height);
+        let count = columns as usize * rows as usize;
+
+        let keyframe = self.keyframe || (width, height) != (self.width, self.height);
+        if keyframe {
+            self.hashes = vec![0; count];
+            (self.width, self.height, self.keyframe) = (width, height, false);
+        }
+
+        let mut tiles = BTreeMap::new();
+        for index in 0..count as u32 {
+            let (x, y, tile_width, tile_height) = bounds(index, width, height);
+
+            let mut hasher = DefaultHasher::new();
+            let rows = frame
+                .data()
+                .chunks_exact(width as usize * 4)
+                .skip(y as usize)
+                .take(tile_height as usize)
+                .map(|row| &row[x as usize * 4..(x + tile_width) as usize * 4]);
+            for row in rows.clone() {
+                row.hash(&mut hasher);
+            }
+
+            let hash = hasher.finish();
+            if !keyframe && self.hashes[index as usize] == hash {
+                continue;
+            }
+            self.hashes[index as usize] = hash;
+
+            let rgb: Vec<u8> = rows
+                .flat_map(|row| row.chunks_exact(4))
+                .flat_map(|pixel| [pixel[2], pixel[1], pixel[0]])
+                .collect();
+            let png = encode_png(tile_width, tile_height, &rgb).expect("a tile has every pixel");
+            tiles.insert(index, png.into());
+        }
+
+        // A delta without any tiles doesn't change anything, so keeps the same sequence
+        let base = self.sequence;
+        if keyframe || !tiles.is_empty() {
+            self.sequence += 1;
+        }
+
+        let header = TileHeader {
+            keyframe,
+            base,
+            sequence: self.sequence,
+        };
+        Tiles {
+            header,
+            width,
+            height,
+            tiles,
+        }
+        .write()
+    }
+}
+
+/// Part of a frame, as tightly packed RGB pixels
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    rgb: Vec<u8>,
+}
+
+impl<'a> Region {
+    pub fn x(&'a self) -> u32 {
+        self.x
+    }
+
+    pub fn y(&'a self) -> u32 {
+        self.y
+    }
+
+    pub fn width(&'a self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&'a self) -> u32 {
+        self.height
+    }
+
+    pub fn rgb(&'a self) -> &'a [u8] {
+        &self.rgb
+    }
+}
+
+/// Decodes a stream of tiles packets, checking each one follows on from the last. It only keeps
+/// track of where the stream is up to, the pixels are left for whatever draws them to keep
+#[derive(Default)]
+pub struct TileDecoder {
+    /// The size and sequence of the last packet decoded
+    last: Option<(u32, u32, u64)>,
+}
+
+impl TileDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decode the parts of the screen a packet changes. Deltas have to follow on from the last
+    /// packet decoded, and fail with [`DecodeError::MissingKeyframe`] otherwise
+    pub fn decode(&mut self, packet: &Packet) -> Result<Vec<Region>, DecodeError> {
+        let tiles = Tiles::read(packet)?;
+        let (width, height, header) = (tiles.width, tiles.height, tiles.header);
+
+        if !header.keyframe && self.last != Some((width, height, header.base)) {
+            return Err(DecodeError::MissingKeyframe);
+        }
+
+        let mut regions = Vec::with_capacity(tiles.tiles.len());
+        for (index, png) in tiles.tiles {
+            let (x, y, tile_width, tile_height) = bounds(index, width, height);
+            let rgb = decode_png(&png, tile_width, tile_height)?;
+
+            regions.push(Region {
+                x,
+                y,
+                width: tile_width,
+                height: tile_height,
+                rgb,
+            });
+        }
+
+        self.last = Some((width, height, header.sequence));
+        Ok(regions)
+    }
+}
+
+/// Draw the tiles of a keyframe onto a black frame, the frame is only allocated once the packet's
+/// size has been checked against [`MAX_SIZE`](super::MAX_SIZE)
+pub(super) fn decode_keyframe(packet: &Packet) -> Result<Frame, DecodeError> {
+    let (width, height) = (packet.width() as usize, packet.height() as usize);
+    let regions = TileDecoder::new().decode(packet)?;
+
+    let mut data = vec![0; width * height * 4];
+    for region in regions {
+        let (x, region_width) = (region.x as usize, region.width as usize);
+        let rows = region.rgb.chunks_exact(region_width * 3);
+        for (y, row) in (region.y as usize..).zip(rows) {
+            let start = (y * width + x) * 4;
+            for (pixel, rgb) in data[start..start + region_width * 4]
+                .chunks_exact_mut(4)
+                .zip(row.chunks_exact(3))
+            {
+                pixel[..3].copy_from_slice(&[rgb[2], rgb[1], rgb[0]]);
+            }
+        }
+    }
+
+    Ok(Frame::new(packet.width(), packet.height(), data))
+}
+
+/// The whole of a screen so far, built up from the packets sent for it, so anyone who starts
+/// watching partway can be sent it all at once as a keyframe
+#[derive(Default, Clone)]
+pub struct Snapshot {
+    state: Option<State>,
+}
+
+#[derive(Clone)]
+enum State {
+    /// The last packet, when it doesn't depend on the ones before
+    Whole(Packet),
+    Tiles(Tiles),
+    /// Every H.264 frame since the last keyframe
+    H264(Frames),
+}
+
+impl Snapshot {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the next packet to the snapshot, returns whether it changed the screen. Deltas have to
+    /// follow on from the last packet added
+    pub fn apply(&mut self, packet: &Packet) -> Result<bool, DecodeError> {
+        match packet.encoding() {
+            Encoding::Raw | Encoding::Jpeg => {
+                let changed = !matches!(&self.state, Some(State::Whole(last)) if last == packet);
+                self.state = Some(State::Whole(packet.clone()));
+                return Ok(changed);
+            }
+            Encoding::H264 => return self.apply_h264(packet),
+            Encoding::Tiles => {}
+        }
+
+        let tiles = Tiles::read(packet)?;
+        if tiles.header.keyframe {
+            self.state = Some(State::Tiles(tiles));
+            return Ok(true);
+        }
+
+        match &mut self.state {
+            Some(State::Tiles(snapshot)) => {
+                let changed = !tiles.tiles.is_empty();
+                snapshot.merge(tiles)?;
+                Ok(changed)
+            }
+            _ => Err(DecodeError::MissingKeyframe),
+        }
+    }
+
+    fn apply_h264(&mut self, packet: &Packet) -> Result<bool, DecodeError> {
+        let frames = Frames::read(packet)?;
+        if frames.header.keyframe() {
+            self.state = Some(State::H264(frames));
+            return Ok(true);
+        }
+
+        match &mut self.state {
+            Some(State::H264(snapshot)) => {
+                let changed = !frames.frames.is_empty();
+                snapshot.merge(frames)?;
+                Ok(changed)
+            }
+            _ => Err(DecodeError::MissingKeyframe),
+        }
+    }
+
+    /// The whole screen as a keyframe, `None` if nothing has been added yet
+    pub fn packet(&self) -> Option<Packet> {
+        match self.state.as_ref()? {
+            State::Whole(packet) => Some(packet.clone()),
+            State::Tiles(tiles) => Some(tiles.write()),
+            State::H264(frames) => Some(frames.write()),
+        }
+    }
+}
@@ -0,0 +1,238 @@
+//! Keeping copies of a machine's process list in sync across the monitor, server and dashboard
+//!
+//! Whoever owns the list sends a full [`ProcessSnapshot`] when someone subscribes, then a
+//! [`ProcessUpdate`] for every change after that. Every update carries a sequence number one
+//! higher than the last, so a subscriber that misses an update can tell and ask for a new
+//! snapshot instead of silently drifting out of sync.
+
+use crate::Process;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+/// Sequence number of a change to a process list
+pub type Sequence = u64;
+
+/// Weather or not a process has started or stopped
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub enum ProcessStatus {
+    Start(Process),
+    Stop(Process),
+}
+
+/// The full process list of a machine, as of the update with sequence number `seq`
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ProcessSnapshot {
+    #[cfg_attr(feature = "openapi", schema(value_type = u64))]
+    seq: Sequence,
+    processes: Vec<Process>,
+}
+
+impl<'a> ProcessSnapshot {
+    pub fn seq(&'a self) -> Sequence {
+        self.seq
+    }
+
+    pub fn processes(&'a self) -> &'a [Process] {
+        &self.processes
+    }
+}
+
+/// A single change to a process list
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct ProcessUpdate {
+    seq: Sequence,
+    status: ProcessStatus,
+}
+
+impl<'a> ProcessUpdate {
+    pub fn seq(&'a self) -> Sequence {
+        self.seq
+    }
+
+    pub fn status(&'a self) -> &'a ProcessStatus {
+        &self.status
+    }
+}
+
+/// Either a snapshot or an update, this is what is actually sent over the wire
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub enum ProcessSync {
+    Snapshot(ProcessSnapshot),
+    Update(ProcessUpdate),
+}
+
+/// An update was missed, the subscriber needs a new snapshot before it can carry on
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SyncGap {
+    /// The sequence number that was expected next, `None` if no snapshot has been received yet
+    pub expected: Option<Sequence>,
+    /// The sequence number that was received
+    pub received: Sequence,
+}
+
+impl Display for SyncGap {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.expected {
+            Some(expected) => write!(
+                f,
+                "expected process update {expected} but got {}",
+                self.received
+            ),
+            None => write!(f, "got process update {} before a snapshot", self.received),
+        }
+    }
+}
+
+impl std::error::Error for SyncGap {}
+
+/// A copy of a machine's process list, used by both the owner of the list to record changes and by
+/// subscribers to apply them
+#[derive(Debug, Clone, Default)]
+pub struct ProcessList {
+    /// Sequence number of the last change applied, `None` until the first snapshot
+    seq: Option<Sequence>,
+    processes: BTreeMap<u32, Process>,
+}
+
+impl ProcessList {
+    /// Create an empty list that is the source of truth, i.e. one that changes are recorded on
+    /// rather than applied to
+    pub fn new_source() -> Self {
+        Self {
+            seq: Some(0),
+            processes: BTreeMap::new(),
+        }
+    }
+
+    /// Whether the list has been synced with a snapshot
+    pub fn is_synced(&self) -> bool {
+        self.seq.is_some()
+    }
+
+    pub fn seq(&self) -> Option<Sequence> {
+        self.seq
+    }
+
+    pub fn get(&self, pid: u32) -> Option<&Process> {
+        self.processes.get(&pid)
+    }
+
+    pub fn processes(&self) -> impl Iterator<Item = &Process> {
+        self.processes.values()
+    }
+
+    pub fn snapshot(&self) -> ProcessSnapshot {
+        ProcessSnapshot {
+            seq: self.seq.unwrap_or_default(),
+            processes: self.processes.values().cloned().collect(),
+        }
+    }
+
+    fn apply_status(&mut self, status: &ProcessStatus) {
+        match status {
+            ProcessStatus::Start(process) => {
+                self.processes.insert(*process.pid(), process.clone());
+            }
+            ProcessStatus::Stop(process) => {
+                self.processes.remove(process.pid());
+            }
+        }
+    }
+
+    /// Record a change on the source of truth, returns the update to send to subscribers
+    pub fn record(&mut self, status: ProcessStatus) -> ProcessUpdate {
+        let seq = self.seq.map(|seq| seq + 1).unwrap_or_default();
+        self.apply_status(&status);
+        self.seq = Some(seq);
+
+        ProcessUpdate { seq, status }
+    }
+
+    /// Apply a snapshot or update received from the owner of the list
+    ///
+    /// Updates older than the current state are ignored, this happens when updates that were
+    /// already in flight arrive after a resync. If an update was skipped a [`SyncGap`] is
+    /// returned and nothing is changed, a new snapshot is needed to recover.
+    pub fn apply(&mut self, sync: &ProcessSync) -> Result<(), SyncGap> {
+        match sync {
+            ProcessSync::Snapshot(snapshot) => {
+                self.seq = Some(snapshot.seq);
+                self.processes = snapshot
+                    .processes
+                    .iter()
+                    .map(|process| (*process.pid(), process.clone()))
+                    .collect();
+                Ok(())
+            }
+            ProcessSync::Update(update) => match self.seq {
+                Some(seq) if update.seq <= seq => Ok(()),
+                Some(seq) if update.seq == seq + 1 => {
+                    self.apply_status(&update.status);
+                    self.seq = Some(update.seq);
+                    Ok(())
+                }
+                expected => Err(SyncGap {
+                    expected: expected.map(|seq| seq + 1),
+                    received: update.seq,
+                }),
+            },
+        }
+    }
+}
+
+/// A process the monitor has suspended or throttled, monitors from protocol version 9 send the
+/// server every one they have whenever that changes
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Restriction {
+    pid: u32,
+    /// Used along with the pid to tell the process apart from a later one with the same pid
+    start_time: u64,
+    suspended: bool,
+    /// The percentage of one CPU the process is limited to
+    throttle: Option<u8>,
+}
+
+impl<'a> Restriction {
+    pub fn pid(&'a self) -> u32 {
+        self.pid
+    }
+
+    pub fn start_time(&'a self) -> u64 {
+        self.start_time
+    }
+
+    pub fn suspended(&'a self) -> bool {
+        self.suspended
+    }
+
+    pub fn throttle(&'a self) -> Option<u8> {
+        self.throttle
+    }
+
+    /// Whether this is about the given process
+    pub fn is_for(&self, process: &Process) -> bool {
+        self.pid == *process.pid() && self.start_time == process.start_time()
+    }
+
+    pub fn new(pid: u32, start_time: u64) -> Self {
+        Self {
+            pid,
+            start_time,
+            suspended: false,
+            throttle: None,
+        }
+    }
+
+    pub fn with_suspended(mut self, suspended: bool) -> Self {
+        self.suspended = suspended;
+        self
+    }
+
+    pub fn with_throttle(mut self, throttle: Option<u8>) -> Self {
+        self.throttle = throttle;
+        self
+    }
+}
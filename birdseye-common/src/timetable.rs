@@ -0,0 +1,284 @@
+//! The weekly timetable of every room, used by the server to switch rules and room locks on and
+//! off as periods start and end
+//!
+//! Times in a timetable have no timezone, the server reads them in its configured timezone.
+
+use crate::policy::RuleId;
+use crate::RoomId;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use time::{Date, Duration, PrimitiveDateTime, Time, Weekday};
+
+time::serde::format_description!(hour_minute, Time, "[hour]:[minute]");
+
+/// A class that happens in a room at the same time every week
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Period {
+    /// What the period is, rule sets are matched to periods by this
+    name: String,
+    room: RoomId,
+    /// The day of the week, e.g. `Monday`
+    #[cfg_attr(feature = "openapi", schema(value_type = String))]
+    day: Weekday,
+    /// Written as `HH:MM`
+    #[serde(with = "hour_minute")]
+    #[cfg_attr(feature = "openapi", schema(value_type = String))]
+    start: Time,
+    /// Written as `HH:MM`, the period is over at this time
+    #[serde(with = "hour_minute")]
+    #[cfg_attr(feature = "openapi", schema(value_type = String))]
+    end: Time,
+}
+
+impl<'a> Period {
+    pub fn name(&'a self) -> &'a str {
+        &self.name
+    }
+
+    pub fn room(&'a self) -> &'a RoomId {
+        &self.room
+    }
+
+    pub fn day(&'a self) -> Weekday {
+        self.day
+    }
+
+    pub fn start(&'a self) -> Time {
+        self.start
+    }
+
+    pub fn end(&'a self) -> Time {
+        self.end
+    }
+
+    /// Whether the period is on at the given time, ignoring holidays
+    pub fn is_running(&self, at: PrimitiveDateTime) -> bool {
+        self.day == at.weekday() && self.start <= at.time() && at.time() < self.end
+    }
+
+    pub fn new(name: &str, room: RoomId, day: Weekday, start: Time, end: Time) -> Self {
+        Self {
+            name: name.to_string(),
+            room,
+            day,
+            start,
+            end,
+        }
+    }
+}
+
+/// Days where no periods run, such as school holidays
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Holiday {
+    name: String,
+    /// The first day of the holiday, written as `YYYY-MM-DD`
+    #[cfg_attr(feature = "openapi", schema(value_type = String))]
+    start: Date,
+    /// The last day of the holiday, written as `YYYY-MM-DD`
+    #[cfg_attr(feature = "openapi", schema(value_type = String))]
+    end: Date,
+}
+
+impl<'a> Holiday {
+    pub fn name(&'a self) -> &'a str {
+        &self.name
+    }
+
+    pub fn start(&'a self) -> Date {
+        self.start
+    }
+
+    pub fn end(&'a self) -> Date {
+        self.end
+    }
+
+    pub fn new(name: &str, start: Date, end: Date) -> Self {
+        Self {
+            name: name.to_string(),
+            start,
+            end,
+        }
+    }
+}
+
+/// What changes while periods with a given name are running
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct RuleSet {
+    /// The name of the periods this applies during
+    period: String,
+    /// Rules that are only enforced during these periods
+    #[serde(default)]
+    #[cfg_attr(feature = "openapi", schema(value_type = Vec<u64>))]
+    rules: Vec<RuleId>,
+    /// Lock every machine in the room for the whole period
+    #[serde(default)]
+    lock: bool,
+}
+
+impl<'a> RuleSet {
+    pub fn period(&'a self) -> &'a str {
+        &self.period
+    }
+
+    pub fn rules(&'a self) -> &'a [RuleId] {
+        &self.rules
+    }
+
+    pub fn lock(&'a self) -> bool {
+        self.lock
+    }
+
+    pub fn new(period: &str, rules: Vec<RuleId>, lock: bool) -> Self {
+        Self {
+            period: period.to_string(),
+            rules,
+            lock,
+        }
+    }
+}
+
+/// Reasons a timetable can't be used
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub enum TimetableError {
+    /// A period doesn't end after it starts, contains the period's name. Periods can't go past
+    /// midnight
+    PeriodEndsBeforeStart(String),
+    /// A holiday ends before it starts, contains the holiday's name
+    HolidayEndsBeforeStart(String),
+}
+
+impl Display for TimetableError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TimetableError::PeriodEndsBeforeStart(name) => {
+                write!(f, "period {name} has to end after it starts")
+            }
+            TimetableError::HolidayEndsBeforeStart(name) => {
+                write!(f, "holiday {name} ends before it starts")
+            }
+        }
+    }
+}
+
+impl std::error::Error for TimetableError {}
+
+/// Every room's periods, the holidays they don't run on and what happens during them
+///
+/// Rules that aren't in any rule set are always enforced, rules that are in one are only enforced
+/// while a period named by one of their rule sets is running in the machine's room.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Timetable {
+    #[serde(default)]
+    periods: Vec<Period>,
+    #[serde(default)]
+    holidays: Vec<Holiday>,
+    #[serde(default)]
+    rule_sets: Vec<RuleSet>,
+}
+
+impl<'a> Timetable {
+    pub fn periods(&'a self) -> &'a [Period] {
+        &self.periods
+    }
+
+    pub fn holidays(&'a self) -> &'a [Holiday] {
+        &self.holidays
+    }
+
+    pub fn rule_sets(&'a self) -> &'a [RuleSet] {
+        &self.rule_sets
+    }
+
+    pub fn new(periods: Vec<Period>, holidays: Vec<Holiday>, rule_sets: Vec<RuleSet>) -> Self {
+        Self {
+            periods,
+            holidays,
+            rule_sets,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), TimetableError> {
+        if let Some(period) = self
+            .periods
+            .iter()
+            .find(|period| period.end <= period.start)
+        {
+            return Err(TimetableError::PeriodEndsBeforeStart(period.name.clone()));
+        }
+
+        if let Some(holiday) = self
+            .holidays
+            .iter()
+            .find(|holiday| holiday.end < holiday.start)
+        {
+            return Err(TimetableError::HolidayEndsBeforeStart(holiday.name.clone()));
+        }
+
+        Ok(())
+    }
+
+    pub fn is_holiday(&self, date: Date) -> bool {
+        self.holidays
+            .iter()
+            .any(|holiday| holiday.start <= date && date <= holiday.end)
+    }
+
+    /// Get the periods running in a room at the given local time
+    pub fn running(&'a self, room: &RoomId, at: PrimitiveDateTime) -> Vec<&'a Period> {
+        if self.is_holiday(at.date()) {
+            return vec![];
+        }
+
+        self.periods
+            .iter()
+            .filter(|period| &period.room == room && period.is_running(at))
+            .collect()
+    }
+
+    /// Whether a rule is enforced while the given periods are running
+    pub fn is_enforced(&self, rule: RuleId, running: &[&Period]) -> bool {
+        let mut sets = self
+            .rule_sets
+            .iter()
+            .filter(|set| set.rules.contains(&rule))
+            .peekable();
+
+        sets.peek().is_none() || sets.any(|set| is_named(running, &set.period))
+    }
+
+    /// Whether machines should be locked while the given periods are running
+    pub fn is_locked(&self, running: &[&Period]) -> bool {
+        self.rule_sets
+            .iter()
+            .any(|set| set.lock && is_named(running, &set.period))
+    }
+
+    /// Get the first time after `after` that a period starts or ends, holidays aren't taken into
+    /// account so this can be a period that won't run
+    pub fn next_change(&self, after: PrimitiveDateTime) -> Option<PrimitiveDateTime> {
+        (0..=7)
+            .map(|days| after.date() + Duration::days(days))
+            .flat_map(|date| {
+                self.periods
+                    .iter()
+                    .filter(move |period| period.day == date.weekday())
+                    .flat_map(move |period| {
+                        [
+                            PrimitiveDateTime::new(date, period.start),
+                            PrimitiveDateTime::new(date, period.end),
+                        ]
+                    })
+            })
+            .filter(|time| *time > after)
+            .min()
+    }
+}
+
+fn is_named(periods: &[&Period], name: &str) -> bool {
+    periods.iter().any(|period| period.name == name)
+}
@@ -0,0 +1,112 @@
+use birdseye_common::screen::h264::{codec, frames, KEYFRAME_INTERVAL};
+use birdseye_common::screen::tiles::TileHeader;
+use birdseye_common::screen::{DecodeError, Encoder, Encoding, Frame, H264Encoder, Snapshot};
+
+/// Smooth gradients, like most of a desktop
+fn gradient(width: u32, height: u32) -> Frame {
+    let mut rgb = vec![];
+    for y in 0..height as usize {
+        for x in 0..width as usize {
+            rgb.extend_from_slice(&[(x * 4) as u8, (y * 4) as u8, ((x + y) * 2) as u8]);
+        }
+    }
+    Frame::from_rgb(width, height, &rgb)
+}
+
+/// A frame with a square of a different colour drawn on it
+fn with_square(frame: &Frame, x: u32, y: u32, size: u32) -> Frame {
+    let mut rgb = frame.to_rgb();
+    for row in y..y + size {
+        for column in x..x + size {
+            let at = (row * frame.width() + column) as usize * 3;
+            rgb[at..at + 3].copy_from_slice(&[255, 0, 0]);
+        }
+    }
+    Frame::from_rgb(frame.width(), frame.height(), &rgb)
+}
+
+fn mean_error(a: &Frame, b: &Frame) -> f64 {
+    let (a, b) = (a.to_rgb(), b.to_rgb());
+    let total: u64 = a.iter().zip(&b).map(|(a, b)| a.abs_diff(*b) as u64).sum();
+    total as f64 / a.len() as f64
+}
+
+#[test]
+fn video_decodes_close_to_the_original() {
+    // Odd sizes are padded to encode them
+    let frame = gradient(37, 19);
+    let packet = H264Encoder::new(2_000_000, 5).encode(&frame);
+
+    assert_eq!(packet.encoding(), Encoding::H264);
+    assert_eq!((packet.width(), packet.height()), (37, 19));
+    assert!(packet.is_keyframe());
+
+    let decoded = packet.decode().unwrap();
+    assert_eq!((decoded.width(), decoded.height()), (37, 19));
+    assert!(mean_error(&frame, &decoded) < 8.0);
+}
+
+#[test]
+fn deltas_only_hold_what_moved() {
+    let frame = gradient(320, 240);
+    let second = with_square(&frame, 16, 16, 16);
+    let third = with_square(&second, 200, 160, 16);
+
+    let mut encoder = H264Encoder::new(2_000_000, 5);
+    let first = encoder.encode(&frame);
+    let a = encoder.encode(&second);
+    let b = encoder.encode(&third);
+
+    assert!(!a.is_keyframe());
+    assert!(a.data().len() * 4 < first.data().len());
+    let (a_header, b_header) = (TileHeader::read(&a).unwrap(), TileHeader::read(&b).unwrap());
+    assert_eq!(b_header.base(), a_header.sequence());
+
+    // Deltas need the frames before them, a keyframe followed by them plays all of them
+    assert_eq!(a.decode(), Err(DecodeError::MissingKeyframe));
+    assert_eq!(first.followed_by(&b), Err(DecodeError::MissingKeyframe));
+    let whole = first.followed_by(&a).unwrap().followed_by(&b).unwrap();
+    assert!(whole.is_keyframe());
+    assert_eq!(frames(&whole).unwrap().len(), 3);
+    assert!(mean_error(&third, &whole.decode().unwrap()) < 8.0);
+
+    // Snapshots keep every frame since the keyframe
+    let mut snapshot = Snapshot::new();
+    assert_eq!(snapshot.apply(&a), Err(DecodeError::MissingKeyframe));
+    assert_eq!(snapshot.apply(&first), Ok(true));
+    assert_eq!(snapshot.apply(&a), Ok(true));
+    assert_eq!(snapshot.apply(&b), Ok(true));
+    assert_eq!(snapshot.packet(), Some(whole));
+}
+
+#[test]
+fn keyframes_are_sent_regularly() {
+    let frame = gradient(64, 48);
+    let mut encoder = H264Encoder::new(1_000_000, 2);
+
+    let packets: Vec<_> = (0..KEYFRAME_INTERVAL * 2 + 1)
+        .map(|moved| encoder.encode(&with_square(&frame, moved, 0, 8)))
+        .collect();
+    let keyframes: Vec<_> = (0..packets.len())
+        .filter(|index| packets[*index].is_keyframe())
+        .collect();
+    assert_eq!(keyframes, vec![0, KEYFRAME_INTERVAL as usize * 2]);
+
+    // Changing the frame rate or size starts again with a keyframe
+    encoder.set_frame_rate(5);
+    assert!(encoder.encode(&frame).is_keyframe());
+    assert!(!encoder.encode(&frame).is_keyframe());
+    assert!(encoder.encode(&gradient(32, 24)).is_keyframe());
+}
+
+#[test]
+fn keyframes_say_which_codec_they_need() {
+    let mut encoder = H264Encoder::new(1_000_000, 5);
+    let keyframe = encoder.encode(&gradient(64, 48));
+    let delta = encoder.encode(&with_square(&gradient(64, 48), 0, 0, 8));
+
+    let name = codec(&keyframe).unwrap();
+    assert!(name.starts_with("avc1."), "{name}");
+    assert_eq!(name.len(), 11);
+    assert_eq!(codec(&delta), None);
+}
@@ -0,0 +1,132 @@
+use birdseye_common::policy::{Action, CompiledPolicy, Pattern, PolicyError, Rule};
+use birdseye_common::{Process, RoomId, User};
+
+fn process(name: &str, exe: &str, user: &str) -> Process {
+    Process::new(1234, name, &User::new(user)).with_exe(exe)
+}
+
+fn glob(glob: &str) -> Pattern {
+    Pattern::Glob(glob.to_string())
+}
+
+fn regex(regex: &str) -> Pattern {
+    Pattern::Regex(regex.to_string())
+}
+
+#[test]
+fn globs_match_the_whole_name_ignoring_case() {
+    let policy = CompiledPolicy::compile(vec![
+        Rule::new(1, "Steam", Action::Kill).with_process(glob("steam*.exe"))
+    ])
+    .unwrap();
+
+    assert!(policy.check(&process("Steam.exe", "", "student")).is_some());
+    assert!(policy
+        .check(&process("steamwebhelper.exe", "", "student"))
+        .is_some());
+    assert!(policy
+        .check(&process("notsteam.exe", "", "student"))
+        .is_none());
+    // Characters that mean something in a regex are matched literally
+    assert!(policy.check(&process("steamXexe", "", "student")).is_none());
+}
+
+#[test]
+fn every_condition_has_to_match() {
+    let rule = Rule::new(1, "Games", Action::Suspend)
+        .with_process(regex("^(minecraft|roblox)"))
+        .with_exe(glob("/home/*"))
+        .with_user("student");
+    let policy = CompiledPolicy::compile(vec![rule]).unwrap();
+
+    assert!(policy
+        .check(&process("minecraft", "/home/student/mc", "student"))
+        .is_some());
+    assert!(policy
+        .check(&process("minecraft", "/usr/bin/mc", "student"))
+        .is_none());
+    assert!(policy
+        .check(&process("minecraft", "/home/teacher/mc", "teacher"))
+        .is_none());
+    assert!(policy
+        .check(&Process::new(1, "minecraft", &User::new("student")))
+        .is_none());
+}
+
+#[test]
+fn the_most_drastic_rule_wins() {
+    let policy = CompiledPolicy::compile(vec![
+        Rule::new(1, "Watch browsers", Action::Alert).with_process(glob("*fox*")),
+        Rule::new(2, "No firefox", Action::Kill).with_process(glob("firefox")),
+        Rule::new(3, "Pause foxes", Action::Suspend).with_process(glob("fox*")),
+    ])
+    .unwrap();
+
+    assert_eq!(
+        policy
+            .check(&process("firefox", "", "student"))
+            .unwrap()
+            .id(),
+        2
+    );
+    assert_eq!(
+        policy.check(&process("foxit", "", "student")).unwrap().id(),
+        3
+    );
+    assert_eq!(
+        policy
+            .check(&process("icefox2", "", "student"))
+            .unwrap()
+            .id(),
+        1
+    );
+}
+
+#[test]
+fn throttling_is_between_alerting_and_suspending() {
+    let policy = CompiledPolicy::compile(vec![
+        Rule::new(1, "Watch browsers", Action::Alert).with_process(glob("*fox*")),
+        Rule::new(2, "Slow foxes", Action::Throttle(20)).with_process(glob("*fox")),
+        Rule::new(3, "Pause firefox", Action::Suspend).with_process(glob("firefox")),
+    ])
+    .unwrap();
+
+    let broken = |name| policy.check(&process(name, "", "student")).unwrap().id();
+    assert_eq!(broken("firefox"), 3);
+    assert_eq!(broken("icefox"), 2);
+    assert_eq!(broken("foxit"), 1);
+}
+
+#[test]
+fn bad_rules_are_refused() {
+    let empty = Rule::new(1, "Everything", Action::Kill).with_user("student");
+    assert_eq!(
+        CompiledPolicy::compile(vec![empty]).err(),
+        Some(PolicyError::NoPattern("Everything".to_string()))
+    );
+
+    let invalid = Rule::new(1, "Broken", Action::Kill).with_process(regex("(unclosed"));
+    assert!(matches!(
+        CompiledPolicy::compile(vec![invalid]),
+        Err(PolicyError::InvalidPattern(name, _)) if name == "Broken"
+    ));
+
+    for percent in [0, 101] {
+        let throttle = Rule::new(1, "Throttle", Action::Throttle(percent)).with_process(glob("*"));
+        assert_eq!(
+            CompiledPolicy::compile(vec![throttle]).err(),
+            Some(PolicyError::InvalidThrottle("Throttle".to_string()))
+        );
+    }
+}
+
+#[test]
+fn rules_without_rooms_apply_everywhere() {
+    let everywhere = Rule::new(1, "Everywhere", Action::Alert);
+    let room_12 = everywhere.clone().with_rooms(vec![RoomId::new("Room 12")]);
+
+    assert!(everywhere.applies_to(None));
+    assert!(room_12.applies_to(Some(&RoomId::new("Room 12"))));
+    assert!(!room_12.applies_to(Some(&RoomId::new("Room 14"))));
+    assert!(!room_12.applies_to(None));
+}
@@ -0,0 +1,224 @@
+use birdseye_common::codec::Codec;
+use birdseye_common::command::Command;
+use birdseye_common::handshake::{HandshakeError, Hello, MIN_PROTOCOL_VERSION};
+use birdseye_common::machine::{Display, NetworkInterface};
+use birdseye_common::screen::{Encoding, Frame, Packet};
+use birdseye_common::sync::Restriction;
+use birdseye_common::{Machine, MachineId, Process, Session, User};
+
+fn full_process() -> Process {
+    let user = User::new("student").with_id("1000");
+
+    Process::new(4242, "firefox", &user)
+        .with_parent_pid(1)
+        .with_exe("/usr/lib/firefox/firefox")
+        .with_cmd(&["/usr/lib/firefox/firefox".into(), "--new-window".into()])
+        .with_start_time(1_656_000_000)
+        .with_usage(12.5, 512 * 1024 * 1024)
+        .with_session(Session::new(3, Some("/dev/pts/0")))
+}
+
+fn minimal_process() -> Process {
+    Process::new(1, "init", &User::new("root"))
+}
+
+#[test]
+fn process_round_trips_through_bincode() {
+    for process in [full_process(), minimal_process()] {
+        let bytes = bincode::serialize(&process).unwrap();
+        let decoded: Process = bincode::deserialize(&bytes).unwrap();
+
+        assert_eq!(decoded, process);
+    }
+}
+
+#[test]
+fn process_round_trips_through_json() {
+    for process in [full_process(), minimal_process()] {
+        let json = serde_json::to_string(&process).unwrap();
+        let decoded: Process = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(decoded, process);
+    }
+}
+
+#[test]
+fn user_round_trips_with_windows_sid() {
+    let user =
+        User::new("DESKTOP\\student").with_id("S-1-5-21-3623811015-3361044348-30300820-1013");
+
+    let bytes = bincode::serialize(&user).unwrap();
+    assert_eq!(bincode::deserialize::<User>(&bytes).unwrap(), user);
+
+    let json = serde_json::to_string(&user).unwrap();
+    assert_eq!(serde_json::from_str::<User>(&json).unwrap(), user);
+}
+
+#[test]
+fn machine_round_trips() {
+    let machine = Machine::new(MachineId::new("4c4c4544-0042"), "LAB12-PC04", "0.1.0")
+        .with_os("Windows", "Windows 11 Education 22H2")
+        .with_interfaces(vec![NetworkInterface::new(
+            "Ethernet",
+            Some("aa:bb:cc:dd:ee:ff"),
+            vec!["10.0.12.4".parse().unwrap(), "fe80::1".parse().unwrap()],
+        )])
+        .with_displays(vec![
+            Display::new(0, 1920, 1080),
+            Display::new(1, 1280, 1024),
+        ]);
+
+    let bytes = bincode::serialize(&machine).unwrap();
+    assert_eq!(bincode::deserialize::<Machine>(&bytes).unwrap(), machine);
+
+    let json = serde_json::to_string(&machine).unwrap();
+    assert_eq!(serde_json::from_str::<Machine>(&json).unwrap(), machine);
+}
+
+#[test]
+fn process_round_trips_through_every_codec() {
+    for codec in Codec::ALL {
+        let bytes = codec.encode(&full_process()).unwrap();
+        assert_eq!(codec.decode::<Process>(&bytes).unwrap(), full_process());
+    }
+}
+
+#[test]
+fn codec_negotiation_picks_first_known_subprotocol() {
+    assert_eq!(
+        Codec::negotiate("chat, birdseye.json, birdseye.bincode"),
+        Some(Codec::Json)
+    );
+    assert_eq!(Codec::negotiate("chat"), None);
+}
+
+#[test]
+fn kill_round_trips_without_moving_older_commands() {
+    let kill = Command::Kill {
+        pid: 4242,
+        start_time: 1_656_000_000,
+        tree: true,
+    };
+    for codec in Codec::ALL {
+        let bytes = codec.encode(&kill).unwrap();
+        assert_eq!(codec.decode::<Command>(&bytes).unwrap(), kill);
+    }
+
+    // Monitors from before protocol version 8 still have to understand the commands they knew
+    let bytes = bincode::serialize(&Command::KillProcess { pid: 4242 }).unwrap();
+    assert_eq!(&bytes[..4], &0u32.to_le_bytes());
+    assert_eq!(kill.min_version(), 8);
+    assert_eq!(Command::Lock.min_version(), 1);
+}
+
+#[test]
+fn restrictions_round_trip() {
+    let restrictions = vec![
+        Restriction::new(4242, 1_656_000_000).with_suspended(true),
+        Restriction::new(4243, 1_656_000_001).with_throttle(Some(20)),
+    ];
+
+    for codec in Codec::ALL {
+        let bytes = codec.encode(&restrictions).unwrap();
+        assert_eq!(
+            codec.decode::<Vec<Restriction>>(&bytes).unwrap(),
+            restrictions
+        );
+    }
+    assert!(restrictions[0].is_for(&full_process()));
+    assert!(!restrictions[1].is_for(&full_process()));
+    assert_eq!(
+        Command::Resume {
+            pid: 4242,
+            start_time: 0
+        }
+        .min_version(),
+        9
+    );
+}
+
+#[test]
+fn frames_drop_row_padding_and_round_trip() {
+    // Two rows of two pixels, each padded out to 12 bytes
+    let padded = [
+        1, 2, 3, 0, 4, 5, 6, 0, 9, 9, 9, 9, //
+        7, 8, 9, 0, 10, 11, 12, 0, 9, 9, 9, 9,
+    ];
+    let frame = Frame::from_padded(2, 2, 12, &padded);
+    assert_eq!(
+        frame.data(),
+        &[1, 2, 3, 0, 4, 5, 6, 0, 7, 8, 9, 0, 10, 11, 12, 0]
+    );
+
+    for codec in Codec::ALL {
+        let bytes = codec.encode(&frame).unwrap();
+        assert_eq!(codec.decode::<Frame>(&bytes).unwrap(), frame);
+    }
+
+    // Frames are still encoded the same way as when they were a plain byte vector
+    let bytes = bincode::serialize(&frame).unwrap();
+    assert_eq!(&bytes[8..16], &16u64.to_le_bytes());
+}
+
+#[test]
+fn packets_round_trip() {
+    let packet = Packet::new(Encoding::Jpeg, 1920, 1080, vec![0xff, 0xd8, 0xff, 0xd9]);
+
+    for codec in Codec::ALL {
+        let bytes = codec.encode(&packet).unwrap();
+        assert_eq!(codec.decode::<Packet>(&bytes).unwrap(), packet);
+    }
+}
+
+/// How [`User`] was laid out by protocol versions 1 and 2
+#[derive(serde::Serialize)]
+struct LegacyUser {
+    name: String,
+}
+
+/// How [`Process`] was laid out by protocol versions 1 and 2
+#[derive(serde::Serialize)]
+struct LegacyProcess {
+    pid: u32,
+    name: String,
+    user: LegacyUser,
+}
+
+#[test]
+fn peers_with_the_old_process_layout_are_refused() {
+    let legacy = LegacyProcess {
+        pid: 4242,
+        name: "firefox".into(),
+        user: LegacyUser {
+            name: "student".into(),
+        },
+    };
+    let bytes = bincode::serialize(&legacy).unwrap();
+    assert!(bincode::deserialize::<Process>(&bytes).is_err());
+
+    // So the handshake turns away anything that might still send it
+    let ours = Hello::new("birdseye-server", []);
+    for version in [1, 2] {
+        let theirs: Hello = serde_json::from_value(serde_json::json!({
+            "version": version,
+            "min_version": 1,
+            "agent": "birdseye-monitor",
+            "capabilities": [],
+        }))
+        .unwrap();
+        assert!(matches!(
+            ours.negotiate(&theirs),
+            Err(HandshakeError::PeerTooOld { .. })
+        ));
+    }
+    assert!(ours.negotiate(&Hello::legacy("birdseye-monitor")).is_err());
+
+    let theirs: Hello = serde_json::from_value(serde_json::json!({
+        "version": MIN_PROTOCOL_VERSION,
+        "min_version": 1,
+        "agent": "birdseye-monitor",
+        "capabilities": [],
+    }))
+    .unwrap();
+    assert_eq!(ours.negotiate(&theirs).unwrap().version(), 3);
+}
@@ -0,0 +1,286 @@
+use birdseye_common::screen::jpeg::{decode_jpeg, encode_jpeg};
+use birdseye_common::screen::png::{decode_png, encode_png};
+use birdseye_common::screen::tiles::{TileHeader, TILE_SIZE};
+use birdseye_common::screen::{
+    DecodeError, Encoder, Encoding, Frame, JpegEncoder, Packet, Snapshot, TileDecoder, TileEncoder,
+    MAX_SIZE,
+};
+
+/// Smooth gradients, like most of a desktop
+fn gradient(width: u16, height: u16) -> Vec<u8> {
+    let mut rgb = vec![];
+    for y in 0..height as usize {
+        for x in 0..width as usize {
+            rgb.extend_from_slice(&[(x * 4) as u8, (y * 4) as u8, ((x + y) * 2) as u8]);
+        }
+    }
+    rgb
+}
+
+/// Pixels that have nothing to do with their neighbours, which compress badly
+fn noise(width: u32, height: u32) -> Frame {
+    let mut state = 1u32;
+    let data: Vec<u8> = (0..width * height * 4)
+        .map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (state >> 16) as u8
+        })
+        .collect();
+    Frame::new(width, height, data)
+}
+
+fn mean_error(a: &[u8], b: &[u8]) -> f64 {
+    let total: u64 = a.iter().zip(b).map(|(a, b)| a.abs_diff(*b) as u64).sum();
+    total as f64 / a.len() as f64
+}
+
+#[test]
+fn jpegs_are_well_formed() {
+    let rgb: Vec<u8> = (0..21 * 13 * 3).map(|i| (i * 7 % 256) as u8).collect();
+    let jpeg = encode_jpeg(21, 13, &rgb, 75).unwrap();
+
+    assert_eq!(&jpeg[..2], &[0xff, 0xd8]);
+    assert_eq!(&jpeg[jpeg.len() - 2..], &[0xff, 0xd9]);
+
+    // Higher quality keeps more detail
+    assert!(
+        encode_jpeg(21, 13, &rgb, 95).unwrap().len() > encode_jpeg(21, 13, &rgb, 10).unwrap().len()
+    );
+}
+
+#[test]
+fn jpegs_decode_close_to_the_original() {
+    // Sizes that aren't a multiple of the block size
+    let rgb = gradient(37, 19);
+
+    let (width, height, decoded) = decode_jpeg(&encode_jpeg(37, 19, &rgb, 90).unwrap()).unwrap();
+    assert_eq!((width, height, decoded.len()), (37, 19, rgb.len()));
+    assert!(mean_error(&rgb, &decoded) < 3.0);
+
+    let (_, _, rough) = decode_jpeg(&encode_jpeg(37, 19, &rgb, 10).unwrap()).unwrap();
+    assert!(mean_error(&rgb, &rough) > mean_error(&rgb, &decoded));
+}
+
+#[test]
+fn broken_jpegs_are_refused() {
+    let jpeg = encode_jpeg(16, 16, &gradient(16, 16), 75).unwrap();
+
+    for end in [jpeg.len() / 2, jpeg.len() - 10] {
+        assert_eq!(decode_jpeg(&jpeg[..end]), Err(DecodeError::Truncated));
+    }
+    assert!(matches!(
+        decode_jpeg(b"not a jpeg"),
+        Err(DecodeError::Corrupt(_))
+    ));
+
+    // Only colour JPEGs become frames
+    let mut grey = vec![];
+    jpeg_encoder::Encoder::new(&mut grey, 75)
+        .encode(&[128; 16 * 16], 16, 16, jpeg_encoder::ColorType::Luma)
+        .unwrap();
+    assert!(matches!(
+        decode_jpeg(&grey),
+        Err(DecodeError::Unsupported(_))
+    ));
+    assert!(encode_jpeg(16, 16, &[0; 10], 75).is_err());
+}
+
+#[test]
+fn packets_decode_back_into_frames() {
+    let frame = Frame::from_rgb(37, 19, &gradient(37, 19));
+    assert_eq!(frame.to_rgb(), gradient(37, 19));
+
+    let raw = Packet::from(frame.clone());
+    assert_eq!(raw.encoding(), Encoding::Raw);
+    assert_eq!(raw.decode(), Ok(frame.clone()));
+
+    let jpeg = JpegEncoder::new(10_000_000, 1).encode(&frame);
+    assert_eq!(jpeg.encoding(), Encoding::Jpeg);
+    assert_eq!((jpeg.width(), jpeg.height()), (37, 19));
+    assert!(mean_error(frame.data(), jpeg.decode().unwrap().data()) < 6.0);
+
+    assert!(Packet::new(Encoding::Raw, 2, 2, vec![0; 15])
+        .decode()
+        .is_err());
+    let wrong_size = Packet::new(Encoding::Jpeg, 36, 19, jpeg.data().clone());
+    assert!(matches!(wrong_size.decode(), Err(DecodeError::Corrupt(_))));
+}
+
+#[test]
+fn the_encoder_keeps_to_its_bitrate() {
+    // 1 KiB a frame can't fit noise, so the quality keeps dropping to the lowest
+    let mut encoder = JpegEncoder::new(8 * 1024 * 5, 5);
+    assert_eq!(encoder.budget(), 1024);
+    for _ in 0..20 {
+        encoder.encode(&noise(128, 128));
+    }
+    assert_eq!(encoder.quality(), 5);
+
+    // Plenty of room for a plain screen, so the quality goes up to the highest
+    let mut encoder = JpegEncoder::new(8 * 1024 * 1024, 1);
+    let start = encoder.quality();
+    let plain = Frame::from_rgb(64, 64, &[128; 64 * 64 * 3]);
+    encoder.encode(&plain);
+    assert!(encoder.quality() > start);
+    for _ in 0..20 {
+        encoder.encode(&plain);
+    }
+    assert_eq!(encoder.quality(), 90);
+
+    // Fewer frames a second leaves more room for each
+    encoder.set_frame_rate(4);
+    assert_eq!(encoder.budget(), 256 * 1024);
+    encoder.set_frame_rate(0);
+    assert_eq!(encoder.budget(), 1024 * 1024);
+}
+
+/// A frame with a square of a different colour drawn on it
+fn with_square(frame: &Frame, x: u32, y: u32, size: u32) -> Frame {
+    let mut rgb = frame.to_rgb();
+    for row in y..y + size {
+        for column in x..x + size {
+            let at = (row * frame.width() + column) as usize * 3;
+            rgb[at..at + 3].copy_from_slice(&[255, 0, 0]);
+        }
+    }
+    Frame::from_rgb(frame.width(), frame.height(), &rgb)
+}
+
+#[test]
+fn pngs_round_trip() {
+    for (width, height) in [(37, 19), (1, 1), (64, 3)] {
+        let rgb = gradient(width, height);
+        let png = encode_png(width as u32, height as u32, &rgb).unwrap();
+        assert_eq!(decode_png(&png, width as u32, height as u32), Ok(rgb));
+    }
+
+    let png = encode_png(16, 16, &gradient(16, 16)).unwrap();
+    assert!(decode_png(&png[..png.len() - 20], 16, 16).is_err());
+    let mut flipped = png.clone();
+    flipped[40] ^= 1;
+    assert!(matches!(
+        decode_png(&flipped, 16, 16),
+        Err(DecodeError::Corrupt(_))
+    ));
+    assert!(encode_png(16, 16, &[0; 10]).is_err());
+}
+
+#[test]
+fn frames_bigger_than_the_limit_are_refused_before_decoding() {
+    // A keyframe with no tiles would otherwise be decoded into a huge black frame
+    let mut data = vec![1];
+    data.extend_from_slice(&[0; 20]);
+    let huge = Packet::new(Encoding::Tiles, MAX_SIZE + 1, MAX_SIZE + 1, data.clone());
+    assert_eq!(huge.decode(), Err(DecodeError::TooBig));
+    assert_eq!(TileDecoder::new().decode(&huge), Err(DecodeError::TooBig));
+    assert_eq!(Snapshot::new().apply(&huge), Err(DecodeError::TooBig));
+    let fits = Packet::new(Encoding::Tiles, 64, 64, data);
+    assert_eq!(fits.decode().map(|frame| frame.width()), Ok(64));
+
+    // The sizes inside JPEGs and PNGs are checked too, not just the packet's
+    let mut jpeg = encode_jpeg(16, 16, &gradient(16, 16), 75).unwrap();
+    let sof = jpeg
+        .windows(2)
+        .position(|bytes| bytes == [0xff, 0xc0])
+        .unwrap();
+    jpeg[sof + 5..sof + 9].copy_from_slice(&[0xff, 0xff, 0xff, 0xff]);
+    assert_eq!(decode_jpeg(&jpeg), Err(DecodeError::TooBig));
+
+    let png = encode_png(MAX_SIZE + 1, 1, &vec![0; (MAX_SIZE as usize + 1) * 3]).unwrap();
+    assert!(matches!(
+        decode_png(&png, 64, 1),
+        Err(DecodeError::Corrupt(_))
+    ));
+}
+
+#[test]
+fn only_changed_tiles_are_sent() {
+    let frame = Frame::from_rgb(150, 100, &gradient(150, 100));
+    let mut encoder = TileEncoder::new();
+
+    let first = encoder.encode(&frame);
+    assert_eq!(first.encoding(), Encoding::Tiles);
+    assert!(first.is_keyframe());
+    assert_eq!(first.decode(), Ok(frame.clone()));
+
+    // Nothing changed, so nothing is sent and the sequence stays the same
+    let same = encoder.encode(&frame);
+    let header = TileHeader::read(&same).unwrap();
+    assert!(!header.keyframe());
+    assert_eq!(header.base(), header.sequence());
+    assert!(same.data().len() < 32);
+
+    // A change inside one tile only sends that tile
+    let changed = with_square(&frame, 70, 10, 8);
+    let delta = encoder.encode(&changed);
+    let mut decoder = TileDecoder::new();
+    decoder.decode(&first).unwrap();
+    let regions = decoder.decode(&delta).unwrap();
+    assert_eq!(regions.len(), 1);
+    assert_eq!((regions[0].x(), regions[0].y()), (TILE_SIZE, 0));
+    assert_eq!(
+        (regions[0].width(), regions[0].height()),
+        (TILE_SIZE, TILE_SIZE)
+    );
+
+    // Deltas can't be decoded on their own
+    assert_eq!(delta.decode(), Err(DecodeError::MissingKeyframe));
+
+    encoder.request_keyframe();
+    let keyframe = encoder.encode(&changed);
+    assert!(keyframe.is_keyframe());
+    assert_eq!(keyframe.decode(), Ok(changed));
+}
+
+#[test]
+fn tile_deltas_merge() {
+    let frame = Frame::from_rgb(150, 100, &gradient(150, 100));
+    let second = with_square(&frame, 0, 0, 4);
+    let third = with_square(&second, 140, 90, 4);
+
+    let mut encoder = TileEncoder::new();
+    let first = encoder.encode(&frame);
+    let a = encoder.encode(&second);
+    let b = encoder.encode(&third);
+
+    // Skipping a delta means the next one doesn't follow on
+    let mut decoder = TileDecoder::new();
+    decoder.decode(&first).unwrap();
+    assert_eq!(decoder.decode(&b), Err(DecodeError::MissingKeyframe));
+
+    let merged = a.followed_by(&b).unwrap();
+    assert!(!merged.is_keyframe());
+    assert_eq!(decoder.decode(&merged).unwrap().len(), 2);
+    assert_eq!(first.followed_by(&b), Err(DecodeError::MissingKeyframe));
+
+    // Merging onto a keyframe makes a new keyframe
+    let whole = first.followed_by(&a).unwrap().followed_by(&b).unwrap();
+    assert!(whole.is_keyframe());
+    assert_eq!(whole.decode(), Ok(third));
+}
+
+#[test]
+fn snapshots_hold_the_whole_screen() {
+    let frame = Frame::from_rgb(150, 100, &gradient(150, 100));
+    let changed = with_square(&frame, 100, 64, 10);
+
+    let mut encoder = TileEncoder::new();
+    let first = encoder.encode(&frame);
+    let delta = encoder.encode(&changed);
+    let same = encoder.encode(&changed);
+
+    let mut snapshot = Snapshot::new();
+    assert_eq!(snapshot.packet(), None);
+    assert_eq!(snapshot.apply(&delta), Err(DecodeError::MissingKeyframe));
+
+    assert_eq!(snapshot.apply(&first), Ok(true));
+    assert_eq!(snapshot.apply(&delta), Ok(true));
+    assert_eq!(snapshot.apply(&same), Ok(false));
+    assert_eq!(snapshot.packet().unwrap().decode(), Ok(changed));
+
+    // Other encodings replace the snapshot
+    let raw = Packet::from(frame.clone());
+    assert_eq!(snapshot.apply(&raw), Ok(true));
+    assert_eq!(snapshot.apply(&raw), Ok(false));
+    assert_eq!(snapshot.packet(), Some(raw));
+}
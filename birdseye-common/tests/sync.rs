@@ -0,0 +1,63 @@
+use birdseye_common::sync::{ProcessList, ProcessStatus, ProcessSync, SyncGap};
+use birdseye_common::{Process, User};
+
+fn process(pid: u32) -> Process {
+    Process::new(pid, &format!("process-{pid}"), &User::new("student"))
+}
+
+#[test]
+fn subscriber_follows_source() {
+    let mut source = ProcessList::new_source();
+    source.record(ProcessStatus::Start(process(1)));
+
+    let mut subscriber = ProcessList::default();
+    subscriber
+        .apply(&ProcessSync::Snapshot(source.snapshot()))
+        .unwrap();
+
+    let update = source.record(ProcessStatus::Start(process(2)));
+    subscriber.apply(&ProcessSync::Update(update)).unwrap();
+    let update = source.record(ProcessStatus::Stop(process(1)));
+    subscriber.apply(&ProcessSync::Update(update)).unwrap();
+
+    assert_eq!(subscriber.seq(), source.seq());
+    assert_eq!(subscriber.snapshot(), source.snapshot());
+}
+
+#[test]
+fn missed_update_is_detected_and_resynced() {
+    let mut source = ProcessList::new_source();
+    let mut subscriber = ProcessList::default();
+    subscriber
+        .apply(&ProcessSync::Snapshot(source.snapshot()))
+        .unwrap();
+
+    let _missed = source.record(ProcessStatus::Start(process(1)));
+    let update = source.record(ProcessStatus::Start(process(2)));
+
+    assert_eq!(
+        subscriber.apply(&ProcessSync::Update(update.clone())),
+        Err(SyncGap {
+            expected: Some(1),
+            received: 2
+        })
+    );
+
+    // After a resync, updates that were already in flight are ignored
+    subscriber
+        .apply(&ProcessSync::Snapshot(source.snapshot()))
+        .unwrap();
+    subscriber.apply(&ProcessSync::Update(update)).unwrap();
+
+    assert_eq!(subscriber.snapshot(), source.snapshot());
+}
+
+#[test]
+fn update_before_snapshot_is_a_gap() {
+    let mut source = ProcessList::new_source();
+    let update = source.record(ProcessStatus::Start(process(1)));
+
+    let mut subscriber = ProcessList::default();
+    assert!(subscriber.apply(&ProcessSync::Update(update)).is_err());
+    assert!(!subscriber.is_synced());
+}
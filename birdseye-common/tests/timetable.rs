@@ -0,0 +1,169 @@
+use birdseye_common::timetable::{Holiday, Period, RuleSet, Timetable, TimetableError};
+use birdseye_common::RoomId;
+use time::macros::{date, datetime, time};
+use time::Weekday;
+
+fn room_12() -> RoomId {
+    RoomId::new("Room 12")
+}
+
+fn timetable() -> Timetable {
+    Timetable::new(
+        vec![
+            Period::new(
+                "Maths",
+                room_12(),
+                Weekday::Monday,
+                time!(9:00),
+                time!(10:00),
+            ),
+            Period::new(
+                "Exam",
+                room_12(),
+                Weekday::Monday,
+                time!(11:00),
+                time!(12:30),
+            ),
+            Period::new(
+                "Maths",
+                RoomId::new("Room 14"),
+                Weekday::Tuesday,
+                time!(9:00),
+                time!(10:00),
+            ),
+        ],
+        vec![Holiday::new(
+            "Term break",
+            date!(2024 - 04 - 13),
+            date!(2024 - 04 - 28),
+        )],
+        vec![
+            RuleSet::new("Maths", vec![1], false),
+            RuleSet::new("Exam", vec![1, 2], true),
+        ],
+    )
+}
+
+#[test]
+fn periods_run_from_their_start_up_to_their_end() {
+    let timetable = timetable();
+    // 2024-03-04 is a Monday
+    let running = |at| timetable.running(&room_12(), at);
+
+    assert!(running(datetime!(2024-03-04 8:59)).is_empty());
+    assert_eq!(running(datetime!(2024-03-04 9:00))[0].name(), "Maths");
+    assert_eq!(running(datetime!(2024-03-04 9:59:59))[0].name(), "Maths");
+    assert!(running(datetime!(2024-03-04 10:00)).is_empty());
+    // Other days and rooms
+    assert!(running(datetime!(2024-03-05 9:30)).is_empty());
+    assert_eq!(
+        timetable
+            .running(&RoomId::new("Room 14"), datetime!(2024-03-05 9:30))
+            .len(),
+        1
+    );
+}
+
+#[test]
+fn nothing_runs_on_holidays() {
+    let timetable = timetable();
+
+    assert!(timetable.is_holiday(date!(2024 - 04 - 13)));
+    assert!(timetable.is_holiday(date!(2024 - 04 - 28)));
+    assert!(!timetable.is_holiday(date!(2024 - 04 - 29)));
+    assert!(timetable
+        .running(&room_12(), datetime!(2024-04-15 9:30))
+        .is_empty());
+    assert!(!timetable
+        .running(&room_12(), datetime!(2024-04-29 9:30))
+        .is_empty());
+}
+
+#[test]
+fn rule_sets_switch_rules_and_locks() {
+    let timetable = timetable();
+    let maths = timetable.running(&room_12(), datetime!(2024-03-04 9:30));
+    let exam = timetable.running(&room_12(), datetime!(2024-03-04 11:30));
+    let nothing = timetable.running(&room_12(), datetime!(2024-03-04 13:00));
+
+    // Rules that aren't in a rule set are always enforced
+    assert!(timetable.is_enforced(3, &nothing));
+    assert!(timetable.is_enforced(1, &maths));
+    assert!(!timetable.is_enforced(1, &nothing));
+    assert!(!timetable.is_enforced(2, &maths));
+    assert!(timetable.is_enforced(2, &exam));
+
+    assert!(timetable.is_locked(&exam));
+    assert!(!timetable.is_locked(&maths));
+}
+
+#[test]
+fn the_next_change_is_the_closest_start_or_end() {
+    let timetable = timetable();
+
+    assert_eq!(
+        timetable.next_change(datetime!(2024-03-04 8:00)),
+        Some(datetime!(2024-03-04 9:00))
+    );
+    assert_eq!(
+        timetable.next_change(datetime!(2024-03-04 9:00)),
+        Some(datetime!(2024-03-04 10:00))
+    );
+    // After the last period of the week it wraps round to next Monday
+    assert_eq!(
+        timetable.next_change(datetime!(2024-03-05 10:00)),
+        Some(datetime!(2024-03-11 9:00))
+    );
+    assert_eq!(
+        Timetable::default().next_change(datetime!(2024-03-04 8:00)),
+        None
+    );
+}
+
+#[test]
+fn periods_and_holidays_have_to_end_after_they_start() {
+    assert_eq!(timetable().validate(), Ok(()));
+
+    let backwards = Timetable::new(
+        vec![Period::new(
+            "Maths",
+            room_12(),
+            Weekday::Monday,
+            time!(10:00),
+            time!(9:00),
+        )],
+        vec![],
+        vec![],
+    );
+    assert_eq!(
+        backwards.validate(),
+        Err(TimetableError::PeriodEndsBeforeStart("Maths".into()))
+    );
+
+    let backwards = Timetable::new(
+        vec![],
+        vec![Holiday::new(
+            "Break",
+            date!(2024 - 04 - 28),
+            date!(2024 - 04 - 13),
+        )],
+        vec![],
+    );
+    assert_eq!(
+        backwards.validate(),
+        Err(TimetableError::HolidayEndsBeforeStart("Break".into()))
+    );
+}
+
+#[test]
+fn times_are_written_for_people() {
+    let json = serde_json::to_value(timetable()).unwrap();
+    let period = &json["periods"][0];
+
+    assert_eq!(period["day"], "Monday");
+    assert_eq!(period["start"], "09:00");
+    assert_eq!(json["holidays"][0]["start"], "2024-04-13");
+
+    let back: Timetable = serde_json::from_value(json).unwrap();
+    assert_eq!(back, timetable());
+}
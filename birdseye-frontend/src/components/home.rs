@@ -1,47 +1,107 @@
-use crate::socket_worker::{InMsg, OutMsg, ServerSocket};
-use log::{debug, error};
-use web_sys::HtmlInputElement;
+use crate::components::Machines;
+use crate::router::Route;
+use crate::socket_worker::{OutMsg, ServerSocket};
+use birdseye_common::auth::AccountInfo;
+use gloo::net::http::Request;
+use log::error;
+use wasm_bindgen_futures::spawn_local;
 use yew::prelude::*;
 use yew_agent::{use_bridge, UseBridgeHandle};
+use yew_router::prelude::*;
 
-#[function_component(Home)]
-pub fn home() -> Html {
-    let content = use_state(|| "".to_string());
-    let input_ref = use_node_ref();
+/// Status of the connection to the server, this is kept separate from [`Home`] so the socket isn't
+/// opened until we know we are logged in
+#[function_component(ConnectionStatus)]
+fn connection_status() -> Html {
+    let status = use_state(|| "Connecting...".to_string());
 
-    let bridge: UseBridgeHandle<ServerSocket> = use_bridge({
-        let content = content.clone();
+    let _bridge: UseBridgeHandle<ServerSocket> = use_bridge({
+        let status = status.clone();
         move |msg| match msg {
-            OutMsg::Hello(msg) => content.set(msg),
+            OutMsg::Connected(negotiated) => status.set(format!(
+                "Connected to {} (protocol version {})",
+                negotiated.agent(),
+                negotiated.version()
+            )),
+            OutMsg::Rejected(reason) => status.set(format!("Connection rejected: {reason}")),
+            OutMsg::CommandUpdate { .. }
+            | OutMsg::Machine { .. }
+            | OutMsg::Processes { .. }
+            | OutMsg::SubscribeDenied(_)
+            | OutMsg::PolicyViolation { .. }
+            | OutMsg::Restrictions { .. }
+            | OutMsg::Frame { .. } => {}
         }
     });
 
-    let send_msg = {
-        let input_ref = input_ref.clone();
-        Callback::from(move |_: MouseEvent| {
-            let target: HtmlInputElement = match input_ref.cast() {
-                Some(elm) => elm,
-                None => {
-                    error!("Element was not Input");
-                    return;
-                }
-            };
+    html! {
+        <p>{(*status).clone()}</p>
+    }
+}
 
-            let text = target.value();
+/// Describe which rooms the logged in teacher can see
+fn rooms_summary(account: &AccountInfo) -> String {
+    let rooms: Vec<_> = account.rooms().iter().map(|room| room.as_str()).collect();
 
-            debug!("{text}");
-            bridge.send(InMsg::Hello(text));
-        })
-    };
+    match (account.is_admin(), rooms.is_empty()) {
+        (true, _) => "You can see every room".to_string(),
+        (false, true) => "You haven't been assigned to any rooms yet".to_string(),
+        (false, false) => format!("Rooms: {}", rooms.join(", ")),
+    }
+}
+
+#[function_component(Home)]
+pub fn home() -> Html {
+    let account = use_state(|| None::<AccountInfo>);
+    let history = use_history().unwrap();
+
+    // Send anyone who isn't logged in to the login page
+    {
+        let account = account.clone();
+        let history = history.clone();
+        use_effect_with_deps(
+            move |_| {
+                spawn_local(async move {
+                    match Request::get("/session").send().await {
+                        Ok(response) if response.ok() => match response.json().await {
+                            Ok(info) => account.set(Some(info)),
+                            Err(ex) => error!("Could not read session {ex}"),
+                        },
+                        Ok(_) => history.push(Route::Login),
+                        Err(ex) => error!("Could not get session {ex}"),
+                    }
+                });
+                || ()
+            },
+            (),
+        );
+    }
+
+    let logout = Callback::from(move |_| {
+        let history = history.clone();
+        spawn_local(async move {
+            if let Err(ex) = Request::post("/logout").send().await {
+                error!("Could not log out {ex}");
+            }
+            history.push(Route::Login);
+        });
+    });
 
     html! {
         <div class="com-home">
             <h1>{"Hello bois!!"}</h1>
 
-            <input ref={input_ref} />
-            <button onclick={send_msg}>{"Send message"}</button>
-
-            <p>{(*content).clone()}</p>
+            if let Some(account) = &*account {
+                <p>
+                    {format!("Logged in as {}", account.username())}
+                    <button onclick={logout}>{"Log out"}</button>
+                </p>
+                <p>{rooms_summary(account)}</p>
+                <ConnectionStatus />
+                <Machines />
+            } else {
+                <p>{"Checking login..."}</p>
+            }
         </div>
     }
 }
@@ -0,0 +1,69 @@
+use crate::router::Route;
+use birdseye_common::auth::LoginRequest;
+use gloo::net::http::Request;
+use log::error;
+use wasm_bindgen_futures::spawn_local;
+use web_sys::HtmlInputElement;
+use yew::prelude::*;
+use yew_router::prelude::*;
+
+#[function_component(Login)]
+pub fn login() -> Html {
+    let username = use_node_ref();
+    let password = use_node_ref();
+    let error = use_state(|| None::<String>);
+    let history = use_history().unwrap();
+
+    let onsubmit = {
+        let username = username.clone();
+        let password = password.clone();
+        let error = error.clone();
+
+        Callback::from(move |event: FocusEvent| {
+            event.prevent_default();
+
+            let request = LoginRequest {
+                username: username.cast::<HtmlInputElement>().unwrap().value(),
+                password: password.cast::<HtmlInputElement>().unwrap().value(),
+            };
+            let error = error.clone();
+            let history = history.clone();
+
+            spawn_local(async move {
+                let response = match Request::post("/login").json(&request) {
+                    Ok(request) => request.send().await,
+                    Err(ex) => Err(ex),
+                };
+
+                match response {
+                    Ok(response) if response.ok() => history.push(Route::Home),
+                    Ok(response) if response.status() == 401 => {
+                        error.set(Some("Incorrect username or password".into()))
+                    }
+                    Ok(response) => error.set(Some(format!(
+                        "Could not log in: {}",
+                        response.status_text()
+                    ))),
+                    Err(ex) => {
+                        error!("Could not send login request {ex}");
+                        error.set(Some("Could not reach the server".into()))
+                    }
+                }
+            });
+        })
+    };
+
+    html! {
+        <div class="com-login">
+            <form {onsubmit}>
+                <h2>{"Log in"}</h2>
+                <input ref={username} type="text" placeholder="Username" autocomplete="username" required=true />
+                <input ref={password} type="password" placeholder="Password" autocomplete="current-password" required=true />
+                if let Some(error) = &*error {
+                    <p class="error">{error}</p>
+                }
+                <button type="submit">{"Log in"}</button>
+            </form>
+        </div>
+    }
+}
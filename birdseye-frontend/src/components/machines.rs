@@ -0,0 +1,198 @@
+use crate::components::Screen;
+use crate::socket_worker::{CommandStatus, InMsg, OutMsg, ServerSocket};
+use birdseye_common::api::MachineStatus;
+use birdseye_common::command::Command;
+use birdseye_common::frontend::Topic;
+use birdseye_common::sync::Restriction;
+use birdseye_common::{MachineId, Process};
+use gloo::net::http::Request;
+use log::error;
+use wasm_bindgen_futures::spawn_local;
+use yew::prelude::*;
+use yew_agent::{use_bridge, UseBridgeHandle};
+
+/// How much of a CPU the throttle button lets a process use
+const THROTTLE_PERCENT: u8 = 20;
+
+#[derive(Properties, PartialEq)]
+pub struct ProcessesProps {
+    pub machine: MachineId,
+}
+
+/// The processes running on a machine, with buttons to suspend, throttle or kill them
+#[function_component(Processes)]
+fn processes(props: &ProcessesProps) -> Html {
+    let processes = use_state(Vec::<Process>::new);
+    let restrictions = use_state(Vec::<Restriction>::new);
+    let status = use_state(|| None::<String>);
+    // What the last command sent does, used to describe how it's going
+    let action = use_mut_ref(|| "kill");
+
+    let bridge: UseBridgeHandle<ServerSocket> = use_bridge({
+        let machine = props.machine.clone();
+        let processes = processes.clone();
+        let restrictions = restrictions.clone();
+        let status = status.clone();
+        let action = action.clone();
+        move |msg| match msg {
+            OutMsg::Processes {
+                machine: from,
+                processes: mut list,
+            } if from == machine => {
+                list.sort_by(|a, b| a.name().cmp(b.name()).then(a.pid().cmp(b.pid())));
+                processes.set(list);
+            }
+            OutMsg::Restrictions {
+                machine: from,
+                restrictions: list,
+            } if from == machine => restrictions.set(list),
+            OutMsg::CommandUpdate { status: update, .. } => {
+                let action = *action.borrow();
+                status.set(Some(match update {
+                    CommandStatus::Sent | CommandStatus::Acknowledged => {
+                        format!("Trying to {action}...")
+                    }
+                    CommandStatus::Completed(Ok(())) => "Done".to_string(),
+                    CommandStatus::Completed(Err(ex)) => format!("Could not {action}: {ex}"),
+                }))
+            }
+            _ => {}
+        }
+    });
+
+    // Only get the processes of the machine being looked at
+    {
+        let bridge = bridge.clone();
+        use_effect_with_deps(
+            move |machine: &MachineId| {
+                let topic = Topic::Machine(machine.clone());
+                bridge.send(InMsg::Subscribe(topic.clone()));
+                move || bridge.send(InMsg::Unsubscribe(topic))
+            },
+            props.machine.clone(),
+        );
+    }
+
+    let send = |name: &'static str, command: Command| {
+        let bridge = bridge.clone();
+        let machine = props.machine.clone();
+        let action = action.clone();
+        Callback::from(move |_| {
+            *action.borrow_mut() = name;
+            bridge.send(InMsg::Command {
+                machine: machine.clone(),
+                command: command.clone(),
+            })
+        })
+    };
+
+    let buttons = |process: &Process| {
+        let (pid, start_time) = (*process.pid(), process.start_time());
+        let restriction = restrictions
+            .iter()
+            .find(|restriction| restriction.is_for(process));
+        let state = match restriction.map(|r| (r.suspended(), r.throttle())) {
+            Some((true, _)) => "Suspended".to_string(),
+            Some((false, Some(percent))) => format!("Throttled to {percent}%"),
+            _ => String::new(),
+        };
+
+        html! {
+            <>
+                <td>{state}</td>
+                <td>
+                    if restriction.is_some() {
+                        <button onclick={send("resume", Command::Resume { pid, start_time })}>{"Resume"}</button>
+                    } else {
+                        <button onclick={send("suspend", Command::Suspend { pid, start_time })}>{"Suspend"}</button>
+                        <button onclick={send("throttle", Command::Throttle { pid, start_time, percent: THROTTLE_PERCENT })}>
+                            {format!("Throttle to {THROTTLE_PERCENT}%")}
+                        </button>
+                    }
+                    <button onclick={send("kill", Command::Kill { pid, start_time, tree: false })}>{"Kill"}</button>
+                    <button onclick={send("kill", Command::Kill { pid, start_time, tree: true })}>{"Kill tree"}</button>
+                </td>
+            </>
+        }
+    };
+
+    html! {
+        <div class="com-processes">
+            if let Some(status) = &*status {
+                <p>{status}</p>
+            }
+            <table>
+                <tr>
+                    <th>{"Pid"}</th>
+                    <th>{"Name"}</th>
+                    <th>{"User"}</th>
+                    <th>{"State"}</th>
+                    <th></th>
+                </tr>
+                { for processes.iter().map(|process| html! {
+                    <tr>
+                        <td>{process.pid()}</td>
+                        <td>{process.name()}</td>
+                        <td>{process.user().name()}</td>
+                        { buttons(process) }
+                    </tr>
+                }) }
+            </table>
+        </div>
+    }
+}
+
+/// Every machine the teacher can see, picking one shows its screen and processes
+#[function_component(Machines)]
+pub fn machines() -> Html {
+    let machines = use_state(Vec::<MachineStatus>::new);
+    let selected = use_state(|| None::<MachineId>);
+
+    {
+        let machines = machines.clone();
+        use_effect_with_deps(
+            move |_| {
+                spawn_local(async move {
+                    match Request::get("/api/v1/machines").send().await {
+                        Ok(response) => match response.json().await {
+                            Ok(list) => machines.set(list),
+                            Err(ex) => error!("Could not read machines {ex}"),
+                        },
+                        Err(ex) => error!("Could not get machines {ex}"),
+                    }
+                });
+                || ()
+            },
+            (),
+        );
+    }
+
+    html! {
+        <div class="com-machines">
+            <ul>
+                { for machines.iter().map(|status| {
+                    let onclick = {
+                        let selected = selected.clone();
+                        let id = status.machine.id().clone();
+                        Callback::from(move |_| selected.set(Some(id.clone())))
+                    };
+                    let online = match status.presence.is_online() {
+                        true => "online",
+                        false => "offline",
+                    };
+
+                    html! {
+                        <li>
+                            <button {onclick}>{status.machine.hostname()}</button>
+                            {format!(" ({online})")}
+                        </li>
+                    }
+                }) }
+            </ul>
+            if let Some(machine) = &*selected {
+                <Screen machine={machine.clone()} />
+                <Processes machine={machine.clone()} />
+            }
+        </div>
+    }
+}
@@ -1,5 +1,11 @@
 mod home;
+mod login;
+mod machines;
 mod not_found;
+mod screen;
 
 pub use home::Home;
+pub use login::Login;
+pub use machines::Machines;
 pub use not_found::NotFound;
+pub use screen::Screen;
@@ -0,0 +1,320 @@
+use crate::socket_worker::{InMsg, OutMsg, ServerSocket};
+use birdseye_common::screen::h264;
+use birdseye_common::screen::tiles::TileHeader;
+use birdseye_common::screen::{DecodeError, Encoding, Packet, TileDecoder};
+use birdseye_common::MachineId;
+use js_sys::{global, Array, Function, Object, Reflect, Uint8Array};
+use log::error;
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::{Clamped, JsCast, JsValue};
+use wasm_bindgen_futures::{spawn_local, JsFuture};
+use web_sys::{
+    Blob, BlobPropertyBag, CanvasRenderingContext2d, HtmlCanvasElement, ImageBitmap, ImageData,
+};
+use yew::prelude::*;
+use yew_agent::{use_bridge, UseBridgeHandle};
+
+#[derive(Properties, PartialEq)]
+pub struct ScreenProps {
+    pub machine: MachineId,
+}
+
+/// Get a canvas ready to draw a frame on, resizing it if the frame is a different size
+fn context(
+    canvas: &HtmlCanvasElement,
+    width: u32,
+    height: u32,
+) -> Result<CanvasRenderingContext2d, JsValue> {
+    if canvas.width() != width || canvas.height() != height {
+        canvas.set_width(width);
+        canvas.set_height(height);
+    }
+
+    canvas
+        .get_context("2d")?
+        .ok_or("canvas has no 2d context")?
+        .dyn_into::<CanvasRenderingContext2d>()
+        .map_err(JsValue::from)
+}
+
+/// Draw a raw frame onto a canvas
+fn draw_raw(canvas: &HtmlCanvasElement, packet: &Packet) -> Result<(), JsValue> {
+    let frame = packet
+        .decode()
+        .map_err(|ex| JsValue::from(ex.to_string()))?;
+
+    // Frames are BGR0 and canvases want RGBA
+    let mut pixels = frame.data().to_vec();
+    for pixel in pixels.chunks_exact_mut(4) {
+        pixel.swap(0, 2);
+        pixel[3] = u8::MAX;
+    }
+    let image = ImageData::new_with_u8_clamped_array_and_sh(
+        Clamped(&pixels),
+        frame.width(),
+        frame.height(),
+    )?;
+
+    context(canvas, frame.width(), frame.height())?.put_image_data(&image, 0.0, 0.0)
+}
+
+/// Draw the tiles that changed onto a canvas, keyframes resize it first
+fn draw_tiles(
+    canvas: &HtmlCanvasElement,
+    decoder: &mut TileDecoder,
+    packet: &Packet,
+) -> Result<(), JsValue> {
+    let regions = decoder
+        .decode(packet)
+        .map_err(|ex| JsValue::from(ex.to_string()))?;
+    let context = context(canvas, packet.width(), packet.height())?;
+
+    for region in regions {
+        let pixels: Vec<u8> = region
+            .rgb()
+            .chunks_exact(3)
+            .flat_map(|pixel| [pixel[0], pixel[1], pixel[2], u8::MAX])
+            .collect();
+        let image = ImageData::new_with_u8_clamped_array_and_sh(
+            Clamped(&pixels),
+            region.width(),
+            region.height(),
+        )?;
+        context.put_image_data(&image, region.x() as f64, region.y() as f64)?;
+    }
+
+    Ok(())
+}
+
+/// Have the browser decode a JPEG, then draw it onto a canvas
+async fn draw_jpeg(canvas: HtmlCanvasElement, packet: Packet) -> Result<(), JsValue> {
+    let parts = Array::of1(&Uint8Array::from(packet.data().as_ref()));
+    let options = BlobPropertyBag::new();
+    options.set_type("image/jpeg");
+    let blob = Blob::new_with_u8_array_sequence_and_options(&parts, &options)?;
+
+    let window = web_sys::window().ok_or("no window")?;
+    let bitmap = JsFuture::from(window.create_image_bitmap_with_blob(&blob)?)
+        .await?
+        .dyn_into::<ImageBitmap>()?;
+
+    let drawn = context(&canvas, packet.width(), packet.height())
+        .and_then(|context| context.draw_image_with_image_bitmap(&bitmap, 0.0, 0.0));
+    bitmap.close();
+    drawn
+}
+
+/// Call a method on a javascript object
+fn call(target: &JsValue, method: &str, args: &Array) -> Result<JsValue, JsValue> {
+    let method = Reflect::get(target, &JsValue::from_str(method))?.dyn_into::<Function>()?;
+    Reflect::apply(&method, target, args)
+}
+
+/// Make an object out of its properties
+fn object(properties: &[(&str, JsValue)]) -> Result<Object, JsValue> {
+    let object = Object::new();
+    for (name, value) in properties {
+        Reflect::set(&object, &JsValue::from_str(name), value)?;
+    }
+    Ok(object)
+}
+
+/// Construct one of the browser's WebCodecs classes
+fn construct(class: &str, init: &Object) -> Result<JsValue, JsValue> {
+    let class = Reflect::get(&global(), &JsValue::from_str(class))?;
+    if class.is_undefined() {
+        return Err(JsValue::from_str("this browser can't decode H.264"));
+    }
+    Reflect::construct(class.unchecked_ref(), &Array::of1(init))
+}
+
+/// Plays H.264 onto a canvas with the browser's WebCodecs `VideoDecoder`. web-sys only has
+/// WebCodecs behind its unstable APIs, so the decoder is driven through [`Reflect`]
+struct VideoPlayer {
+    canvas: HtmlCanvasElement,
+    decoder: JsValue,
+    /// Called by the decoder, kept for as long as it is
+    _output: Closure<dyn FnMut(JsValue)>,
+    _error: Closure<dyn FnMut(JsValue)>,
+    /// The codec and size the decoder was last configured with
+    configured: Option<(String, u32, u32)>,
+    /// The sequence of the last packet decoded
+    last: Option<u64>,
+    /// Counts up with each frame, the decoder wants every frame to have a timestamp
+    timestamp: u32,
+}
+
+impl VideoPlayer {
+    fn new(canvas: HtmlCanvasElement) -> Result<Self, JsValue> {
+        let output = {
+            let canvas = canvas.clone();
+            Closure::<dyn FnMut(JsValue)>::new(move |frame: JsValue| {
+                let drawn = canvas
+                    .get_context("2d")
+                    .and_then(|context| context.ok_or(JsValue::from("canvas has no 2d context")))
+                    .and_then(|context| {
+                        call(
+                            &context,
+                            "drawImage",
+                            &Array::of3(&frame, &0.into(), &0.into()),
+                        )
+                    });
+                if let Err(ex) = drawn {
+                    error!("Could not draw frame {ex:?}");
+                }
+                // Decoded frames hold on to memory on the GPU until they are closed
+                let _ = call(&frame, "close", &Array::new());
+            })
+        };
+        let error = Closure::<dyn FnMut(JsValue)>::new(|ex: JsValue| {
+            error!("Could not decode frame {ex:?}");
+        });
+
+        let init = object(&[
+            ("output", output.as_ref().clone()),
+            ("error", error.as_ref().clone()),
+        ])?;
+        Ok(Self {
+            canvas,
+            decoder: construct("VideoDecoder", &init)?,
+            _output: output,
+            _error: error,
+            configured: None,
+            last: None,
+            timestamp: 0,
+        })
+    }
+
+    /// Whether the decoder has stopped after an error, and a new player is needed
+    fn is_closed(&self) -> bool {
+        Reflect::get(&self.decoder, &JsValue::from_str("state"))
+            .is_ok_and(|state| state.as_string().as_deref() == Some("closed"))
+    }
+
+    /// Queue the frames of a packet to be decoded, they are drawn once they are. Deltas have to
+    /// follow on from the last packet played
+    fn play(&mut self, packet: &Packet) -> Result<(), JsValue> {
+        let error = |ex: DecodeError| JsValue::from(ex.to_string());
+        let header = TileHeader::read(packet).map_err(error)?;
+        if !header.keyframe() && self.last != Some(header.base()) {
+            return Err(JsValue::from_str("frame needs the frames before it"));
+        }
+
+        if header.keyframe() {
+            let codec = h264::codec(packet).ok_or("keyframe has no sequence parameter set")?;
+            let wanted = (codec, packet.width(), packet.height());
+            if self.configured.as_ref() != Some(&wanted) {
+                let config = object(&[
+                    ("codec", JsValue::from_str(&wanted.0)),
+                    ("optimizeForLatency", JsValue::from_bool(true)),
+                ])?;
+                call(&self.decoder, "configure", &Array::of1(&config))?;
+                self.configured = Some(wanted);
+            }
+        }
+        // Odd sizes are decoded a pixel bigger, which falls off the edge of the canvas
+        context(&self.canvas, packet.width(), packet.height())?;
+
+        for (index, frame) in h264::frames(packet).map_err(error)?.iter().enumerate() {
+            let kind = match index == 0 && header.keyframe() {
+                true => "key",
+                false => "delta",
+            };
+            let chunk = construct(
+                "EncodedVideoChunk",
+                &object(&[
+                    ("type", JsValue::from_str(kind)),
+                    ("timestamp", JsValue::from(self.timestamp)),
+                    ("data", Uint8Array::from(frame.as_ref()).into()),
+                ])?,
+            )?;
+            call(&self.decoder, "decode", &Array::of1(&chunk))?;
+            self.timestamp = self.timestamp.wrapping_add(1);
+        }
+
+        self.last = Some(header.sequence());
+        Ok(())
+    }
+}
+
+/// The live screen of a machine
+#[function_component(Screen)]
+pub fn screen(props: &ScreenProps) -> Html {
+    let canvas = use_node_ref();
+    // Frames that arrive while the browser is still decoding the last one are skipped
+    let decoding = use_mut_ref(|| false);
+    let tiles = use_mut_ref(TileDecoder::new);
+    let video = use_mut_ref(|| None::<VideoPlayer>);
+
+    let bridge: UseBridgeHandle<ServerSocket> = use_bridge({
+        let machine = props.machine.clone();
+        let canvas = canvas.clone();
+        move |msg| match msg {
+            OutMsg::Frame {
+                machine: from,
+                packet,
+            } if from == machine => {
+                let canvas = match canvas.cast::<HtmlCanvasElement>() {
+                    Some(canvas) => canvas,
+                    None => return,
+                };
+
+                match packet.encoding() {
+                    Encoding::Raw => {
+                        if let Err(ex) = draw_raw(&canvas, &packet) {
+                            error!("Could not draw frame {ex:?}");
+                        }
+                    }
+                    Encoding::Jpeg if !*decoding.borrow() => {
+                        *decoding.borrow_mut() = true;
+                        let decoding = decoding.clone();
+                        spawn_local(async move {
+                            if let Err(ex) = draw_jpeg(canvas, packet).await {
+                                error!("Could not draw frame {ex:?}");
+                            }
+                            *decoding.borrow_mut() = false;
+                        });
+                    }
+                    Encoding::Jpeg => {}
+                    Encoding::Tiles => {
+                        if let Err(ex) = draw_tiles(&canvas, &mut tiles.borrow_mut(), &packet) {
+                            error!("Could not draw frame {ex:?}");
+                        }
+                    }
+                    Encoding::H264 => {
+                        let mut video = video.borrow_mut();
+                        if video.as_ref().is_none_or(VideoPlayer::is_closed) {
+                            *video = match VideoPlayer::new(canvas) {
+                                Ok(player) => Some(player),
+                                Err(ex) => {
+                                    error!("Could not play video {ex:?}");
+                                    return;
+                                }
+                            };
+                        }
+                        if let Some(Err(ex)) = video.as_mut().map(|player| player.play(&packet)) {
+                            error!("Could not draw frame {ex:?}");
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    });
+
+    {
+        let bridge = bridge.clone();
+        use_effect_with_deps(
+            move |machine: &MachineId| {
+                let machine = machine.clone();
+                bridge.send(InMsg::WatchScreen(machine.clone()));
+                move || bridge.send(InMsg::UnwatchScreen(machine))
+            },
+            props.machine.clone(),
+        );
+    }
+
+    html! {
+        <canvas class="com-screen" ref={canvas} />
+    }
+}
@@ -8,6 +8,8 @@ pub enum Route {
     Home,
     #[at("/static/index.html")]
     Index,
+    #[at("/login")]
+    Login,
 
     #[not_found]
     #[at("/404")]
@@ -18,5 +20,6 @@ pub fn switch(route: &Route) -> Html {
     match route {
         Route::NotFound => html! {<NotFound />},
         Route::Home | Route::Index => html! {<Home />},
+        Route::Login => html! {<Login />},
     }
 }
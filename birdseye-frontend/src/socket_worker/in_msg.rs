@@ -1,6 +1,23 @@
+use birdseye_common::command::Command;
+use birdseye_common::frontend::Topic;
+use birdseye_common::MachineId;
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Debug)]
 pub enum InMsg {
-    Hello(String),
+    /// Run a command on a machine, progress is reported back with
+    /// [`OutMsg::CommandUpdate`](super::OutMsg::CommandUpdate)
+    Command {
+        machine: MachineId,
+        command: Command,
+    },
+    /// Start receiving [`OutMsg::Machine`](super::OutMsg::Machine) and
+    /// [`OutMsg::Processes`](super::OutMsg::Processes) for every machine in a topic
+    Subscribe(Topic),
+    /// Stop receiving updates for a topic
+    Unsubscribe(Topic),
+    /// Start receiving [`OutMsg::Frame`](super::OutMsg::Frame) from a machine
+    WatchScreen(MachineId),
+    /// Stop receiving frames from a machine
+    UnwatchScreen(MachineId),
 }
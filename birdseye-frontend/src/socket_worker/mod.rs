@@ -1,26 +1,62 @@
 mod in_msg;
 mod out_msg;
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
-use birdseye_common::frontend::WsMessage;
+use birdseye_common::codec::Codec;
+use birdseye_common::command::RequestId;
+use birdseye_common::frontend::{Topic, WsMessage};
+use birdseye_common::handshake::Hello;
+use birdseye_common::sync::ProcessList;
+use birdseye_common::MachineId;
 use futures::channel::mpsc::{channel, Sender};
 use futures::{SinkExt, StreamExt};
 use gloo::net::websocket::WebSocketError;
 use gloo::net::websocket::{futures::WebSocket, Message};
 pub use in_msg::InMsg;
 use log::{debug, error};
-pub use out_msg::OutMsg;
+pub use out_msg::{CommandStatus, OutMsg};
 use wasm_bindgen_futures::spawn_local;
 use yew_agent::{Agent, AgentLink, HandlerId, Public};
 
+/// JSON is used for debug builds so messages can be read in the browser's devtools
+#[cfg(debug_assertions)]
+const CODEC: Codec = Codec::Json;
+#[cfg(not(debug_assertions))]
+const CODEC: Codec = Codec::Bincode;
+
 pub struct ServerSocket {
     link: AgentLink<Self>,
     tx: Sender<Result<Message, WebSocketError>>,
     subscribers: HashSet<HandlerId>,
+    hello: Hello,
+    status: Option<OutMsg>,
+    next_request_id: RequestId,
+    /// Which component sent each request that is waiting on a response
+    pending: HashMap<RequestId, HandlerId>,
+    /// Topics that have been subscribed to
+    topics: HashSet<Topic>,
+    /// Process lists of the machines in the subscribed topics
+    processes: HashMap<MachineId, ProcessList>,
+    /// Machines whose screens are being watched
+    screens: HashSet<MachineId>,
 }
 
 impl ServerSocket {
+    fn send(&self, msg: &WsMessage) {
+        let bytes = CODEC.encode(msg).expect("Error serializing message");
+        let msg = if CODEC.is_text() {
+            Message::Text(String::from_utf8(bytes).expect("Text codec produced invalid utf8"))
+        } else {
+            Message::Bytes(bytes)
+        };
+        let mut tx = self.tx.clone();
+
+        spawn_local(async move {
+            tx.send(Ok(msg)).await.unwrap();
+        });
+    }
+
     fn broadcast(&self, msg: OutMsg) {
         for entry in self.subscribers.iter() {
             self.link.respond(*entry, msg.clone());
@@ -35,8 +71,11 @@ impl Agent for ServerSocket {
     type Output = OutMsg;
 
     fn create(link: AgentLink<Self>) -> Self {
-        let ws = WebSocket::open("wss://be.laspruca.nz:3030/dashboard")
-            .expect("Could not create connection to websocket (be.laspruca.nz)");
+        let ws = WebSocket::open_with_protocol(
+            "wss://be.laspruca.nz:3030/dashboard",
+            CODEC.subprotocol(),
+        )
+        .expect("Could not create connection to websocket (be.laspruca.nz)");
 
         let (write, mut read) = ws.split();
         let (tx, rx) = channel(10);
@@ -49,22 +88,17 @@ impl Agent for ServerSocket {
                         error!("Error receiving message from websocket {ex}");
                     }
 
-                    Ok(msg) => match msg {
-                        Message::Text(_) => {
-                            error!("Should never recieve text from server, wtf?");
-                        }
-                        Message::Bytes(bytes) => {
-                            let bytes: WsMessage = match bincode::deserialize(bytes.as_ref()) {
-                                Ok(val) => val,
-                                Err(ex) => {
-                                    error!("Could not decode message from server {ex}");
-                                    continue;
-                                }
-                            };
-
-                            socket_link.send_message(bytes);
+                    Ok(msg) => {
+                        let bytes = match &msg {
+                            Message::Text(text) => text.as_bytes(),
+                            Message::Bytes(bytes) => bytes.as_slice(),
+                        };
+
+                        match CODEC.decode::<WsMessage>(bytes) {
+                            Ok(val) => socket_link.send_message(val),
+                            Err(ex) => error!("Could not decode message from server {ex}"),
                         }
-                    },
+                    }
                 };
             }
         });
@@ -75,28 +109,169 @@ impl Agent for ServerSocket {
                 .expect("Could not send message to server");
         });
 
-        Self {
+        let slf = Self {
             link,
             tx,
             subscribers: HashSet::new(),
-        }
+            hello: Hello::new(concat!("birdseye-frontend/", env!("CARGO_PKG_VERSION")), []),
+            status: None,
+            next_request_id: 0,
+            pending: HashMap::new(),
+            topics: HashSet::new(),
+            processes: HashMap::new(),
+            screens: HashSet::new(),
+        };
+
+        // The server will not talk to us until it has our hello
+        slf.send(&WsMessage::Hello(slf.hello.clone()));
+
+        slf
     }
 
     fn update(&mut self, msg: Self::Message) {
         debug!("Got server response {msg:?}");
-        match msg {
-            WsMessage::Hello(msg) => self.broadcast(OutMsg::Hello(msg)),
-        }
+        let status = match msg {
+            WsMessage::Ack(id) => {
+                if let Some(handler) = self.pending.get(&id) {
+                    self.link.respond(
+                        *handler,
+                        OutMsg::CommandUpdate {
+                            id,
+                            status: CommandStatus::Acknowledged,
+                        },
+                    );
+                }
+                return;
+            }
+            WsMessage::Response { id, result } => {
+                if let Some(handler) = self.pending.remove(&id) {
+                    self.link.respond(
+                        handler,
+                        OutMsg::CommandUpdate {
+                            id,
+                            status: CommandStatus::Completed(result),
+                        },
+                    );
+                }
+                return;
+            }
+            WsMessage::Processes { machine, sync } => {
+                // Machines in a room aren't known until the server tells us about them
+                let list = self.processes.entry(machine.clone()).or_default();
+
+                if let Err(gap) = list.apply(&sync) {
+                    debug!("{gap} for {machine}, resyncing");
+                    self.send(&WsMessage::ResyncProcesses(machine));
+                    return;
+                }
+
+                let processes = list.processes().cloned().collect();
+                self.broadcast(OutMsg::Processes { machine, processes });
+                return;
+            }
+            WsMessage::Machine { machine, presence } => {
+                self.broadcast(OutMsg::Machine { machine, presence });
+                return;
+            }
+            WsMessage::SubscribeDenied(topic) => {
+                // Forget the topic so asking again will send another subscribe
+                self.topics.remove(&topic);
+                self.broadcast(OutMsg::SubscribeDenied(topic));
+                return;
+            }
+            WsMessage::PolicyViolation { machine, violation } => {
+                self.broadcast(OutMsg::PolicyViolation { machine, violation });
+                return;
+            }
+            WsMessage::Restrictions {
+                machine,
+                restrictions,
+            } => {
+                self.broadcast(OutMsg::Restrictions {
+                    machine,
+                    restrictions,
+                });
+                return;
+            }
+            WsMessage::Frame { machine, frame } => {
+                let packet = frame.into();
+                self.broadcast(OutMsg::Frame { machine, packet });
+                return;
+            }
+            WsMessage::Packet { machine, packet } => {
+                self.broadcast(OutMsg::Frame { machine, packet });
+                return;
+            }
+            WsMessage::Request { .. }
+            | WsMessage::SubscribeProcesses(_)
+            | WsMessage::UnsubscribeProcesses(_)
+            | WsMessage::Subscribe(_)
+            | WsMessage::Unsubscribe(_)
+            | WsMessage::ResyncProcesses(_)
+            | WsMessage::WatchScreen(_)
+            | WsMessage::UnwatchScreen(_) => {
+                error!("Should never receive a dashboard message from the server");
+                return;
+            }
+            WsMessage::Hello(hello) => match self.hello.negotiate(&hello) {
+                Ok(negotiated) => OutMsg::Connected(negotiated),
+                Err(ex) => OutMsg::Rejected(ex.to_string()),
+            },
+            WsMessage::Rejected(reason) => {
+                error!("Server rejected connection: {reason}");
+                OutMsg::Rejected(reason)
+            }
+        };
+
+        self.status = Some(status.clone());
+        self.broadcast(status);
     }
 
-    fn handle_input(&mut self, msg: Self::Input, _id: HandlerId) {
-        let bytes = bincode::serialize(&msg).expect("Error serializing message");
-        let mut tx = self.tx.clone();
+    fn handle_input(&mut self, msg: Self::Input, handler: HandlerId) {
         debug!("Got event: {msg:?}");
+        match msg {
+            InMsg::Command { machine, command } => {
+                let id = self.next_request_id;
+                self.next_request_id += 1;
+                self.pending.insert(id, handler);
 
-        spawn_local(async move {
-            tx.send(Ok(Message::Bytes(bytes))).await.unwrap();
-        });
+                self.send(&WsMessage::Request {
+                    id,
+                    machine,
+                    command,
+                });
+                self.link.respond(
+                    handler,
+                    OutMsg::CommandUpdate {
+                        id,
+                        status: CommandStatus::Sent,
+                    },
+                );
+            }
+            InMsg::Subscribe(topic) => {
+                if self.topics.insert(topic.clone()) {
+                    self.send(&WsMessage::Subscribe(topic));
+                }
+            }
+            InMsg::Unsubscribe(topic) => {
+                if self.topics.remove(&topic) {
+                    if let Topic::Machine(machine) = &topic {
+                        self.processes.remove(machine);
+                    }
+                    self.send(&WsMessage::Unsubscribe(topic));
+                }
+            }
+            InMsg::WatchScreen(machine) => {
+                if self.screens.insert(machine.clone()) {
+                    self.send(&WsMessage::WatchScreen(machine));
+                }
+            }
+            InMsg::UnwatchScreen(machine) => {
+                if self.screens.remove(&machine) {
+                    self.send(&WsMessage::UnwatchScreen(machine));
+                }
+            }
+        }
     }
 
     fn name_of_resource() -> &'static str {
@@ -105,9 +280,15 @@ impl Agent for ServerSocket {
 
     fn connected(&mut self, id: HandlerId) {
         self.subscribers.insert(id);
+
+        // Let late subscribers know how the handshake went
+        if let Some(status) = &self.status {
+            self.link.respond(id, status.clone());
+        }
     }
 
     fn disconnected(&mut self, id: HandlerId) {
         self.subscribers.remove(&id);
+        self.pending.retain(|_, handler| *handler != id);
     }
 }
@@ -1,6 +1,58 @@
+use birdseye_common::command::{CommandError, RequestId};
+use birdseye_common::frontend::Topic;
+use birdseye_common::handshake::Negotiated;
+use birdseye_common::machine::Presence;
+use birdseye_common::policy::Violation;
+use birdseye_common::screen::Packet;
+use birdseye_common::sync::Restriction;
+use birdseye_common::{Machine, MachineId, Process};
 use serde::{Deserialize, Serialize};
 
+/// How far along a command sent by the dashboard is
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum CommandStatus {
+    /// The command has been sent to the server
+    Sent,
+    /// The server has passed the command on to the machine
+    Acknowledged,
+    /// The command has finished running
+    Completed(Result<(), CommandError>),
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub enum OutMsg {
-    Hello(String),
+    /// The handshake with the server succeeded
+    Connected(Negotiated),
+    /// The server refused the connection, contains the reason given by the server
+    Rejected(String),
+    /// Progress of a command, only sent to the component that sent the command
+    CommandUpdate {
+        id: RequestId,
+        status: CommandStatus,
+    },
+    /// A subscribed machine has connected or disconnected, or its inventory has changed
+    Machine {
+        machine: Machine,
+        presence: Presence,
+    },
+    /// The current process list of a subscribed machine, sent every time it changes
+    Processes {
+        machine: MachineId,
+        processes: Vec<Process>,
+    },
+    /// The server refused a subscription because the topic is outside of the teacher's rooms
+    SubscribeDenied(Topic),
+    /// A process on a subscribed machine broke a blacklist rule
+    PolicyViolation {
+        machine: MachineId,
+        violation: Violation,
+    },
+    /// The processes on a subscribed machine that are suspended or throttled, sent every time it
+    /// changes
+    Restrictions {
+        machine: MachineId,
+        restrictions: Vec<Restriction>,
+    },
+    /// The latest frame from the screen of a watched machine
+    Frame { machine: MachineId, packet: Packet },
 }
@@ -0,0 +1,179 @@
+//! Capturing the primary display and encoding it for the server to relay to the dashboards
+//! watching it
+//!
+//! Frames are captured and encoded on their own thread, as capturers can't be moved between
+//! threads, and only the latest one is kept. The link sends whichever frame is newest once it has
+//! finished sending the last one, so frames captured while the link is busy are skipped rather
+//! than queued. Tiles and H.264 only hold what changed, so a snapshot of the whole screen is kept
+//! with each frame to send instead when one is skipped.
+//!
+//! How much is captured follows the [`Demand`] from the server. Nothing is captured while nobody
+//! is looking, thumbnails only need a small frame a second, and the full frame rate is only used
+//! while a teacher has the machine open.
+
+use crate::config::Config;
+use birdseye_common::screen::{
+    Demand, Encoder, Encoding, Frame, H264Encoder, JpegEncoder, Packet, RawEncoder, Snapshot,
+    TileEncoder,
+};
+use futures::executor::block_on;
+use scrap::{Capturer, Display};
+use std::io::ErrorKind::WouldBlock;
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+/// How many frames are captured a second if the config doesn't say
+const DEFAULT_FRAME_RATE: u32 = 5;
+
+/// Kilobits a second the screen is encoded into if the config doesn't say
+const DEFAULT_BITRATE: u32 = 1000;
+
+/// How many frames are captured a second while only thumbnails are needed
+const THUMBNAIL_FRAME_RATE: u32 = 1;
+
+/// How long to wait for the display to have a new frame ready
+const FRAME_POLL: Duration = Duration::from_millis(5);
+
+/// How long to wait before trying again when the display can't be captured, e.g. while nobody is
+/// logged in
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// A frame captured from the display, along with it encoded
+#[derive(Clone)]
+pub struct Captured {
+    /// Only sent to servers older than protocol version 11, which can't decode packets
+    pub frame: Frame,
+    pub packet: Packet,
+    /// Every packet so far, for when the last one doesn't follow on from what was sent
+    pub whole: Snapshot,
+}
+
+/// The link's side of the screen being captured
+pub struct Screen {
+    /// Always holds the latest frame
+    pub frames: watch::Receiver<Option<Captured>>,
+    /// How much of the screen the server needs, nothing is captured until it is told
+    pub demand: watch::Sender<Demand>,
+}
+
+/// Block until the server needs frames, returns false if it never will
+fn wait_for_demand(demand: &mut watch::Receiver<Demand>) -> bool {
+    while *demand.borrow_and_update() == Demand::None {
+        if block_on(demand.changed()).is_err() {
+            return false;
+        }
+    }
+
+    true
+}
+
+/// Capture and encode frames until the display stops working or they are no longer needed
+fn capture(
+    frames: &watch::Sender<Option<Captured>>,
+    demand: &watch::Receiver<Demand>,
+    encoder: &mut dyn Encoder,
+    whole: &mut Snapshot,
+    frame_rate: u32,
+) {
+    let display = match Display::primary() {
+        Ok(display) => display,
+        Err(ex) => {
+            warn!("Could not find the primary display: {ex}");
+            return;
+        }
+    };
+    let (width, height) = (display.width(), display.height());
+
+    let mut capturer = match Capturer::new(display) {
+        Ok(capturer) => capturer,
+        Err(ex) => {
+            warn!("Could not capture the primary display: {ex}");
+            return;
+        }
+    };
+    info!("Capturing {width}x{height} display");
+
+    let mut next = Instant::now();
+    loop {
+        let wanted = *demand.borrow();
+        let frame_rate = match wanted {
+            Demand::None => return,
+            Demand::Thumbnails { .. } => THUMBNAIL_FRAME_RATE,
+            Demand::Full => frame_rate,
+        };
+        // The bitrate is shared between however many frames there are a second
+        encoder.set_frame_rate(frame_rate);
+
+        match capturer.frame() {
+            Ok(buffer) => {
+                // Rows are padded to the display's stride
+                let stride = buffer.len() / height;
+                let mut frame = Frame::from_padded(width as u32, height as u32, stride, &buffer);
+                if let Demand::Thumbnails { width } = wanted {
+                    frame = frame.downscale(width);
+                }
+                let packet = encoder.encode(&frame);
+                match whole.apply(&packet) {
+                    Ok(_) => {
+                        frames.send_replace(Some(Captured {
+                            frame,
+                            packet,
+                            whole: whole.clone(),
+                        }));
+                    }
+                    Err(ex) => warn!("Could not add a frame to the snapshot: {ex}"),
+                }
+            }
+            Err(ex) if ex.kind() == WouldBlock => {
+                thread::sleep(FRAME_POLL);
+                continue;
+            }
+            Err(ex) => {
+                warn!("Stopped capturing the display: {ex}");
+                return;
+            }
+        }
+
+        next += Duration::from_secs(1) / frame_rate;
+        match next.checked_duration_since(Instant::now()) {
+            Some(wait) => thread::sleep(wait),
+            // Capturing is running behind, don't try to catch up
+            None => next = Instant::now(),
+        }
+    }
+}
+
+/// Start capturing the primary display as much as [`Screen::demand`] says, at up to
+/// [`Config::frame_rate`] encoded with [`Config::encoding`]. JPEGs and H.264 are kept to
+/// [`Config::bitrate`]
+pub fn capture_screen(config: &Config) -> Screen {
+    let frame_rate = config.frame_rate.unwrap_or(DEFAULT_FRAME_RATE).max(1);
+    let bitrate = config
+        .bitrate
+        .unwrap_or(DEFAULT_BITRATE)
+        .saturating_mul(1000);
+    let mut encoder: Box<dyn Encoder> = match config.encoding.unwrap_or(Encoding::Tiles) {
+        Encoding::Raw => Box::new(RawEncoder),
+        Encoding::Jpeg => Box::new(JpegEncoder::new(bitrate, frame_rate)),
+        Encoding::Tiles => Box::new(TileEncoder::new()),
+        Encoding::H264 => Box::new(H264Encoder::new(bitrate, frame_rate)),
+    };
+    let mut whole = Snapshot::new();
+    let (tx, frames) = watch::channel(None);
+    let (demand, mut demanded) = watch::channel(Demand::None);
+
+    thread::spawn(move || {
+        while wait_for_demand(&mut demanded) {
+            capture(&tx, &demanded, encoder.as_mut(), &mut whole, frame_rate);
+
+            // Displays come and go with logins and resolution changes, so keep trying
+            if *demanded.borrow() != Demand::None {
+                thread::sleep(RETRY_DELAY);
+            }
+        }
+    });
+
+    Screen { frames, demand }
+}
@@ -0,0 +1,122 @@
+//! Getting a client certificate from the server with a one-time enrollment token
+
+use crate::config::{config_path, Config};
+use birdseye_common::backend::{EnrollRequest, EnrollResponse};
+use birdseye_common::Machine;
+use rcgen::{Certificate, CertificateParams, DnType};
+use std::error::Error;
+use std::fs::{read, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use tokio::net::lookup_host;
+use tracing::{info, warn};
+
+type EnrollError = Box<dyn Error + Send + Sync>;
+
+/// Name of the file an enrolled monitor keeps its certificate in, next to the config file
+const CLIENT_CERT: &str = "client.pem";
+
+/// Name of the file an enrolled monitor keeps its private key in, next to the config file
+const CLIENT_KEY: &str = "client-key.pem";
+
+/// Write a file that only the monitor's user can read
+fn write_private(path: &Path, contents: &str) -> std::io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    options.open(path)?.write_all(contents.as_bytes())
+}
+
+async fn request_certificate(
+    config: &Config,
+    machine: &Machine,
+    token: &str,
+) -> Result<(String, String), EnrollError> {
+    let server = &config.server;
+
+    // The key is made here and only the signing request is sent, so the key never leaves this
+    // machine
+    let mut params = CertificateParams::new(vec![]);
+    params
+        .distinguished_name
+        .push(DnType::CommonName, machine.id().as_str());
+    let key = Certificate::from_params(params)?;
+
+    // Connect to the host, but validate the certificate against the domain if there is one
+    let domain = server.domain.as_deref().unwrap_or(&server.host);
+    let addr = lookup_host((server.host.as_str(), server.port))
+        .await?
+        .next()
+        .ok_or_else(|| format!("Could not resolve {}", server.host))?;
+
+    let mut client = reqwest::Client::builder()
+        .https_only(true)
+        .resolve(domain, addr);
+    if let Some(ca_cert) = &config.ca_cert {
+        client = client.add_root_certificate(reqwest::Certificate::from_pem(&read(ca_cert)?)?);
+    }
+
+    let response: EnrollResponse = client
+        .build()?
+        .post(format!("https://{domain}:{}/enroll", server.port))
+        .json(&EnrollRequest {
+            token: token.to_string(),
+            machine: machine.id().clone(),
+            csr: key.serialize_request_pem()?,
+        })
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    Ok((response.certificate, key.serialize_private_key_pem()))
+}
+
+/// Make sure the monitor has a client certificate if it can get one. A certificate from an
+/// earlier enrollment is used if there is one, otherwise the enrollment token is swapped for a new
+/// certificate
+pub async fn enroll(config: &mut Config, machine: &Machine) {
+    if config.client_cert.is_some() || config.client_key.is_some() {
+        return;
+    }
+
+    let dir = config_path()
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+    let (cert_path, key_path) = (dir.join(CLIENT_CERT), dir.join(CLIENT_KEY));
+    if cert_path.exists() && key_path.exists() {
+        config.client_cert = Some(cert_path);
+        config.client_key = Some(key_path);
+        return;
+    }
+
+    let token = match &config.enrollment_token {
+        Some(token) => token,
+        None => return,
+    };
+
+    let (cert, key) = match request_certificate(config, machine, token).await {
+        Ok(issued) => issued,
+        Err(ex) => {
+            warn!("Could not enroll with the server: {ex}");
+            return;
+        }
+    };
+
+    if let Err(ex) = write_private(&key_path, &key) {
+        warn!("Could not save {}: {ex}", key_path.display());
+        return;
+    }
+    if let Err(ex) = write_private(&cert_path, &cert) {
+        warn!("Could not save {}: {ex}", cert_path.display());
+        return;
+    }
+
+    info!("Enrolled as {}", machine.id());
+    config.client_cert = Some(cert_path);
+    config.client_key = Some(key_path);
+}
@@ -0,0 +1,220 @@
+//! Killing processes for the dashboard and the blacklist
+//!
+//! Processes are asked to exit first and only killed once they've had [`Config::kill_grace`] to
+//! do it. Critical system processes, the ones in [`Config::protected`] and the monitor itself are
+//! never signalled, and neither is a process that has exited and had its pid given to another one.
+
+use crate::client::process::Processes;
+use crate::config::Config;
+use crate::platform::is_critical_process;
+use birdseye_common::command::CommandError;
+use birdseye_common::Process;
+use std::sync::Arc;
+use std::time::Duration;
+use sysinfo::{Pid, PidExt, ProcessExt, ProcessStatus, Signal, System, SystemExt};
+use tokio::time::{sleep, Instant};
+use tracing::{debug, info};
+
+/// How long processes get to exit if the config doesn't say
+const DEFAULT_GRACE: Duration = Duration::from_secs(5);
+
+/// How often to check whether processes have exited after being asked to
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A process that is going to be signalled, as it was when the kill started
+struct Target {
+    pid: Pid,
+    parent: Option<u32>,
+    start_time: u64,
+    name: String,
+}
+
+impl From<&sysinfo::Process> for Target {
+    fn from(process: &sysinfo::Process) -> Self {
+        Self {
+            pid: process.pid(),
+            parent: process.parent().map(|parent| parent.as_u32()),
+            start_time: process.start_time(),
+            name: process.name().to_string(),
+        }
+    }
+}
+
+impl Target {
+    /// Get the process, as long as it is the same one and not a new one given the same pid
+    fn find<'a>(&self, sys: &'a System) -> Option<&'a sysinfo::Process> {
+        sys.process(self.pid)
+            .filter(|process| process.start_time() == self.start_time)
+    }
+
+    /// Whether the process still has to exit, zombies have and are just waiting on their parent
+    fn is_running(&self, sys: &System) -> bool {
+        self.find(sys)
+            .filter(|process| process.status() != ProcessStatus::Zombie)
+            .is_some()
+    }
+
+    /// Send the process a signal, does nothing if it has already exited
+    fn send(&self, sys: &System, signal: Signal) -> Result<(), CommandError> {
+        match self.find(sys).map(|process| process.kill_with(signal)) {
+            None | Some(Some(true)) => Ok(()),
+            Some(Some(false)) => Err(CommandError::Failed(format!(
+                "could not send {signal:?} to {} ({})",
+                self.name, self.pid
+            ))),
+            Some(None) => Err(CommandError::Unsupported),
+        }
+    }
+}
+
+/// Kills processes, keeping the ones the machine needs safe
+#[derive(Clone)]
+pub struct Killer {
+    grace: Duration,
+    protected: Arc<Vec<String>>,
+}
+
+impl Killer {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            grace: config.kill_grace.map_or(DEFAULT_GRACE, Duration::from_secs),
+            protected: Arc::new(config.protected.clone()),
+        }
+    }
+
+    /// Refuse to touch the monitor, critical system processes and the ones protected in the config
+    pub fn check(&self, pid: u32, parent_pid: Option<u32>, name: &str) -> Result<(), CommandError> {
+        if pid == std::process::id() {
+            return Err(CommandError::PermissionDenied(
+                "the monitor can't be killed".into(),
+            ));
+        }
+
+        if is_critical_process(pid, parent_pid, name)
+            || self.protected.iter().any(|protected| protected == name)
+        {
+            return Err(CommandError::PermissionDenied(format!(
+                "{name} ({pid}) is a protected process"
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Ask a process the monitor has seen to exit, and all of its children along with it if `tree`
+    /// is set, killing any that are still running once the grace period is up. `start_time` is
+    /// the start time the dashboard was shown, if it knows it
+    pub async fn kill(
+        &self,
+        processes: &Processes,
+        pid: u32,
+        start_time: Option<u64>,
+        tree: bool,
+    ) -> Result<(), CommandError> {
+        let process = processes.find(pid, start_time)?;
+
+        let mut sys = System::new();
+        sys.refresh_processes();
+
+        let root = sys
+            .process(Pid::from_u32(pid))
+            .filter(|running| running.start_time() == process.start_time())
+            .map(Target::from)
+            .ok_or(CommandError::ProcessNotFound(pid))?;
+
+        // Parents always come before their children
+        let mut targets = vec![root];
+        let mut i = 0;
+        while tree && i < targets.len() {
+            let parent = targets[i].pid;
+            targets.extend(
+                sys.processes()
+                    .values()
+                    .filter(|child| child.parent() == Some(parent))
+                    .map(Target::from),
+            );
+            i += 1;
+        }
+
+        // Nothing is signalled unless everything can be
+        for target in &targets {
+            self.check(target.pid.as_u32(), target.parent, &target.name)?;
+        }
+
+        info!(
+            "Killing {} ({pid}) and {} children",
+            process.name(),
+            targets.len() - 1
+        );
+
+        // Children go first so they don't get moved to another parent while theirs is exiting
+        for target in targets.iter().rev() {
+            match target.send(&sys, Signal::Term) {
+                // Windows can only kill
+                Err(CommandError::Unsupported) => target.send(&sys, Signal::Kill)?,
+                result => result?,
+            }
+        }
+
+        let deadline = Instant::now() + self.grace;
+        loop {
+            targets.retain(|target| sys.refresh_process(target.pid) && target.is_running(&sys));
+            if targets.is_empty() {
+                return Ok(());
+            }
+
+            if Instant::now() >= deadline {
+                break;
+            }
+            sleep(POLL_INTERVAL).await;
+        }
+
+        debug!(
+            "{} processes were still running after {}s, killing them",
+            targets.len(),
+            self.grace.as_secs()
+        );
+        for target in targets.iter().rev() {
+            target.send(&sys, Signal::Kill)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Find a process that is still running, as long as its pid hasn't been reused by another process
+/// since it was seen
+fn find_running(process: &Process, sys: &mut System) -> Result<(), CommandError> {
+    let pid = Pid::from_u32(*process.pid());
+
+    match sys.refresh_process(pid) {
+        true => sys.process(pid),
+        false => None,
+    }
+    .filter(|running| running.start_time() == process.start_time())
+    .map(|_| ())
+    .ok_or(CommandError::ProcessNotFound(*process.pid()))
+}
+
+/// Make sure a process is still running, and is the same one that was seen
+pub fn check_running(process: &Process) -> Result<(), CommandError> {
+    find_running(process, &mut System::new())
+}
+
+/// Send a signal to a process, as long as its pid hasn't been reused by another process since it
+/// was seen
+pub fn signal(process: &Process, signal: Signal) -> Result<(), CommandError> {
+    let mut sys = System::new();
+    find_running(process, &mut sys)?;
+    let running = sys
+        .process(Pid::from_u32(*process.pid()))
+        .ok_or(CommandError::ProcessNotFound(*process.pid()))?;
+
+    match running.kill_with(signal) {
+        Some(true) => Ok(()),
+        Some(false) => Err(CommandError::Failed(format!(
+            "could not send {signal:?} to the process"
+        ))),
+        None => Err(CommandError::Unsupported),
+    }
+}
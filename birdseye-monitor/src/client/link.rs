@@ -0,0 +1,434 @@
+//! The connection from the monitor to the BirdsEye server
+
+use crate::client::capture::{Captured, Screen};
+use crate::client::policy::Enforcer;
+use crate::client::process::Processes;
+use crate::client::restrict::Restrictions;
+use crate::config::Config;
+use birdseye_common::backend::{CommandResult, MonitorMessage, ServerMessage, HEARTBEAT_INTERVAL};
+use birdseye_common::codec::Codec;
+use birdseye_common::command::{Command, CommandError};
+use birdseye_common::handshake::{Capability, Hello, Negotiated};
+use birdseye_common::policy::Violation;
+use birdseye_common::screen::tiles::TileHeader;
+use birdseye_common::screen::{Demand, Encoding, Packet};
+use birdseye_common::sync::{ProcessStatus, ProcessSync, ProcessUpdate, Sequence};
+use birdseye_common::Machine;
+use futures::{SinkExt, StreamExt};
+use rustls::internal::pemfile::{certs, pkcs8_private_keys, rsa_private_keys};
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio::time::{interval, sleep};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{client_async_tls_with_config, Connector, MaybeTlsStream, WebSocketStream};
+use tracing::{debug, info, warn};
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+type LinkError = Box<dyn Error + Send + Sync>;
+
+/// Longest time to wait between attempts to connect to the server
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Load a private key, in either PKCS8 or RSA format
+fn load_key(path: &Path) -> Result<rustls::PrivateKey, LinkError> {
+    let mut key = vec![];
+    File::open(path)?.read_to_end(&mut key)?;
+
+    let mut keys = pkcs8_private_keys(&mut key.as_slice()).unwrap_or_default();
+    if keys.is_empty() {
+        keys = rsa_private_keys(&mut key.as_slice()).unwrap_or_default();
+    }
+
+    keys.into_iter()
+        .next()
+        .ok_or_else(|| format!("Could not read a private key from {}", path.display()).into())
+}
+
+/// Build the TLS config used to connect to the server, trusting the `ca_cert` from the config on
+/// top of the usual web roots and presenting the client certificate if there is one
+fn tls_config(config: &Config) -> Result<rustls::ClientConfig, LinkError> {
+    let mut tls = rustls::ClientConfig::new();
+    tls.root_store
+        .add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS);
+
+    if let Some(ca_cert) = &config.ca_cert {
+        let mut reader = BufReader::new(File::open(ca_cert)?);
+        tls.root_store
+            .add_pem_file(&mut reader)
+            .map_err(|_| format!("Could not read certificates from {}", ca_cert.display()))?;
+    }
+
+    match (&config.client_cert, &config.client_key) {
+        (Some(client_cert), Some(client_key)) => {
+            let mut reader = BufReader::new(File::open(client_cert)?);
+            let certs = certs(&mut reader).map_err(|_| {
+                format!("Could not read certificates from {}", client_cert.display())
+            })?;
+            tls.set_single_client_cert(certs, load_key(client_key)?)?;
+        }
+        (None, None) => {}
+        _ => return Err("client_cert and client_key must be set together".into()),
+    }
+
+    Ok(tls)
+}
+
+async fn connect(config: &Config) -> Result<Socket, LinkError> {
+    let server = &config.server;
+
+    // Connect to the host, but validate the certificate against the domain if there is one
+    let domain = server.domain.as_deref().unwrap_or(&server.host);
+    let url = format!("wss://{domain}:{}/monitor", server.port);
+
+    let tcp = TcpStream::connect((server.host.as_str(), server.port)).await?;
+    let connector = Connector::Rustls(Arc::new(tls_config(config)?));
+    let (socket, _) = client_async_tls_with_config(url, tcp, None, Some(connector)).await?;
+
+    Ok(socket)
+}
+
+async fn send(socket: &mut Socket, msg: &MonitorMessage) -> Result<(), LinkError> {
+    let bytes = Codec::Bincode.encode(msg)?;
+    socket.send(Message::Binary(bytes)).await?;
+    Ok(())
+}
+
+/// Wait for the next message from the server, `None` if the server has closed the connection
+async fn recv(socket: &mut Socket) -> Result<Option<ServerMessage>, LinkError> {
+    while let Some(msg) = socket.next().await {
+        match msg? {
+            Message::Binary(bytes) => return Ok(Some(Codec::Bincode.decode(&bytes)?)),
+            Message::Close(_) => return Ok(None),
+            _ => continue,
+        }
+    }
+
+    Ok(None)
+}
+
+/// Swap hellos with the server and tell it which machine we are
+async fn handshake(socket: &mut Socket, machine: &Machine) -> Result<Negotiated, LinkError> {
+    let mut capabilities = vec![Capability::CaptureScreen, Capability::KillProcesses];
+    if machine.displays().len() > 1 {
+        capabilities.push(Capability::MultiDisplay);
+    }
+
+    let hello = Hello::new(
+        concat!("birdseye-monitor/", env!("CARGO_PKG_VERSION")),
+        capabilities,
+    );
+    send(
+        socket,
+        &MonitorMessage::Hello {
+            hello: hello.clone(),
+            hostname: machine.hostname().to_string(),
+        },
+    )
+    .await?;
+
+    let negotiated = match recv(socket).await? {
+        Some(ServerMessage::Hello(server_hello)) => hello.negotiate(&server_hello)?,
+        Some(ServerMessage::Rejected(reason)) => {
+            return Err(format!("Server rejected monitor: {reason}").into())
+        }
+        Some(msg) => return Err(format!("Expected hello from server, got {msg:?}").into()),
+        None => return Err("Server closed connection during handshake".into()),
+    };
+
+    send(socket, &MonitorMessage::Machine(machine.clone())).await?;
+
+    Ok(negotiated)
+}
+
+/// Send the whole process list to the server, returns the sequence number of the snapshot
+async fn send_snapshot(
+    socket: &mut Socket,
+    negotiated: &Negotiated,
+    processes: &Processes,
+) -> Result<Sequence, LinkError> {
+    let snapshot = processes.snapshot();
+    let seq = snapshot.seq();
+
+    if negotiated.version() >= 4 {
+        send(
+            socket,
+            &MonitorMessage::Processes(ProcessSync::Snapshot(snapshot)),
+        )
+        .await?;
+    } else {
+        for process in snapshot.processes() {
+            send(socket, &MonitorMessage::ProcessStarted(process.clone())).await?;
+        }
+    }
+
+    Ok(seq)
+}
+
+async fn send_update(
+    socket: &mut Socket,
+    negotiated: &Negotiated,
+    update: ProcessUpdate,
+) -> Result<(), LinkError> {
+    let msg = if negotiated.version() >= 4 {
+        MonitorMessage::Processes(ProcessSync::Update(update))
+    } else {
+        match update.status().clone() {
+            ProcessStatus::Start(process) => MonitorMessage::ProcessStarted(process),
+            ProcessStatus::Stop(process) => MonitorMessage::ProcessStopped(process),
+        }
+    };
+
+    send(socket, &msg).await
+}
+
+async fn send_restrictions(
+    socket: &mut Socket,
+    restrictions: &Restrictions,
+) -> Result<(), LinkError> {
+    send(socket, &MonitorMessage::Restrictions(restrictions.list())).await
+}
+
+/// Run a command sent by the server
+async fn run_command(
+    command: Command,
+    processes: Processes,
+    enforcer: Enforcer,
+) -> Result<(), CommandError> {
+    let killer = enforcer.killer();
+    let restrictions = enforcer.restrictions();
+    let locker = enforcer.locker();
+
+    match command {
+        Command::KillProcess { pid } => killer.kill(&processes, pid, None, false).await,
+        Command::Kill {
+            pid,
+            start_time,
+            tree,
+        } => killer.kill(&processes, pid, Some(start_time), tree).await,
+        Command::Suspend { pid, start_time } => {
+            restrictions.suspend(&processes.find(pid, Some(start_time))?)
+        }
+        Command::Resume { pid, start_time } => {
+            restrictions.resume(&processes.find(pid, Some(start_time))?)
+        }
+        Command::Throttle {
+            pid,
+            start_time,
+            percent,
+        } => restrictions.throttle(&processes.find(pid, Some(start_time))?, percent),
+        Command::Lock => locker.lock(),
+        Command::Unlock => locker.unlock(),
+        Command::ShowMessage(text) => locker.show_message(&text),
+    }
+}
+
+/// Pick what to send the server for a captured frame. Servers older than protocol version 11 get
+/// the raw frame, as do ones older than 12 for tiles and 14 for H.264. Tiles and H.264 that don't
+/// follow on from the last ones sent are swapped for the whole screen, returns `None` if there is
+/// nothing new to send
+fn frame_message(
+    negotiated: &Negotiated,
+    captured: Captured,
+    sent: &mut Option<u64>,
+) -> Option<MonitorMessage> {
+    let Captured {
+        frame,
+        packet,
+        whole,
+    } = captured;
+
+    let supported = match packet.encoding() {
+        Encoding::Raw | Encoding::Jpeg => 11,
+        Encoding::Tiles => 12,
+        Encoding::H264 => 14,
+    };
+    if negotiated.version() < supported {
+        return Some(match negotiated.version() >= 11 {
+            true => MonitorMessage::Packet(Packet::from(frame)),
+            false => MonitorMessage::Frame(frame),
+        });
+    }
+    if !matches!(packet.encoding(), Encoding::Tiles | Encoding::H264) {
+        return Some(MonitorMessage::Packet(packet));
+    }
+
+    let header = TileHeader::read(&packet).ok()?;
+    if !header.keyframe() && sent.is_some_and(|sent| header.sequence() <= sent) {
+        return None;
+    }
+
+    let packet = match header.keyframe() || Some(header.base()) == *sent {
+        true => packet,
+        false => whole.packet()?,
+    };
+    *sent = TileHeader::read(&packet)
+        .ok()
+        .map(|header| header.sequence());
+    Some(MonitorMessage::Packet(packet))
+}
+
+/// Run a single connection to the server until it closes
+async fn run_connection(
+    config: &Config,
+    machine: &Machine,
+    processes: &Processes,
+    updates: &mut mpsc::Receiver<ProcessUpdate>,
+    enforcer: &Enforcer,
+    violations: &mut mpsc::Receiver<Violation>,
+    screen: &mut Screen,
+) -> Result<(), LinkError> {
+    let mut socket = connect(config).await?;
+    let negotiated = handshake(&mut socket, machine).await?;
+
+    info!(
+        "Connected to {} using protocol version {}",
+        negotiated.agent(),
+        negotiated.version()
+    );
+
+    // Servers from protocol version 13 say how much of the screen they need, older ones want it
+    // all. Servers older than 10 don't take frames at all
+    match negotiated.version() {
+        13.. => {}
+        10..=12 => {
+            screen.demand.send_replace(Demand::Full);
+        }
+        _ => {
+            screen.demand.send_replace(Demand::None);
+        }
+    }
+
+    let mut last_seq = send_snapshot(&mut socket, &negotiated, processes).await?;
+    let restrictions = enforcer.restrictions();
+    if negotiated.version() >= 9 {
+        send_restrictions(&mut socket, restrictions).await?;
+    }
+    let mut heartbeat = interval(HEARTBEAT_INTERVAL);
+    // Commands can take a while, killing waits for processes to exit, so they are run in the
+    // background and their results sent once they're done
+    let (results_tx, mut results) = mpsc::channel(16);
+    // The sequence of the last tiles sent, the server starts with nothing so needs a keyframe
+    let mut sent_tiles = None;
+
+    loop {
+        tokio::select! {
+            _ = heartbeat.tick() => send(&mut socket, &MonitorMessage::Heartbeat).await?,
+            update = updates.recv() => {
+                let update = match update {
+                    Some(update) => update,
+                    None => return Ok(()),
+                };
+
+                if update.seq() <= last_seq {
+                    // Already covered by the last snapshot
+                    continue;
+                } else if update.seq() == last_seq + 1 {
+                    last_seq = update.seq();
+                    send_update(&mut socket, &negotiated, update).await?;
+                } else {
+                    // Updates were dropped while we were busy, start again from a snapshot
+                    debug!("Missed process updates, sending snapshot");
+                    last_seq = send_snapshot(&mut socket, &negotiated, processes).await?;
+                }
+            }
+            // Only servers that sent rules get violations, and only servers from protocol version 7
+            // send rules
+            Some(violation) = violations.recv() => {
+                send(&mut socket, &MonitorMessage::PolicyViolation(violation)).await?;
+            }
+            _ = restrictions.changed(), if negotiated.version() >= 9 => {
+                send_restrictions(&mut socket, restrictions).await?;
+            }
+            // Sending a frame holds up the link, anything captured meanwhile is replaced by the
+            // newest frame
+            Ok(()) = screen.frames.changed(), if negotiated.version() >= 10 => {
+                let captured = screen.frames.borrow().clone();
+                if let Some(captured) = captured {
+                    if let Some(msg) = frame_message(&negotiated, captured, &mut sent_tiles) {
+                        send(&mut socket, &msg).await?;
+                    }
+                }
+            }
+            Some((id, result)) = results.recv() => {
+                send(&mut socket, &MonitorMessage::CommandResult { id, result }).await?;
+            }
+            msg = recv(&mut socket) => match msg? {
+                Some(ServerMessage::Command { id, command }) => {
+                    let (results, processes, enforcer) =
+                        (results_tx.clone(), processes.clone(), enforcer.clone());
+                    tokio::spawn(async move {
+                        let result = CommandResult::from(run_command(command, processes, enforcer).await);
+                        let _ = results.send((id, result)).await;
+                    });
+                }
+                Some(ServerMessage::ResyncProcesses) => {
+                    last_seq = send_snapshot(&mut socket, &negotiated, processes).await?;
+                }
+                Some(ServerMessage::Policy(rules)) => {
+                    // Processes that were already running are held to new rules too
+                    if enforcer.set_rules(rules) {
+                        for process in processes.snapshot().processes() {
+                            enforcer.check(process);
+                        }
+                    }
+                }
+                Some(ServerMessage::Demand(wanted)) => {
+                    debug!("Server needs {wanted:?} of the screen");
+                    screen.demand.send_replace(wanted);
+                }
+                Some(ServerMessage::Rejected(reason)) => {
+                    return Err(format!("Server rejected monitor: {reason}").into())
+                }
+                Some(msg) => warn!("Unexpected message from server {msg:?}"),
+                None => return Ok(()),
+            }
+        }
+    }
+}
+
+/// Keep a connection to the server open for as long as the monitor is running, reconnecting with
+/// an increasing delay whenever it drops
+pub async fn run_link(
+    config: Config,
+    machine: Machine,
+    processes: Processes,
+    mut updates: mpsc::Receiver<ProcessUpdate>,
+    enforcer: Enforcer,
+    mut violations: mpsc::Receiver<Violation>,
+    mut screen: Screen,
+) {
+    let mut backoff = Duration::from_secs(1);
+
+    loop {
+        let result = run_connection(
+            &config,
+            &machine,
+            &processes,
+            &mut updates,
+            &enforcer,
+            &mut violations,
+            &mut screen,
+        )
+        .await;
+        // Nobody can see the screen without a server
+        screen.demand.send_replace(Demand::None);
+
+        match result {
+            Ok(()) => {
+                info!("Connection to server closed");
+                backoff = Duration::from_secs(1);
+            }
+            Err(err) => warn!("Connection to server failed: {err}"),
+        }
+
+        info!("Reconnecting in {}s", backoff.as_secs());
+        sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
@@ -0,0 +1,95 @@
+//! Locking the screen and showing messages on it, for the dashboard and the timetable
+//!
+//! Students can unlock a locked screen with their own password, so while the server wants it
+//! locked the screen is locked again every [`RELOCK_INTERVAL`]. The screen stays locked while the
+//! link is down, only the server unlocks it, which it does when the monitor reconnects if the lock
+//! ended in the meantime. Only a lock the monitor made is ever undone, a screen the student locked
+//! themselves is left alone.
+
+use crate::platform::{lock_screen, show_message, unlock_screen};
+use birdseye_common::command::CommandError;
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time::sleep;
+use tracing::{info, warn};
+
+/// How often the screen is locked again while it should be
+const RELOCK_INTERVAL: Duration = Duration::from_secs(5);
+
+fn screen_error(what: &str, ex: io::Error) -> CommandError {
+    match ex.kind() {
+        io::ErrorKind::Unsupported | io::ErrorKind::NotFound => CommandError::Unsupported,
+        _ => CommandError::Failed(format!("could not {what}: {ex}")),
+    }
+}
+
+/// Keep the screen locked until told otherwise
+async fn relock(mut locked: watch::Receiver<bool>) {
+    loop {
+        while !*locked.borrow_and_update() {
+            if locked.changed().await.is_err() {
+                return;
+            }
+        }
+
+        sleep(RELOCK_INTERVAL).await;
+        if *locked.borrow() {
+            if let Err(ex) = lock_screen() {
+                warn!("Could not lock the screen again: {ex}");
+            }
+        }
+    }
+}
+
+/// Whether the monitor has locked the screen, has to be created inside the runtime
+#[derive(Clone)]
+pub struct Locker {
+    locked: Arc<watch::Sender<bool>>,
+}
+
+impl Default for Locker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Locker {
+    pub fn new() -> Self {
+        let (locked, rx) = watch::channel(false);
+        tokio::spawn(relock(rx));
+
+        Self {
+            locked: Arc::new(locked),
+        }
+    }
+
+    /// Lock the screen, and keep it locked until [`Locker::unlock`]
+    pub fn lock(&self) -> Result<(), CommandError> {
+        lock_screen().map_err(|ex| screen_error("lock the screen", ex))?;
+
+        if !self.locked.send_replace(true) {
+            info!("Locked the screen");
+        }
+        Ok(())
+    }
+
+    /// Undo [`Locker::lock`], does nothing if the monitor didn't lock the screen
+    pub fn unlock(&self) -> Result<(), CommandError> {
+        if !self.locked.send_replace(false) {
+            return Ok(());
+        }
+
+        info!("Unlocked the screen");
+        unlock_screen().map_err(|ex| screen_error("unlock the screen", ex))
+    }
+
+    /// Show a message to whoever is using the machine
+    pub fn show_message(&self, text: &str) -> Result<(), CommandError> {
+        show_message(text).map_err(|ex| screen_error("show the message", ex))?;
+
+        info!("Showed message: {text}");
+        Ok(())
+    }
+}
@@ -0,0 +1,82 @@
+use crate::platform::get_machine_id;
+use birdseye_common::machine::{Display, NetworkInterface};
+use birdseye_common::{Machine, MachineId};
+use network_interface::NetworkInterfaceConfig;
+use sysinfo::{System, SystemExt};
+use tracing::warn;
+
+/// Get the network interfaces of the machine, skipping loopback interfaces
+fn get_interfaces() -> Vec<NetworkInterface> {
+    let interfaces = match network_interface::NetworkInterface::show() {
+        Ok(interfaces) => interfaces,
+        Err(err) => {
+            warn!("Could not list network interfaces {err}");
+            return vec![];
+        }
+    };
+
+    interfaces
+        .iter()
+        .map(|interface| {
+            let addresses = interface
+                .addr
+                .iter()
+                .map(|addr| addr.ip())
+                .filter(|ip| !ip.is_loopback())
+                .collect::<Vec<_>>();
+
+            NetworkInterface::new(&interface.name, interface.mac_addr.as_deref(), addresses)
+        })
+        .filter(|interface| !interface.addresses().is_empty())
+        .collect()
+}
+
+/// Get the displays attached to the machine, the primary display is always first
+fn get_displays() -> Vec<Display> {
+    let primary = scrap::Display::primary()
+        .map(|display| (display.width(), display.height()))
+        .ok();
+
+    let mut displays = primary.into_iter().collect::<Vec<_>>();
+
+    // scrap offers no way to compare displays, so assume the primary is the first with its size
+    let mut skipped_primary = false;
+    for display in scrap::Display::all().unwrap_or_default() {
+        let size = (display.width(), display.height());
+        if !skipped_primary && Some(size) == primary {
+            skipped_primary = true;
+            continue;
+        }
+        displays.push(size);
+    }
+
+    displays
+        .into_iter()
+        .enumerate()
+        .map(|(index, (width, height))| Display::new(index as u32, width as u32, height as u32))
+        .collect()
+}
+
+/// Collect the inventory information about this machine that is sent to the server
+pub fn get_machine() -> Machine {
+    let mut sys = System::default();
+    sys.refresh_system();
+
+    let hostname = sys.host_name().unwrap_or_else(|| "unknown".into());
+
+    // Fall back to the hostname if there is no better id, this is unlikely to collide in a school
+    let id = get_machine_id().unwrap_or_else(|| {
+        warn!("Could not get a machine id, using the hostname instead");
+        hostname.clone()
+    });
+
+    Machine::new(MachineId::new(&id), &hostname, env!("CARGO_PKG_VERSION"))
+        .with_os(
+            &sys.name().unwrap_or_default(),
+            &sys.long_os_version()
+                .or_else(|| sys.os_version())
+                .unwrap_or_default(),
+        )
+        .with_interfaces(get_interfaces())
+        .with_displays(get_displays())
+}
@@ -1 +1,9 @@
+pub mod capture;
+pub mod enroll;
+pub mod kill;
+pub mod link;
+pub mod lock;
+pub mod machine;
+pub mod policy;
 pub mod process;
+pub mod restrict;
@@ -0,0 +1,132 @@
+//! Enforcing the application blacklist sent by the server
+
+use crate::client::kill::{signal, Killer};
+use crate::client::lock::Locker;
+use crate::client::restrict::Restrictions;
+use birdseye_common::command::CommandError;
+use birdseye_common::policy::{Action, CompiledPolicy, Rule, Violation};
+use birdseye_common::Process;
+use std::sync::{Arc, RwLock};
+use sysinfo::Signal;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+use tracing::{debug, info, warn};
+
+#[derive(Default)]
+struct Policy {
+    /// The rules as sent by the server, kept to tell if new rules are any different
+    rules: Vec<Rule>,
+    compiled: CompiledPolicy,
+}
+
+/// Checks processes against the rules from the server and carries out the action of any rule they
+/// break, violations are queued up to be sent to the server
+#[derive(Clone)]
+pub struct Enforcer {
+    policy: Arc<RwLock<Policy>>,
+    violations: mpsc::Sender<Violation>,
+    killer: Killer,
+    restrictions: Restrictions,
+    locker: Locker,
+}
+
+impl Enforcer {
+    pub fn new(killer: Killer) -> (Self, mpsc::Receiver<Violation>) {
+        let (violations, rx) = mpsc::channel(64);
+        let enforcer = Self {
+            policy: Arc::default(),
+            violations,
+            restrictions: Restrictions::new(killer.clone()),
+            killer,
+            locker: Locker::new(),
+        };
+
+        (enforcer, rx)
+    }
+
+    pub fn killer(&self) -> &Killer {
+        &self.killer
+    }
+
+    /// The processes suspended or throttled, by rules or by the dashboard
+    pub fn restrictions(&self) -> &Restrictions {
+        &self.restrictions
+    }
+
+    /// Whether the screen has been locked, by the dashboard or the timetable
+    pub fn locker(&self) -> &Locker {
+        &self.locker
+    }
+
+    /// Replace the rules, returns false if they are the same as before. The server checks rules
+    /// before saving them, but any that don't compile here are left out rather than dropping the
+    /// whole policy
+    pub fn set_rules(&self, rules: Vec<Rule>) -> bool {
+        let mut policy = self.policy.write().unwrap();
+        if policy.rules == rules {
+            return false;
+        }
+
+        let valid = rules
+            .iter()
+            .filter(|rule| {
+                let compiled = CompiledPolicy::compile(vec![(*rule).clone()]);
+                if let Err(ex) = &compiled {
+                    warn!("Ignoring rule from server: {ex}");
+                }
+                compiled.is_ok()
+            })
+            .cloned()
+            .collect();
+
+        // Every rule that is left compiled on its own, so they compile together
+        policy.compiled = CompiledPolicy::compile(valid).unwrap_or_default();
+        policy.rules = rules;
+        info!("Enforcing {} rules", policy.rules.len());
+
+        true
+    }
+
+    /// Check a process against the rules, carrying out the action of the rule it breaks if any
+    pub fn check(&self, process: &Process) {
+        let rule = match self.policy.read().unwrap().compiled.check(process) {
+            Some(rule) => rule.clone(),
+            None => return,
+        };
+
+        let error = match rule.action() {
+            Action::Alert => None,
+            Action::Suspend => self.restrictions.suspend(process).err(),
+            Action::Kill => self.kill(process).err(),
+            Action::Throttle(percent) => self.restrictions.throttle(process, percent).err(),
+        };
+
+        info!(
+            "{} ({}) broke rule {}, action: {}",
+            process.name(),
+            process.pid(),
+            rule.name(),
+            rule.action()
+        );
+
+        match self
+            .violations
+            .try_send(Violation::new(&rule, process.clone(), error))
+        {
+            Ok(()) | Err(TrySendError::Closed(_)) => {}
+            Err(TrySendError::Full(_)) => {
+                debug!(
+                    "Dropped violation of rule {}, the server is behind",
+                    rule.name()
+                )
+            }
+        }
+    }
+
+    /// Kill a process that broke a rule straight away, unless it is one the machine needs
+    fn kill(&self, process: &Process) -> Result<(), CommandError> {
+        self.killer
+            .check(*process.pid(), process.parent_pid(), process.name())?;
+        signal(process, Signal::Kill)
+    }
+}
@@ -1,7 +1,15 @@
+use crate::client::policy::Enforcer;
+use crate::platform::get_process_session;
+use birdseye_common::command::CommandError;
+use birdseye_common::sync::{ProcessList, ProcessSnapshot, ProcessStatus, ProcessUpdate};
 use birdseye_common::{Process, User};
-use std::collections::HashMap;
-use sysinfo::{PidExt, ProcessExt, System, SystemExt, UserExt};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use sysinfo::{Pid, PidExt, ProcessExt, System, SystemExt, UserExt};
 use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::time::interval;
+use tracing::debug;
 
 /// Function to convert sysinfo process to my process
 fn sysinfo_to_be_process(process: &sysinfo::Process, sys: &System) -> Process {
@@ -9,67 +17,136 @@ fn sysinfo_to_be_process(process: &sysinfo::Process, sys: &System) -> Process {
     let usr = process
         .user_id()
         .map(|id| {
-            sys.get_user_by_id(id)
+            let name = sys
+                .get_user_by_id(id)
                 .map(|user| user.name())
-                .unwrap_or("Unknown user")
+                .unwrap_or("Unknown user");
+
+            User::new(name).with_id(&(**id).to_string())
         })
-        .unwrap_or_else(|| "Unknown user");
+        .unwrap_or_else(|| User::new("Unknown user"));
 
     // Convert sysinfo's process to my process
-    Process::new(process.pid().as_u32(), process.name(), &User::new(usr))
+    let pid = process.pid().as_u32();
+    let mut be_process = Process::new(pid, process.name(), &usr)
+        .with_cmd(process.cmd())
+        .with_start_time(process.start_time())
+        // sysinfo reports memory in KB
+        .with_usage(process.cpu_usage(), process.memory() * 1024);
+
+    if let Some(exe) = process.exe().to_str().filter(|exe| !exe.is_empty()) {
+        be_process = be_process.with_exe(exe);
+    }
+
+    if let Some(parent) = process.parent() {
+        be_process = be_process.with_parent_pid(parent.as_u32());
+    }
+
+    if let Some(session) = get_process_session(pid) {
+        be_process = be_process.with_session(session);
+    }
+
+    be_process
 }
 
-/// Struct to represent weather or not a process has started or stopped
-/// Stores name and process id
-#[derive(Debug)]
-pub enum ProcessStatus {
-    Start(Process),
-    Stop(Process),
+/// Handle to the process list kept up to date by [`monitor_processes`]
+#[derive(Clone)]
+pub struct Processes(Arc<Mutex<ProcessList>>);
+
+impl Processes {
+    /// Get a snapshot of the process list, updates after this will have a higher sequence number
+    pub fn snapshot(&self) -> ProcessSnapshot {
+        self.0.lock().unwrap().snapshot()
+    }
+
+    /// Get a process the server was told about, `start_time` makes sure it is the one the
+    /// dashboard saw and not a later one given the same pid
+    pub fn find(&self, pid: u32, start_time: Option<u64>) -> Result<Process, CommandError> {
+        self.0
+            .lock()
+            .unwrap()
+            .get(pid)
+            .filter(|process| start_time.is_none_or(|start| start == process.start_time()))
+            .cloned()
+            .ok_or(CommandError::ProcessNotFound(pid))
+    }
 }
 
 /// Start a process to monitor the running processes on the system and notify over a tokio mpsc channel
-pub fn monitor_processes() -> mpsc::Receiver<ProcessStatus> {
-    let (tx, rx) = mpsc::channel(5);
+///
+/// Updates are dropped if the receiver falls behind rather than holding up the monitor, the
+/// receiver will see a gap in the sequence numbers and can send a new snapshot instead
+///
+/// Every process that starts is checked against the blacklist by `enforcer`, which is also told
+/// about every process that stops
+pub fn monitor_processes(enforcer: Enforcer) -> (Processes, mpsc::Receiver<ProcessUpdate>) {
+    let (tx, rx) = mpsc::channel(256);
+    let list = Processes(Arc::new(Mutex::new(ProcessList::new_source())));
 
+    let processes = list.clone();
     tokio::spawn(async move {
         let mut sys = System::default();
-        let mut processes = HashMap::new();
+        let mut interval = interval(Duration::from_secs(1));
 
         loop {
+            interval.tick().await;
+
             // Refresh sys struct to make sure all the things and stuff are up-to-date
             sys.refresh_processes();
             sys.refresh_users_list();
 
             let running_processes = sys.processes();
-            let processes2 = processes.clone();
+            let mut updates = vec![];
+            let mut processes = processes.0.lock().unwrap();
 
             // Find all process that have just started, store them and report back to system
-            for (pid, process) in running_processes
-                .iter()
-                .filter(|(key, _)| !processes2.contains_key(*key))
-            {
-                let process = sysinfo_to_be_process(process, &sys);
-
-                // Record information and send result
-                processes.insert(*pid, process.clone());
-                tx.send(ProcessStatus::Start(process)).await.unwrap();
-            }
+            let started = running_processes
+                .values()
+                .filter(|process| processes.get(process.pid().as_u32()).is_none())
+                .map(|process| sysinfo_to_be_process(process, &sys))
+                .collect::<Vec<_>>();
 
-            let processes2 = processes.clone();
+            for process in &started {
+                updates.push(processes.record(ProcessStatus::Start(process.clone())));
+            }
 
             // Find all process that have just stoped, store them and report back to system
-            for (pid, process) in processes2
-                .iter()
-                .filter(|(key, _)| !running_processes.contains_key(*key))
-            {
-                // Record information and send result
-                processes.remove(pid);
-                tx.send(ProcessStatus::Stop(process.clone())).await.unwrap();
+            let stopped = processes
+                .processes()
+                .filter(|process| !running_processes.contains_key(&Pid::from_u32(*process.pid())))
+                .cloned()
+                .collect::<Vec<_>>();
+
+            for process in &stopped {
+                updates.push(processes.record(ProcessStatus::Stop(process.clone())));
+            }
+
+            drop(processes);
+
+            for process in &stopped {
+                enforcer.restrictions().stopped(process);
+            }
+
+            for process in &started {
+                enforcer.check(process);
+            }
+
+            for update in updates {
+                match tx.try_send(update) {
+                    Ok(()) => {}
+                    Err(TrySendError::Full(update)) => {
+                        debug!(
+                            "Dropped process update {}, receiver is behind",
+                            update.seq()
+                        )
+                    }
+                    Err(TrySendError::Closed(_)) => return,
+                }
             }
         }
     });
 
-    rx
+    (list, rx)
 }
 
 /// Get all the processes running on the current system
@@ -79,7 +156,7 @@ pub fn get_all_processes() -> Vec<Process> {
     sys.refresh_processes();
     sys.refresh_users_list();
     sys.processes()
-        .iter()
-        .map(|(_, x)| sysinfo_to_be_process(x, &sys))
+        .values()
+        .map(|x| sysinfo_to_be_process(x, &sys))
         .collect()
 }
@@ -0,0 +1,161 @@
+//! Suspending and throttling processes, gentler than killing them as nothing the student was doing
+//! is lost
+//!
+//! The monitor remembers what it has done to each process so it can be undone, and tells the
+//! server every time that changes so the dashboard can show it. Resuming a process undoes both.
+
+use crate::client::kill::{check_running, signal, Killer};
+use crate::platform::{throttle_process, unthrottle_process};
+use birdseye_common::command::CommandError;
+use birdseye_common::sync::Restriction;
+use birdseye_common::Process;
+use std::collections::BTreeMap;
+use std::io;
+use std::sync::{Arc, Mutex};
+use sysinfo::Signal;
+use tokio::sync::Notify;
+use tracing::{info, warn};
+
+#[derive(Default)]
+struct Restricted {
+    start_time: u64,
+    suspended: bool,
+    /// The percentage of a CPU
+    throttle: Option<u8>,
+}
+
+fn cgroup_error(ex: io::Error) -> CommandError {
+    match ex.kind() {
+        io::ErrorKind::Unsupported => CommandError::Unsupported,
+        _ => CommandError::Failed(format!("could not change the process's cgroup: {ex}")),
+    }
+}
+
+/// Every process the monitor has suspended or throttled
+#[derive(Clone)]
+pub struct Restrictions {
+    killer: Killer,
+    processes: Arc<Mutex<BTreeMap<u32, Restricted>>>,
+    changed: Arc<Notify>,
+}
+
+impl Restrictions {
+    pub fn new(killer: Killer) -> Self {
+        Self {
+            killer,
+            processes: Arc::default(),
+            changed: Arc::default(),
+        }
+    }
+
+    pub fn list(&self) -> Vec<Restriction> {
+        self.processes
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(pid, restricted)| {
+                Restriction::new(*pid, restricted.start_time)
+                    .with_suspended(restricted.suspended)
+                    .with_throttle(restricted.throttle)
+            })
+            .collect()
+    }
+
+    /// Wait until a process is suspended, throttled, resumed or stops
+    pub async fn changed(&self) {
+        self.changed.notified().await
+    }
+
+    fn update(&self, process: &Process, f: impl FnOnce(&mut Restricted)) {
+        let mut processes = self.processes.lock().unwrap();
+        let restricted = processes.entry(*process.pid()).or_default();
+
+        // Whatever had the pid before has stopped
+        if restricted.start_time != process.start_time() {
+            *restricted = Restricted {
+                start_time: process.start_time(),
+                ..Restricted::default()
+            };
+        }
+
+        f(restricted);
+        if !restricted.suspended && restricted.throttle.is_none() {
+            processes.remove(process.pid());
+        }
+
+        self.changed.notify_one();
+    }
+
+    /// Stop a process with `SIGSTOP`
+    pub fn suspend(&self, process: &Process) -> Result<(), CommandError> {
+        self.killer
+            .check(*process.pid(), process.parent_pid(), process.name())?;
+        signal(process, Signal::Stop)?;
+
+        info!("Suspended {} ({})", process.name(), process.pid());
+        self.update(process, |restricted| restricted.suspended = true);
+        Ok(())
+    }
+
+    /// Limit a process to `percent` of one CPU, using a cgroup v2 CPU quota
+    pub fn throttle(&self, process: &Process, percent: u8) -> Result<(), CommandError> {
+        if !(1..=100).contains(&percent) {
+            return Err(CommandError::Failed(format!(
+                "can't throttle to {percent}%, it has to be between 1% and 100%"
+            )));
+        }
+        self.killer
+            .check(*process.pid(), process.parent_pid(), process.name())?;
+        check_running(process)?;
+
+        throttle_process(*process.pid(), percent).map_err(cgroup_error)?;
+
+        info!(
+            "Throttled {} ({}) to {percent}%",
+            process.name(),
+            process.pid()
+        );
+        self.update(process, |restricted| restricted.throttle = Some(percent));
+        Ok(())
+    }
+
+    /// Undo [`Restrictions::suspend`] and [`Restrictions::throttle`]. The process is sent
+    /// `SIGCONT` and moved back to its original cgroup even if the monitor doesn't know it
+    /// restricted it, so processes restricted before the monitor restarted can be resumed too
+    pub fn resume(&self, process: &Process) -> Result<(), CommandError> {
+        signal(process, Signal::Continue)?;
+        unthrottle_process(*process.pid()).map_err(cgroup_error)?;
+
+        info!("Resumed {} ({})", process.name(), process.pid());
+        self.update(process, |restricted| {
+            restricted.suspended = false;
+            restricted.throttle = None;
+        });
+        Ok(())
+    }
+
+    /// Forget a process that has stopped, removing its cgroup if it was throttled
+    pub fn stopped(&self, process: &Process) {
+        let removed = {
+            let mut processes = self.processes.lock().unwrap();
+            match processes.get(process.pid()) {
+                Some(restricted) if restricted.start_time == process.start_time() => {
+                    processes.remove(process.pid())
+                }
+                _ => None,
+            }
+        };
+
+        if let Some(restricted) = removed {
+            if restricted.throttle.is_some() {
+                if let Err(ex) = unthrottle_process(*process.pid()) {
+                    warn!(
+                        "Could not clean up after throttling {}: {ex}",
+                        process.pid()
+                    );
+                }
+            }
+            self.changed.notify_one();
+        }
+    }
+}
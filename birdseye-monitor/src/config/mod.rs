@@ -1,24 +1,41 @@
 mod server;
 
 use crate::config::server::ServerConfig;
+use birdseye_common::screen::Encoding;
 use serde::{Deserialize, Serialize};
 use std::env::args;
 use std::fs::read_to_string;
 use std::{env::var, path::PathBuf};
-use tracing::{warn};
+use tracing::warn;
 
 /// Configuration for the monitor application
 ///
 /// # Configuration
-/// | Field       | Environment Variable | Type             | Default           | Description                                              |
-/// |-------------|----------------------|------------------|-------------------|----------------------------------------------------------|
-/// | server_addr | SERVER_ADDR          | String           | "localhost:42069" | The address to the birdseye server                       |
-/// | ca_cert     | CA_CERT              | Option<PathBuff> | None              | Any additional CA Certificates to be used by application |
+/// | Field            | Environment Variable | Type             | Default           | Description                                                                                        |
+/// |------------------|----------------------|------------------|-------------------|----------------------------------------------------------------------------------------------------|
+/// | server_addr      | SERVER_ADDR          | String           | "localhost:42069" | The address to the birdseye server                                                                 |
+/// | ca_cert          | CA_CERT              | Option<PathBuff> | None              | Any additional CA Certificates to be used by application                                           |
+/// | client_cert      | CLIENT_CERT          | Option<PathBuf>  | None              | Certificate used to prove which machine this is to the server, must be set along with `client_key` |
+/// | client_key       | CLIENT_KEY           | Option<PathBuf>  | None              | Private key for `client_cert`                                                                      |
+/// | enrollment_token | ENROLLMENT_TOKEN     | Option<String>   | None              | One-time token from the server, used to get a client certificate if there isn't one yet            |
+/// | kill_grace       | KILL_GRACE           | Option<u64>      | 5                 | Seconds a process is given to exit after being asked to, before it is killed                       |
+/// | protected        | PROTECTED            | Vec<String>      | []                | Names of processes that are never killed on top of critical ones, comma separated in the env       |
+/// | frame_rate       | FRAME_RATE           | Option<u32>      | 5                 | How many frames are captured from the screen a second                                              |
+/// | bitrate          | BITRATE              | Option<u32>      | 1000              | Kilobits a second the screen is encoded into, the picture gets blurrier to keep to it              |
+/// | encoding         | ENCODING             | Option<Encoding> | Tiles             | How the screen is encoded, raw, jpeg, tiles to only send the parts that change, or h264 for video  |
 #[derive(Debug, Serialize, Deserialize, Default)]
 #[serde(default)]
 pub struct Config {
     pub server: ServerConfig,
     pub ca_cert: Option<PathBuf>,
+    pub client_cert: Option<PathBuf>,
+    pub client_key: Option<PathBuf>,
+    pub enrollment_token: Option<String>,
+    pub kill_grace: Option<u64>,
+    pub protected: Vec<String>,
+    pub frame_rate: Option<u32>,
+    pub bitrate: Option<u32>,
+    pub encoding: Option<Encoding>,
 }
 
 impl Config {
@@ -34,10 +51,78 @@ impl Config {
             }
         }
 
+        if let Ok(client_cert) = var("CLIENT_CERT") {
+            match client_cert.parse() {
+                Ok(client_cert) => slf.client_cert = Some(client_cert),
+                Err(err) => warn!("Could not pass value for CLIENT_CERT: {err}, ignoring"),
+            }
+        }
+
+        if let Ok(client_key) = var("CLIENT_KEY") {
+            match client_key.parse() {
+                Ok(client_key) => slf.client_key = Some(client_key),
+                Err(err) => warn!("Could not pass value for CLIENT_KEY: {err}, ignoring"),
+            }
+        }
+
+        if let Ok(enrollment_token) = var("ENROLLMENT_TOKEN") {
+            slf.enrollment_token = Some(enrollment_token);
+        }
+
+        if let Ok(kill_grace) = var("KILL_GRACE") {
+            match kill_grace.parse() {
+                Ok(kill_grace) => slf.kill_grace = Some(kill_grace),
+                Err(err) => warn!("Could not pass value for KILL_GRACE: {err}, ignoring"),
+            }
+        }
+
+        if let Ok(protected) = var("PROTECTED") {
+            slf.protected = protected
+                .split(',')
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(String::from)
+                .collect();
+        }
+
+        if let Ok(frame_rate) = var("FRAME_RATE") {
+            match frame_rate.parse() {
+                Ok(frame_rate) => slf.frame_rate = Some(frame_rate),
+                Err(err) => warn!("Could not pass value for FRAME_RATE: {err}, ignoring"),
+            }
+        }
+
+        if let Ok(bitrate) = var("BITRATE") {
+            match bitrate.parse() {
+                Ok(bitrate) => slf.bitrate = Some(bitrate),
+                Err(err) => warn!("Could not pass value for BITRATE: {err}, ignoring"),
+            }
+        }
+
+        if let Ok(encoding) = var("ENCODING") {
+            match encoding.trim().to_lowercase().as_str() {
+                "raw" => slf.encoding = Some(Encoding::Raw),
+                "jpeg" => slf.encoding = Some(Encoding::Jpeg),
+                "tiles" => slf.encoding = Some(Encoding::Tiles),
+                "h264" => slf.encoding = Some(Encoding::H264),
+                _ => warn!(
+                    "Could not pass value for ENCODING: unknown encoding {encoding}, ignoring"
+                ),
+            }
+        }
+
         slf
     }
 }
 
+/// Where the config file is, `CONFIG_FILE` or `config.toml` in the working directory
+pub fn config_path() -> PathBuf {
+    match var("CONFIG_FILE") {
+        Ok(path) => path.into(),
+        Err(_) => "config.toml".into(),
+    }
+}
+
 pub fn load_config() -> Config {
     let use_env = args().any(|arg| &arg == "--env" || &arg == "-e");
     let mut slf = Config::default();
@@ -45,13 +130,7 @@ pub fn load_config() -> Config {
     if use_env {
         Config::from_env();
     } else {
-        let path = if let Ok(pth) = var("CONFIG_FILE") {
-            pth
-        } else {
-            "config.toml".into()
-        };
-
-        match read_to_string(&path) {
+        match read_to_string(config_path()) {
             Ok(file) => match toml::from_str::<Config>(&file) {
                 Ok(val) => {
                     slf = val;
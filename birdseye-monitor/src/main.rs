@@ -2,6 +2,12 @@ mod client;
 mod config;
 mod platform;
 
+use crate::client::capture::capture_screen;
+use crate::client::enroll::enroll;
+use crate::client::kill::Killer;
+use crate::client::link::run_link;
+use crate::client::machine::get_machine;
+use crate::client::policy::Enforcer;
 use crate::client::process::monitor_processes;
 use crate::config::load_config;
 use crate::platform::get_current_user;
@@ -15,61 +21,26 @@ async fn main() {
         .init();
 
     // Load application configuration
-    let _config = load_config();
-
-    let mut stream = monitor_processes();
+    let mut config = load_config();
+    let machine = get_machine();
 
     info!("Current user is: {:?}", get_current_user());
+    info!("Running on {:?}", machine);
+
+    // Get a client certificate if this machine hasn't been enrolled yet
+    enroll(&mut config, &machine).await;
 
     for usr in sysinfo::System::default().users() {
         info!("{:?}", usr);
     }
 
-    use scrap::{Capturer, Display};
-    use std::io::ErrorKind::WouldBlock;
-    use std::io::Write;
-    use std::process::{Command, Stdio};
-
-    let d = Display::primary().unwrap();
-    let (w, h) = (d.width(), d.height());
-
-    let child = Command::new("ffplay")
-        .args(&[
-            "-f",
-            "rawvideo",
-            "-pixel_format",
-            "bgr0",
-            "-video_size",
-            &format!("{}x{}", w, h),
-            "-framerate",
-            "60",
-            "-",
-        ])
-        .stdin(Stdio::piped())
-        .spawn()
-        .expect("This example requires ffplay.");
-
-    let mut capturer = Capturer::new(d).unwrap();
-    let mut out = child.stdin.unwrap();
-
-    loop {
-        match capturer.frame() {
-            Ok(frame) => {
-                // Write the frame, removing end-of-row padding.
-                let stride = frame.len() / h;
-                let rowlen = 4 * w;
-                for row in frame.chunks(stride) {
-                    let row = &row[..rowlen];
-                    out.write_all(row).unwrap();
-                }
-            }
-            Err(ref e) if e.kind() == WouldBlock => {
-                // Wait for the frame.
-            }
-            Err(_) => {
-                // We're done here.
-                break;
-            }
-        }
-    }
+    // Keep the server up to date with the processes running on this machine and what is on its
+    // screen, and enforce the blacklist it sends
+    let (enforcer, violations) = Enforcer::new(Killer::new(&config));
+    let (processes, updates) = monitor_processes(enforcer.clone());
+    let screen = capture_screen(&config);
+    run_link(
+        config, machine, processes, updates, enforcer, violations, screen,
+    )
+    .await;
 }
@@ -1,12 +1,16 @@
-use birdseye_common::User;
+use birdseye_common::{Session, User};
 use std::collections::HashMap;
+use std::fs::{create_dir_all, read_dir, read_to_string, remove_dir, remove_file, write};
+use std::io;
 use std::os::unix::prelude::*;
-use sysinfo::{System, SystemExt, Uid, UserExt};
+use std::path::{Path, PathBuf};
+use std::process::Command;
 use tracing::debug;
 use walkdir::WalkDir;
 
 fn user_to_be_user(usr: users::User) -> User {
     User::new(usr.name().to_str().unwrap_or("Unable to get username"))
+        .with_id(&usr.uid().to_string())
 }
 
 /// Black magic fuckery to find the current GUI user in linux
@@ -37,3 +41,262 @@ pub fn get_current_user() -> Option<User> {
     // Return it 👍
     users::get_user_by_uid(*users.last()?.0).map(user_to_be_user)
 }
+
+/// Turn the tty_nr field of `/proc/[pid]/stat` back into a device path
+fn tty_nr_to_path(tty_nr: u32) -> Option<String> {
+    let major = (tty_nr >> 8) & 0xfff;
+    let minor = (tty_nr & 0xff) | ((tty_nr >> 12) & 0xfff00);
+
+    match major {
+        0 => None,
+        // Virtual consoles
+        4 if minor < 64 => Some(format!("/dev/tty{minor}")),
+        // Pseudo terminals, these are what terminal emulators and ssh use
+        136..=143 => Some(format!("/dev/pts/{}", (major - 136) * 256 + minor)),
+        _ => Some(format!("/dev/char/{major}:{minor}")),
+    }
+}
+
+/// Get the session id and controlling terminal of a process from `/proc/[pid]/stat`
+pub fn get_process_session(pid: u32) -> Option<Session> {
+    let stat = read_to_string(format!("/proc/{pid}/stat")).ok()?;
+
+    // The process name is wrapped in brackets and can contain spaces, so skip past it before
+    // splitting, the fields after it are state, ppid, pgrp, session and tty_nr
+    let mut fields = stat[stat.rfind(')')? + 1..].split_whitespace().skip(3);
+    let session = fields.next()?.parse().ok()?;
+    let tty_nr = fields.next()?.parse::<i32>().ok()? as u32;
+
+    Some(Session::new(session, tty_nr_to_path(tty_nr).as_deref()))
+}
+
+/// Get the id systemd/dbus generated for this machine when it was installed
+pub fn get_machine_id() -> Option<String> {
+    ["/etc/machine-id", "/var/lib/dbus/machine-id"]
+        .iter()
+        .filter_map(|path| read_to_string(path).ok())
+        .map(|id| id.trim().to_string())
+        .find(|id| !id.is_empty())
+}
+
+/// Processes the machine can't run without, killing one would log everybody out or take the
+/// machine down. Names are as the kernel reports them, which cuts them off at 15 characters
+const CRITICAL_PROCESSES: &[&str] = &[
+    "systemd",
+    "init",
+    "kthreadd",
+    "systemd-journal",
+    "systemd-logind",
+    "systemd-udevd",
+    "dbus-daemon",
+    "dbus-broker",
+    "polkitd",
+    "NetworkManager",
+    "Xorg",
+    "Xwayland",
+    "gdm",
+    "gdm-session-wor",
+    "sddm",
+    "lightdm",
+    "login",
+    "sshd",
+];
+
+/// Whether a process is one the machine can't run without, which includes every kernel thread
+pub fn is_critical_process(pid: u32, parent_pid: Option<u32>, name: &str) -> bool {
+    // pid 2 is kthreadd, which every kernel thread is started by
+    pid <= 2 || parent_pid == Some(2) || CRITICAL_PROCESSES.contains(&name)
+}
+
+/// Where the cgroup v2 hierarchy is mounted
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// Throttled processes each get a cgroup of their own under this one
+const THROTTLE_CGROUP: &str = "birdseye";
+
+/// The cgroup each throttled process came from is kept in a file named after its pid in here, so
+/// it can still be put back if the monitor restarts. Like the cgroups, it is gone after a reboot
+const ORIGINAL_CGROUPS: &str = "/run/birdseye-monitor/cgroups";
+
+/// The period `cpu.max` quotas are out of, in microseconds
+const CPU_PERIOD: u32 = 100_000;
+
+fn throttle_cgroup(pid: u32) -> PathBuf {
+    Path::new(CGROUP_ROOT)
+        .join(THROTTLE_CGROUP)
+        .join(pid.to_string())
+}
+
+fn original_cgroup(pid: u32) -> PathBuf {
+    Path::new(ORIGINAL_CGROUPS).join(pid.to_string())
+}
+
+/// Get the cgroup a process is in, relative to the root of the hierarchy
+fn process_cgroup(pid: u32) -> io::Result<String> {
+    // The unified hierarchy is the one with id 0 and no controllers listed
+    read_to_string(format!("/proc/{pid}/cgroup"))?
+        .lines()
+        .find_map(|line| line.strip_prefix("0::"))
+        .map(String::from)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::Unsupported, "cgroup v2 is not in use"))
+}
+
+/// Whether the cpu controller is listed in a cgroup's `cgroup.controllers` or
+/// `cgroup.subtree_control`
+fn has_cpu(file: &Path) -> io::Result<bool> {
+    Ok(read_to_string(file)?
+        .split_whitespace()
+        .any(|controller| controller == "cpu"))
+}
+
+/// Switch the cpu controller on for the children of a cgroup. Writing to `cgroup.subtree_control`
+/// can quietly do nothing, e.g. when systemd owns the cgroup, so it is read back to check
+fn enable_cpu(cgroup: &Path) -> io::Result<()> {
+    let control = cgroup.join("cgroup.subtree_control");
+    if has_cpu(&control)? {
+        return Ok(());
+    }
+
+    if let Err(ex) = write(&control, "+cpu") {
+        debug!("Could not enable the cpu controller in {cgroup:?}: {ex}");
+    }
+    match has_cpu(&control)? {
+        true => Ok(()),
+        false => Err(io::Error::other(format!(
+            "the cpu controller could not be enabled in {cgroup:?}"
+        ))),
+    }
+}
+
+/// Limit a process to `percent` of one CPU by moving it into a cgroup of its own with a CPU quota,
+/// the cgroup it was in is recorded so [`unthrottle_process`] can put it back. Throttling a
+/// process that already is just changes its quota
+pub fn throttle_process(pid: u32, percent: u8) -> io::Result<()> {
+    let root = Path::new(CGROUP_ROOT);
+    let parent = root.join(THROTTLE_CGROUP);
+    let cgroup = throttle_cgroup(pid);
+
+    if !has_cpu(&root.join("cgroup.controllers"))? {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "the cpu controller is not available",
+        ));
+    }
+
+    // Once moved the process is in our cgroup, so only the first throttle knows where it came from
+    let record = original_cgroup(pid);
+    if !record.exists() {
+        let original = process_cgroup(pid)?;
+        create_dir_all(ORIGINAL_CGROUPS)?;
+        write(&record, original)?;
+    }
+
+    // The cpu controller has to be switched on in every cgroup above the one with the quota
+    create_dir_all(&cgroup)?;
+    enable_cpu(root)?;
+    enable_cpu(&parent)?;
+
+    let quota = CPU_PERIOD / 100 * percent as u32;
+    write(cgroup.join("cpu.max"), format!("{quota} {CPU_PERIOD}"))?;
+    write(cgroup.join("cgroup.procs"), pid.to_string())
+}
+
+/// Undo [`throttle_process`], anything the process started while it was throttled is moved back
+/// into its original cgroup along with it. Does nothing if the process wasn't throttled
+pub fn unthrottle_process(pid: u32) -> io::Result<()> {
+    let record = original_cgroup(pid);
+    let original = match read_to_string(&record) {
+        Ok(original) => original,
+        Err(ex) if ex.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(ex) => return Err(ex),
+    };
+
+    let cgroup = throttle_cgroup(pid);
+    let members = match read_to_string(cgroup.join("cgroup.procs")) {
+        Ok(members) => members,
+        Err(ex) if ex.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(ex) => return Err(ex),
+    };
+
+    let procs = Path::new(CGROUP_ROOT)
+        .join(original.trim_start_matches('/'))
+        .join("cgroup.procs");
+    for member in members.lines() {
+        match write(&procs, member) {
+            Ok(()) => {}
+            // Anything that has exited since can't be moved, and doesn't need to be
+            Err(_) if !Path::new("/proc").join(member).exists() => {}
+            Err(ex) if member == pid.to_string() => return Err(ex),
+            Err(ex) => debug!("Could not move {member} back to {original}: {ex}"),
+        }
+    }
+
+    match remove_dir(cgroup) {
+        Err(ex) if ex.kind() != io::ErrorKind::NotFound => return Err(ex),
+        _ => {}
+    }
+    remove_file(record)
+}
+
+/// Run a program, failing if it exits unsuccessfully
+fn run(command: &mut Command) -> io::Result<()> {
+    let status = command.status()?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(io::Error::other(format!(
+            "{:?} exited with {status}",
+            command.get_program()
+        ))),
+    }
+}
+
+/// Lock every graphical session with whatever screen locker it uses, through logind
+pub fn lock_screen() -> io::Result<()> {
+    run(Command::new("loginctl").arg("lock-sessions"))
+}
+
+/// Undo [`lock_screen`]
+pub fn unlock_screen() -> io::Result<()> {
+    run(Command::new("loginctl").arg("unlock-sessions"))
+}
+
+/// Where each logged in user's session bus is, by uid
+const USER_RUNTIME: &str = "/run/user";
+
+/// Show a notification on the desktop of everyone logged in, fails if nobody saw it
+pub fn show_message(text: &str) -> io::Result<()> {
+    let mut shown = false;
+
+    for entry in read_dir(USER_RUNTIME)?.flatten() {
+        let bus = entry.path().join("bus");
+        let (uid, gid) = match (entry.file_name().to_str().map(str::parse), bus.metadata()) {
+            (Some(Ok(uid)), Ok(meta)) => (uid, meta.gid()),
+            _ => continue,
+        };
+
+        // Notifications go through the user's own session bus, which only they can talk to
+        let result = run(Command::new("notify-send")
+            .uid(uid)
+            .gid(gid)
+            .env(
+                "DBUS_SESSION_BUS_ADDRESS",
+                format!("unix:path={}", bus.display()),
+            )
+            .args([
+                "--urgency=critical",
+                "--app-name=BirdsEye",
+                "BirdsEye",
+                text,
+            ]));
+        match result {
+            Ok(()) => shown = true,
+            Err(ex) if ex.kind() == io::ErrorKind::NotFound => return Err(ex),
+            Err(ex) => debug!("Could not show a message to user {uid}: {ex}"),
+        }
+    }
+
+    match shown {
+        true => Ok(()),
+        false => Err(io::Error::other("nobody is logged in to show it to")),
+    }
+}
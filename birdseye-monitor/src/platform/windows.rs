@@ -1,5 +1,7 @@
 //! Windows specific implementatinos for common activities
-use birdseye_common::User;
+use birdseye_common::{Session, User};
+use std::io;
+use std::process::Command;
 use wmi::{COMLibrary, WMIConnection};
 
 #[derive(serde::Serialize, serde::Deserialize)]
@@ -15,6 +17,14 @@ impl Into<User> for &UserQuery {
     }
 }
 
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename = "Win32_ComputerSystemProduct")]
+#[serde(rename_all = "PascalCase")]
+struct ProductQuery {
+    #[serde(rename = "UUID")]
+    uuid: String,
+}
+
 pub fn get_current_user() -> Option<User> {
     let com_con = COMLibrary::new().ok()?;
     let wmi_con = WMIConnection::new(com_con.into()).ok()?;
@@ -23,3 +33,80 @@ pub fn get_current_user() -> Option<User> {
 
     Some(users_query.first()?.into())
 }
+
+/// Get the SMBIOS UUID of the machine, this is set by the manufacturer and survives reinstalls
+pub fn get_machine_id() -> Option<String> {
+    let com_con = COMLibrary::new().ok()?;
+    let wmi_con = WMIConnection::new(com_con.into()).ok()?;
+
+    let product_query: Vec<ProductQuery> = wmi_con.query().ok()?;
+
+    Some(product_query.first()?.uuid.clone())
+}
+
+/// Sessions are not reported on windows yet
+pub fn get_process_session(_pid: u32) -> Option<Session> {
+    None
+}
+
+/// Processes windows can't run without, killing most of these blue screens the machine
+const CRITICAL_PROCESSES: &[&str] = &[
+    "System",
+    "Registry",
+    "smss.exe",
+    "csrss.exe",
+    "wininit.exe",
+    "winlogon.exe",
+    "services.exe",
+    "lsass.exe",
+    "svchost.exe",
+    "dwm.exe",
+    "fontdrvhost.exe",
+    "LogonUI.exe",
+];
+
+/// Whether a process is one the machine can't run without
+pub fn is_critical_process(pid: u32, _parent_pid: Option<u32>, name: &str) -> bool {
+    // 0 is the idle process and 4 is the kernel
+    pid <= 4
+        || CRITICAL_PROCESSES
+            .iter()
+            .any(|critical| critical.eq_ignore_ascii_case(name))
+}
+
+/// Throttling uses cgroups, which windows doesn't have
+pub fn throttle_process(_pid: u32, _percent: u8) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// Nothing can have been throttled on windows
+pub fn unthrottle_process(_pid: u32) -> io::Result<()> {
+    Ok(())
+}
+
+/// Run a program, failing if it exits unsuccessfully
+fn run(command: &mut Command) -> io::Result<()> {
+    let status = command.status()?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(io::Error::other(format!(
+            "{:?} exited with {status}",
+            command.get_program()
+        ))),
+    }
+}
+
+/// Lock the workstation, going back to the sign in screen
+pub fn lock_screen() -> io::Result<()> {
+    run(Command::new("rundll32.exe").arg("user32.dll,LockWorkStation"))
+}
+
+/// Windows can't be unlocked without signing back in, so the student has to do that themselves
+pub fn unlock_screen() -> io::Result<()> {
+    Ok(())
+}
+
+/// Show a message box on every session
+pub fn show_message(text: &str) -> io::Result<()> {
+    run(Command::new("msg").args(["*", text]))
+}
@@ -0,0 +1,337 @@
+//! Teacher accounts able to log in to the dashboard, and the endpoints admins use to manage them
+
+use crate::auth;
+use crate::database::{DatabaseError, Repository};
+use crate::sessions::Session;
+use crate::state::{with_state, SharedState};
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::SaltString;
+use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use birdseye_common::auth::{AccountInfo, NewAccount};
+use birdseye_common::RoomId;
+use std::collections::BTreeSet;
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+use tracing::{info, warn};
+use warp::http::StatusCode;
+use warp::reply::Response;
+use warp::{Filter, Rejection, Reply};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Account {
+    username: String,
+    /// Argon2 hash of the password in PHC string format
+    password_hash: String,
+    admin: bool,
+    /// The rooms the teacher teaches in
+    rooms: BTreeSet<RoomId>,
+}
+
+impl<'a> Account {
+    pub fn username(&'a self) -> &'a str {
+        &self.username
+    }
+
+    pub fn password_hash(&'a self) -> &'a str {
+        &self.password_hash
+    }
+
+    pub fn is_admin(&self) -> bool {
+        self.admin
+    }
+
+    pub fn rooms(&'a self) -> &'a BTreeSet<RoomId> {
+        &self.rooms
+    }
+
+    pub fn info(&self) -> AccountInfo {
+        AccountInfo::new(
+            &self.username,
+            self.admin,
+            self.rooms.iter().cloned().collect(),
+        )
+    }
+
+    pub fn new(username: &str, password_hash: &str, admin: bool, rooms: BTreeSet<RoomId>) -> Self {
+        Self {
+            username: username.to_string(),
+            password_hash: password_hash.to_string(),
+            admin,
+            rooms,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum AccountError {
+    AlreadyExists(String),
+    NotFound(String),
+    Hash(argon2::password_hash::Error),
+    Database(DatabaseError),
+}
+
+impl Display for AccountError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AccountError::AlreadyExists(username) => {
+                write!(f, "an account called {username} already exists")
+            }
+            AccountError::NotFound(username) => write!(f, "there is no account called {username}"),
+            AccountError::Hash(ex) => write!(f, "could not hash password: {ex}"),
+            AccountError::Database(ex) => write!(f, "could not access accounts: {ex}"),
+        }
+    }
+}
+
+impl std::error::Error for AccountError {}
+
+impl From<DatabaseError> for AccountError {
+    fn from(ex: DatabaseError) -> Self {
+        AccountError::Database(ex)
+    }
+}
+
+fn hash_password(password: &str) -> Result<String, AccountError> {
+    let salt = SaltString::generate(&mut OsRng);
+
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(AccountError::Hash)
+}
+
+fn verify_password(password: &str, hash: &str) -> bool {
+    PasswordHash::new(hash)
+        .map(|hash| {
+            Argon2::default()
+                .verify_password(password.as_bytes(), &hash)
+                .is_ok()
+        })
+        .unwrap_or_default()
+}
+
+/// Every teacher account, kept in the database
+pub struct Accounts {
+    db: Arc<dyn Repository>,
+    /// Checked against when someone tries to log in as an account that doesn't exist, so logging
+    /// in takes as long whether or not the username is right
+    dummy_hash: String,
+}
+
+impl Accounts {
+    pub fn new(db: Arc<dyn Repository>) -> Result<Self, AccountError> {
+        Ok(Self {
+            db,
+            dummy_hash: hash_password("")?,
+        })
+    }
+
+    pub fn is_empty(&self) -> Result<bool, AccountError> {
+        Ok(self.db.accounts()?.is_empty())
+    }
+
+    /// Get an account by username, `None` if there is no such account or it could not be loaded
+    pub fn get(&self, username: &str) -> Option<Account> {
+        self.db.account(username).unwrap_or_else(|ex| {
+            warn!("Could not load account {username}: {ex}");
+            None
+        })
+    }
+
+    /// Get every account
+    pub fn list(&self) -> Result<Vec<Account>, AccountError> {
+        Ok(self.db.accounts()?)
+    }
+
+    /// Create a new account and save it
+    pub fn create(
+        &self,
+        username: &str,
+        password: &str,
+        admin: bool,
+        rooms: BTreeSet<RoomId>,
+    ) -> Result<(), AccountError> {
+        let account = Account::new(username, &hash_password(password)?, admin, rooms);
+
+        if self.db.create_account(&account)? {
+            Ok(())
+        } else {
+            Err(AccountError::AlreadyExists(username.to_string()))
+        }
+    }
+
+    /// Change the rooms a teacher is assigned to, returns the updated account
+    pub fn set_rooms(
+        &self,
+        username: &str,
+        rooms: BTreeSet<RoomId>,
+    ) -> Result<Account, AccountError> {
+        if !self.db.set_account_rooms(username, &rooms)? {
+            return Err(AccountError::NotFound(username.to_string()));
+        }
+
+        self.db
+            .account(username)?
+            .ok_or_else(|| AccountError::NotFound(username.to_string()))
+    }
+
+    /// Get the account with the given username if the password is correct
+    pub fn verify(&self, username: &str, password: &str) -> Option<Account> {
+        match self.get(username) {
+            Some(account) if verify_password(password, &account.password_hash) => Some(account),
+            Some(_) => None,
+            None => {
+                verify_password(password, &self.dummy_hash);
+                None
+            }
+        }
+    }
+}
+
+/// Create an admin account from the command line, this is how the first account is made
+pub fn create_admin(
+    db: Arc<dyn Repository>,
+    username: Option<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let username = username.ok_or("Usage: birdseye-server create-admin <username>")?;
+    let accounts = Accounts::new(db)?;
+
+    let password = rpassword::prompt_password(format!("Password for {username}: "))?;
+    if password.is_empty() {
+        return Err("Password can not be empty".into());
+    }
+    if rpassword::prompt_password("Confirm password: ")? != password {
+        return Err("Passwords do not match".into());
+    }
+
+    accounts.create(&username, &password, true, BTreeSet::new())?;
+    println!("Created admin {username}");
+
+    Ok(())
+}
+
+async fn list_accounts(_session: Session, state: SharedState) -> Result<Response, Rejection> {
+    match state.accounts.list() {
+        Ok(accounts) => {
+            let accounts: Vec<_> = accounts.iter().map(Account::info).collect();
+            Ok(warp::reply::json(&accounts).into_response())
+        }
+        Err(ex) => {
+            warn!("Could not list accounts: {ex}");
+            Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response())
+        }
+    }
+}
+
+async fn create_account(
+    session: Session,
+    request: NewAccount,
+    state: SharedState,
+) -> Result<Response, Rejection> {
+    if request.username.is_empty() || request.password.is_empty() {
+        return Ok(StatusCode::BAD_REQUEST.into_response());
+    }
+
+    // Hashing is slow on purpose, so keep it off the async threads
+    let create_state = state.clone();
+    let NewAccount {
+        username,
+        password,
+        admin,
+        rooms,
+    } = request;
+    let create_username = username.clone();
+    let result = tokio::task::spawn_blocking(move || {
+        create_state.accounts.create(
+            &create_username,
+            &password,
+            admin,
+            rooms.into_iter().collect(),
+        )
+    })
+    .await;
+
+    match result {
+        Ok(Ok(())) => {
+            info!("{} created account {username}", session.username());
+            match state.accounts.get(&username) {
+                Some(account) => Ok(warp::reply::with_status(
+                    warp::reply::json(&account.info()),
+                    StatusCode::CREATED,
+                )
+                .into_response()),
+                None => Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response()),
+            }
+        }
+        Ok(Err(AccountError::AlreadyExists(_))) => Ok(StatusCode::CONFLICT.into_response()),
+        Ok(Err(ex)) => {
+            warn!("Could not create account {username}: {ex}");
+            Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response())
+        }
+        Err(_) => Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response()),
+    }
+}
+
+async fn set_rooms(
+    username: String,
+    session: Session,
+    rooms: Vec<RoomId>,
+    state: SharedState,
+) -> Result<Response, Rejection> {
+    let account = match state
+        .accounts
+        .set_rooms(&username, rooms.into_iter().collect())
+    {
+        Ok(account) => account,
+        Err(AccountError::NotFound(_)) => return Ok(StatusCode::NOT_FOUND.into_response()),
+        Err(ex) => {
+            warn!("Could not change rooms for {username}: {ex}");
+            return Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+        }
+    };
+
+    // Dashboards that are already open lose anything the teacher can no longer see
+    state.hub.retain_topics(&username, |topic| {
+        state.rooms.can_subscribe(&account, topic)
+    });
+
+    info!(
+        "{} assigned {username} to rooms {:?}",
+        session.username(),
+        account.rooms()
+    );
+    Ok(warp::reply::json(&account.info()).into_response())
+}
+
+/// `GET /accounts`, `POST /accounts` and `PUT /accounts/<username>/rooms`, all only usable by
+/// admins
+pub fn routes(state: SharedState) -> impl Filter<Extract = (Response,), Error = Rejection> + Clone {
+    let list = warp::get()
+        .and(warp::path!("accounts"))
+        .and(auth::admin(state.clone()))
+        .and(with_state(state.clone()))
+        .and_then(list_accounts);
+
+    let create = warp::post()
+        .and(warp::path!("accounts"))
+        .and(auth::admin(state.clone()))
+        .and(warp::body::content_length_limit(4 * 1024))
+        .and(warp::body::json())
+        .and(with_state(state.clone()))
+        .and_then(create_account);
+
+    let set_rooms = warp::put()
+        .and(warp::path!("accounts" / String / "rooms"))
+        .and(auth::admin(state.clone()))
+        .and(warp::body::content_length_limit(4 * 1024))
+        .and(warp::body::json())
+        .and(with_state(state))
+        .and_then(set_rooms);
+
+    list.or(create)
+        .unify()
+        .or(set_rooms)
+        .unify()
+        .recover(auth::handle_rejection)
+        .unify()
+}
@@ -0,0 +1,834 @@
+//! Plain JSON over HTTP under `/api/v1`, for scripts and integrations that don't want to speak the
+//! dashboard's WebSocket protocol
+//!
+//! Requests are authenticated with the same session cookie as the dashboard, log in with
+//! `POST /login` first. Teachers only see the machines in their rooms, the same as on the
+//! dashboard. The OpenAPI document is generated from the `#[utoipa::path]` on each handler and
+//! served at `/api/v1/openapi.json`.
+
+use crate::accounts::Account;
+use crate::auth::{self, SESSION_COOKIE};
+use crate::history::HistoryError;
+use crate::policies::{push_policies, RuleError};
+use crate::sessions::Session;
+use crate::state::{with_state, SharedState};
+use crate::timetable::ScheduleError;
+use birdseye_common::api::{ImportQuery, ImportResult, MachineStatus, RoomInfo, ThumbnailQuery};
+use birdseye_common::command::{Command, CommandError};
+use birdseye_common::frontend::Topic;
+use birdseye_common::history::HistoryQuery;
+use birdseye_common::policy::{Rule, RuleId};
+use birdseye_common::timetable::Timetable;
+use birdseye_common::{MachineId, RoomId};
+use percent_encoding::percent_decode_str;
+use tracing::{info, warn};
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+use warp::http::header::{CACHE_CONTROL, CONTENT_TYPE, ETAG, IF_NONE_MATCH};
+use warp::http::StatusCode;
+use warp::hyper::body::Bytes;
+use warp::reply::Response;
+use warp::{Filter, Rejection, Reply};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "BirdsEye"),
+    paths(
+        list_machines,
+        get_machine,
+        get_processes,
+        get_thumbnail,
+        run_command,
+        list_rooms,
+        set_room,
+        get_history,
+        list_policies,
+        create_policy,
+        update_policy,
+        delete_policy,
+        get_timetable,
+        set_timetable,
+        import_timetable
+    ),
+    components(schemas(
+        MachineStatus,
+        RoomInfo,
+        birdseye_common::Machine,
+        birdseye_common::MachineId,
+        birdseye_common::machine::NetworkInterface,
+        birdseye_common::machine::Display,
+        birdseye_common::machine::Presence,
+        birdseye_common::RoomId,
+        birdseye_common::sync::ProcessSnapshot,
+        birdseye_common::Process,
+        birdseye_common::User,
+        birdseye_common::Session,
+        birdseye_common::command::Command,
+        birdseye_common::command::CommandError,
+        birdseye_common::history::HistoryPage,
+        birdseye_common::history::HistoryEntry,
+        birdseye_common::history::Event,
+        birdseye_common::history::EventKind,
+        birdseye_common::policy::Rule,
+        birdseye_common::policy::Pattern,
+        birdseye_common::policy::Action,
+        birdseye_common::policy::PolicyError,
+        birdseye_common::policy::Violation,
+        birdseye_common::timetable::Timetable,
+        birdseye_common::timetable::Period,
+        birdseye_common::timetable::Holiday,
+        birdseye_common::timetable::RuleSet,
+        birdseye_common::timetable::TimetableError,
+        birdseye_common::api::ImportResult,
+        crate::timetable::IcsError,
+    )),
+    modifiers(&SessionCookie)
+)]
+pub struct ApiDoc;
+
+/// Adds the session cookie to the document as the way to authenticate
+struct SessionCookie;
+
+impl Modify for SessionCookie {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        if let Some(components) = openapi.components.as_mut() {
+            components.add_security_scheme(
+                "session",
+                SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::new(SESSION_COOKIE))),
+            );
+        }
+    }
+}
+
+/// Path segments are percent encoded, room names often have spaces in them
+fn decode(segment: &str) -> Option<String> {
+    percent_decode_str(segment)
+        .decode_utf8()
+        .ok()
+        .map(|segment| segment.into_owned())
+}
+
+fn status(state: &SharedState, machine: &MachineId) -> Option<MachineStatus> {
+    state
+        .registry
+        .list()
+        .into_iter()
+        .find(|(registered, _)| registered.id() == machine)
+        .map(|(machine, presence)| MachineStatus {
+            room: state.rooms.room(machine.id()),
+            machine,
+            presence,
+        })
+}
+
+/// The status code a failed command is returned with
+fn command_status(error: &CommandError) -> StatusCode {
+    match error {
+        CommandError::UnknownMachine(_) | CommandError::ProcessNotFound(_) => StatusCode::NOT_FOUND,
+        CommandError::MachineOffline(_) => StatusCode::CONFLICT,
+        CommandError::Timeout => StatusCode::GATEWAY_TIMEOUT,
+        CommandError::Unsupported => StatusCode::UNPROCESSABLE_ENTITY,
+        CommandError::PermissionDenied(_) => StatusCode::FORBIDDEN,
+        CommandError::Failed(_) => StatusCode::BAD_GATEWAY,
+    }
+}
+
+/// List every machine the account can see
+#[utoipa::path(
+    get,
+    path = "/api/v1/machines",
+    responses(
+        (status = 200, description = "Machines sorted by hostname", body = [MachineStatus]),
+        (status = 401, description = "Not logged in"),
+    ),
+    security(("session" = []))
+)]
+async fn list_machines(account: Account, state: SharedState) -> Result<Response, Rejection> {
+    let machines: Vec<_> = state
+        .registry
+        .list()
+        .into_iter()
+        .filter(|(machine, _)| state.rooms.can_see(&account, machine.id()))
+        .map(|(machine, presence)| MachineStatus {
+            room: state.rooms.room(machine.id()),
+            machine,
+            presence,
+        })
+        .collect();
+
+    Ok(warp::reply::json(&machines).into_response())
+}
+
+/// Get a single machine
+#[utoipa::path(
+    get,
+    path = "/api/v1/machines/{id}",
+    params(("id" = String, Path, description = "The machine's id")),
+    responses(
+        (status = 200, body = MachineStatus),
+        (status = 401, description = "Not logged in"),
+        (status = 403, description = "The machine is not in one of the account's rooms"),
+        (status = 404, description = "No machine with that id has ever connected"),
+    ),
+    security(("session" = []))
+)]
+async fn get_machine(
+    id: String,
+    account: Account,
+    state: SharedState,
+) -> Result<Response, Rejection> {
+    let machine = MachineId::new(&decode(&id).ok_or_else(warp::reject::not_found)?);
+    if !state.rooms.can_see(&account, &machine) {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+
+    match status(&state, &machine) {
+        Some(status) => Ok(warp::reply::json(&status).into_response()),
+        None => Ok(StatusCode::NOT_FOUND.into_response()),
+    }
+}
+
+/// Get the processes running on a machine
+#[utoipa::path(
+    get,
+    path = "/api/v1/machines/{id}/processes",
+    params(("id" = String, Path, description = "The machine's id")),
+    responses(
+        (status = 200, body = ProcessSnapshot),
+        (status = 401, description = "Not logged in"),
+        (status = 403, description = "The machine is not in one of the account's rooms"),
+        (status = 404, description = "The machine hasn't sent its process list"),
+    ),
+    security(("session" = []))
+)]
+async fn get_processes(
+    id: String,
+    account: Account,
+    state: SharedState,
+) -> Result<Response, Rejection> {
+    let machine = MachineId::new(&decode(&id).ok_or_else(warp::reject::not_found)?);
+    if !state.rooms.can_see(&account, &machine) {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+
+    let snapshot = state
+        .processes
+        .with_snapshots(&[machine], |snapshots| snapshots.into_iter().next());
+
+    match snapshot {
+        Some((_, snapshot)) => Ok(warp::reply::json(&snapshot).into_response()),
+        None => Ok(StatusCode::NOT_FOUND.into_response()),
+    }
+}
+
+/// Get a JPEG thumbnail of a machine's screen. The thumbnail only changes when the screen does, send
+/// its ETag back in `If-None-Match` to only download it when it has
+#[utoipa::path(
+    get,
+    path = "/api/v1/machines/{id}/thumbnail",
+    params(("id" = String, Path, description = "The machine's id"), ThumbnailQuery),
+    responses(
+        (status = 200, description = "The thumbnail", content_type = "image/jpeg"),
+        (status = 304, description = "The thumbnail hasn't changed since the ETag was sent"),
+        (status = 401, description = "Not logged in"),
+        (status = 403, description = "The machine is not in one of the account's rooms"),
+        (status = 404, description = "The machine hasn't sent a frame"),
+    ),
+    security(("session" = []))
+)]
+async fn get_thumbnail(
+    id: String,
+    account: Account,
+    query: ThumbnailQuery,
+    if_none_match: Option<String>,
+    state: SharedState,
+) -> Result<Response, Rejection> {
+    let machine = MachineId::new(&decode(&id).ok_or_else(warp::reject::not_found)?);
+    if !state.rooms.can_see(&account, &machine) {
+        return Ok(StatusCode::FORBIDDEN.into_response());
+    }
+
+    // Encoding a thumbnail would hold up the other requests on this thread
+    let thumbnail = tokio::task::spawn_blocking(move || {
+        state.thumbnails.get(&state.screens, &machine, query.width)
+    })
+    .await
+    .ok()
+    .flatten();
+
+    let thumbnail = match thumbnail {
+        Some(thumbnail) => thumbnail,
+        None => return Ok(StatusCode::NOT_FOUND.into_response()),
+    };
+
+    // Thumbnails can change at any time, so browsers have to check every time they're shown
+    let unchanged = if_none_match
+        .map(|tags| tags.split(',').any(|tag| tag.trim() == thumbnail.etag()))
+        .unwrap_or_default();
+    let mut response = match unchanged {
+        true => StatusCode::NOT_MODIFIED.into_response(),
+        false => {
+            let mut response = Response::new(thumbnail.jpeg().clone().into());
+            response
+                .headers_mut()
+                .insert(CONTENT_TYPE, "image/jpeg".parse().unwrap());
+            response
+        }
+    };
+    let headers = response.headers_mut();
+    headers.insert(ETAG, thumbnail.etag().parse().unwrap());
+    headers.insert(CACHE_CONTROL, "no-cache".parse().unwrap());
+
+    Ok(response)
+}
+
+/// Run a command on a machine and wait for it to finish
+#[utoipa::path(
+    post,
+    path = "/api/v1/machines/{id}/commands",
+    params(("id" = String, Path, description = "The machine's id")),
+    request_body = Command,
+    responses(
+        (status = 204, description = "The command ran"),
+        (status = 401, description = "Not logged in"),
+        (status = 403, description = "Permission denied", body = CommandError),
+        (status = 404, description = "Unknown machine or process", body = CommandError),
+        (status = 409, description = "The machine is offline", body = CommandError),
+        (status = 422, description = "The machine doesn't support the command", body = CommandError),
+        (status = 502, description = "The command failed", body = CommandError),
+        (status = 504, description = "The machine didn't reply in time", body = CommandError),
+    ),
+    security(("session" = []))
+)]
+async fn run_command(
+    id: String,
+    session: Session,
+    command: Command,
+    state: SharedState,
+) -> Result<Response, Rejection> {
+    let machine = MachineId::new(&decode(&id).ok_or_else(warp::reject::not_found)?);
+    let allowed = state
+        .accounts
+        .get(session.username())
+        .map(|account| state.rooms.can_see(&account, &machine))
+        .unwrap_or_default();
+
+    let result = match allowed {
+        true => match state.command_link(&machine, &command) {
+            Ok(link) => {
+                let result = state.commands.run(&machine, &link, command.clone()).await;
+                if result.is_ok() {
+                    state.schedule.commanded(&machine, &command);
+                }
+                result
+            }
+            Err(ex) => Err(ex),
+        },
+        false => {
+            warn!(
+                "{} tried to run {command:?} on {machine} outside of their rooms",
+                session.username()
+            );
+            Err(CommandError::PermissionDenied(
+                "the machine is not in one of your rooms".into(),
+            ))
+        }
+    };
+
+    match result {
+        Ok(()) => {
+            info!("{} ran {command:?} on {machine}", session.username());
+            Ok(StatusCode::NO_CONTENT.into_response())
+        }
+        Err(ex) => Ok(
+            warp::reply::with_status(warp::reply::json(&ex), command_status(&ex)).into_response(),
+        ),
+    }
+}
+
+/// List the rooms the account can see, admins see every room with a machine in it
+#[utoipa::path(
+    get,
+    path = "/api/v1/rooms",
+    responses(
+        (status = 200, description = "Rooms sorted by id", body = [RoomInfo]),
+        (status = 401, description = "Not logged in"),
+    ),
+    security(("session" = []))
+)]
+async fn list_rooms(account: Account, state: SharedState) -> Result<Response, Rejection> {
+    let mut rooms = state.rooms.list();
+
+    if !account.is_admin() {
+        rooms.retain(|room| account.rooms().contains(&room.id));
+
+        // Rooms without any machines in them yet are still the teacher's
+        for id in account.rooms() {
+            if !rooms.iter().any(|room| &room.id == id) {
+                rooms.push(RoomInfo {
+                    id: id.clone(),
+                    machines: vec![],
+                });
+            }
+        }
+        rooms.sort_by(|a, b| a.id.cmp(&b.id));
+    }
+
+    Ok(warp::reply::json(&rooms).into_response())
+}
+
+/// Replace the machines in a room, machines are moved out of whichever room they were in before
+#[utoipa::path(
+    put,
+    path = "/api/v1/rooms/{id}",
+    params(("id" = String, Path, description = "The room's id")),
+    request_body = [MachineId],
+    responses(
+        (status = 200, body = RoomInfo),
+        (status = 401, description = "Not logged in"),
+        (status = 403, description = "Not an admin"),
+    ),
+    security(("session" = []))
+)]
+async fn set_room(
+    id: String,
+    session: Session,
+    machines: Vec<MachineId>,
+    state: SharedState,
+) -> Result<Response, Rejection> {
+    let room = RoomId::new(&decode(&id).ok_or_else(warp::reject::not_found)?);
+
+    if let Err(ex) = state.rooms.set_machines(&room, &machines) {
+        warn!("Could not change the machines in {room}: {ex}");
+        return Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+    }
+
+    // Dashboards that are already open lose any machine that moved out of the teacher's rooms
+    match state.accounts.list() {
+        Ok(accounts) => {
+            for account in accounts {
+                state.hub.retain_topics(account.username(), |topic| {
+                    state.rooms.can_subscribe(&account, topic)
+                });
+            }
+        }
+        Err(ex) => warn!("Could not load accounts to update their subscriptions: {ex}"),
+    }
+
+    info!(
+        "{} put machines {machines:?} in room {room}",
+        session.username()
+    );
+
+    // Machines that moved now have a different set of rules, and may need locking or unlocking
+    push_policies(&state);
+    state.schedule.changed();
+
+    let mut machines = state.rooms.machines(&Topic::Room(room.clone()));
+    machines.sort();
+    Ok(warp::reply::json(&RoomInfo { id: room, machines }).into_response())
+}
+
+/// Look through the history of processes started and stopped
+#[utoipa::path(
+    get,
+    path = "/api/v1/history",
+    params(HistoryQuery),
+    responses(
+        (status = 200, description = "Events newest first", body = HistoryPage),
+        (status = 401, description = "Not logged in"),
+        (status = 403, description = "The machine or room is not one the account can see"),
+    ),
+    security(("session" = []))
+)]
+async fn get_history(
+    account: Account,
+    query: HistoryQuery,
+    state: SharedState,
+) -> Result<Response, Rejection> {
+    match state.history.query(&account, &state.rooms, &query) {
+        Ok(page) => Ok(warp::reply::json(&page).into_response()),
+        Err(HistoryError::Forbidden) => Ok(StatusCode::FORBIDDEN.into_response()),
+        Err(ex) => {
+            warn!("Could not load history: {ex}");
+            Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response())
+        }
+    }
+}
+
+/// Turn the result of changing a rule into a response, sending every monitor its new rules if
+/// it worked
+async fn rule_response(
+    state: &SharedState,
+    session: &Session,
+    result: Result<Option<Rule>, RuleError>,
+    status: StatusCode,
+) -> Response {
+    let rule = match result {
+        Ok(rule) => rule,
+        Err(RuleError::Invalid(ex)) => {
+            return warp::reply::with_status(warp::reply::json(&ex), StatusCode::BAD_REQUEST)
+                .into_response()
+        }
+        Err(RuleError::NotFound(_)) => return StatusCode::NOT_FOUND.into_response(),
+        Err(ex) => {
+            warn!("Could not change rules: {ex}");
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    push_policies(state);
+
+    match rule {
+        Some(rule) => {
+            info!(
+                "{} saved rule {} ({})",
+                session.username(),
+                rule.name(),
+                rule.id()
+            );
+            warp::reply::with_status(warp::reply::json(&rule), status).into_response()
+        }
+        None => status.into_response(),
+    }
+}
+
+/// List every application blacklist rule
+#[utoipa::path(
+    get,
+    path = "/api/v1/policies",
+    responses(
+        (status = 200, description = "Rules sorted by id", body = [Rule]),
+        (status = 401, description = "Not logged in"),
+    ),
+    security(("session" = []))
+)]
+async fn list_policies(_session: Session, state: SharedState) -> Result<Response, Rejection> {
+    Ok(warp::reply::json(&state.policies.list()).into_response())
+}
+
+/// Add a blacklist rule, it is sent to every monitor it applies to straight away
+#[utoipa::path(
+    post,
+    path = "/api/v1/policies",
+    request_body(content = Rule, description = "The id is ignored"),
+    responses(
+        (status = 201, description = "The rule with its id", body = Rule),
+        (status = 400, description = "A pattern is missing or invalid, or the throttle is out of range", body = PolicyError),
+        (status = 401, description = "Not logged in"),
+        (status = 403, description = "Not an admin"),
+    ),
+    security(("session" = []))
+)]
+async fn create_policy(
+    session: Session,
+    rule: Rule,
+    state: SharedState,
+) -> Result<Response, Rejection> {
+    let result = state.policies.create(rule).map(Some);
+    Ok(rule_response(&state, &session, result, StatusCode::CREATED).await)
+}
+
+/// Replace a blacklist rule
+#[utoipa::path(
+    put,
+    path = "/api/v1/policies/{id}",
+    params(("id" = u64, Path, description = "The rule's id")),
+    request_body(content = Rule, description = "The id is ignored"),
+    responses(
+        (status = 200, body = Rule),
+        (status = 400, description = "A pattern is missing or invalid, or the throttle is out of range", body = PolicyError),
+        (status = 401, description = "Not logged in"),
+        (status = 403, description = "Not an admin"),
+        (status = 404, description = "No rule with that id"),
+    ),
+    security(("session" = []))
+)]
+async fn update_policy(
+    id: RuleId,
+    session: Session,
+    rule: Rule,
+    state: SharedState,
+) -> Result<Response, Rejection> {
+    let result = state.policies.update(id, rule).map(Some);
+    Ok(rule_response(&state, &session, result, StatusCode::OK).await)
+}
+
+/// Remove a blacklist rule
+#[utoipa::path(
+    delete,
+    path = "/api/v1/policies/{id}",
+    params(("id" = u64, Path, description = "The rule's id")),
+    responses(
+        (status = 204, description = "The rule was removed"),
+        (status = 401, description = "Not logged in"),
+        (status = 403, description = "Not an admin"),
+        (status = 404, description = "No rule with that id"),
+    ),
+    security(("session" = []))
+)]
+async fn delete_policy(
+    id: RuleId,
+    session: Session,
+    state: SharedState,
+) -> Result<Response, Rejection> {
+    let result = state.policies.delete(id).map(|()| None);
+    if result.is_ok() {
+        info!("{} removed rule {id}", session.username());
+    }
+    Ok(rule_response(&state, &session, result, StatusCode::NO_CONTENT).await)
+}
+
+/// Turn a timetable that couldn't be saved into a response
+fn schedule_error(ex: ScheduleError) -> Response {
+    match ex {
+        ScheduleError::Invalid(ex) => {
+            warp::reply::with_status(warp::reply::json(&ex), StatusCode::BAD_REQUEST)
+                .into_response()
+        }
+        ScheduleError::Ics(ex) => {
+            warp::reply::with_status(warp::reply::json(&ex), StatusCode::BAD_REQUEST)
+                .into_response()
+        }
+        ex => {
+            warn!("Could not change the timetable: {ex}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Get every room's periods, the holidays and the rule sets
+#[utoipa::path(
+    get,
+    path = "/api/v1/timetable",
+    responses(
+        (status = 200, body = Timetable),
+        (status = 401, description = "Not logged in"),
+    ),
+    security(("session" = []))
+)]
+async fn get_timetable(_session: Session, state: SharedState) -> Result<Response, Rejection> {
+    Ok(warp::reply::json(&state.schedule.get()).into_response())
+}
+
+/// Replace the timetable, rules and locks are switched to match it straight away
+#[utoipa::path(
+    put,
+    path = "/api/v1/timetable",
+    request_body = Timetable,
+    responses(
+        (status = 200, body = Timetable),
+        (status = 400, description = "A period or holiday ends before it starts", body = TimetableError),
+        (status = 401, description = "Not logged in"),
+        (status = 403, description = "Not an admin"),
+    ),
+    security(("session" = []))
+)]
+async fn set_timetable(
+    session: Session,
+    timetable: Timetable,
+    state: SharedState,
+) -> Result<Response, Rejection> {
+    if let Err(ex) = state.schedule.set(timetable) {
+        return Ok(schedule_error(ex));
+    }
+
+    info!("{} changed the timetable", session.username());
+    // Rules can have moved between rule sets without the running periods changing
+    push_policies(&state);
+
+    Ok(warp::reply::json(&state.schedule.get()).into_response())
+}
+
+/// Read periods and holidays from an iCalendar file. The periods of every room in the file are
+/// replaced, holidays are added and rule sets are kept
+#[utoipa::path(
+    post,
+    path = "/api/v1/timetable/import",
+    params(ImportQuery),
+    request_body(content = String, content_type = "text/calendar"),
+    responses(
+        (status = 200, body = ImportResult),
+        (status = 400, description = "The file could not be read", body = IcsError),
+        (status = 401, description = "Not logged in"),
+        (status = 403, description = "Not an admin"),
+    ),
+    security(("session" = []))
+)]
+async fn import_timetable(
+    session: Session,
+    query: ImportQuery,
+    body: Bytes,
+    state: SharedState,
+) -> Result<Response, Rejection> {
+    let ics = match std::str::from_utf8(&body) {
+        Ok(ics) => ics,
+        Err(_) => return Ok(StatusCode::BAD_REQUEST.into_response()),
+    };
+
+    let (timetable, skipped) = match state.schedule.import(ics, query.room.as_ref()) {
+        Ok(result) => result,
+        Err(ex) => return Ok(schedule_error(ex)),
+    };
+
+    info!(
+        "{} imported {} periods and {} holidays, skipping {skipped} events",
+        session.username(),
+        timetable.periods().len(),
+        timetable.holidays().len()
+    );
+    push_policies(&state);
+
+    Ok(warp::reply::json(&ImportResult { timetable, skipped }).into_response())
+}
+
+/// Every route under `/api/v1`, see [`ApiDoc`] for what they are
+pub fn routes(state: SharedState) -> impl Filter<Extract = (Response,), Error = Rejection> + Clone {
+    let openapi = warp::get()
+        .and(warp::path!("api" / "v1" / "openapi.json"))
+        .map(|| warp::reply::json(&ApiDoc::openapi()).into_response());
+
+    let list_machines = warp::get()
+        .and(warp::path!("api" / "v1" / "machines"))
+        .and(auth::account(state.clone()))
+        .and(with_state(state.clone()))
+        .and_then(list_machines);
+
+    let get_machine = warp::get()
+        .and(warp::path!("api" / "v1" / "machines" / String))
+        .and(auth::account(state.clone()))
+        .and(with_state(state.clone()))
+        .and_then(get_machine);
+
+    let get_processes = warp::get()
+        .and(warp::path!(
+            "api" / "v1" / "machines" / String / "processes"
+        ))
+        .and(auth::account(state.clone()))
+        .and(with_state(state.clone()))
+        .and_then(get_processes);
+
+    let get_thumbnail = warp::get()
+        .and(warp::path!(
+            "api" / "v1" / "machines" / String / "thumbnail"
+        ))
+        .and(auth::account(state.clone()))
+        .and(warp::query::<ThumbnailQuery>())
+        .and(warp::header::optional::<String>(IF_NONE_MATCH.as_str()))
+        .and(with_state(state.clone()))
+        .and_then(get_thumbnail);
+
+    let run_command = warp::post()
+        .and(warp::path!("api" / "v1" / "machines" / String / "commands"))
+        .and(auth::authenticated(state.clone()))
+        .and(warp::body::content_length_limit(16 * 1024))
+        .and(warp::body::json())
+        .and(with_state(state.clone()))
+        .and_then(run_command);
+
+    let list_rooms = warp::get()
+        .and(warp::path!("api" / "v1" / "rooms"))
+        .and(auth::account(state.clone()))
+        .and(with_state(state.clone()))
+        .and_then(list_rooms);
+
+    let set_room = warp::put()
+        .and(warp::path!("api" / "v1" / "rooms" / String))
+        .and(auth::admin(state.clone()))
+        .and(warp::body::content_length_limit(64 * 1024))
+        .and(warp::body::json())
+        .and(with_state(state.clone()))
+        .and_then(set_room);
+
+    let get_history = warp::get()
+        .and(warp::path!("api" / "v1" / "history"))
+        .and(auth::account(state.clone()))
+        .and(warp::query::<HistoryQuery>())
+        .and(with_state(state.clone()))
+        .and_then(get_history);
+
+    let list_policies = warp::get()
+        .and(warp::path!("api" / "v1" / "policies"))
+        .and(auth::authenticated(state.clone()))
+        .and(with_state(state.clone()))
+        .and_then(list_policies);
+
+    let create_policy = warp::post()
+        .and(warp::path!("api" / "v1" / "policies"))
+        .and(auth::admin(state.clone()))
+        .and(warp::body::content_length_limit(16 * 1024))
+        .and(warp::body::json())
+        .and(with_state(state.clone()))
+        .and_then(create_policy);
+
+    let update_policy = warp::put()
+        .and(warp::path!("api" / "v1" / "policies" / RuleId))
+        .and(auth::admin(state.clone()))
+        .and(warp::body::content_length_limit(16 * 1024))
+        .and(warp::body::json())
+        .and(with_state(state.clone()))
+        .and_then(update_policy);
+
+    let delete_policy = warp::delete()
+        .and(warp::path!("api" / "v1" / "policies" / RuleId))
+        .and(auth::admin(state.clone()))
+        .and(with_state(state.clone()))
+        .and_then(delete_policy);
+
+    let get_timetable = warp::get()
+        .and(warp::path!("api" / "v1" / "timetable"))
+        .and(auth::authenticated(state.clone()))
+        .and(with_state(state.clone()))
+        .and_then(get_timetable);
+
+    let set_timetable = warp::put()
+        .and(warp::path!("api" / "v1" / "timetable"))
+        .and(auth::admin(state.clone()))
+        .and(warp::body::content_length_limit(1024 * 1024))
+        .and(warp::body::json())
+        .and(with_state(state.clone()))
+        .and_then(set_timetable);
+
+    let import_timetable = warp::post()
+        .and(warp::path!("api" / "v1" / "timetable" / "import"))
+        .and(auth::admin(state.clone()))
+        .and(warp::query::<ImportQuery>())
+        .and(warp::body::content_length_limit(4 * 1024 * 1024))
+        .and(warp::body::bytes())
+        .and(with_state(state))
+        .and_then(import_timetable);
+
+    openapi
+        .or(list_machines)
+        .unify()
+        .or(get_machine)
+        .unify()
+        .or(get_processes)
+        .unify()
+        .or(get_thumbnail)
+        .unify()
+        .or(run_command)
+        .unify()
+        .or(list_rooms)
+        .unify()
+        .or(set_room)
+        .unify()
+        .or(get_history)
+        .unify()
+        .or(list_policies)
+        .unify()
+        .or(create_policy)
+        .unify()
+        .or(update_policy)
+        .unify()
+        .or(delete_policy)
+        .unify()
+        .or(get_timetable)
+        .unify()
+        .or(set_timetable)
+        .unify()
+        .or(import_timetable)
+        .unify()
+        .recover(auth::handle_rejection)
+        .unify()
+}
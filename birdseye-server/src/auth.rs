@@ -0,0 +1,171 @@
+//! Login and logout endpoints, and the filter used to require a login on other routes
+
+use crate::accounts::Account;
+use crate::sessions::Session;
+use crate::state::{with_state, SharedState};
+use birdseye_common::auth::LoginRequest;
+use std::convert::Infallible;
+use tracing::{info, warn};
+use warp::http::header::SET_COOKIE;
+use warp::http::StatusCode;
+use warp::reject::Reject;
+use warp::reply::Response;
+use warp::{Filter, Rejection, Reply};
+
+/// Name of the cookie holding the session token
+pub const SESSION_COOKIE: &str = "birdseye_session";
+
+/// The request needs a logged in session and doesn't have one
+#[derive(Debug)]
+pub struct Unauthorized;
+
+impl Reject for Unauthorized {}
+
+/// The request is authenticated but isn't allowed to do what it asked
+#[derive(Debug)]
+pub struct Forbidden;
+
+impl Reject for Forbidden {}
+
+/// Build the `Set-Cookie` header for a session, the cookie can't be read by scripts and is only
+/// sent over https to this site
+fn session_cookie(token: &str, max_age: u64) -> String {
+    format!(
+        "{SESSION_COOKIE}={token}; Path=/; Max-Age={max_age}; HttpOnly; Secure; SameSite=Strict"
+    )
+}
+
+/// Filter that extracts the session of the logged in teacher, rejecting with [`Unauthorized`] if
+/// there isn't one
+pub fn authenticated(
+    state: SharedState,
+) -> impl Filter<Extract = (Session,), Error = Rejection> + Clone {
+    warp::cookie::optional::<String>(SESSION_COOKIE)
+        .and(with_state(state))
+        .and_then(|token: Option<String>, state: SharedState| async move {
+            token
+                .and_then(|token| state.sessions.get(&token))
+                .ok_or_else(|| warp::reject::custom(Unauthorized))
+        })
+}
+
+/// Like [`authenticated`] but extracts the account the session belongs to, looked up each time so
+/// changes to the account apply straight away
+pub fn account(state: SharedState) -> impl Filter<Extract = (Account,), Error = Rejection> + Clone {
+    authenticated(state.clone())
+        .and(with_state(state))
+        .and_then(|session: Session, state: SharedState| async move {
+            state
+                .accounts
+                .get(session.username())
+                .ok_or_else(|| warp::reject::custom(Unauthorized))
+        })
+}
+
+/// Like [`authenticated`] but the session must also belong to an admin, rejecting with
+/// [`Forbidden`] otherwise. The account is looked up each time like in [`account`], so an admin
+/// who is demoted loses access straight away
+pub fn admin(state: SharedState) -> impl Filter<Extract = (Session,), Error = Rejection> + Clone {
+    authenticated(state.clone())
+        .and(with_state(state))
+        .and_then(|session: Session, state: SharedState| async move {
+            match state.accounts.get(session.username()) {
+                Some(account) if account.is_admin() => Ok(session),
+                Some(_) => Err(warp::reject::custom(Forbidden)),
+                None => Err(warp::reject::custom(Unauthorized)),
+            }
+        })
+}
+
+async fn login(request: LoginRequest, state: SharedState) -> Result<Response, Infallible> {
+    // Hashing is slow on purpose, so keep it off the async threads
+    let verify_state = state.clone();
+    let username = request.username.clone();
+    let account = tokio::task::spawn_blocking(move || {
+        verify_state
+            .accounts
+            .verify(&request.username, &request.password)
+    })
+    .await
+    .ok()
+    .flatten();
+
+    let account = match account {
+        Some(account) => account,
+        None => {
+            warn!("Failed login for {username}");
+            return Ok(StatusCode::UNAUTHORIZED.into_response());
+        }
+    };
+
+    info!("{} logged in", account.username());
+    let token = state.sessions.create(&account);
+    let cookie = session_cookie(&token, state.sessions.ttl().as_secs());
+
+    Ok(
+        warp::reply::with_header(warp::reply::json(&account.info()), SET_COOKIE, cookie)
+            .into_response(),
+    )
+}
+
+async fn logout(token: Option<String>, state: SharedState) -> Result<Response, Infallible> {
+    if let Some(token) = token {
+        state.sessions.remove(&token);
+    }
+
+    Ok(
+        warp::reply::with_header(StatusCode::NO_CONTENT, SET_COOKIE, session_cookie("", 0))
+            .into_response(),
+    )
+}
+
+/// `POST /login`, `POST /logout` and `GET /session`
+pub fn routes(state: SharedState) -> impl Filter<Extract = (Response,), Error = Rejection> + Clone {
+    let login = warp::post()
+        .and(warp::path("login"))
+        .and(warp::path::end())
+        .and(warp::body::content_length_limit(4 * 1024))
+        .and(warp::body::json())
+        .and(with_state(state.clone()))
+        .and_then(login);
+
+    let logout = warp::post()
+        .and(warp::path("logout"))
+        .and(warp::path::end())
+        .and(warp::cookie::optional::<String>(SESSION_COOKIE))
+        .and(with_state(state.clone()))
+        .and_then(logout);
+
+    // The account is looked up again so changes made since logging in show up
+    let session = warp::get()
+        .and(warp::path("session"))
+        .and(warp::path::end())
+        .and(authenticated(state.clone()))
+        .and(with_state(state))
+        .map(
+            |session: Session, state: SharedState| match state.accounts.get(session.username()) {
+                Some(account) => warp::reply::json(&account.info()).into_response(),
+                None => StatusCode::UNAUTHORIZED.into_response(),
+            },
+        );
+
+    login
+        .or(logout)
+        .unify()
+        .or(session)
+        .unify()
+        .recover(handle_rejection)
+        .unify()
+}
+
+/// Turn an [`Unauthorized`] or [`Forbidden`] rejection into a 401 or 403 response, anything else
+/// is passed on
+pub async fn handle_rejection(rejection: Rejection) -> Result<Response, Rejection> {
+    if rejection.find::<Unauthorized>().is_some() {
+        Ok(StatusCode::UNAUTHORIZED.into_response())
+    } else if rejection.find::<Forbidden>().is_some() {
+        Ok(StatusCode::FORBIDDEN.into_response())
+    } else {
+        Err(rejection)
+    }
+}
@@ -0,0 +1,129 @@
+//! The server's own certificate authority, used to issue client certificates to monitors
+
+use birdseye_common::MachineId;
+use rcgen::{
+    BasicConstraints, Certificate, CertificateParams, CertificateSigningRequest, DistinguishedName,
+    DnType, ExtendedKeyUsagePurpose, IsCa, KeyPair, RcgenError,
+};
+use std::fmt::{Display, Formatter};
+use std::fs::{read_to_string, write, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+use time::{Duration, OffsetDateTime};
+use tokio_rustls::rustls;
+use tracing::info;
+
+/// How long certificates issued to monitors are valid for
+const CERT_VALIDITY: Duration = Duration::days(2 * 365);
+
+/// How long the CA's own certificate is valid for
+const CA_VALIDITY: Duration = Duration::days(20 * 365);
+
+#[derive(Debug)]
+pub enum CaError {
+    Io(std::io::Error),
+    Certificate(RcgenError),
+}
+
+impl Display for CaError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CaError::Io(ex) => write!(f, "could not access CA files: {ex}"),
+            CaError::Certificate(ex) => write!(f, "certificate error: {ex}"),
+        }
+    }
+}
+
+impl std::error::Error for CaError {}
+
+impl From<RcgenError> for CaError {
+    fn from(ex: RcgenError) -> Self {
+        CaError::Certificate(ex)
+    }
+}
+
+pub struct CertificateAuthority {
+    /// Used to sign certificates, re-creating the certificate from its params gives a different
+    /// certificate than the one on disk, so the original is kept in `der`
+    signer: Certificate,
+    der: Vec<u8>,
+}
+
+impl CertificateAuthority {
+    /// Load the CA from `cert_path` and `key_path`, creating a new CA if they don't exist yet
+    pub fn load_or_create(cert_path: &Path, key_path: &Path) -> Result<Self, CaError> {
+        let (cert_pem, key_pem) = match (read_to_string(cert_path), read_to_string(key_path)) {
+            (Ok(cert), Ok(key)) => (cert, key),
+            (Err(ex), _) | (_, Err(ex)) if ex.kind() != ErrorKind::NotFound => {
+                return Err(CaError::Io(ex))
+            }
+            _ => {
+                info!("Creating certificate authority in {}", cert_path.display());
+
+                let mut params = CertificateParams::new(vec![]);
+                params.is_ca = IsCa::Ca(BasicConstraints::Constrained(0));
+                params
+                    .distinguished_name
+                    .push(DnType::CommonName, "BirdsEye Monitor CA");
+                params.not_before = OffsetDateTime::now_utc();
+                params.not_after = OffsetDateTime::now_utc() + CA_VALIDITY;
+
+                let ca = Certificate::from_params(params)?;
+                let (cert, key) = (ca.serialize_pem()?, ca.serialize_private_key_pem());
+
+                write_private(key_path, &key).map_err(CaError::Io)?;
+                write(cert_path, &cert).map_err(CaError::Io)?;
+                (cert, key)
+            }
+        };
+
+        let params = CertificateParams::from_ca_cert_pem(&cert_pem, KeyPair::from_pem(&key_pem)?)?;
+        let der = pem_to_der(&cert_pem)?;
+
+        Ok(Self {
+            signer: Certificate::from_params(params)?,
+            der,
+        })
+    }
+
+    /// The CA's certificate, for adding to the trusted roots of the TLS server
+    pub fn certificate(&self) -> rustls::Certificate {
+        rustls::Certificate(self.der.clone())
+    }
+
+    /// Sign a monitor's certificate signing request, the certificate is always issued to `machine`
+    /// whatever the request asked for
+    pub fn sign(&self, csr: &str, machine: &MachineId, serial: u64) -> Result<String, CaError> {
+        let mut csr = CertificateSigningRequest::from_pem(csr)?;
+
+        let mut name = DistinguishedName::new();
+        name.push(DnType::CommonName, machine.as_str());
+        csr.params.distinguished_name = name;
+        csr.params.serial_number = Some(serial);
+        csr.params.is_ca = IsCa::NoCa;
+        csr.params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+        csr.params.subject_alt_names = vec![];
+        csr.params.not_before = OffsetDateTime::now_utc();
+        csr.params.not_after = OffsetDateTime::now_utc() + CERT_VALIDITY;
+
+        Ok(csr.serialize_pem_with_signer(&self.signer)?)
+    }
+}
+
+/// Write a file that only the server's user can read
+fn write_private(path: &Path, contents: &str) -> std::io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    options.open(path)?.write_all(contents.as_bytes())
+}
+
+fn pem_to_der(pem: &str) -> Result<Vec<u8>, CaError> {
+    rustls::internal::pemfile::certs(&mut pem.as_bytes())
+        .ok()
+        .and_then(|certs| certs.into_iter().next())
+        .map(|cert| cert.0)
+        .ok_or(CaError::Certificate(RcgenError::CouldNotParseCertificate))
+}
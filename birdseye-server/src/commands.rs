@@ -0,0 +1,76 @@
+//! Tracking of commands sent to monitors so their results can be matched back up to the request
+//!
+//! Results are only matched against commands sent to the machine that replied, so a monitor can't
+//! answer for another machine by guessing its request ids.
+
+use birdseye_common::backend::{CommandResult, ServerMessage};
+use birdseye_common::command::{Command, CommandError, RequestId};
+use birdseye_common::MachineId;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::timeout;
+use tracing::warn;
+
+/// Commands that have been sent to monitors and are waiting on a result
+pub struct Commands {
+    next_id: AtomicU64,
+    pending: Mutex<HashMap<(MachineId, RequestId), oneshot::Sender<CommandResult>>>,
+    timeout: Duration,
+}
+
+impl Commands {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            next_id: AtomicU64::new(0),
+            pending: Mutex::new(HashMap::new()),
+            timeout,
+        }
+    }
+
+    /// Send a command down `machine`'s link and wait for its monitor to reply, fails with
+    /// [`CommandError::Timeout`] if no reply arrives in time
+    pub async fn run(
+        &self,
+        machine: &MachineId,
+        link: &mpsc::Sender<ServerMessage>,
+        command: Command,
+    ) -> Result<(), CommandError> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let key = (machine.clone(), id);
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert(key.clone(), tx);
+
+        if link
+            .send(ServerMessage::Command { id, command })
+            .await
+            .is_err()
+        {
+            self.pending.lock().unwrap().remove(&key);
+            return Err(CommandError::Failed("the machine disconnected".into()));
+        }
+
+        let result = timeout(self.timeout, rx).await;
+
+        // Make sure a late reply doesn't find a stale entry
+        self.pending.lock().unwrap().remove(&key);
+
+        match result {
+            Ok(Ok(result)) => result.into(),
+            Ok(Err(_)) => Err(CommandError::Failed("the machine disconnected".into())),
+            Err(_) => Err(CommandError::Timeout),
+        }
+    }
+
+    /// Pass the result of a command sent to `machine` on to whoever is waiting for it
+    pub fn resolve(&self, machine: &MachineId, id: RequestId, result: CommandResult) {
+        match self.pending.lock().unwrap().remove(&(machine.clone(), id)) {
+            Some(tx) => {
+                let _ = tx.send(result);
+            }
+            None => warn!("Got result from {machine} for unknown or timed out command {id}"),
+        }
+    }
+}
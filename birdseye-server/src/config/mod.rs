@@ -4,20 +4,24 @@ mod server;
 
 pub use server::ServerConfig;
 
+use birdseye_common::{MachineId, RoomId};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::env::{args, var};
 use std::fs::read_to_string;
 use tracing::warn;
 
 /// Configuration for the birdseye-server application
 /// # Configuration
-/// | Field     | Environment Variable | Type         | Default                                  | Description                                        |
-/// |-----------|----------------------|--------------|------------------------------------------|----------------------------------------------------|
-/// | be_server | BE_SERVER            | ServerConfig | See [ServerConfig](birdseye-server::ServerConfig) | The configuration for the birdseye-server |
+/// | Field     | Environment Variable | Type                               | Default                                           | Description                                                                                                     |
+/// |-----------|----------------------|------------------------------------|---------------------------------------------------|-----------------------------------------------------------------------------------------------------------------|
+/// | be_server | BE_SERVER            | ServerConfig                       | See [ServerConfig](birdseye-server::ServerConfig) | The configuration for the birdseye-server                                                                       |
+/// | rooms     | None                 | Map of RoomId to list of MachineId | Empty                                             | The machines in each room, e.g. `"Room 12" = ["<machine id>"]`, copied into the database when the server starts |
 #[derive(Serialize, Deserialize, Debug, Default)]
 #[serde(default)]
 pub struct Config {
     pub be_server: ServerConfig,
+    pub rooms: BTreeMap<RoomId, Vec<MachineId>>,
 }
 
 impl Config {
@@ -25,6 +29,8 @@ impl Config {
         Self {
             // Get the config for the BirdsEye Server
             be_server: ServerConfig::from_env(),
+            // Rooms can only be set in the config file
+            rooms: BTreeMap::new(),
         }
     }
 }
@@ -8,12 +8,24 @@ use tracing::warn;
 /// Configuration for the Birds Eye birdseye-server
 ///
 /// # Configuration
-/// | Field | Environment Variable | Type    | Default       | Description                                                               |
-/// |-------|----------------------|---------|---------------|---------------------------------------------------------------------------|
-/// | key   | BE_SERVER_KEY        | PathBuf | `key.pem`     | The location of the key to be used by the birdseye-server for TLS         |
-/// | cert  | BE_SERVER_CERT       | PathBuf | `cert.pem`    | The location of the certificate to be used by the birdseye-server for TLS |
-/// | host  | BE_SERVER_HOST       | String  | `"127.0.0.1"` | The host for the BirdsEye birdseye-server to bind to                      |
-/// | port  | BE_SERVER_PORT       | u16     | `42069`       | The port for the BirdsEye birdseye-server to bind to                      |
+/// | Field               | Environment Variable   | Type            | Default       | Description                                                                                            |
+/// |---------------------|------------------------|-----------------|---------------|--------------------------------------------------------------------------------------------------------|
+/// | key                 | BE_SERVER_KEY          | PathBuf         | `key.pem`     | The location of the key to be used by the birdseye-server for TLS                                      |
+/// | cert                | BE_SERVER_CERT         | PathBuf         | `cert.pem`    | The location of the certificate to be used by the birdseye-server for TLS                              |
+/// | host                | BE_SERVER_HOST         | String          | `"127.0.0.1"` | The host for the BirdsEye birdseye-server to bind to                                                   |
+/// | port                | BE_SERVER_PORT         | u16             | `42069`       | The port for the BirdsEye birdseye-server to bind to                                                   |
+/// | command_timeout     | BE_COMMAND_TIMEOUT     | u64             | `10`          | Seconds to wait for a monitor to reply to a command before giving up                                   |
+/// | client_buffer       | BE_CLIENT_BUFFER       | usize           | `256`         | Messages queued for a dashboard before new ones are dropped                                            |
+/// | ca_cert             | BE_CA_CERT             | PathBuf         | `ca.pem`      | Certificate of the internal CA used to issue monitor client certificates, created if it doesn't exist  |
+/// | ca_key              | BE_CA_KEY              | PathBuf         | `ca-key.pem`  | Private key of the internal CA                                                                         |
+/// | token_ttl           | BE_TOKEN_TTL           | u64             | `24`          | Hours an enrollment token can be used for                                                              |
+/// | client_ca           | BE_CLIENT_CA           | Option<PathBuf> | `None`        | Extra CA trusted for monitor client certificates on top of the internal CA                             |
+/// | require_client_cert | BE_REQUIRE_CLIENT_CERT | bool            | `false`       | Refuse monitors that don't present a client certificate                                                |
+/// | database            | BE_DATABASE            | PathBuf         | `birdseye.db` | SQLite database holding accounts, machines, rooms, enrollment and history, created if it doesn't exist |
+/// | session_ttl         | BE_SESSION_TTL         | u64             | `12`          | Hours a login lasts before the teacher has to log in again                                             |
+/// | timezone            | BE_TIMEZONE            | String          | `"UTC"`       | IANA timezone the timetable is in, e.g. `"Pacific/Auckland"`                                           |
+/// | thumbnail_widths    | BE_THUMBNAIL_WIDTHS    | Vec<u32>        | `[160, 320]`  | Widths screen thumbnails can be made in, comma separated in the env                                    |
+/// | thumbnail_quality   | BE_THUMBNAIL_QUALITY   | u8              | `75`          | JPEG quality of screen thumbnails, from 1 to 100                                                       |
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(default)]
 pub struct ServerConfig {
@@ -22,6 +34,18 @@ pub struct ServerConfig {
     pub host: String,
     pub port: u16,
     pub static_path: PathBuf,
+    pub command_timeout: u64,
+    pub client_buffer: usize,
+    pub ca_cert: PathBuf,
+    pub ca_key: PathBuf,
+    pub token_ttl: u64,
+    pub client_ca: Option<PathBuf>,
+    pub require_client_cert: bool,
+    pub database: PathBuf,
+    pub session_ttl: u64,
+    pub timezone: String,
+    pub thumbnail_widths: Vec<u32>,
+    pub thumbnail_quality: u8,
 }
 
 impl ServerConfig {
@@ -59,6 +83,109 @@ impl ServerConfig {
             }
         }
 
+        // Get how long to wait for monitors to reply to commands
+        if let Ok(timeout) = var("BE_COMMAND_TIMEOUT") {
+            match timeout.parse() {
+                Ok(timeout) => slf.command_timeout = timeout,
+                Err(err) => warn!("Invalid timeout for BE_COMMAND_TIMEOUT {err}, using default 10"),
+            }
+        }
+
+        // Get how many messages can be queued for each dashboard
+        if let Ok(buffer) = var("BE_CLIENT_BUFFER") {
+            match buffer.parse() {
+                Ok(buffer) => slf.client_buffer = buffer,
+                Err(err) => warn!("Invalid size for BE_CLIENT_BUFFER {err}, using default 256"),
+            }
+        }
+
+        // Get the paths for the internal CA
+        if let Ok(ca_cert) = var("BE_CA_CERT") {
+            match ca_cert.parse() {
+                Ok(ca_cert) => slf.ca_cert = ca_cert,
+                Err(err) => warn!("Invalid path for BE_CA_CERT {err}, using default `ca.pem`"),
+            }
+        }
+
+        if let Ok(ca_key) = var("BE_CA_KEY") {
+            match ca_key.parse() {
+                Ok(ca_key) => slf.ca_key = ca_key,
+                Err(err) => warn!("Invalid path for BE_CA_KEY {err}, using default `ca-key.pem`"),
+            }
+        }
+
+        // Get how long enrollment tokens last
+        if let Ok(ttl) = var("BE_TOKEN_TTL") {
+            match ttl.parse() {
+                Ok(ttl) => slf.token_ttl = ttl,
+                Err(err) => warn!("Invalid hours for BE_TOKEN_TTL {err}, using default 24"),
+            }
+        }
+
+        // Get the extra CA for monitor client certificates
+        if let Ok(client_ca) = var("BE_CLIENT_CA") {
+            match client_ca.parse() {
+                Ok(client_ca) => slf.client_ca = Some(client_ca),
+                Err(err) => warn!("Invalid path for BE_CLIENT_CA {err}, ignoring"),
+            }
+        }
+
+        // Get whether monitors must have a client certificate
+        if let Ok(require) = var("BE_REQUIRE_CLIENT_CERT") {
+            match require.parse() {
+                Ok(require) => slf.require_client_cert = require,
+                Err(err) => {
+                    warn!("Invalid bool for BE_REQUIRE_CLIENT_CERT {err}, using default false")
+                }
+            }
+        }
+
+        // Get where the database is
+        if let Ok(database) = var("BE_DATABASE") {
+            match database.parse() {
+                Ok(database) => slf.database = database,
+                Err(err) => {
+                    warn!("Invalid path for BE_DATABASE {err}, using default `birdseye.db`")
+                }
+            }
+        }
+
+        // Get how long logins last
+        if let Ok(ttl) = var("BE_SESSION_TTL") {
+            match ttl.parse() {
+                Ok(ttl) => slf.session_ttl = ttl,
+                Err(err) => warn!("Invalid hours for BE_SESSION_TTL {err}, using default 12"),
+            }
+        }
+
+        // Get the timezone the timetable is in
+        if let Ok(timezone) = var("BE_TIMEZONE") {
+            slf.timezone = timezone;
+        }
+
+        // Get the sizes screen thumbnails are made in
+        if let Ok(widths) = var("BE_THUMBNAIL_WIDTHS") {
+            match widths
+                .split(',')
+                .map(|width| width.trim().parse())
+                .collect()
+            {
+                Ok(widths) => slf.thumbnail_widths = widths,
+                Err(err) => {
+                    warn!("Invalid widths for BE_THUMBNAIL_WIDTHS {err}, using default 160,320")
+                }
+            }
+        }
+
+        if let Ok(quality) = var("BE_THUMBNAIL_QUALITY") {
+            match quality.parse() {
+                Ok(quality) => slf.thumbnail_quality = quality,
+                Err(err) => {
+                    warn!("Invalid quality for BE_THUMBNAIL_QUALITY {err}, using default 75")
+                }
+            }
+        }
+
         slf
     }
 }
@@ -71,6 +198,18 @@ impl Default for ServerConfig {
             port: 42069,
             host: "127.0.0.1".into(),
             static_path: "static".into(),
+            command_timeout: 10,
+            client_buffer: 256,
+            ca_cert: "ca.pem".into(),
+            ca_key: "ca-key.pem".into(),
+            token_ttl: 24,
+            client_ca: None,
+            require_client_cert: false,
+            database: "birdseye.db".into(),
+            session_ttl: 12,
+            timezone: "UTC".into(),
+            thumbnail_widths: vec![160, 320],
+            thumbnail_quality: 75,
         }
     }
 }
@@ -0,0 +1,408 @@
+//! Handling for the websocket connections made by the dashboard
+
+use crate::hub::ClientId;
+use crate::processes::RESTRICTION_VERSION;
+use crate::sessions::Session;
+use crate::socket::{decode, encode};
+use crate::state::SharedState;
+use birdseye_common::codec::Codec;
+use birdseye_common::command::{Command, CommandError, RequestId};
+use birdseye_common::frontend::{Topic, WsMessage};
+use birdseye_common::handshake::{HandshakeError, Hello, Negotiated};
+use birdseye_common::screen::tiles::TileHeader;
+use birdseye_common::screen::{Encoding, Packet};
+use birdseye_common::sync::ProcessSync;
+use birdseye_common::MachineId;
+use futures_util::{SinkExt, StreamExt};
+use std::collections::HashMap;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info, warn};
+use warp::ws::WebSocket;
+
+/// How many frames can be waiting for a dashboard on top of the one being sent to it
+const FRAME_BUFFER: usize = 1;
+
+/// The first protocol version where dashboards are sent [`WsMessage::Packet`]
+const PACKET_VERSION: u32 = 11;
+
+/// The first protocol version where dashboards are sent packets with [`Encoding::Tiles`]
+const TILES_VERSION: u32 = 12;
+
+/// The first protocol version where dashboards are sent packets with [`Encoding::H264`]
+const H264_VERSION: u32 = 14;
+
+/// The hello sent by the server to every dashboard
+fn server_hello() -> Hello {
+    Hello::new(concat!("birdseye-server/", env!("CARGO_PKG_VERSION")), [])
+}
+
+/// Send a message to a dashboard, returns false if the socket has been closed
+async fn send(websocket: &mut WebSocket, codec: Codec, msg: &WsMessage) -> bool {
+    match encode(codec, msg) {
+        Some(msg) => websocket.send(msg).await.is_ok(),
+        None => true,
+    }
+}
+
+/// Wait for the dashboard's hello and reply with our own, returns `None` if the dashboard was
+/// rejected or disconnected
+async fn handshake(websocket: &mut WebSocket, codec: Codec) -> Option<Negotiated> {
+    let msg = match websocket.next().await? {
+        Ok(msg) => msg,
+        Err(ex) => {
+            error!("websocket error: {ex:?}");
+            return None;
+        }
+    };
+
+    let result = match decode::<WsMessage>(codec, &msg) {
+        Ok(WsMessage::Hello(hello)) => server_hello().negotiate(&hello),
+        Ok(_) | Err(_) => Err(HandshakeError::ExpectedHello),
+    };
+
+    match result {
+        Ok(negotiated) => send(websocket, codec, &WsMessage::Hello(server_hello()))
+            .await
+            .then_some(negotiated),
+        Err(ex) => {
+            warn!("Rejecting dashboard: {ex}");
+            send(websocket, codec, &WsMessage::Rejected(ex.to_string())).await;
+            let _ = websocket.close().await;
+            None
+        }
+    }
+}
+
+/// Whether the teacher logged in to a session can see a machine, the account is looked up each time
+/// so a change to the teacher's rooms applies straight away
+fn can_see(state: &SharedState, session: &Session, machine: &MachineId) -> bool {
+    state
+        .accounts
+        .get(session.username())
+        .map(|account| state.rooms.can_see(&account, machine))
+        .unwrap_or_default()
+}
+
+/// Pass a command on to the machine it is for, the dashboard is sent an ack once the command has
+/// reached the machine's link
+async fn run_command(
+    state: &SharedState,
+    session: &Session,
+    tx: &mpsc::Sender<WsMessage>,
+    id: RequestId,
+    machine: MachineId,
+    command: Command,
+) -> Result<(), CommandError> {
+    if !can_see(state, session, &machine) {
+        warn!(
+            "{} tried to run {command:?} on {machine} outside of their rooms",
+            session.username()
+        );
+        return Err(CommandError::PermissionDenied(
+            "the machine is not in one of your rooms".into(),
+        ));
+    }
+
+    let link = state.command_link(&machine, &command)?;
+    let _ = tx.send(WsMessage::Ack(id)).await;
+
+    let result = state.commands.run(&machine, &link, command.clone()).await;
+    if result.is_ok() {
+        state.schedule.commanded(&machine, &command);
+    }
+    result
+}
+
+/// Subscribe a dashboard to a topic and send it the current state of every machine in the topic,
+/// returns false if the teacher isn't allowed to see the topic. `version` is the protocol version
+/// the dashboard negotiated
+fn subscribe(
+    state: &SharedState,
+    session: &Session,
+    client: ClientId,
+    version: u32,
+    topic: Topic,
+) -> bool {
+    let allowed = state
+        .accounts
+        .get(session.username())
+        .map(|account| state.rooms.can_subscribe(&account, &topic))
+        .unwrap_or_default();
+    if !allowed {
+        warn!(
+            "{} tried to subscribe to {topic:?} outside of their rooms",
+            session.username()
+        );
+        return false;
+    }
+
+    let machines = state.rooms.machines(&topic);
+
+    // The stores are held until the dashboard is subscribed, so it can't miss a change made
+    // between the snapshot and the subscription
+    state.registry.with_machines(&machines, |registered| {
+        state.processes.with_snapshots(&machines, |snapshots| {
+            state.processes.with_restrictions(&machines, |restricted| {
+                state.hub.subscribe(client, topic);
+
+                for (machine, presence) in registered {
+                    state
+                        .hub
+                        .send(client, WsMessage::Machine { machine, presence });
+                }
+
+                for (machine, snapshot) in snapshots {
+                    let sync = ProcessSync::Snapshot(snapshot);
+                    state
+                        .hub
+                        .send(client, WsMessage::Processes { machine, sync });
+                }
+
+                if version >= RESTRICTION_VERSION {
+                    for (machine, restrictions) in restricted {
+                        let msg = WsMessage::Restrictions {
+                            machine,
+                            restrictions,
+                        };
+                        state.hub.send(client, msg);
+                    }
+                }
+            })
+        })
+    });
+
+    true
+}
+
+/// Start relaying frames from a machine's screen to a dashboard, returns `None` if the teacher
+/// can't see the machine. A frame is only handed over once the dashboard has taken the last one,
+/// and they stop once the dashboard is no longer watching. Dashboards older than
+/// [`PACKET_VERSION`] are only sent raw frames, and ones older than [`TILES_VERSION`] are sent
+/// the whole screen decoded instead of tiles
+fn watch_screen(
+    state: &SharedState,
+    session: &Session,
+    client: ClientId,
+    version: u32,
+    machine: MachineId,
+    frames: mpsc::Sender<WsMessage>,
+) -> Option<JoinHandle<()>> {
+    if !can_see(state, session, &machine) {
+        warn!(
+            "{} tried to watch {machine} outside of their rooms",
+            session.username()
+        );
+        return None;
+    }
+
+    state.hub.watch_screen(client, machine.clone());
+    let mut screen = state.screens.watch(&machine);
+    let state = state.clone();
+
+    Some(tokio::spawn(async move {
+        // The sequence of the last tiles sent, so deltas can be checked to follow on from it
+        let mut sent = None;
+
+        loop {
+            let packet = screen.borrow_and_update().clone();
+            if !state.hub.is_watching(client, &machine) {
+                break;
+            }
+
+            let packet = match packet {
+                Some(packet) if matches!(packet.encoding(), Encoding::Tiles | Encoding::H264) => {
+                    next_tiles(&state, &machine, version, packet, &mut sent)
+                }
+                packet => packet,
+            };
+
+            let machine = machine.clone();
+            let msg = match packet {
+                Some(packet) if version >= PACKET_VERSION => {
+                    Some(WsMessage::Packet { machine, packet })
+                }
+                Some(packet) if packet.encoding() != Encoding::Jpeg => packet
+                    .decode()
+                    .ok()
+                    .map(|frame| WsMessage::Frame { machine, frame }),
+                _ => None,
+            };
+
+            if let Some(msg) = msg {
+                if frames.send(msg).await.is_err() {
+                    break;
+                }
+            }
+
+            if screen.changed().await.is_err() {
+                break;
+            }
+        }
+    }))
+}
+
+/// Work out what to send a dashboard for a tiles or H.264 packet. A delta is sent as is if it
+/// follows on from the last packet the dashboard was sent, otherwise it is sent the whole screen
+/// instead. Dashboards that can't take the encoding are sent the whole screen decoded. Returns
+/// `None` if the dashboard already has it
+fn next_tiles(
+    state: &SharedState,
+    machine: &MachineId,
+    version: u32,
+    packet: Packet,
+    sent: &mut Option<u64>,
+) -> Option<Packet> {
+    let header = TileHeader::read(&packet).ok()?;
+    let supported = match packet.encoding() {
+        Encoding::H264 => H264_VERSION,
+        _ => TILES_VERSION,
+    };
+    if version < supported {
+        let (_, whole) = state.screens.latest(machine)?;
+        return whole.decode().ok().map(Packet::from);
+    }
+
+    if !header.keyframe() && sent.is_some_and(|sent| header.sequence() <= sent) {
+        return None;
+    }
+
+    let follows = header.keyframe() || Some(header.base()) == *sent;
+    let packet = match follows {
+        true => packet,
+        false => state.screens.latest(machine)?.1,
+    };
+    *sent = TileHeader::read(&packet)
+        .ok()
+        .map(|header| header.sequence());
+    Some(packet)
+}
+
+/// Send a dashboard a new snapshot of a machine's process list after it missed an update
+fn resync_processes(state: &SharedState, client: ClientId, machine: MachineId) {
+    if !state.hub.is_subscribed(client, &machine) {
+        return;
+    }
+
+    state.processes.with_snapshots(&[machine], |snapshots| {
+        for (machine, snapshot) in snapshots {
+            let sync = ProcessSync::Snapshot(snapshot);
+            state
+                .hub
+                .send(client, WsMessage::Processes { machine, sync });
+        }
+    });
+}
+
+/// Run a dashboard connection until it is closed
+pub async fn handle_dashboard(
+    state: SharedState,
+    session: Session,
+    mut websocket: WebSocket,
+    codec: Codec,
+) {
+    let negotiated = match handshake(&mut websocket, codec).await {
+        Some(negotiated) => negotiated,
+        None => return,
+    };
+
+    info!(
+        "{} connected to the dashboard with {} using protocol version {} and {codec}",
+        session.username(),
+        negotiated.agent(),
+        negotiated.version()
+    );
+
+    let version = negotiated.version();
+    let (mut sink, mut stream) = websocket.split();
+    let (client, mut events) = state.hub.connect(session.username(), version);
+    let (tx, mut replies) = mpsc::channel::<WsMessage>(32);
+    let (frames_tx, mut frames) = mpsc::channel::<WsMessage>(FRAME_BUFFER);
+    let mut screens = HashMap::<MachineId, JoinHandle<()>>::new();
+
+    // Replies to requests are waited on so they are never dropped, while events from the hub are
+    // dropped if the dashboard can't keep up and frames are skipped
+    let writer = tokio::spawn(async move {
+        loop {
+            let msg = tokio::select! {
+                Some(msg) = replies.recv() => msg,
+                Some(msg) = events.recv() => msg,
+                Some(msg) = frames.recv() => msg,
+                else => break,
+            };
+
+            if let Some(msg) = encode(codec, &msg) {
+                if sink.send(msg).await.is_err() {
+                    break;
+                }
+            }
+        }
+    });
+
+    while let Some(msg) = stream.next().await {
+        let msg = match msg {
+            Ok(msg) if msg.is_close() => break,
+            Ok(msg) if msg.is_binary() || msg.is_text() => msg,
+            Ok(_) => continue,
+            Err(ex) => {
+                error!("websocket error: {ex:?}");
+                break;
+            }
+        };
+
+        match decode::<WsMessage>(codec, &msg) {
+            Ok(WsMessage::Request {
+                id,
+                machine,
+                command,
+            }) => {
+                let state = state.clone();
+                let session = session.clone();
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    let result = run_command(&state, &session, &tx, id, machine, command).await;
+                    let _ = tx.send(WsMessage::Response { id, result }).await;
+                });
+            }
+            Ok(WsMessage::Subscribe(topic)) => {
+                if !subscribe(&state, &session, client, version, topic.clone()) && version >= 6 {
+                    let _ = tx.send(WsMessage::SubscribeDenied(topic)).await;
+                }
+            }
+            Ok(WsMessage::Unsubscribe(topic)) => state.hub.unsubscribe(client, &topic),
+            Ok(WsMessage::ResyncProcesses(machine)) => resync_processes(&state, client, machine),
+            // Dashboards older than protocol version 5 resync by subscribing again
+            Ok(WsMessage::SubscribeProcesses(machine)) => {
+                subscribe(&state, &session, client, version, Topic::Machine(machine));
+            }
+            Ok(WsMessage::UnsubscribeProcesses(machine)) => {
+                state.hub.unsubscribe(client, &Topic::Machine(machine))
+            }
+            Ok(WsMessage::WatchScreen(machine)) => {
+                let frames = frames_tx.clone();
+                let task = watch_screen(&state, &session, client, version, machine.clone(), frames);
+                if let Some(old) = task.and_then(|task| screens.insert(machine, task)) {
+                    old.abort();
+                }
+            }
+            Ok(WsMessage::UnwatchScreen(machine)) => {
+                state.hub.unwatch_screen(client, &machine);
+                if let Some(task) = screens.remove(&machine) {
+                    task.abort();
+                }
+            }
+            Ok(msg) => warn!("Unexpected message from dashboard {msg:?}"),
+            Err(ex) => warn!("Could not decode message from dashboard {ex}"),
+        }
+    }
+
+    state.hub.disconnect(client);
+    for task in screens.values() {
+        task.abort();
+    }
+    drop(tx);
+    drop(frames_tx);
+    let _ = writer.await;
+
+    debug!("{} disconnected from the dashboard", session.username());
+}
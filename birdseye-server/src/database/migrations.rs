@@ -0,0 +1,124 @@
+//! Versioned changes to the database schema
+//!
+//! The version a database is at is kept in SQLite's `user_version`. Migrations are only ever
+//! added to the end of [`MIGRATIONS`], a migration that has been released must never change.
+
+use super::DatabaseError;
+use rusqlite::Connection;
+use tracing::info;
+
+/// Each migration takes the database from the version before it to its own version, the first
+/// migration is version 1
+const MIGRATIONS: &[&str] = &[
+    // 1: Everything the server started out keeping
+    r#"
+    CREATE TABLE accounts (
+        username TEXT PRIMARY KEY NOT NULL,
+        password_hash TEXT NOT NULL,
+        admin INTEGER NOT NULL
+    );
+
+    CREATE TABLE rooms (
+        id TEXT PRIMARY KEY NOT NULL
+    );
+
+    CREATE TABLE account_rooms (
+        username TEXT NOT NULL REFERENCES accounts (username) ON DELETE CASCADE,
+        room TEXT NOT NULL REFERENCES rooms (id) ON DELETE CASCADE,
+        PRIMARY KEY (username, room)
+    );
+
+    CREATE TABLE machines (
+        id TEXT PRIMARY KEY NOT NULL,
+        -- The machine's inventory as JSON
+        data TEXT NOT NULL,
+        last_seen INTEGER NOT NULL
+    );
+
+    CREATE TABLE room_machines (
+        machine TEXT PRIMARY KEY NOT NULL,
+        room TEXT NOT NULL REFERENCES rooms (id) ON DELETE CASCADE
+    );
+
+    CREATE TABLE enrollment_tokens (
+        token TEXT PRIMARY KEY NOT NULL,
+        expires INTEGER NOT NULL
+    );
+
+    CREATE TABLE certificates (
+        serial TEXT PRIMARY KEY NOT NULL,
+        machine TEXT NOT NULL,
+        issued INTEGER NOT NULL,
+        revoked INTEGER NOT NULL DEFAULT 0
+    );
+
+    CREATE INDEX certificates_machine ON certificates (machine);
+
+    CREATE TABLE policies (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        -- The policy as JSON
+        data TEXT NOT NULL
+    );
+
+    CREATE TABLE events (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        time INTEGER NOT NULL,
+        machine TEXT NOT NULL,
+        -- The event as JSON
+        data TEXT NOT NULL
+    );
+
+    CREATE INDEX events_machine_time ON events (machine, time);
+    "#,
+    // 2: Columns to filter process history on, taken out of the event's JSON
+    r#"
+    ALTER TABLE events ADD COLUMN kind TEXT NOT NULL DEFAULT '';
+    ALTER TABLE events ADD COLUMN username TEXT;
+    ALTER TABLE events ADD COLUMN process TEXT;
+    ALTER TABLE events ADD COLUMN pid INTEGER;
+    -- When the process started, used to match a stop to its start
+    ALTER TABLE events ADD COLUMN started INTEGER;
+
+    UPDATE events SET kind = CASE json_extract(data, '$.kind')
+        WHEN 'Connected' THEN 'connected'
+        WHEN 'Disconnected' THEN 'disconnected'
+        ELSE kind
+    END;
+
+    CREATE INDEX events_time ON events (time);
+    CREATE INDEX events_username_time ON events (username, time);
+    CREATE INDEX events_process_run ON events (machine, pid, started);
+    "#,
+    // 3: The timetable, there is only ever one row
+    r#"
+    CREATE TABLE timetable (
+        id INTEGER PRIMARY KEY CHECK (id = 0),
+        -- The timetable as JSON
+        data TEXT NOT NULL
+    );
+    "#,
+];
+
+/// Bring a database up to the latest version, every migration runs in its own transaction
+pub fn migrate(conn: &mut Connection) -> Result<(), DatabaseError> {
+    let latest = MIGRATIONS.len() as u32;
+    let version: u32 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+
+    if version > latest {
+        return Err(DatabaseError::TooNew {
+            version,
+            supported: latest,
+        });
+    }
+
+    for (migration, version) in MIGRATIONS.iter().zip(1..).skip(version as usize) {
+        info!("Migrating database to version {version}");
+
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", version)?;
+        tx.commit()?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,162 @@
+//! Everything the server keeps between restarts
+//!
+//! The rest of the server only talks to the database through [`Repository`], so tests can swap in
+//! an in-memory database with [`SqliteRepository::in_memory`].
+
+mod migrations;
+mod sqlite;
+
+pub use sqlite::SqliteRepository;
+
+use crate::accounts::Account;
+use birdseye_common::history::Event;
+use birdseye_common::policy::{Rule, RuleId};
+use birdseye_common::timetable::Timetable;
+use birdseye_common::{Machine, MachineId, RoomId};
+use std::collections::BTreeSet;
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug)]
+pub enum DatabaseError {
+    Sqlite(rusqlite::Error),
+    /// A value stored as JSON could not be read or written
+    Data(serde_json::Error),
+    /// The database was written by a newer version of the server
+    TooNew {
+        version: u32,
+        supported: u32,
+    },
+}
+
+impl Display for DatabaseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DatabaseError::Sqlite(ex) => write!(f, "database error: {ex}"),
+            DatabaseError::Data(ex) => write!(f, "could not read stored data: {ex}"),
+            DatabaseError::TooNew { version, supported } => write!(
+                f,
+                "database is at version {version} but this server only supports up to {supported}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DatabaseError {}
+
+impl From<rusqlite::Error> for DatabaseError {
+    fn from(ex: rusqlite::Error) -> Self {
+        DatabaseError::Sqlite(ex)
+    }
+}
+
+impl From<serde_json::Error> for DatabaseError {
+    fn from(ex: serde_json::Error) -> Self {
+        DatabaseError::Data(ex)
+    }
+}
+
+/// A machine that has connected to the server at some point
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredMachine {
+    pub machine: Machine,
+    /// The last time the machine was connected, in seconds since the unix epoch
+    pub last_seen: u64,
+}
+
+/// Which events to get from the history, every field that is set has to match
+#[derive(Debug, Clone, Default)]
+pub struct EventFilter {
+    /// Only events from these machines
+    pub machines: Option<Vec<MachineId>>,
+    pub user: Option<String>,
+    /// Part of the process name, ignoring case
+    pub process: Option<String>,
+    /// Events from this time on, along with the starts of processes that were still running
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+    /// Only events with an id lower than this
+    pub before: Option<u64>,
+}
+
+/// Storage for everything the server needs to remember
+pub trait Repository: Send + Sync {
+    /// Get every account
+    fn accounts(&self) -> Result<Vec<Account>, DatabaseError>;
+
+    /// Get an account by username
+    fn account(&self, username: &str) -> Result<Option<Account>, DatabaseError>;
+
+    /// Add a new account, returns false if there is already an account with the same username
+    fn create_account(&self, account: &Account) -> Result<bool, DatabaseError>;
+
+    /// Replace the rooms an account is assigned to, returns false if there is no such account
+    fn set_account_rooms(
+        &self,
+        username: &str,
+        rooms: &BTreeSet<RoomId>,
+    ) -> Result<bool, DatabaseError>;
+
+    /// Get the room each machine is in
+    fn room_machines(&self) -> Result<Vec<(RoomId, MachineId)>, DatabaseError>;
+
+    /// Replace the machines in a room, a machine can only be in one room so it is taken out of any
+    /// other room first
+    fn set_room_machines(&self, room: &RoomId, machines: &[MachineId])
+        -> Result<(), DatabaseError>;
+
+    /// Get every machine that has ever connected
+    fn machines(&self) -> Result<Vec<StoredMachine>, DatabaseError>;
+
+    /// Add or update a machine
+    fn save_machine(&self, machine: &StoredMachine) -> Result<(), DatabaseError>;
+
+    /// Store an enrollment token that can be used until `expires`, in seconds since the unix epoch
+    fn create_token(&self, token: &str, expires: u64) -> Result<(), DatabaseError>;
+
+    /// Remove a token, returning when it would have expired if it existed
+    fn take_token(&self, token: &str) -> Result<Option<u64>, DatabaseError>;
+
+    /// Remove every token that expired before `now`
+    fn prune_tokens(&self, now: u64) -> Result<(), DatabaseError>;
+
+    /// Record a certificate issued to a machine, `serial` is the serial number in hex
+    fn add_certificate(
+        &self,
+        serial: &str,
+        machine: &MachineId,
+        issued: u64,
+    ) -> Result<(), DatabaseError>;
+
+    /// Revoke every certificate issued to a machine, returns how many were revoked
+    fn revoke_certificates(&self, machine: &MachineId) -> Result<usize, DatabaseError>;
+
+    /// Whether the certificate with the given serial number, in hex, has been revoked
+    fn is_revoked(&self, serial: &str) -> Result<bool, DatabaseError>;
+
+    /// Whether any certificate has been issued to a machine, revoked or not
+    fn has_certificates(&self, machine: &MachineId) -> Result<bool, DatabaseError>;
+
+    /// Get every blacklist rule, ordered by id
+    fn policies(&self) -> Result<Vec<Rule>, DatabaseError>;
+
+    /// Add a blacklist rule, returns the id it was given
+    fn create_policy(&self, rule: &Rule) -> Result<RuleId, DatabaseError>;
+
+    /// Replace the rule with the same id, returns false if there is no such rule
+    fn update_policy(&self, rule: &Rule) -> Result<bool, DatabaseError>;
+
+    /// Remove a rule, returns false if there is no such rule
+    fn delete_policy(&self, id: RuleId) -> Result<bool, DatabaseError>;
+
+    /// Get the timetable, empty if one has never been saved
+    fn timetable(&self) -> Result<Timetable, DatabaseError>;
+
+    /// Replace the timetable
+    fn save_timetable(&self, timetable: &Timetable) -> Result<(), DatabaseError>;
+
+    /// Add events to the history
+    fn record_events(&self, events: &[Event]) -> Result<(), DatabaseError>;
+
+    /// Get up to `limit` events matching a filter along with their ids, newest first
+    fn events(&self, filter: &EventFilter, limit: u32) -> Result<Vec<(u64, Event)>, DatabaseError>;
+}
@@ -0,0 +1,474 @@
+//! [`Repository`] backed by an embedded SQLite database
+
+use super::migrations::migrate;
+use super::{DatabaseError, EventFilter, Repository, StoredMachine};
+use crate::accounts::Account;
+use birdseye_common::history::{Event, EventKind};
+use birdseye_common::policy::{Rule, RuleId};
+use birdseye_common::timetable::Timetable;
+use birdseye_common::{Machine, MachineId, RoomId};
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension};
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::sync::Mutex;
+
+pub struct SqliteRepository {
+    conn: Mutex<Connection>,
+}
+
+impl SqliteRepository {
+    /// Open the database at `path`, creating it if it doesn't exist, and bring it up to date
+    pub fn open(path: &Path) -> Result<Self, DatabaseError> {
+        Self::new(Connection::open(path)?)
+    }
+
+    /// Create a database that only lives in memory, for tests
+    pub fn in_memory() -> Result<Self, DatabaseError> {
+        Self::new(Connection::open_in_memory()?)
+    }
+
+    fn new(mut conn: Connection) -> Result<Self, DatabaseError> {
+        conn.pragma_update(None, "foreign_keys", true)?;
+        migrate(&mut conn)?;
+
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    fn account_rooms(conn: &Connection, username: &str) -> Result<BTreeSet<RoomId>, DatabaseError> {
+        let mut query = conn.prepare_cached("SELECT room FROM account_rooms WHERE username = ?")?;
+        let rooms = query
+            .query_map([username], |row| Ok(RoomId::new(&row.get::<_, String>(0)?)))?
+            .collect::<Result<_, _>>()?;
+
+        Ok(rooms)
+    }
+
+    /// Make sure a room exists so it can be referenced
+    fn add_room(conn: &Connection, room: &RoomId) -> Result<(), DatabaseError> {
+        conn.execute(
+            "INSERT OR IGNORE INTO rooms (id) VALUES (?)",
+            [room.as_str()],
+        )?;
+        Ok(())
+    }
+}
+
+impl Repository for SqliteRepository {
+    fn accounts(&self) -> Result<Vec<Account>, DatabaseError> {
+        let conn = self.conn.lock().unwrap();
+        let mut query = conn.prepare_cached(
+            "SELECT username, password_hash, admin FROM accounts ORDER BY username",
+        )?;
+        let rows = query
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .collect::<Result<Vec<(String, String, bool)>, _>>()?;
+
+        rows.into_iter()
+            .map(|(username, password_hash, admin)| {
+                let rooms = Self::account_rooms(&conn, &username)?;
+                Ok(Account::new(&username, &password_hash, admin, rooms))
+            })
+            .collect()
+    }
+
+    fn account(&self, username: &str) -> Result<Option<Account>, DatabaseError> {
+        let conn = self.conn.lock().unwrap();
+        let row = conn
+            .query_row(
+                "SELECT password_hash, admin FROM accounts WHERE username = ?",
+                [username],
+                |row| Ok((row.get::<_, String>(0)?, row.get(1)?)),
+            )
+            .optional()?;
+
+        match row {
+            Some((password_hash, admin)) => {
+                let rooms = Self::account_rooms(&conn, username)?;
+                Ok(Some(Account::new(username, &password_hash, admin, rooms)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn create_account(&self, account: &Account) -> Result<bool, DatabaseError> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+
+        let inserted = tx.execute(
+            "INSERT OR IGNORE INTO accounts (username, password_hash, admin) VALUES (?, ?, ?)",
+            params![
+                account.username(),
+                account.password_hash(),
+                account.is_admin()
+            ],
+        )?;
+        if inserted == 0 {
+            return Ok(false);
+        }
+
+        for room in account.rooms() {
+            Self::add_room(&tx, room)?;
+            tx.execute(
+                "INSERT INTO account_rooms (username, room) VALUES (?, ?)",
+                [account.username(), room.as_str()],
+            )?;
+        }
+
+        tx.commit()?;
+        Ok(true)
+    }
+
+    fn set_account_rooms(
+        &self,
+        username: &str,
+        rooms: &BTreeSet<RoomId>,
+    ) -> Result<bool, DatabaseError> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+
+        let exists = tx
+            .query_row(
+                "SELECT 1 FROM accounts WHERE username = ?",
+                [username],
+                |_| Ok(()),
+            )
+            .optional()?
+            .is_some();
+        if !exists {
+            return Ok(false);
+        }
+
+        tx.execute("DELETE FROM account_rooms WHERE username = ?", [username])?;
+        for room in rooms {
+            Self::add_room(&tx, room)?;
+            tx.execute(
+                "INSERT INTO account_rooms (username, room) VALUES (?, ?)",
+                [username, room.as_str()],
+            )?;
+        }
+
+        tx.commit()?;
+        Ok(true)
+    }
+
+    fn room_machines(&self) -> Result<Vec<(RoomId, MachineId)>, DatabaseError> {
+        let conn = self.conn.lock().unwrap();
+        let mut query = conn.prepare_cached("SELECT room, machine FROM room_machines")?;
+        let rows = query
+            .query_map([], |row| {
+                Ok((
+                    RoomId::new(&row.get::<_, String>(0)?),
+                    MachineId::new(&row.get::<_, String>(1)?),
+                ))
+            })?
+            .collect::<Result<_, _>>()?;
+
+        Ok(rows)
+    }
+
+    fn set_room_machines(
+        &self,
+        room: &RoomId,
+        machines: &[MachineId],
+    ) -> Result<(), DatabaseError> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+
+        Self::add_room(&tx, room)?;
+        tx.execute("DELETE FROM room_machines WHERE room = ?", [room.as_str()])?;
+        for machine in machines {
+            tx.execute(
+                "INSERT OR REPLACE INTO room_machines (machine, room) VALUES (?, ?)",
+                [machine.as_str(), room.as_str()],
+            )?;
+        }
+
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn machines(&self) -> Result<Vec<StoredMachine>, DatabaseError> {
+        let conn = self.conn.lock().unwrap();
+        let mut query = conn.prepare_cached("SELECT data, last_seen FROM machines")?;
+        let rows = query
+            .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        rows.into_iter()
+            .map(|(data, last_seen)| {
+                Ok(StoredMachine {
+                    machine: serde_json::from_str::<Machine>(&data)?,
+                    last_seen,
+                })
+            })
+            .collect()
+    }
+
+    fn save_machine(&self, machine: &StoredMachine) -> Result<(), DatabaseError> {
+        let data = serde_json::to_string(&machine.machine)?;
+
+        self.conn.lock().unwrap().execute(
+            "INSERT OR REPLACE INTO machines (id, data, last_seen) VALUES (?, ?, ?)",
+            params![machine.machine.id().as_str(), data, machine.last_seen],
+        )?;
+        Ok(())
+    }
+
+    fn create_token(&self, token: &str, expires: u64) -> Result<(), DatabaseError> {
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO enrollment_tokens (token, expires) VALUES (?, ?)",
+            params![token, expires],
+        )?;
+        Ok(())
+    }
+
+    fn take_token(&self, token: &str) -> Result<Option<u64>, DatabaseError> {
+        let expires = self
+            .conn
+            .lock()
+            .unwrap()
+            .query_row(
+                "DELETE FROM enrollment_tokens WHERE token = ? RETURNING expires",
+                [token],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        Ok(expires)
+    }
+
+    fn prune_tokens(&self, now: u64) -> Result<(), DatabaseError> {
+        self.conn
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM enrollment_tokens WHERE expires <= ?", [now])?;
+        Ok(())
+    }
+
+    fn add_certificate(
+        &self,
+        serial: &str,
+        machine: &MachineId,
+        issued: u64,
+    ) -> Result<(), DatabaseError> {
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO certificates (serial, machine, issued) VALUES (?, ?, ?)",
+            params![serial, machine.as_str(), issued],
+        )?;
+        Ok(())
+    }
+
+    fn revoke_certificates(&self, machine: &MachineId) -> Result<usize, DatabaseError> {
+        let revoked = self.conn.lock().unwrap().execute(
+            "UPDATE certificates SET revoked = 1 WHERE machine = ? AND revoked = 0",
+            [machine.as_str()],
+        )?;
+        Ok(revoked)
+    }
+
+    fn is_revoked(&self, serial: &str) -> Result<bool, DatabaseError> {
+        let revoked = self
+            .conn
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT revoked FROM certificates WHERE serial = ?",
+                [serial],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        Ok(revoked.unwrap_or_default())
+    }
+
+    fn has_certificates(&self, machine: &MachineId) -> Result<bool, DatabaseError> {
+        let found = self.conn.lock().unwrap().query_row(
+            "SELECT EXISTS (SELECT 1 FROM certificates WHERE machine = ?)",
+            [machine.as_str()],
+            |row| row.get(0),
+        )?;
+        Ok(found)
+    }
+
+    fn policies(&self) -> Result<Vec<Rule>, DatabaseError> {
+        let conn = self.conn.lock().unwrap();
+        let mut query = conn.prepare_cached("SELECT id, data FROM policies ORDER BY id")?;
+        let rows = query
+            .query_map([], |row| Ok((row.get(0)?, row.get::<_, String>(1)?)))?
+            .collect::<Result<Vec<(RuleId, String)>, _>>()?;
+
+        // The id column is the source of truth, the id in the JSON is only there for completeness
+        rows.into_iter()
+            .map(|(id, data)| Ok(serde_json::from_str::<Rule>(&data)?.with_id(id)))
+            .collect()
+    }
+
+    fn create_policy(&self, rule: &Rule) -> Result<RuleId, DatabaseError> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+
+        tx.execute("INSERT INTO policies (data) VALUES ('')", [])?;
+        let id = tx.last_insert_rowid() as RuleId;
+        tx.execute(
+            "UPDATE policies SET data = ? WHERE id = ?",
+            params![serde_json::to_string(&rule.clone().with_id(id))?, id],
+        )?;
+
+        tx.commit()?;
+        Ok(id)
+    }
+
+    fn update_policy(&self, rule: &Rule) -> Result<bool, DatabaseError> {
+        let updated = self.conn.lock().unwrap().execute(
+            "UPDATE policies SET data = ? WHERE id = ?",
+            params![serde_json::to_string(rule)?, rule.id()],
+        )?;
+        Ok(updated > 0)
+    }
+
+    fn delete_policy(&self, id: RuleId) -> Result<bool, DatabaseError> {
+        let deleted = self
+            .conn
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM policies WHERE id = ?", [id])?;
+        Ok(deleted > 0)
+    }
+
+    fn timetable(&self) -> Result<Timetable, DatabaseError> {
+        let data: Option<String> = self
+            .conn
+            .lock()
+            .unwrap()
+            .query_row("SELECT data FROM timetable WHERE id = 0", [], |row| {
+                row.get(0)
+            })
+            .optional()?;
+
+        match data {
+            Some(data) => Ok(serde_json::from_str(&data)?),
+            None => Ok(Timetable::default()),
+        }
+    }
+
+    fn save_timetable(&self, timetable: &Timetable) -> Result<(), DatabaseError> {
+        self.conn.lock().unwrap().execute(
+            "INSERT OR REPLACE INTO timetable (id, data) VALUES (0, ?)",
+            [serde_json::to_string(timetable)?],
+        )?;
+        Ok(())
+    }
+
+    fn record_events(&self, events: &[Event]) -> Result<(), DatabaseError> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+
+        {
+            let mut insert = tx.prepare_cached(
+                "INSERT INTO events (time, machine, data, kind, username, process, pid, started)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+            )?;
+
+            for event in events {
+                let process = event.kind().process();
+                insert.execute(params![
+                    event.time(),
+                    event.machine().as_str(),
+                    serde_json::to_string(event)?,
+                    event_kind(event.kind()),
+                    process.map(|process| process.user().name()),
+                    process.map(|process| process.name()),
+                    process.map(|process| *process.pid()),
+                    process.map(|process| process.start_time()),
+                ])?;
+            }
+        }
+
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn events(&self, filter: &EventFilter, limit: u32) -> Result<Vec<(u64, Event)>, DatabaseError> {
+        let mut conditions = vec![];
+        let mut values = vec![];
+
+        if let Some(machines) = &filter.machines {
+            let placeholders = vec!["?"; machines.len()].join(", ");
+            conditions.push(format!("machine IN ({placeholders})"));
+            values.extend(
+                machines
+                    .iter()
+                    .map(|machine| Value::Text(machine.as_str().to_string())),
+            );
+        }
+        if let Some(user) = &filter.user {
+            conditions.push("username = ?".to_string());
+            values.push(Value::Text(user.clone()));
+        }
+        if let Some(process) = &filter.process {
+            // LIKE ignores case for ascii, the wildcards in the search itself are escaped
+            let escaped = process
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_");
+            conditions.push("process LIKE ? ESCAPE '\\'".to_string());
+            values.push(Value::Text(format!("%{escaped}%")));
+        }
+        if let Some(to) = filter.to {
+            conditions.push("time <= ?".to_string());
+            values.push(Value::Integer(to as i64));
+        }
+        if let Some(from) = filter.from {
+            // Processes that started before the range but hadn't stopped by the start of it were
+            // still running during it
+            conditions.push(
+                "(time >= ? OR (kind = 'process_started' AND NOT EXISTS (
+                    SELECT 1 FROM events stop WHERE stop.kind = 'process_stopped'
+                        AND stop.machine = events.machine AND stop.pid = events.pid
+                        AND stop.started = events.started AND stop.time < ?
+                )))"
+                .to_string(),
+            );
+            values.push(Value::Integer(from as i64));
+            values.push(Value::Integer(from as i64));
+        }
+        if let Some(before) = filter.before {
+            conditions.push("id < ?".to_string());
+            values.push(Value::Integer(before as i64));
+        }
+
+        let conditions = match conditions.is_empty() {
+            true => String::new(),
+            false => format!("WHERE {}", conditions.join(" AND ")),
+        };
+        values.push(Value::Integer(limit.into()));
+
+        let conn = self.conn.lock().unwrap();
+        let mut query = conn.prepare(&format!(
+            "SELECT id, data FROM events {conditions} ORDER BY id DESC LIMIT ?"
+        ))?;
+        let rows = query
+            .query_map(params_from_iter(values), |row| {
+                Ok((row.get(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<Result<Vec<(u64, String)>, _>>()?;
+
+        rows.into_iter()
+            .map(|(id, data)| Ok((id, serde_json::from_str(&data)?)))
+            .collect()
+    }
+}
+
+/// The name stored in the `kind` column of the events table
+fn event_kind(kind: &EventKind) -> &'static str {
+    match kind {
+        EventKind::Connected => "connected",
+        EventKind::Disconnected => "disconnected",
+        EventKind::ProcessStarted(_) => "process_started",
+        EventKind::ProcessStopped(_) => "process_stopped",
+        EventKind::PolicyViolation(_) => "policy_violation",
+    }
+}
@@ -0,0 +1,198 @@
+//! Enrollment tokens, the certificates issued with them and revoking those certificates
+//!
+//! An admin creates a one-time token, which a monitor swaps for a client certificate signed by the
+//! server's [`CertificateAuthority`](crate::ca::CertificateAuthority). Revoking a machine revokes
+//! every certificate issued to it, so a stolen or retired machine can no longer connect.
+
+use crate::auth::{self, Forbidden};
+use crate::database::{DatabaseError, Repository};
+use crate::registry::now;
+use crate::sessions::Session;
+use crate::state::{with_state, SharedState};
+use birdseye_common::auth::{EnrollmentToken, RevokeRequest};
+use birdseye_common::backend::{EnrollRequest, EnrollResponse, ServerMessage};
+use birdseye_common::MachineId;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use std::sync::Arc;
+use tracing::{info, warn};
+use warp::http::StatusCode;
+use warp::reply::Response;
+use warp::{Filter, Rejection, Reply};
+
+/// Enrollment tokens and the certificates issued with them, kept in the database
+pub struct Enrollment {
+    db: Arc<dyn Repository>,
+    token_ttl: u64,
+}
+
+impl Enrollment {
+    /// Tokens last for `token_ttl` seconds
+    pub fn new(db: Arc<dyn Repository>, token_ttl: u64) -> Self {
+        Self { db, token_ttl }
+    }
+
+    /// Create a new one-time token
+    pub fn create_token(&self) -> Result<EnrollmentToken, DatabaseError> {
+        let mut bytes = [0u8; 16];
+        OsRng.fill_bytes(&mut bytes);
+        let token: String = bytes.iter().map(|byte| format!("{byte:02x}")).collect();
+        let expires = now() + self.token_ttl;
+
+        self.db.prune_tokens(now())?;
+        self.db.create_token(&token, expires)?;
+
+        Ok(EnrollmentToken { token, expires })
+    }
+
+    /// Use up a token and record the certificate issued with it, `issue` is only called if the
+    /// token is valid. Returns `None` if the token is not valid
+    pub fn redeem<T, E>(
+        &self,
+        token: &str,
+        machine: &MachineId,
+        issue: impl FnOnce(u64) -> Result<T, E>,
+    ) -> Result<Option<Result<T, E>>, DatabaseError> {
+        // Taking the token removes it, so two monitors can't both use it
+        let expires = match self.db.take_token(token)? {
+            Some(expires) if expires > now() => expires,
+            _ => return Ok(None),
+        };
+
+        // Serial numbers must be positive, so the top bit is left clear
+        let serial = OsRng.next_u64() >> 1;
+        let result = issue(serial);
+
+        match &result {
+            Ok(_) => self
+                .db
+                .add_certificate(&format!("{serial:x}"), machine, now())?,
+            // A bad request shouldn't cost the monitor its token
+            Err(_) => self.db.create_token(token, expires)?,
+        }
+
+        Ok(Some(result))
+    }
+
+    /// Revoke every certificate issued to a machine, returns how many were revoked
+    pub fn revoke(&self, machine: &MachineId) -> Result<usize, DatabaseError> {
+        self.db.revoke_certificates(machine)
+    }
+
+    /// Whether the certificate with the given serial number, in hex, has been revoked. Fails
+    /// closed, a certificate that can't be checked is treated as revoked
+    pub fn is_revoked(&self, serial: &str) -> bool {
+        self.db.is_revoked(serial).unwrap_or_else(|ex| {
+            warn!("Could not check if certificate {serial} is revoked: {ex}");
+            true
+        })
+    }
+
+    /// Whether a machine has been enrolled, so it can only connect with its certificate. A revoked
+    /// machine could otherwise get back in by leaving its certificate out. Fails closed like
+    /// [`Enrollment::is_revoked`]
+    pub fn needs_certificate(&self, machine: &MachineId) -> bool {
+        self.db.has_certificates(machine).unwrap_or_else(|ex| {
+            warn!("Could not check if {machine} has been enrolled: {ex}");
+            true
+        })
+    }
+}
+
+async fn create_token(_session: Session, state: SharedState) -> Result<Response, Rejection> {
+    match state.enrollment.create_token() {
+        Ok(token) => Ok(warp::reply::json(&token).into_response()),
+        Err(ex) => {
+            warn!("Could not create enrollment token: {ex}");
+            Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response())
+        }
+    }
+}
+
+async fn enroll(request: EnrollRequest, state: SharedState) -> Result<Response, Rejection> {
+    let result = state
+        .enrollment
+        .redeem(&request.token, &request.machine, |serial| {
+            state.ca.sign(&request.csr, &request.machine, serial)
+        });
+
+    match result {
+        Ok(Some(Ok(certificate))) => {
+            info!("Issued certificate to {}", request.machine);
+            Ok(warp::reply::json(&EnrollResponse { certificate }).into_response())
+        }
+        Ok(Some(Err(ex))) => {
+            warn!("Could not sign certificate for {}: {ex}", request.machine);
+            Ok(StatusCode::BAD_REQUEST.into_response())
+        }
+        Ok(None) => {
+            warn!("{} tried to enroll with an invalid token", request.machine);
+            Err(warp::reject::custom(Forbidden))
+        }
+        Err(ex) => {
+            warn!("Could not enroll {}: {ex}", request.machine);
+            Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response())
+        }
+    }
+}
+
+async fn revoke(
+    session: Session,
+    request: RevokeRequest,
+    state: SharedState,
+) -> Result<Response, Rejection> {
+    let revoked = match state.enrollment.revoke(&request.machine) {
+        Ok(revoked) => revoked,
+        Err(ex) => {
+            warn!("Could not revoke certificates: {ex}");
+            return Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+        }
+    };
+
+    info!(
+        "{} revoked {revoked} certificates for {}",
+        session.username(),
+        request.machine
+    );
+
+    // Kick the machine off if it is connected right now
+    if let Ok(link) = state.monitor_link(&request.machine) {
+        let _ = link
+            .send(ServerMessage::Rejected("Certificate revoked".into()))
+            .await;
+    }
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+/// `POST /enrollment/tokens`, `POST /enrollment/revoke` and `POST /enroll`
+pub fn routes(state: SharedState) -> impl Filter<Extract = (Response,), Error = Rejection> + Clone {
+    let create_token = warp::post()
+        .and(warp::path!("enrollment" / "tokens"))
+        .and(auth::admin(state.clone()))
+        .and(with_state(state.clone()))
+        .and_then(create_token);
+
+    let revoke = warp::post()
+        .and(warp::path!("enrollment" / "revoke"))
+        .and(auth::admin(state.clone()))
+        .and(warp::body::content_length_limit(4 * 1024))
+        .and(warp::body::json())
+        .and(with_state(state.clone()))
+        .and_then(revoke);
+
+    let enroll = warp::post()
+        .and(warp::path!("enroll"))
+        .and(warp::body::content_length_limit(16 * 1024))
+        .and(warp::body::json())
+        .and(with_state(state))
+        .and_then(enroll);
+
+    create_token
+        .or(revoke)
+        .unify()
+        .or(enroll)
+        .unify()
+        .recover(auth::handle_rejection)
+        .unify()
+}
@@ -0,0 +1,190 @@
+//! Every process the monitors have seen start and stop or break a rule, and the endpoint teachers
+//! use to look back through it
+
+use crate::accounts::Account;
+use crate::auth;
+use crate::database::{DatabaseError, EventFilter, Repository};
+use crate::registry::now;
+use crate::rooms::Rooms;
+use crate::sessions::Session;
+use crate::state::{with_state, SharedState};
+use birdseye_common::frontend::Topic;
+use birdseye_common::history::{Event, EventKind, HistoryEntry, HistoryPage, HistoryQuery};
+use birdseye_common::policy::Violation;
+use birdseye_common::sync::ProcessStatus;
+use birdseye_common::MachineId;
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+use tracing::warn;
+use warp::http::StatusCode;
+use warp::reply::Response;
+use warp::{Filter, Rejection, Reply};
+
+/// How many events are returned when a request doesn't say
+const DEFAULT_LIMIT: u32 = 100;
+/// The most events that can be asked for at once
+const MAX_LIMIT: u32 = 1000;
+
+#[derive(Debug)]
+pub enum HistoryError {
+    /// The machine or room asked for is not one the account can see
+    Forbidden,
+    Database(DatabaseError),
+}
+
+impl Display for HistoryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HistoryError::Forbidden => write!(f, "not allowed to see that machine or room"),
+            HistoryError::Database(ex) => write!(f, "{ex}"),
+        }
+    }
+}
+
+impl std::error::Error for HistoryError {}
+
+impl From<DatabaseError> for HistoryError {
+    fn from(ex: DatabaseError) -> Self {
+        HistoryError::Database(ex)
+    }
+}
+
+/// The audit log of processes started and stopped on every machine
+pub struct History {
+    db: Arc<dyn Repository>,
+}
+
+impl History {
+    pub fn new(db: Arc<dyn Repository>) -> Self {
+        Self { db }
+    }
+
+    /// Store changes to a machine's process list as having happened now
+    pub fn record(&self, machine: &MachineId, changes: Vec<ProcessStatus>) {
+        if changes.is_empty() {
+            return;
+        }
+
+        let time = now();
+        let events: Vec<_> = changes
+            .into_iter()
+            .map(|status| {
+                let kind = match status {
+                    ProcessStatus::Start(process) => EventKind::ProcessStarted(process),
+                    ProcessStatus::Stop(process) => EventKind::ProcessStopped(process),
+                };
+                Event::new(time, machine, kind)
+            })
+            .collect();
+
+        if let Err(ex) = self.db.record_events(&events) {
+            warn!("Could not record process history for {machine}: {ex}");
+        }
+    }
+
+    /// Store a process breaking a blacklist rule as having happened now
+    pub fn record_violation(&self, machine: &MachineId, violation: Violation) {
+        let event = Event::new(now(), machine, EventKind::PolicyViolation(violation));
+        if let Err(ex) = self.db.record_events(&[event]) {
+            warn!("Could not record policy violation on {machine}: {ex}");
+        }
+    }
+
+    /// Get a page of events, teachers only get events from machines in their rooms
+    pub fn query(
+        &self,
+        account: &Account,
+        rooms: &Rooms,
+        query: &HistoryQuery,
+    ) -> Result<HistoryPage, HistoryError> {
+        let mut machines = None;
+
+        if let Some(machine) = &query.machine {
+            if !rooms.can_see(account, machine) {
+                return Err(HistoryError::Forbidden);
+            }
+            machines = Some(vec![machine.clone()]);
+        }
+
+        if let Some(room) = &query.room {
+            let topic = Topic::Room(room.clone());
+            if !rooms.can_subscribe(account, &topic) {
+                return Err(HistoryError::Forbidden);
+            }
+            let in_room = rooms.machines(&topic);
+            machines = Some(match machines {
+                Some(machines) => machines
+                    .into_iter()
+                    .filter(|machine| in_room.contains(machine))
+                    .collect(),
+                None => in_room,
+            });
+        }
+
+        // Without a machine or room a teacher gets everything they can see
+        if machines.is_none() && !account.is_admin() {
+            machines = Some(
+                account
+                    .rooms()
+                    .iter()
+                    .flat_map(|room| rooms.machines(&Topic::Room(room.clone())))
+                    .collect(),
+            );
+        }
+
+        let filter = EventFilter {
+            machines,
+            user: query.user.clone(),
+            process: query.process.clone(),
+            from: query.from,
+            to: query.to,
+            before: query.before,
+        };
+        let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+        let events: Vec<_> = self
+            .db
+            .events(&filter, limit)?
+            .into_iter()
+            .map(|(id, event)| HistoryEntry { id, event })
+            .collect();
+        let next = match events.len() == limit as usize {
+            true => events.last().map(|entry| entry.id),
+            false => None,
+        };
+
+        Ok(HistoryPage { events, next })
+    }
+}
+
+async fn get_history(
+    session: Session,
+    query: HistoryQuery,
+    state: SharedState,
+) -> Result<Response, Rejection> {
+    let account = match state.accounts.get(session.username()) {
+        Some(account) => account,
+        None => return Ok(StatusCode::UNAUTHORIZED.into_response()),
+    };
+
+    match state.history.query(&account, &state.rooms, &query) {
+        Ok(page) => Ok(warp::reply::json(&page).into_response()),
+        Err(HistoryError::Forbidden) => Ok(StatusCode::FORBIDDEN.into_response()),
+        Err(ex) => {
+            warn!("Could not load history: {ex}");
+            Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response())
+        }
+    }
+}
+
+/// `GET /history`, filtered with the fields of [`HistoryQuery`] in the query string
+pub fn routes(state: SharedState) -> impl Filter<Extract = (Response,), Error = Rejection> + Clone {
+    warp::get()
+        .and(warp::path!("history"))
+        .and(auth::authenticated(state.clone()))
+        .and(warp::query::<HistoryQuery>())
+        .and(with_state(state))
+        .and_then(get_history)
+        .recover(auth::handle_rejection)
+        .unify()
+}
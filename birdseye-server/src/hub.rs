@@ -0,0 +1,222 @@
+//! Fans events from monitors out to the dashboards that have subscribed to them
+//!
+//! Every dashboard gets its own bounded queue. Messages are never waited on, if a dashboard's
+//! queue is full the message is dropped for that dashboard only, so one slow client can't hold up
+//! the monitors or any other dashboard. Dropped process updates are picked up by the dashboard as a
+//! gap in the sequence numbers, which it recovers from by asking for a new snapshot.
+//!
+//! The hub also knows what every dashboard is looking at, so it works out the [`Demand`] for each
+//! machine's screen.
+
+use crate::rooms::Rooms;
+use birdseye_common::frontend::{Topic, WsMessage};
+use birdseye_common::screen::Demand;
+use birdseye_common::{MachineId, RoomId};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{mpsc, watch};
+use tracing::debug;
+
+/// The id of a dashboard connected to the hub
+pub type ClientId = u64;
+
+struct Client {
+    /// The account the dashboard is logged in as
+    username: String,
+    /// The protocol version negotiated with the dashboard
+    version: u32,
+    tx: mpsc::Sender<WsMessage>,
+    topics: HashSet<Topic>,
+    /// Machines whose screens the dashboard is watching
+    screens: HashSet<MachineId>,
+    /// Messages dropped because the client's queue was full
+    dropped: u64,
+}
+
+impl Client {
+    fn wants(&self, machine: &MachineId, room: Option<&RoomId>) -> bool {
+        self.topics.iter().any(|topic| match topic {
+            Topic::Machine(id) => id == machine,
+            Topic::Room(id) => Some(id) == room,
+        })
+    }
+}
+
+pub struct Hub {
+    buffer: usize,
+    next_client: AtomicU64,
+    clients: RwLock<HashMap<ClientId, Client>>,
+    rooms: Rooms,
+    /// Woken up whenever a dashboard starts or stops looking at something
+    changes: watch::Sender<()>,
+}
+
+impl Hub {
+    /// Create a hub where each dashboard can have `buffer` messages queued
+    pub fn new(buffer: usize, rooms: Rooms) -> Self {
+        Self {
+            buffer,
+            next_client: AtomicU64::new(0),
+            clients: RwLock::new(HashMap::new()),
+            rooms,
+            changes: watch::channel(()).0,
+        }
+    }
+
+    /// Add a dashboard logged in as `username` to the hub, returns its id and the receiving end of
+    /// its queue
+    pub fn connect(&self, username: &str, version: u32) -> (ClientId, mpsc::Receiver<WsMessage>) {
+        let id = self.next_client.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = mpsc::channel(self.buffer);
+
+        self.clients.write().unwrap().insert(
+            id,
+            Client {
+                username: username.to_string(),
+                version,
+                tx,
+                topics: HashSet::new(),
+                screens: HashSet::new(),
+                dropped: 0,
+            },
+        );
+
+        (id, rx)
+    }
+
+    pub fn disconnect(&self, client: ClientId) {
+        if let Some(client) = self.clients.write().unwrap().remove(&client) {
+            if client.dropped > 0 {
+                debug!("Dashboard dropped {} messages", client.dropped);
+            }
+        }
+        self.changes.send_replace(());
+    }
+
+    pub fn subscribe(&self, client: ClientId, topic: Topic) {
+        if let Some(client) = self.clients.write().unwrap().get_mut(&client) {
+            client.topics.insert(topic);
+        }
+        self.changes.send_replace(());
+    }
+
+    pub fn unsubscribe(&self, client: ClientId, topic: &Topic) {
+        if let Some(client) = self.clients.write().unwrap().get_mut(&client) {
+            client.topics.remove(topic);
+        }
+        self.changes.send_replace(());
+    }
+
+    /// Drop the subscriptions of every dashboard logged in as `username` that `keep` returns false
+    /// for, used when an account loses access to a room. Screens are kept if `keep` returns true
+    /// for the machine's topic
+    pub fn retain_topics(&self, username: &str, keep: impl Fn(&Topic) -> bool) {
+        for client in self.clients.write().unwrap().values_mut() {
+            if client.username == username {
+                client.topics.retain(|topic| keep(topic));
+                client
+                    .screens
+                    .retain(|machine| keep(&Topic::Machine(machine.clone())));
+            }
+        }
+        self.changes.send_replace(());
+    }
+
+    pub fn watch_screen(&self, client: ClientId, machine: MachineId) {
+        if let Some(client) = self.clients.write().unwrap().get_mut(&client) {
+            client.screens.insert(machine);
+        }
+        self.changes.send_replace(());
+    }
+
+    pub fn unwatch_screen(&self, client: ClientId, machine: &MachineId) {
+        if let Some(client) = self.clients.write().unwrap().get_mut(&client) {
+            client.screens.remove(machine);
+        }
+        self.changes.send_replace(());
+    }
+
+    /// How much of a machine's screen the dashboards need. All of it if any of them are watching
+    /// it, thumbnails `thumbnail_width` wide if any are only subscribed to it, otherwise nothing
+    pub fn demand(&self, machine: &MachineId, thumbnail_width: u32) -> Demand {
+        let room = self.rooms.room(machine);
+        let clients = self.clients.read().unwrap();
+
+        if clients
+            .values()
+            .any(|client| client.screens.contains(machine))
+        {
+            Demand::Full
+        } else if clients
+            .values()
+            .any(|client| client.wants(machine, room.as_ref()))
+        {
+            Demand::Thumbnails {
+                width: thumbnail_width,
+            }
+        } else {
+            Demand::None
+        }
+    }
+
+    /// Get woken up whenever a dashboard starts or stops looking at something, so its
+    /// [`Hub::demand`] can be checked again. Machines moving rooms don't wake it
+    pub fn changes(&self) -> watch::Receiver<()> {
+        self.changes.subscribe()
+    }
+
+    /// Whether a dashboard is still watching a machine's screen, it stops once it unwatches or its
+    /// account loses access to the machine
+    pub fn is_watching(&self, client: ClientId, machine: &MachineId) -> bool {
+        self.clients
+            .read()
+            .unwrap()
+            .get(&client)
+            .map(|client| client.screens.contains(machine))
+            .unwrap_or_default()
+    }
+
+    /// Whether a dashboard is subscribed to a machine, either directly or through its room
+    pub fn is_subscribed(&self, client: ClientId, machine: &MachineId) -> bool {
+        let room = self.rooms.room(machine);
+        self.clients
+            .read()
+            .unwrap()
+            .get(&client)
+            .map(|client| client.wants(machine, room.as_ref()))
+            .unwrap_or_default()
+    }
+
+    fn try_send(client: &mut Client, msg: WsMessage) {
+        match client.tx.try_send(msg) {
+            Ok(()) | Err(TrySendError::Closed(_)) => {}
+            Err(TrySendError::Full(_)) => client.dropped += 1,
+        }
+    }
+
+    /// Queue a message for a single dashboard
+    pub fn send(&self, client: ClientId, msg: WsMessage) {
+        if let Some(client) = self.clients.write().unwrap().get_mut(&client) {
+            Self::try_send(client, msg);
+        }
+    }
+
+    /// Queue a message about a machine for every dashboard subscribed to it
+    pub fn publish(&self, machine: &MachineId, msg: WsMessage) {
+        self.publish_since(0, machine, msg)
+    }
+
+    /// Like [`Hub::publish`] but only for dashboards that negotiated at least `version`, for
+    /// messages older dashboards wouldn't understand
+    pub fn publish_since(&self, version: u32, machine: &MachineId, msg: WsMessage) {
+        let room = self.rooms.room(machine);
+
+        for client in self.clients.write().unwrap().values_mut() {
+            if client.version >= version && client.wants(machine, room.as_ref()) {
+                Self::try_send(client, msg.clone());
+            }
+        }
+    }
+}
@@ -0,0 +1,25 @@
+//! The BirdsEye server, which monitors connect to and dashboards are served from
+
+pub mod accounts;
+pub mod api;
+pub mod auth;
+pub mod ca;
+pub mod commands;
+pub mod config;
+pub mod dashboard;
+pub mod database;
+pub mod enrollment;
+pub mod history;
+pub mod hub;
+pub mod monitor;
+pub mod policies;
+pub mod processes;
+pub mod registry;
+pub mod rooms;
+pub mod screens;
+pub mod sessions;
+pub mod socket;
+pub mod state;
+pub mod thumbnails;
+pub mod timetable;
+pub mod tls;
@@ -1,7 +1,11 @@
-mod config;
-
-use crate::config::load_config;
-use futures_util::{FutureExt, StreamExt};
+use birdseye_server::accounts::{self, create_admin};
+use birdseye_server::config::load_config;
+use birdseye_server::dashboard::handle_dashboard;
+use birdseye_server::database::{Repository, SqliteRepository};
+use birdseye_server::monitor::handle_monitor;
+use birdseye_server::state::{with_state, State};
+use birdseye_server::{api, auth, enrollment, history, socket, timetable, tls};
+use std::sync::Arc;
 use warp::Filter;
 
 #[tokio::main]
@@ -9,7 +13,7 @@ use warp::Filter;
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     use std::net::SocketAddr;
 
-    use tracing::{error, info};
+    use tracing::{info, warn};
 
     tracing_subscriber::fmt::fmt()
         .with_env_filter("debug,h2=info")
@@ -17,21 +21,48 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let config = load_config();
 
+    let db: Arc<dyn Repository> = Arc::new(SqliteRepository::open(&config.be_server.database)?);
+
+    let mut args = std::env::args().skip(1);
+    if args.next().as_deref() == Some("create-admin") {
+        return create_admin(db, args.next());
+    }
+
+    let state = State::new(&config, db)?;
+    if state.accounts.is_empty()? {
+        warn!("There are no accounts, run `birdseye-server create-admin <username>` to make one");
+    }
+
+    tokio::spawn(timetable::run_schedule(state.clone()));
+
     let ws_route = warp::get()
         .and(warp::path("dashboard"))
+        .and(auth::authenticated(state.clone()))
         .and(warp::ws())
-        .map(|ws: warp::ws::Ws| {
-            // And then our closure will be called when it completes...
-            ws.on_upgrade(|websocket| {
-                // Just echo all messages back...
-                let (tx, rx) = websocket.split();
-                rx.forward(tx).map(|result| {
-                    if let Err(e) = result {
-                        error!("websocket error: {:?}", e);
-                    }
-                })
+        .and(socket::offered_subprotocols())
+        .and(with_state(state.clone()))
+        .map(|session, ws: warp::ws::Ws, offered, state| {
+            socket::upgrade(ws, offered, move |websocket, codec| {
+                handle_dashboard(state, session, websocket, codec)
             })
-        });
+        })
+        .recover(auth::handle_rejection);
+
+    let monitor_route = warp::get()
+        .and(warp::path("monitor"))
+        .and(tls::monitor_identity(
+            state.clone(),
+            config.be_server.require_client_cert,
+        ))
+        .and(warp::ws())
+        .and(socket::offered_subprotocols())
+        .and(with_state(state.clone()))
+        .map(|identity, ws: warp::ws::Ws, offered, state| {
+            socket::upgrade(ws, offered, move |websocket, codec| {
+                handle_monitor(state, identity, websocket, codec)
+            })
+        })
+        .recover(auth::handle_rejection);
 
     let files = warp::path("static")
         .and(warp::fs::dir(config.be_server.static_path.clone()))
@@ -45,20 +76,33 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .and(warp::fs::file(index_file))
         .with(warp::log("front-end"));
 
+    let auth_routes = auth::routes(state.clone()).with(warp::log("Auth"));
+    let account_routes = accounts::routes(state.clone()).with(warp::log("Accounts"));
+    let api_routes = api::routes(state.clone()).with(warp::log("API"));
+    let history_routes = history::routes(state.clone()).with(warp::log("History"));
+    let enrollment_routes = enrollment::routes(state.clone()).with(warp::log("Enrollment"));
+
     let routes = ws_route
         .with(warp::log("Frontend Webscoket"))
+        .or(auth_routes)
+        .or(account_routes)
+        .or(enrollment_routes)
+        .or(history_routes)
+        .or(api_routes)
+        .or(monitor_route.with(warp::log("Monitor Websocket")))
         .or(files)
         .or(front_end);
 
     let server_addr = format!("{}:{}", &config.be_server.host, config.be_server.port);
     let server_addr: SocketAddr = server_addr.parse().unwrap();
 
-    warp::serve(routes)
-        .tls()
-        .key_path(config.be_server.key)
-        .cert_path(config.be_server.cert)
-        .run(server_addr)
-        .await;
+    tls::serve(
+        warp::service(routes),
+        &config.be_server,
+        &state.ca,
+        server_addr,
+    )
+    .await?;
 
     info!("Server is vaish");
 
@@ -0,0 +1,381 @@
+//! Handling for the websocket connections made by `birdseye-monitor`
+
+use crate::enrollment::Enrollment;
+use crate::policies::{policy_message, POLICY_VERSION};
+use crate::processes::RESTRICTION_VERSION;
+use crate::socket::{decode, encode};
+use crate::state::SharedState;
+use crate::timetable::send_lock;
+use crate::tls::PeerIdentity;
+use birdseye_common::backend::{MonitorMessage, ServerMessage, HEARTBEAT_INTERVAL};
+use birdseye_common::codec::Codec;
+use birdseye_common::frontend::WsMessage;
+use birdseye_common::handshake::{Capability, HandshakeError, Hello, Negotiated};
+use birdseye_common::machine::Presence;
+use birdseye_common::sync::{ProcessStatus, ProcessSync};
+use birdseye_common::{Machine, MachineId};
+use futures_util::stream::SplitStream;
+use futures_util::{SinkExt, StreamExt};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::{sleep, timeout};
+use tracing::{debug, error, info, warn};
+use warp::ws::{Message, WebSocket};
+
+/// The first protocol version where monitors are sent [`ServerMessage::Demand`]
+const DEMAND_VERSION: u32 = 13;
+
+/// How often a monitor's demand is checked even if no dashboard has changed what it is looking
+/// at, as machines moving rooms changes who is subscribed to them
+const DEMAND_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The hello sent by the server to every monitor
+fn server_hello() -> Hello {
+    Hello::new(
+        concat!("birdseye-server/", env!("CARGO_PKG_VERSION")),
+        Vec::<Capability>::new(),
+    )
+}
+
+/// Keep a monitor told how much of its screen the dashboards need, until the connection closes
+async fn send_demand(state: SharedState, machine: MachineId, tx: mpsc::Sender<ServerMessage>) {
+    let mut changes = state.hub.changes();
+    let mut sent = None;
+
+    loop {
+        let demand = state.hub.demand(&machine, state.thumbnails.largest());
+        if sent != Some(demand) {
+            debug!("{machine} needs {demand:?} of its screen");
+            if tx.send(ServerMessage::Demand(demand)).await.is_err() {
+                return;
+            }
+            sent = Some(demand);
+        }
+
+        tokio::select! {
+            changed = changes.changed() => if changed.is_err() {
+                return;
+            },
+            _ = sleep(DEMAND_INTERVAL) => {}
+        }
+    }
+}
+
+/// Wait for the next data frame from a monitor, returns `None` if the monitor disconnected or has
+/// been quiet for too long
+async fn next_message(stream: &mut SplitStream<WebSocket>) -> Option<Message> {
+    loop {
+        let msg = match timeout(HEARTBEAT_INTERVAL * 3, stream.next()).await {
+            Ok(msg) => msg?,
+            Err(_) => {
+                warn!("Monitor missed its heartbeat, disconnecting");
+                return None;
+            }
+        };
+
+        match msg {
+            Ok(msg) if msg.is_close() => return None,
+            Ok(msg) if msg.is_binary() || msg.is_text() => return Some(msg),
+            Ok(_) => continue,
+            Err(ex) => {
+                error!("websocket error: {ex:?}");
+                return None;
+            }
+        }
+    }
+}
+
+/// Wait for the monitor's hello and reply with our own, then wait for the monitor to say which
+/// machine it is running on. Returns `None` if the monitor was rejected or disconnected
+///
+/// If the monitor presented a client certificate it can only claim to be the machine the
+/// certificate was issued to, without one it can't claim to be a machine that has been enrolled
+async fn handshake(
+    stream: &mut SplitStream<WebSocket>,
+    tx: &mpsc::Sender<ServerMessage>,
+    codec: Codec,
+    identity: Option<&PeerIdentity>,
+    enrollment: &Enrollment,
+) -> Option<(Negotiated, Machine)> {
+    let msg = next_message(stream).await?;
+
+    let (result, hostname) = match decode::<MonitorMessage>(codec, &msg) {
+        Ok(MonitorMessage::Hello { hello, hostname }) => {
+            (server_hello().negotiate(&hello), hostname)
+        }
+        // Version 1 monitors don't send a hello, they are only understood well enough to be
+        // turned away
+        Ok(MonitorMessage::Register { version, hostname }) => {
+            let hello = Hello::legacy(&format!("birdseye-monitor (protocol {version})"));
+            (server_hello().negotiate(&hello), hostname)
+        }
+        Ok(_) | Err(_) => (Err(HandshakeError::ExpectedHello), String::new()),
+    };
+
+    let negotiated = match result {
+        Ok(negotiated) => negotiated,
+        Err(ex) => {
+            warn!("Rejecting monitor {hostname}: {ex}");
+            let _ = tx.send(ServerMessage::Rejected(ex.to_string())).await;
+            return None;
+        }
+    };
+
+    tx.send(ServerMessage::Hello(server_hello())).await.ok()?;
+
+    let msg = next_message(stream).await?;
+    let machine = match decode::<MonitorMessage>(codec, &msg) {
+        Ok(MonitorMessage::Machine(machine)) => machine,
+        Ok(msg) => {
+            warn!("Expected machine from monitor {hostname}, got {msg:?}");
+            return None;
+        }
+        Err(ex) => {
+            warn!("Could not decode machine from monitor {hostname}: {ex}");
+            return None;
+        }
+    };
+
+    let reason = match identity {
+        Some(identity) if identity.machine() != machine.id() => format!(
+            "Certificate was issued to {} but monitor claims to be {}",
+            identity.machine(),
+            machine.id()
+        ),
+        None if enrollment.needs_certificate(machine.id()) => format!(
+            "{} has been enrolled and has to connect with its certificate",
+            machine.id()
+        ),
+        _ => return Some((negotiated, machine)),
+    };
+
+    warn!("Rejecting monitor {hostname}: {reason}");
+    let _ = tx.send(ServerMessage::Rejected(reason)).await;
+    None
+}
+
+/// Pass a change to a machine's process list on to the dashboards subscribed to it
+fn publish_processes(state: &SharedState, machine: &MachineId, sync: ProcessSync) {
+    let msg = WsMessage::Processes {
+        machine: machine.clone(),
+        sync,
+    };
+    state.hub.publish(machine, msg);
+}
+
+/// Let the dashboards subscribed to a machine know it has connected or disconnected
+fn publish_presence(state: &SharedState, machine: &Machine, presence: Presence) {
+    let msg = WsMessage::Machine {
+        machine: machine.clone(),
+        presence,
+    };
+    state.hub.publish(machine.id(), msg);
+}
+
+/// Handle a message sent by a registered monitor
+async fn handle_message(
+    state: &SharedState,
+    tx: &mpsc::Sender<ServerMessage>,
+    machine: &MachineId,
+    msg: MonitorMessage,
+) {
+    match msg {
+        MonitorMessage::Heartbeat => {}
+        MonitorMessage::Processes(sync) => {
+            let result = state.processes.apply(machine, sync, |sync| {
+                publish_processes(state, machine, sync)
+            });
+            match result {
+                Ok(changes) => state.history.record(machine, changes),
+                Err(gap) => {
+                    debug!("{gap} from {machine}, asking for a snapshot");
+                    let _ = tx.send(ServerMessage::ResyncProcesses).await;
+                }
+            }
+        }
+        // Monitors older than protocol version 4 don't number their updates, so the server
+        // numbers them instead
+        MonitorMessage::ProcessStarted(process) => {
+            let status = ProcessStatus::Start(process);
+            state.processes.record(machine, status.clone(), |sync| {
+                publish_processes(state, machine, sync)
+            });
+            state.history.record(machine, vec![status]);
+        }
+        MonitorMessage::ProcessStopped(process) => {
+            let status = ProcessStatus::Stop(process);
+            state.processes.record(machine, status.clone(), |sync| {
+                publish_processes(state, machine, sync)
+            });
+            state.history.record(machine, vec![status]);
+        }
+        MonitorMessage::PolicyViolation(violation) => {
+            let process = violation.process();
+            match violation.error() {
+                Some(ex) => warn!(
+                    "{} ({}) on {machine} broke rule {}, could not {}: {ex}",
+                    process.name(),
+                    process.pid(),
+                    violation.name(),
+                    violation.action()
+                ),
+                None => info!(
+                    "{} ({}) on {machine} broke rule {}, action: {}",
+                    process.name(),
+                    process.pid(),
+                    violation.name(),
+                    violation.action()
+                ),
+            }
+
+            state.history.record_violation(machine, violation.clone());
+            let msg = WsMessage::PolicyViolation {
+                machine: machine.clone(),
+                violation,
+            };
+            state.hub.publish_since(POLICY_VERSION, machine, msg);
+        }
+        MonitorMessage::Restrictions(restrictions) => {
+            state
+                .processes
+                .set_restrictions(machine, restrictions, |restrictions| {
+                    let msg = WsMessage::Restrictions {
+                        machine: machine.clone(),
+                        restrictions,
+                    };
+                    state.hub.publish_since(RESTRICTION_VERSION, machine, msg);
+                });
+        }
+        MonitorMessage::CommandResult { id, result } => state.commands.resolve(machine, id, result),
+        MonitorMessage::Frame(frame) => {
+            if !state.screens.publish(machine, frame.into()) {
+                warn!("Monitor {machine} sent a frame with the wrong number of pixels, ignoring");
+            }
+        }
+        MonitorMessage::Packet(packet) => {
+            if !state.screens.publish(machine, packet) {
+                warn!("Monitor {machine} sent an empty or broken frame, ignoring");
+            }
+        }
+        MonitorMessage::Register { .. } | MonitorMessage::Hello { .. } => {
+            warn!("Monitor {machine} sent a second hello, ignoring")
+        }
+        MonitorMessage::Machine(_) => {
+            warn!("Monitor {machine} tried to change its machine, ignoring")
+        }
+    }
+}
+
+/// Run a monitor connection until it is closed
+pub async fn handle_monitor(
+    state: SharedState,
+    identity: Option<PeerIdentity>,
+    websocket: WebSocket,
+    codec: Codec,
+) {
+    let (mut sink, mut stream) = websocket.split();
+    let (tx, mut rx) = mpsc::channel::<ServerMessage>(32);
+
+    // Commands are sent from other connections, so all messages to the monitor go through a
+    // channel
+    let writer = tokio::spawn(async move {
+        while let Some(msg) = rx.recv().await {
+            let rejected = matches!(msg, ServerMessage::Rejected(_));
+
+            if let Some(msg) = encode(codec, &msg) {
+                if sink.send(msg).await.is_err() {
+                    break;
+                }
+            }
+
+            if rejected {
+                let _ = sink.close().await;
+                break;
+            }
+        }
+    });
+
+    let (negotiated, machine) = match handshake(
+        &mut stream,
+        &tx,
+        codec,
+        identity.as_ref(),
+        &state.enrollment,
+    )
+    .await
+    {
+        Some(registration) => registration,
+        None => {
+            drop(tx);
+            let _ = writer.await;
+            return;
+        }
+    };
+
+    let machine_id = machine.id().clone();
+    let (connection, mut replaced) = state.registry.connect(
+        machine.clone(),
+        negotiated.version(),
+        tx.clone(),
+        |machine, presence| publish_presence(&state, machine, presence),
+    );
+
+    info!(
+        "Machine {} ({}) connected using {} protocol version {} and {codec}, {} machines online",
+        machine.hostname(),
+        machine_id,
+        negotiated.agent(),
+        negotiated.version(),
+        state
+            .registry
+            .list()
+            .iter()
+            .filter(|(_, presence)| presence.is_online())
+            .count()
+    );
+
+    if negotiated.version() >= POLICY_VERSION {
+        let _ = tx
+            .send(policy_message(&state, &machine_id, negotiated.version()))
+            .await;
+    }
+
+    // Monitors keep the screen locked while they are away, so are told whether the lock is still
+    // meant to be there. Unlocking a screen the monitor didn't lock does nothing
+    let locked = state.schedule.is_locked(&machine_id);
+    send_lock(&state, machine_id.clone(), locked);
+
+    // Older monitors always capture every frame
+    let demand = (negotiated.version() >= DEMAND_VERSION)
+        .then(|| tokio::spawn(send_demand(state.clone(), machine_id.clone(), tx.clone())));
+
+    loop {
+        let msg = tokio::select! {
+            msg = next_message(&mut stream) => msg,
+            // Closing the old connection leaves it to the new one
+            _ = &mut replaced => {
+                info!("Machine {machine_id} connected again, closing its old connection");
+                None
+            }
+        };
+
+        match msg.map(|msg| decode::<MonitorMessage>(codec, &msg)) {
+            Some(Ok(msg)) => handle_message(&state, &tx, &machine_id, msg).await,
+            Some(Err(ex)) => warn!("Could not decode message from {machine_id}: {ex}"),
+            None => break,
+        }
+    }
+
+    state
+        .registry
+        .disconnect(&machine_id, connection, |machine, presence| {
+            publish_presence(&state, machine, presence)
+        });
+    if let Some(demand) = demand {
+        demand.abort();
+    }
+    drop(tx);
+    writer.abort();
+
+    info!("Machine {} ({machine_id}) disconnected", machine.hostname());
+}
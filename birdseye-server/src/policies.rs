@@ -0,0 +1,162 @@
+//! Application blacklist rules, kept in the database and pushed to every monitor
+//!
+//! Monitors only get the rules for the room their machine is in that the timetable has switched on,
+//! and get sent them again whenever the rules, rooms or running periods change. Monitors older
+//! than protocol version 7 don't know about rules, so nothing is enforced on them, and monitors
+//! older than version 9 only alert on rules that throttle.
+
+use crate::database::{DatabaseError, Repository};
+use crate::state::SharedState;
+use birdseye_common::backend::ServerMessage;
+use birdseye_common::policy::{Action, CompiledPolicy, PolicyError, Rule, RuleId};
+use birdseye_common::{MachineId, RoomId};
+use std::fmt::{Display, Formatter};
+use std::sync::{Arc, RwLock};
+use tracing::debug;
+
+/// The first protocol version monitors can be sent rules on, and dashboards told about violations
+pub const POLICY_VERSION: u32 = 7;
+
+/// The first protocol version monitors can throttle processes on
+pub const THROTTLE_VERSION: u32 = 9;
+
+#[derive(Debug)]
+pub enum RuleError {
+    Invalid(PolicyError),
+    NotFound(RuleId),
+    Database(DatabaseError),
+}
+
+impl Display for RuleError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RuleError::Invalid(ex) => write!(f, "{ex}"),
+            RuleError::NotFound(id) => write!(f, "there is no rule {id}"),
+            RuleError::Database(ex) => write!(f, "{ex}"),
+        }
+    }
+}
+
+impl std::error::Error for RuleError {}
+
+impl From<PolicyError> for RuleError {
+    fn from(ex: PolicyError) -> Self {
+        RuleError::Invalid(ex)
+    }
+}
+
+impl From<DatabaseError> for RuleError {
+    fn from(ex: DatabaseError) -> Self {
+        RuleError::Database(ex)
+    }
+}
+
+/// Every blacklist rule, the database is only read on startup
+pub struct Policies {
+    db: Arc<dyn Repository>,
+    rules: RwLock<Vec<Rule>>,
+}
+
+impl Policies {
+    pub fn load(db: Arc<dyn Repository>) -> Result<Self, DatabaseError> {
+        let rules = db.policies()?;
+        Ok(Self {
+            db,
+            rules: RwLock::new(rules),
+        })
+    }
+
+    /// Get every rule, ordered by id
+    pub fn list(&self) -> Vec<Rule> {
+        self.rules.read().unwrap().clone()
+    }
+
+    /// Get the rules that apply to a machine in the given room
+    pub fn for_room(&self, room: Option<&RoomId>) -> Vec<Rule> {
+        self.rules
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|rule| rule.applies_to(room))
+            .cloned()
+            .collect()
+    }
+
+    /// Check that a rule compiles and add it, returns the rule with its new id
+    pub fn create(&self, rule: Rule) -> Result<Rule, RuleError> {
+        CompiledPolicy::compile(vec![rule.clone()])?;
+
+        let mut rules = self.rules.write().unwrap();
+        let id = self.db.create_policy(&rule)?;
+        let rule = rule.with_id(id);
+        rules.push(rule.clone());
+
+        Ok(rule)
+    }
+
+    /// Check that a rule compiles and replace the rule with the given id with it
+    pub fn update(&self, id: RuleId, rule: Rule) -> Result<Rule, RuleError> {
+        let rule = rule.with_id(id);
+        CompiledPolicy::compile(vec![rule.clone()])?;
+
+        let mut rules = self.rules.write().unwrap();
+        if !self.db.update_policy(&rule)? {
+            return Err(RuleError::NotFound(id));
+        }
+        if let Some(existing) = rules.iter_mut().find(|existing| existing.id() == id) {
+            *existing = rule.clone();
+        }
+
+        Ok(rule)
+    }
+
+    pub fn delete(&self, id: RuleId) -> Result<(), RuleError> {
+        let mut rules = self.rules.write().unwrap();
+        if !self.db.delete_policy(id)? {
+            return Err(RuleError::NotFound(id));
+        }
+        rules.retain(|rule| rule.id() != id);
+
+        Ok(())
+    }
+}
+
+/// The message that gives a machine's monitor its rules, `version` is the protocol version the
+/// monitor negotiated
+pub fn policy_message(state: &SharedState, machine: &MachineId, version: u32) -> ServerMessage {
+    let room = state.rooms.room(machine);
+    let rules = state.policies.for_room(room.as_ref());
+    let mut rules = state.schedule.enforced(room.as_ref(), rules);
+
+    if version < THROTTLE_VERSION {
+        rules = rules
+            .into_iter()
+            .map(|rule| match rule.action() {
+                Action::Throttle(_) => rule.with_action(Action::Alert),
+                _ => rule,
+            })
+            .collect();
+    }
+
+    ServerMessage::Policy(rules)
+}
+
+/// Send every connected monitor its rules again, called after the rules, rooms or periods change.
+/// Each monitor is sent its rules in the background, so one that has stopped reading can't hold up
+/// the rest
+pub fn push_policies(state: &SharedState) {
+    let links = state.registry.links(POLICY_VERSION);
+    debug!("Sending rules to {} monitors", links.len());
+
+    for (machine, version, link) in links {
+        let state = state.clone();
+        tokio::spawn(async move {
+            // The rules are worked out once there's room for them, so they are never older than
+            // rules queued before them
+            match link.reserve().await {
+                Ok(permit) => permit.send(policy_message(&state, &machine, version)),
+                Err(_) => debug!("Could not send rules to {machine}, it has disconnected"),
+            }
+        });
+    }
+}
@@ -0,0 +1,136 @@
+//! The server's copy of every machine's process list
+
+use birdseye_common::sync::{
+    ProcessList, ProcessSnapshot, ProcessStatus, ProcessSync, Restriction, SyncGap,
+};
+use birdseye_common::{MachineId, Process};
+use std::collections::{HashMap, HashSet};
+use std::sync::RwLock;
+
+/// The first protocol version monitors report the processes they've suspended or throttled on,
+/// and dashboards are sent them
+pub const RESTRICTION_VERSION: u32 = 9;
+
+/// Process lists reported by monitors, along with which processes are suspended or throttled
+///
+/// Changes are passed to `publish` while the store is locked, so anything published is in the
+/// same order as the changes and no change can slip in between a snapshot and the changes after
+/// it
+#[derive(Default)]
+pub struct ProcessStore {
+    machines: RwLock<HashMap<MachineId, ProcessList>>,
+    restrictions: RwLock<HashMap<MachineId, Vec<Restriction>>>,
+}
+
+impl ProcessStore {
+    /// Apply a snapshot or update from a machine's monitor and publish it, if an update was missed
+    /// the caller must ask the monitor for a new snapshot
+    ///
+    /// Returns the processes that actually started or stopped, a snapshot is compared against the
+    /// list it replaces and updates that were already applied are left out
+    pub fn apply(
+        &self,
+        machine: &MachineId,
+        sync: ProcessSync,
+        publish: impl FnOnce(ProcessSync),
+    ) -> Result<Vec<ProcessStatus>, SyncGap> {
+        let mut machines = self.machines.write().unwrap();
+        let list = machines.entry(machine.clone()).or_default();
+
+        let changes = match &sync {
+            ProcessSync::Snapshot(snapshot) => diff(list.processes(), snapshot.processes()),
+            ProcessSync::Update(update) => match list.seq() {
+                Some(seq) if update.seq() <= seq => vec![],
+                _ => vec![update.status().clone()],
+            },
+        };
+
+        list.apply(&sync)?;
+        publish(sync);
+
+        Ok(changes)
+    }
+
+    /// Record a change reported by a monitor that doesn't number its updates, the server's copy
+    /// of the list is treated as the source of truth instead
+    pub fn record(
+        &self,
+        machine: &MachineId,
+        status: ProcessStatus,
+        publish: impl FnOnce(ProcessSync),
+    ) {
+        let mut machines = self.machines.write().unwrap();
+        let list = machines.entry(machine.clone()).or_default();
+
+        if !list.is_synced() {
+            *list = ProcessList::new_source();
+        }
+
+        publish(ProcessSync::Update(list.record(status)));
+    }
+
+    /// Run `f` with the current process lists of some machines, no changes are published until it
+    /// returns
+    pub fn with_snapshots<R>(
+        &self,
+        machines: &[MachineId],
+        f: impl FnOnce(Vec<(MachineId, ProcessSnapshot)>) -> R,
+    ) -> R {
+        let guard = self.machines.read().unwrap();
+        let snapshots = machines
+            .iter()
+            .filter_map(|machine| Some((machine.clone(), guard.get(machine)?.snapshot())))
+            .collect();
+
+        f(snapshots)
+    }
+
+    /// Replace the restricted processes of a machine and publish them
+    pub fn set_restrictions(
+        &self,
+        machine: &MachineId,
+        restrictions: Vec<Restriction>,
+        publish: impl FnOnce(Vec<Restriction>),
+    ) {
+        let mut machines = self.restrictions.write().unwrap();
+        machines.insert(machine.clone(), restrictions.clone());
+        publish(restrictions);
+    }
+
+    /// Run `f` with the restricted processes of the machines that have any, no changes are
+    /// published until it returns
+    pub fn with_restrictions<R>(
+        &self,
+        machines: &[MachineId],
+        f: impl FnOnce(Vec<(MachineId, Vec<Restriction>)>) -> R,
+    ) -> R {
+        let guard = self.restrictions.read().unwrap();
+        let restrictions = machines
+            .iter()
+            .filter_map(|machine| Some((machine.clone(), guard.get(machine)?.clone())))
+            .filter(|(_, restrictions)| !restrictions.is_empty())
+            .collect();
+
+        f(restrictions)
+    }
+}
+
+/// The changes that turn one process list into another, a process that has the same pid as an old
+/// one but started at a different time is a new process
+fn diff<'a>(old: impl Iterator<Item = &'a Process>, new: &[Process]) -> Vec<ProcessStatus> {
+    let key = |process: &Process| (*process.pid(), process.start_time());
+    let old: Vec<&Process> = old.collect();
+    let old_keys: HashSet<_> = old.iter().map(|process| key(process)).collect();
+    let new_keys: HashSet<_> = new.iter().map(key).collect();
+
+    let stopped = old
+        .into_iter()
+        .filter(|process| !new_keys.contains(&key(process)))
+        .map(|process| ProcessStatus::Stop(process.clone()));
+    let started = new
+        .iter()
+        .filter(|process| !old_keys.contains(&key(process)))
+        .map(|process| ProcessStatus::Start(process.clone()));
+
+    stopped.chain(started).collect()
+}
@@ -0,0 +1,224 @@
+//! The machines that have registered with the server and whether they are currently connected
+
+use crate::database::{DatabaseError, Repository, StoredMachine};
+use birdseye_common::backend::ServerMessage;
+use birdseye_common::command::CommandError;
+use birdseye_common::history::{Event, EventKind};
+use birdseye_common::machine::Presence;
+use birdseye_common::{Machine, MachineId};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::{mpsc, oneshot};
+use tracing::warn;
+
+/// Current time in seconds since the unix epoch
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default()
+}
+
+/// A live connection from a machine's monitor
+struct Connection {
+    id: u64,
+    /// The protocol version negotiated with the monitor
+    version: u32,
+    link: mpsc::Sender<ServerMessage>,
+    /// Tells the connection to close when a newer connection from the same machine replaces it
+    close: oneshot::Sender<()>,
+}
+
+struct Entry {
+    machine: Machine,
+    presence: Presence,
+    connection: Option<Connection>,
+}
+
+/// Every machine that has ever registered, machines are kept in the database so they are still
+/// known after a restart
+pub struct Registry {
+    db: Arc<dyn Repository>,
+    next_connection: AtomicU64,
+    machines: RwLock<HashMap<MachineId, Entry>>,
+}
+
+impl Registry {
+    /// Load the machines stored in the database, they are all offline until they connect again
+    pub fn load(db: Arc<dyn Repository>) -> Result<Self, DatabaseError> {
+        let machines = db
+            .machines()?
+            .into_iter()
+            .map(|stored| {
+                let entry = Entry {
+                    presence: Presence::Offline {
+                        last_seen: stored.last_seen,
+                    },
+                    machine: stored.machine,
+                    connection: None,
+                };
+                (entry.machine.id().clone(), entry)
+            })
+            .collect();
+
+        Ok(Self {
+            db,
+            next_connection: AtomicU64::new(0),
+            machines: RwLock::new(machines),
+        })
+    }
+
+    /// Save a machine and record what happened to it, failing to do so doesn't stop the machine
+    /// from connecting so errors are only logged
+    fn store(&self, machine: &Machine, last_seen: u64, kind: EventKind) {
+        let stored = StoredMachine {
+            machine: machine.clone(),
+            last_seen,
+        };
+        if let Err(ex) = self.db.save_machine(&stored) {
+            warn!("Could not save machine {}: {ex}", machine.id());
+        }
+
+        if let Err(ex) = self
+            .db
+            .record_events(&[Event::new(last_seen, machine.id(), kind)])
+        {
+            warn!("Could not record event for {}: {ex}", machine.id());
+        }
+    }
+
+    /// Record that a machine's monitor has connected, if the machine was already connected the old
+    /// connection is told to close. Returns an id which must be passed to [`Registry::disconnect`],
+    /// and a receiver that fires when this connection is replaced in turn
+    ///
+    /// Like the process store, the new presence is passed to `publish` while the registry is
+    /// locked so presence changes are published in order
+    pub fn connect(
+        &self,
+        machine: Machine,
+        version: u32,
+        link: mpsc::Sender<ServerMessage>,
+        publish: impl FnOnce(&Machine, Presence),
+    ) -> (u64, oneshot::Receiver<()>) {
+        let id = self.next_connection.fetch_add(1, Ordering::Relaxed);
+        let (close, closed) = oneshot::channel();
+        let entry = Entry {
+            machine: machine.clone(),
+            presence: Presence::Online { since: now() },
+            connection: Some(Connection {
+                id,
+                version,
+                link,
+                close,
+            }),
+        };
+
+        let mut machines = self.machines.write().unwrap();
+        publish(&entry.machine, entry.presence);
+        self.store(&machine, now(), EventKind::Connected);
+        let replaced = machines
+            .insert(machine.id().clone(), entry)
+            .and_then(|entry| entry.connection);
+        if let Some(old) = replaced {
+            let _ = old.close.send(());
+        }
+
+        (id, closed)
+    }
+
+    /// Record that a machine's monitor has disconnected, does nothing if the machine has since
+    /// reconnected
+    pub fn disconnect(
+        &self,
+        machine: &MachineId,
+        connection: u64,
+        publish: impl FnOnce(&Machine, Presence),
+    ) {
+        let mut machines = self.machines.write().unwrap();
+        let entry = match machines.get_mut(machine) {
+            Some(entry) => entry,
+            None => return,
+        };
+
+        if entry.connection.as_ref().map(|conn| conn.id) == Some(connection) {
+            let last_seen = now();
+            entry.connection = None;
+            entry.presence = Presence::Offline { last_seen };
+            publish(&entry.machine, entry.presence);
+            self.store(&entry.machine, last_seen, EventKind::Disconnected);
+        }
+    }
+
+    /// Run `f` with the given machines along with whether they are online, machines that have
+    /// never connected are left out. No presence changes are published until it returns
+    pub fn with_machines<R>(
+        &self,
+        machines: &[MachineId],
+        f: impl FnOnce(Vec<(Machine, Presence)>) -> R,
+    ) -> R {
+        let guard = self.machines.read().unwrap();
+        let machines = machines
+            .iter()
+            .filter_map(|machine| guard.get(machine))
+            .map(|entry| (entry.machine.clone(), entry.presence))
+            .collect();
+
+        f(machines)
+    }
+
+    /// Get the link used to send messages to a machine's monitor
+    pub fn link(&self, machine: &MachineId) -> Result<mpsc::Sender<ServerMessage>, CommandError> {
+        self.link_since(machine, 0)
+    }
+
+    /// Like [`Registry::link`], but monitors that negotiated a version before `min_version` don't
+    /// support what is going to be sent
+    pub fn link_since(
+        &self,
+        machine: &MachineId,
+        min_version: u32,
+    ) -> Result<mpsc::Sender<ServerMessage>, CommandError> {
+        let machines = self.machines.read().unwrap();
+        let entry = machines
+            .get(machine)
+            .ok_or_else(|| CommandError::UnknownMachine(machine.clone()))?;
+        let conn = entry
+            .connection
+            .as_ref()
+            .ok_or_else(|| CommandError::MachineOffline(machine.clone()))?;
+
+        match conn.version >= min_version {
+            true => Ok(conn.link.clone()),
+            false => Err(CommandError::Unsupported),
+        }
+    }
+
+    /// Get the links of every connected monitor that negotiated at least `min_version`, along with
+    /// the version they negotiated
+    pub fn links(&self, min_version: u32) -> Vec<(MachineId, u32, mpsc::Sender<ServerMessage>)> {
+        self.machines
+            .read()
+            .unwrap()
+            .iter()
+            .filter_map(|(machine, entry)| Some((machine, entry.connection.as_ref()?)))
+            .filter(|(_, conn)| conn.version >= min_version)
+            .map(|(machine, conn)| (machine.clone(), conn.version, conn.link.clone()))
+            .collect()
+    }
+
+    /// Get every machine along with whether it is online, sorted by hostname
+    pub fn list(&self) -> Vec<(Machine, Presence)> {
+        let mut machines = self
+            .machines
+            .read()
+            .unwrap()
+            .values()
+            .map(|entry| (entry.machine.clone(), entry.presence))
+            .collect::<Vec<_>>();
+
+        machines.sort_by(|(a, _), (b, _)| a.hostname().cmp(b.hostname()));
+        machines
+    }
+}
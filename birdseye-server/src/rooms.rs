@@ -0,0 +1,113 @@
+//! The rooms machines are in, and which rooms each teacher is able to see
+//!
+//! Teachers only see the machines in the rooms they have been assigned to, admins see every
+//! machine. Machines that aren't in a room can only be seen by admins.
+//!
+//! Clones of [`Rooms`] share the same map, so a room changed through one is seen by all of them.
+
+use crate::accounts::Account;
+use crate::database::{DatabaseError, Repository};
+use birdseye_common::api::RoomInfo;
+use birdseye_common::frontend::Topic;
+use birdseye_common::{MachineId, RoomId};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, RwLock};
+
+#[derive(Clone)]
+pub struct Rooms {
+    db: Arc<dyn Repository>,
+    /// The room each machine is in
+    machines: Arc<RwLock<HashMap<MachineId, RoomId>>>,
+}
+
+impl Rooms {
+    /// Store the rooms from the config file in the database, then load every room from the
+    /// database
+    pub fn load(
+        db: Arc<dyn Repository>,
+        config: &BTreeMap<RoomId, Vec<MachineId>>,
+    ) -> Result<Self, DatabaseError> {
+        for (room, machines) in config {
+            db.set_room_machines(room, machines)?;
+        }
+
+        let rooms = Self {
+            db,
+            machines: Arc::default(),
+        };
+        rooms.reload()?;
+        Ok(rooms)
+    }
+
+    fn reload(&self) -> Result<(), DatabaseError> {
+        let machines = self
+            .db
+            .room_machines()?
+            .into_iter()
+            .map(|(room, machine)| (machine, room))
+            .collect();
+
+        *self.machines.write().unwrap() = machines;
+        Ok(())
+    }
+
+    /// Replace the machines in a room and save it, machines are taken out of any room they were
+    /// in before
+    pub fn set_machines(&self, room: &RoomId, machines: &[MachineId]) -> Result<(), DatabaseError> {
+        self.db.set_room_machines(room, machines)?;
+        self.reload()
+    }
+
+    /// Get the room a machine is in
+    pub fn room(&self, machine: &MachineId) -> Option<RoomId> {
+        self.machines.read().unwrap().get(machine).cloned()
+    }
+
+    /// Get every room that has a machine in it, sorted by id
+    pub fn list(&self) -> Vec<RoomInfo> {
+        let mut rooms = BTreeMap::<RoomId, Vec<MachineId>>::new();
+        for (machine, room) in self.machines.read().unwrap().iter() {
+            rooms.entry(room.clone()).or_default().push(machine.clone());
+        }
+
+        rooms
+            .into_iter()
+            .map(|(id, mut machines)| {
+                machines.sort();
+                RoomInfo { id, machines }
+            })
+            .collect()
+    }
+
+    /// Get every machine covered by a topic
+    pub fn machines(&self, topic: &Topic) -> Vec<MachineId> {
+        match topic {
+            Topic::Machine(machine) => vec![machine.clone()],
+            Topic::Room(room) => self
+                .machines
+                .read()
+                .unwrap()
+                .iter()
+                .filter(|(_, id)| *id == room)
+                .map(|(machine, _)| machine.clone())
+                .collect(),
+        }
+    }
+
+    /// Whether an account is allowed to see and run commands on a machine
+    pub fn can_see(&self, account: &Account, machine: &MachineId) -> bool {
+        account.is_admin()
+            || self
+                .room(machine)
+                .map(|room| account.rooms().contains(&room))
+                .unwrap_or_default()
+    }
+
+    /// Whether an account is allowed to subscribe to a topic
+    pub fn can_subscribe(&self, account: &Account, topic: &Topic) -> bool {
+        match topic {
+            Topic::Machine(machine) => self.can_see(account, machine),
+            Topic::Room(room) => account.is_admin() || account.rooms().contains(room),
+        }
+    }
+}
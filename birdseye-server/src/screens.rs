@@ -0,0 +1,93 @@
+//! Relays the frames monitors capture from their screens to the dashboards watching them
+//!
+//! Only the latest frame from each machine is kept. Every dashboard watching a machine has a task
+//! that waits for a new frame and hands it over once the dashboard has taken the last one, so a
+//! slow dashboard skips the frames that arrive in the meantime rather than queueing them. Frames
+//! are kept as the [`Packet`]s the monitor sent, and share their data, so the only copy made for
+//! each dashboard is encoding it into its websocket message. A frame that is the same as the last
+//! one is ignored, nothing is sent when the screen hasn't changed.
+//!
+//! Monitors streaming tiles or H.264 only send what changed, so a [`Snapshot`] of each screen is
+//! kept too. Dashboards that start watching partway, or skip a frame, are sent that instead.
+
+use birdseye_common::screen::{Encoding, Packet, Snapshot};
+use birdseye_common::MachineId;
+use std::collections::HashMap;
+use std::sync::RwLock;
+use tokio::sync::watch;
+
+struct Screen {
+    frame: watch::Sender<Option<Packet>>,
+    snapshot: Snapshot,
+    /// How many times the frame has changed
+    generation: u64,
+}
+
+impl Default for Screen {
+    fn default() -> Self {
+        Self {
+            frame: watch::channel(None).0,
+            snapshot: Snapshot::new(),
+            generation: 0,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Screens {
+    machines: RwLock<HashMap<MachineId, Screen>>,
+}
+
+impl Screens {
+    /// Replace the latest frame from a machine, unless it doesn't change anything. Returns false
+    /// if the frame was dropped because it is empty, bigger than
+    /// [`MAX_SIZE`](birdseye_common::screen::MAX_SIZE), raw without as many pixels as its size
+    /// says, or a delta that doesn't follow on from the last frame. Encoded frames aren't decoded
+    /// to check them, that is left to whoever shows them
+    pub fn publish(&self, machine: &MachineId, frame: Packet) -> bool {
+        let pixels = frame.width() as u64 * frame.height() as u64;
+        let valid = match frame.encoding() {
+            Encoding::Raw => frame.data().len() as u64 == pixels * 4,
+            Encoding::Jpeg | Encoding::Tiles | Encoding::H264 => !frame.data().is_empty(),
+        };
+        if frame.check_size().is_err() || !valid {
+            return false;
+        }
+
+        let mut machines = self.machines.write().unwrap();
+        let screen = machines.entry(machine.clone()).or_default();
+
+        match screen.snapshot.apply(&frame) {
+            Ok(true) => {
+                screen.frame.send_replace(Some(frame));
+                screen.generation += 1;
+                true
+            }
+            Ok(false) => true,
+            Err(_) => false,
+        }
+    }
+
+    /// Get the whole of a machine's screen as a keyframe if it has sent one, along with its
+    /// generation. The generation only goes up when the screen changes, so anything made from it
+    /// can be kept until it does
+    pub fn latest(&self, machine: &MachineId) -> Option<(u64, Packet)> {
+        let machines = self.machines.read().unwrap();
+        let screen = machines.get(machine)?;
+
+        Some((screen.generation, screen.snapshot.packet()?))
+    }
+
+    /// Start watching a machine's screen, the receiver holds the latest frame and is woken up
+    /// whenever there is a new one. That frame may only be what changed, see [`Screens::latest`]
+    /// for all of it
+    pub fn watch(&self, machine: &MachineId) -> watch::Receiver<Option<Packet>> {
+        self.machines
+            .write()
+            .unwrap()
+            .entry(machine.clone())
+            .or_default()
+            .frame
+            .subscribe()
+    }
+}
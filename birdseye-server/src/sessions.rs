@@ -0,0 +1,81 @@
+//! Logged in dashboard sessions
+
+use crate::accounts::Account;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+/// A teacher that has logged in
+#[derive(Debug, Clone)]
+pub struct Session {
+    username: String,
+    admin: bool,
+    expires: Instant,
+}
+
+impl<'a> Session {
+    pub fn username(&'a self) -> &'a str {
+        &self.username
+    }
+
+    pub fn is_admin(&self) -> bool {
+        self.admin
+    }
+}
+
+/// Every session that hasn't been logged out, keyed by the token stored in the session cookie
+pub struct Sessions {
+    ttl: Duration,
+    sessions: RwLock<HashMap<String, Session>>,
+}
+
+impl Sessions {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            sessions: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// How long a session lasts after logging in
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// Start a session for an account, returns the session's token
+    pub fn create(&self, account: &Account) -> String {
+        let mut bytes = [0u8; 32];
+        OsRng.fill_bytes(&mut bytes);
+        let token: String = bytes.iter().map(|byte| format!("{byte:02x}")).collect();
+
+        let session = Session {
+            username: account.username().to_string(),
+            admin: account.is_admin(),
+            expires: Instant::now() + self.ttl,
+        };
+
+        let mut sessions = self.sessions.write().unwrap();
+        // Clear out anyone who never logged out while we are here
+        let now = Instant::now();
+        sessions.retain(|_, session| session.expires > now);
+        sessions.insert(token.clone(), session);
+
+        token
+    }
+
+    /// Get the session for a token, `None` if there is no such session or it has expired
+    pub fn get(&self, token: &str) -> Option<Session> {
+        self.sessions
+            .read()
+            .unwrap()
+            .get(token)
+            .filter(|session| session.expires > Instant::now())
+            .cloned()
+    }
+
+    pub fn remove(&self, token: &str) {
+        self.sessions.write().unwrap().remove(token);
+    }
+}
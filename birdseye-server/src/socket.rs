@@ -0,0 +1,51 @@
+//! Helpers shared by every websocket endpoint for upgrading connections and encoding messages
+
+use birdseye_common::codec::{Codec, CodecError};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::future::Future;
+use tracing::error;
+use warp::http::header::SEC_WEBSOCKET_PROTOCOL;
+use warp::reply::Response;
+use warp::ws::{Message, WebSocket, Ws};
+use warp::{Filter, Rejection, Reply};
+
+/// Extracts the subprotocols offered by the client during the websocket handshake
+pub fn offered_subprotocols() -> impl Filter<Extract = (Option<String>,), Error = Rejection> + Clone
+{
+    warp::header::optional::<String>(SEC_WEBSOCKET_PROTOCOL.as_str())
+}
+
+/// Upgrade a websocket using the codec picked from the subprotocols offered by the client, the
+/// chosen subprotocol is echoed back so browsers will accept the connection
+pub fn upgrade<F, Fut>(ws: Ws, offered: Option<String>, handler: F) -> Response
+where
+    F: FnOnce(WebSocket, Codec) -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let codec = offered.as_deref().and_then(Codec::negotiate);
+    let reply = ws.on_upgrade(move |websocket| handler(websocket, codec.unwrap_or_default()));
+
+    match codec {
+        Some(codec) => warp::reply::with_header(reply, SEC_WEBSOCKET_PROTOCOL, codec.subprotocol())
+            .into_response(),
+        None => reply.into_response(),
+    }
+}
+
+/// Encode a message into a websocket frame, JSON is sent as text so it is readable in devtools
+pub fn encode<T: Serialize>(codec: Codec, msg: &T) -> Option<Message> {
+    match codec.encode(msg) {
+        Ok(bytes) if codec.is_text() => Some(Message::text(String::from_utf8_lossy(&bytes))),
+        Ok(bytes) => Some(Message::binary(bytes)),
+        Err(ex) => {
+            error!("Could not encode message with {codec}: {ex}");
+            None
+        }
+    }
+}
+
+/// Decode a websocket frame, both text and binary frames are accepted
+pub fn decode<T: DeserializeOwned>(codec: Codec, msg: &Message) -> Result<T, CodecError> {
+    codec.decode(msg.as_bytes())
+}
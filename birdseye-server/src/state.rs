@@ -0,0 +1,95 @@
+//! State shared between every connection to the server
+
+use crate::accounts::Accounts;
+use crate::ca::CertificateAuthority;
+use crate::commands::Commands;
+use crate::config::Config;
+use crate::database::Repository;
+use crate::enrollment::Enrollment;
+use crate::history::History;
+use crate::hub::Hub;
+use crate::policies::Policies;
+use crate::processes::ProcessStore;
+use crate::registry::Registry;
+use crate::rooms::Rooms;
+use crate::screens::Screens;
+use crate::sessions::Sessions;
+use crate::thumbnails::Thumbnails;
+use crate::timetable::Schedule;
+use birdseye_common::backend::ServerMessage;
+use birdseye_common::command::{Command, CommandError};
+use birdseye_common::MachineId;
+use std::convert::Infallible;
+use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use warp::Filter;
+
+pub struct State {
+    pub accounts: Accounts,
+    pub ca: CertificateAuthority,
+    pub commands: Commands,
+    pub enrollment: Enrollment,
+    pub history: History,
+    pub hub: Hub,
+    pub policies: Policies,
+    pub processes: ProcessStore,
+    pub registry: Registry,
+    pub rooms: Rooms,
+    pub schedule: Schedule,
+    pub screens: Screens,
+    pub sessions: Sessions,
+    pub thumbnails: Thumbnails,
+}
+
+pub type SharedState = Arc<State>;
+
+impl State {
+    pub fn new(config: &Config, db: Arc<dyn Repository>) -> Result<SharedState, Box<dyn Error>> {
+        let server = &config.be_server;
+        let rooms = Rooms::load(db.clone(), &config.rooms)?;
+
+        Ok(Arc::new(Self {
+            accounts: Accounts::new(db.clone())?,
+            ca: CertificateAuthority::load_or_create(&server.ca_cert, &server.ca_key)?,
+            commands: Commands::new(Duration::from_secs(server.command_timeout)),
+            enrollment: Enrollment::new(db.clone(), server.token_ttl * 60 * 60),
+            history: History::new(db.clone()),
+            hub: Hub::new(server.client_buffer, rooms.clone()),
+            policies: Policies::load(db.clone())?,
+            processes: ProcessStore::default(),
+            registry: Registry::load(db.clone())?,
+            rooms,
+            schedule: Schedule::load(db, &server.timezone)?,
+            screens: Screens::default(),
+            sessions: Sessions::new(Duration::from_secs(server.session_ttl * 60 * 60)),
+            thumbnails: Thumbnails::new(&server.thumbnail_widths, server.thumbnail_quality),
+        }))
+    }
+
+    /// Get the link used to send messages to a machine's monitor
+    pub fn monitor_link(
+        &self,
+        machine: &MachineId,
+    ) -> Result<mpsc::Sender<ServerMessage>, CommandError> {
+        self.registry.link(machine)
+    }
+
+    /// Get the link used to send a command to a machine's monitor, as long as the monitor is new
+    /// enough to understand it
+    pub fn command_link(
+        &self,
+        machine: &MachineId,
+        command: &Command,
+    ) -> Result<mpsc::Sender<ServerMessage>, CommandError> {
+        self.registry.link_since(machine, command.min_version())
+    }
+}
+
+/// Filter to pass the shared state into a route
+pub fn with_state(
+    state: SharedState,
+) -> impl Filter<Extract = (SharedState,), Error = Infallible> + Clone {
+    warp::any().map(move || state.clone())
+}
@@ -0,0 +1,141 @@
+//! Small JPEG thumbnails of every machine's screen for the mosaic view, so a dashboard showing a
+//! whole room only has to download a few kilobytes per machine rather than watch every screen
+//!
+//! Thumbnails are made from the latest frame in [`Screens`] when they are first asked for, in the
+//! configured width closest to the one asked for. They are kept until the frame changes, asking
+//! again before then gets the same thumbnail with the same ETag. The ETag is a CRC of the JPEG, so
+//! it stays the same across restarts and server versions.
+
+use crate::screens::Screens;
+use birdseye_common::screen::Frame;
+use birdseye_common::MachineId;
+use bytes::Bytes;
+use jpeg_encoder::{ColorType, Encoder};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tracing::warn;
+
+/// The width used if the config doesn't list any
+const DEFAULT_WIDTH: u32 = 320;
+
+/// A JPEG thumbnail of a machine's screen
+#[derive(Debug, Clone)]
+pub struct Thumbnail {
+    etag: String,
+    jpeg: Bytes,
+}
+
+impl<'a> Thumbnail {
+    /// A strong ETag, quoted ready to be put in a header
+    pub fn etag(&'a self) -> &'a str {
+        &self.etag
+    }
+
+    pub fn jpeg(&'a self) -> &'a Bytes {
+        &self.jpeg
+    }
+}
+
+pub struct Thumbnails {
+    /// Sorted from smallest to largest
+    widths: Vec<u32>,
+    quality: u8,
+    /// The last thumbnail made of each machine in each width, along with the generation of the
+    /// frame it was made from
+    cache: Mutex<HashMap<(MachineId, u32), (u64, Thumbnail)>>,
+}
+
+impl Thumbnails {
+    /// `widths` are the widths thumbnails can be made in, `quality` is the JPEG quality between 1
+    /// and 100
+    pub fn new(widths: &[u32], quality: u8) -> Self {
+        let mut widths: Vec<_> = widths.iter().copied().filter(|width| *width > 0).collect();
+        widths.sort_unstable();
+        widths.dedup();
+        if widths.is_empty() {
+            widths.push(DEFAULT_WIDTH);
+        }
+
+        Self {
+            widths,
+            quality: quality.clamp(1, 100),
+            cache: Mutex::default(),
+        }
+    }
+
+    /// The smallest configured width that is at least as wide as `requested`, or the largest if
+    /// none are. The smallest is used if no width is asked for
+    pub fn width(&self, requested: Option<u32>) -> u32 {
+        let requested = requested.unwrap_or_default();
+
+        self.widths
+            .iter()
+            .copied()
+            .find(|width| *width >= requested)
+            .unwrap_or_else(|| self.largest())
+    }
+
+    /// The widest thumbnails are made, monitors only have to capture this much while nobody has
+    /// their screen open
+    pub fn largest(&self) -> u32 {
+        *self.widths.last().unwrap()
+    }
+
+    /// Get a thumbnail of a machine's screen about `width` pixels wide, `None` if the machine
+    /// hasn't sent a frame or its frame can't be decoded. Making a thumbnail takes a few
+    /// milliseconds, more for H.264 as every frame since the last keyframe is played, so this
+    /// should be called from a blocking task
+    pub fn get(
+        &self,
+        screens: &Screens,
+        machine: &MachineId,
+        width: Option<u32>,
+    ) -> Option<Thumbnail> {
+        let (generation, packet) = screens.latest(machine)?;
+        let key = (machine.clone(), self.width(width));
+
+        if let Some((made_from, thumbnail)) = self.cache.lock().unwrap().get(&key) {
+            if *made_from == generation {
+                return Some(thumbnail.clone());
+            }
+        }
+
+        let frame = match packet.decode() {
+            Ok(frame) => frame,
+            Err(ex) => {
+                warn!("Could not decode the screen of {machine} for a thumbnail: {ex}");
+                return None;
+            }
+        };
+        let (width, height, rgb) = downscale(&frame, key.1);
+        let mut jpeg = vec![];
+        if let Err(ex) =
+            Encoder::new(&mut jpeg, self.quality).encode(&rgb, width, height, ColorType::Rgb)
+        {
+            warn!("Could not make a thumbnail of {machine}: {ex}");
+            return None;
+        }
+
+        let thumbnail = Thumbnail {
+            etag: format!("\"{:08x}-{:x}\"", crc32fast::hash(&jpeg), jpeg.len()),
+            jpeg: Bytes::from(jpeg),
+        };
+
+        self.cache
+            .lock()
+            .unwrap()
+            .insert(key, (generation, thumbnail.clone()));
+        Some(thumbnail)
+    }
+}
+
+/// Shrink a frame to `width` pixels wide keeping its aspect ratio, see [`Frame::downscale`]. Frames
+/// taller than a JPEG can be are cut short. Returns the size and the tightly packed RGB pixels
+pub fn downscale(frame: &Frame, width: u32) -> (u16, u16, Vec<u8>) {
+    let small = frame.downscale(width.min(u16::MAX as u32));
+    let height = small.height().min(u16::MAX as u32);
+
+    let mut rgb = small.to_rgb();
+    rgb.truncate(small.width() as usize * height as usize * 3);
+    (small.width() as u16, height as u16, rgb)
+}
@@ -0,0 +1,332 @@
+//! Reading periods and holidays out of an iCalendar file, as exported by most school management
+//! systems
+//!
+//! Only what a weekly timetable needs is read:
+//! - Events with a time become periods. Recurring events only need to be listed once with a
+//!   weekly or daily `RRULE`, exports that list every week's lesson are folded into one period
+//!   per weekday. Events that don't repeat every week, such as fortnightly ones, are skipped as a
+//!   weekly timetable can't show them. `UNTIL`, `COUNT` and `EXDATE` are ignored, the timetable
+//!   repeats until changed.
+//! - All day events become holidays.
+//! - `SUMMARY` is the name and `LOCATION` the room, unless a room is given.
+//!
+//! Times are moved into the server's timezone, events with a `TZID` the server doesn't know about
+//! are read as if they were already in it. The file itself is read with the [`ical`] crate.
+
+use birdseye_common::timetable::{Holiday, Period, Timetable};
+use birdseye_common::RoomId;
+use ical::parser::ical::component::IcalEvent;
+use ical::parser::ParserError;
+use ical::property::Property;
+use ical::IcalParser;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use time::macros::format_description;
+use time::{Date, Duration, PrimitiveDateTime, Weekday};
+use time_tz::{timezones, OffsetDateTimeExt, PrimitiveDateTimeExt, Tz};
+use utoipa::ToSchema;
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+pub enum IcsError {
+    /// The file doesn't start with `BEGIN:VCALENDAR`
+    NotACalendar,
+    /// The file isn't laid out as an iCalendar file should be, contains why
+    Invalid(String),
+    /// A date or time could not be read, contains the property and its value
+    InvalidDate(String, String),
+}
+
+impl Display for IcsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IcsError::NotACalendar => write!(f, "the file is not an iCalendar file"),
+            IcsError::Invalid(reason) => write!(f, "the file could not be read: {reason}"),
+            IcsError::InvalidDate(property, value) => {
+                write!(f, "{property} has an invalid date or time: {value}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for IcsError {}
+
+impl From<ParserError> for IcsError {
+    fn from(ex: ParserError) -> Self {
+        match ex {
+            ParserError::MissingHeader => IcsError::NotACalendar,
+            ex => IcsError::Invalid(ex.to_string()),
+        }
+    }
+}
+
+/// The periods and holidays read from a file
+#[derive(Debug)]
+pub struct Import {
+    pub timetable: Timetable,
+    /// Events that couldn't be turned into a period or holiday, such as events without a room or
+    /// ones that go past midnight
+    pub skipped: usize,
+}
+
+/// The properties of an event by name, only the first of each is kept
+struct Event<'a> {
+    properties: HashMap<&'a str, &'a Property>,
+}
+
+impl<'a> Event<'a> {
+    fn new(event: &'a IcalEvent) -> Self {
+        let mut properties = HashMap::new();
+        for property in &event.properties {
+            properties.entry(property.name.as_str()).or_insert(property);
+        }
+
+        Self { properties }
+    }
+
+    fn get(&self, name: &str) -> Option<&'a Property> {
+        self.properties.get(name).copied()
+    }
+
+    /// The value of a property, unescaped
+    fn text(&self, name: &str) -> Option<String> {
+        Some(unescape(self.get(name)?.value.as_deref()?))
+    }
+}
+
+/// The first value of a parameter of a property
+fn param<'a>(property: &'a Property, name: &str) -> Option<&'a str> {
+    property
+        .params
+        .as_ref()?
+        .iter()
+        .find(|(key, _)| key == name)?
+        .1
+        .first()
+        .map(String::as_str)
+}
+
+fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n' | 'N') => out.push('\n'),
+            Some(c) => out.push(c),
+            None => {}
+        }
+    }
+
+    out
+}
+
+/// A `DTSTART` or `DTEND`
+enum When {
+    Day(Date),
+    /// The time in the server's timezone, and how many days the date moved getting there
+    Time(PrimitiveDateTime, i64),
+}
+
+/// Find the timezone a `TZID` names. Some programs put a path in front of the name, such as
+/// `/mozilla.org/20050126_1/Pacific/Auckland`, so that is taken off if the whole thing isn't known
+fn find_timezone(tzid: &str) -> Option<&'static Tz> {
+    let tzid = tzid.trim();
+    timezones::get_by_name(tzid).or_else(|| {
+        tzid.match_indices('/')
+            .find_map(|(i, _)| timezones::get_by_name(&tzid[i + 1..]))
+    })
+}
+
+fn parse_when(property: &Property, timezone: &Tz) -> Result<When, IcsError> {
+    let value = property.value.as_deref().unwrap_or_default().trim();
+    let invalid = || IcsError::InvalidDate(property.name.clone(), value.to_string());
+
+    if param(property, "VALUE") == Some("DATE") || value.len() == 8 {
+        return Date::parse(value, format_description!("[year][month][day]"))
+            .map(When::Day)
+            .map_err(|_| invalid());
+    }
+
+    let (value, utc) = match value.strip_suffix('Z') {
+        Some(value) => (value, true),
+        None => (value, false),
+    };
+    let time = PrimitiveDateTime::parse(
+        value,
+        format_description!("[year][month][day]T[hour][minute][second]"),
+    )
+    .map_err(|_| invalid())?;
+
+    let source = param(property, "TZID").and_then(find_timezone);
+    let local = match (utc, source) {
+        (true, _) => time.assume_utc().to_timezone(timezone),
+        (false, Some(source)) => time
+            .assume_timezone(source)
+            .take_first()
+            .ok_or_else(invalid)?
+            .to_timezone(timezone),
+        (false, None) => return Ok(When::Time(time, 0)),
+    };
+    let local = PrimitiveDateTime::new(local.date(), local.time());
+
+    Ok(When::Time(local, (local.date() - time.date()).whole_days()))
+}
+
+fn parse_weekday(day: &str) -> Option<Weekday> {
+    // Monthly rules can have a number in front, e.g. `1MO`
+    let day = day.get(day.len().checked_sub(2)?..)?;
+    Some(match day {
+        "MO" => Weekday::Monday,
+        "TU" => Weekday::Tuesday,
+        "WE" => Weekday::Wednesday,
+        "TH" => Weekday::Thursday,
+        "FR" => Weekday::Friday,
+        "SA" => Weekday::Saturday,
+        "SU" => Weekday::Sunday,
+        _ => return None,
+    })
+}
+
+/// The days of the week an event happens on before moving timezones, `None` if it doesn't repeat
+/// in a way a weekly timetable can show
+fn weekdays(rrule: Option<&str>, start: Weekday) -> Option<Vec<Weekday>> {
+    let rrule = match rrule {
+        Some(rrule) => rrule.to_ascii_uppercase(),
+        None => return Some(vec![start]),
+    };
+
+    let parts: HashMap<_, _> = rrule
+        .split(';')
+        .filter_map(|part| part.split_once('='))
+        .collect();
+    if parts
+        .get("INTERVAL")
+        .is_some_and(|interval| *interval != "1")
+    {
+        return None;
+    }
+    let by_day = parts
+        .get("BYDAY")
+        .map(|days| days.split(',').filter_map(parse_weekday).collect());
+
+    match (parts.get("FREQ").copied(), by_day) {
+        (Some("WEEKLY" | "DAILY"), Some(days)) => Some(days),
+        (Some("WEEKLY"), None) => Some(vec![start]),
+        (Some("DAILY"), None) => Some(vec![
+            Weekday::Monday,
+            Weekday::Tuesday,
+            Weekday::Wednesday,
+            Weekday::Thursday,
+            Weekday::Friday,
+            Weekday::Saturday,
+            Weekday::Sunday,
+        ]),
+        _ => None,
+    }
+}
+
+/// Turn an event into periods or a holiday, returns false if it had to be skipped
+fn add_event(
+    event: &Event,
+    timezone: &Tz,
+    room: Option<&RoomId>,
+    periods: &mut Vec<Period>,
+    holidays: &mut Vec<Holiday>,
+) -> Result<bool, IcsError> {
+    let name = match event.text("SUMMARY") {
+        Some(summary) => summary,
+        None => return Ok(false),
+    };
+    let start = match event.get("DTSTART") {
+        Some(start) => parse_when(start, timezone)?,
+        None => return Ok(false),
+    };
+    let end = event
+        .get("DTEND")
+        .map(|end| parse_when(end, timezone))
+        .transpose()?;
+
+    let (start, shift, end) = match (start, end) {
+        (When::Day(start), end) => {
+            // The end of an all day event is the day after it finishes
+            let end = match end {
+                Some(When::Day(end)) if end > start => end - Duration::days(1),
+                _ => start,
+            };
+            holidays.push(Holiday::new(&name, start, end));
+            return Ok(true);
+        }
+        (When::Time(start, shift), Some(When::Time(end, _))) => (start, shift, end),
+        _ => return Ok(false),
+    };
+
+    if start.date() != end.date() || end <= start {
+        return Ok(false);
+    }
+
+    let room = match room.cloned().or_else(|| {
+        event
+            .text("LOCATION")
+            .filter(|location| !location.is_empty())
+            .map(|location| RoomId::new(&location))
+    }) {
+        Some(room) => room,
+        None => return Ok(false),
+    };
+
+    let original = start.date() - Duration::days(shift);
+    let rrule = event.get("RRULE").and_then(|rrule| rrule.value.as_deref());
+    let days = match weekdays(rrule, original.weekday()) {
+        Some(days) => days,
+        None => return Ok(false),
+    };
+
+    for day in days {
+        // Moving timezone can move the event to another day
+        let day = day.nth_next(shift.rem_euclid(7) as u8);
+        let period = Period::new(&name, room.clone(), day, start.time(), end.time());
+        if !periods.contains(&period) {
+            periods.push(period);
+        }
+    }
+
+    Ok(true)
+}
+
+/// Read the periods and holidays out of an iCalendar file, times are moved into `timezone`. If
+/// `room` is given every period is put in it, otherwise events' locations are used
+pub fn parse_ics(ics: &str, timezone: &Tz, room: Option<&RoomId>) -> Result<Import, IcsError> {
+    let mut periods = vec![];
+    let mut holidays = vec![];
+    let mut skipped = 0;
+
+    let mut calendars = IcalParser::new(ics.as_bytes()).peekable();
+    if calendars.peek().is_none() {
+        return Err(IcsError::NotACalendar);
+    }
+
+    for calendar in calendars {
+        for event in &calendar?.events {
+            if !add_event(
+                &Event::new(event),
+                timezone,
+                room,
+                &mut periods,
+                &mut holidays,
+            )? {
+                skipped += 1;
+            }
+        }
+    }
+
+    Ok(Import {
+        timetable: Timetable::new(periods, holidays, vec![]),
+        skipped,
+    })
+}
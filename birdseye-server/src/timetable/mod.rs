@@ -0,0 +1,279 @@
+//! The timetable, and the task that switches rule sets and room locks on and off as periods start
+//! and end
+//!
+//! Which rules a monitor is sent depends on the periods running in its room, so whenever that
+//! changes every monitor is sent its rules again. Machines in a room with a locking period running
+//! are sent [`Command::Lock`] when it starts and [`Command::Unlock`] when it ends. Machines a
+//! teacher has locked are remembered too, monitors keep their screen locked while disconnected so
+//! are told whether they should still be locked every time they connect.
+
+mod ics;
+
+pub use ics::{parse_ics, IcsError, Import};
+
+use crate::database::{DatabaseError, Repository};
+use crate::policies::push_policies;
+use crate::state::SharedState;
+use birdseye_common::command::Command;
+use birdseye_common::policy::Rule;
+use birdseye_common::timetable::{Timetable, TimetableError};
+use birdseye_common::{MachineId, RoomId};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{Display, Formatter};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use time::{OffsetDateTime, PrimitiveDateTime};
+use time_tz::{timezones, OffsetDateTimeExt, PrimitiveDateTimeExt, Tz};
+use tokio::sync::Notify;
+use tokio::time::timeout;
+use tracing::{debug, info, warn};
+
+/// The longest the schedule waits before checking the timetable again, so clock and timezone
+/// changes are noticed
+const MAX_WAIT: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug)]
+pub enum ScheduleError {
+    /// The configured timezone isn't in the timezone database
+    UnknownTimezone(String),
+    Invalid(TimetableError),
+    Ics(IcsError),
+    Database(DatabaseError),
+}
+
+impl Display for ScheduleError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScheduleError::UnknownTimezone(name) => write!(f, "unknown timezone {name}"),
+            ScheduleError::Invalid(ex) => write!(f, "{ex}"),
+            ScheduleError::Ics(ex) => write!(f, "{ex}"),
+            ScheduleError::Database(ex) => write!(f, "{ex}"),
+        }
+    }
+}
+
+impl std::error::Error for ScheduleError {}
+
+impl From<TimetableError> for ScheduleError {
+    fn from(ex: TimetableError) -> Self {
+        ScheduleError::Invalid(ex)
+    }
+}
+
+impl From<IcsError> for ScheduleError {
+    fn from(ex: IcsError) -> Self {
+        ScheduleError::Ics(ex)
+    }
+}
+
+impl From<DatabaseError> for ScheduleError {
+    fn from(ex: DatabaseError) -> Self {
+        ScheduleError::Database(ex)
+    }
+}
+
+/// The timetable along with what it has currently switched on
+pub struct Schedule {
+    db: Arc<dyn Repository>,
+    timezone: &'static Tz,
+    timetable: RwLock<Timetable>,
+    /// Machines that should be locked because of a period that is running
+    locked: Mutex<BTreeSet<MachineId>>,
+    /// Machines a teacher has locked with [`Command::Lock`] and not unlocked since
+    held: Mutex<BTreeSet<MachineId>>,
+    /// Wakes [`run_schedule`] when something it depends on has changed
+    changed: Notify,
+}
+
+impl Schedule {
+    pub fn load(db: Arc<dyn Repository>, timezone: &str) -> Result<Self, ScheduleError> {
+        let timezone = timezones::get_by_name(timezone)
+            .ok_or_else(|| ScheduleError::UnknownTimezone(timezone.to_string()))?;
+        let timetable = db.timetable()?;
+
+        Ok(Self {
+            db,
+            timezone,
+            timetable: RwLock::new(timetable),
+            locked: Mutex::default(),
+            held: Mutex::default(),
+            changed: Notify::new(),
+        })
+    }
+
+    /// The current time in the server's timezone
+    pub fn now(&self) -> PrimitiveDateTime {
+        let now = OffsetDateTime::now_utc().to_timezone(self.timezone);
+        PrimitiveDateTime::new(now.date(), now.time())
+    }
+
+    pub fn get(&self) -> Timetable {
+        self.timetable.read().unwrap().clone()
+    }
+
+    /// Check a timetable and replace the current one with it
+    pub fn set(&self, timetable: Timetable) -> Result<(), ScheduleError> {
+        timetable.validate()?;
+
+        let mut current = self.timetable.write().unwrap();
+        self.db.save_timetable(&timetable)?;
+        *current = timetable;
+        self.changed();
+
+        Ok(())
+    }
+
+    /// Read periods and holidays from an iCalendar file into the timetable. The periods of every
+    /// room in the file are replaced, holidays are added to the ones already there and rule sets
+    /// are kept. Returns the new timetable and how many events were skipped
+    pub fn import(
+        &self,
+        ics: &str,
+        room: Option<&RoomId>,
+    ) -> Result<(Timetable, usize), ScheduleError> {
+        let Import { timetable, skipped } = parse_ics(ics, self.timezone, room)?;
+        let rooms: BTreeSet<_> = timetable.periods().iter().map(|p| p.room()).collect();
+
+        let current = self.get();
+        let mut periods: Vec<_> = current
+            .periods()
+            .iter()
+            .filter(|period| !rooms.contains(period.room()))
+            .cloned()
+            .collect();
+        periods.extend_from_slice(timetable.periods());
+
+        let mut holidays = current.holidays().to_vec();
+        for holiday in timetable.holidays() {
+            if !holidays.contains(holiday) {
+                holidays.push(holiday.clone());
+            }
+        }
+
+        let timetable = Timetable::new(periods, holidays, current.rule_sets().to_vec());
+        self.set(timetable.clone())?;
+
+        Ok((timetable, skipped))
+    }
+
+    /// Keep the rules that are enforced right now on a machine in the given room
+    pub fn enforced(&self, room: Option<&RoomId>, rules: Vec<Rule>) -> Vec<Rule> {
+        let now = self.now();
+        let timetable = self.timetable.read().unwrap();
+        let running = match room {
+            Some(room) => timetable.running(room, now),
+            None => vec![],
+        };
+
+        rules
+            .into_iter()
+            .filter(|rule| timetable.is_enforced(rule.id(), &running))
+            .collect()
+    }
+
+    /// Whether a machine should be locked, because of a period that is running or because a
+    /// teacher locked it
+    pub fn is_locked(&self, machine: &MachineId) -> bool {
+        self.locked.lock().unwrap().contains(machine) || self.held.lock().unwrap().contains(machine)
+    }
+
+    /// Remember a teacher locking or unlocking a machine, other commands are ignored. Called once
+    /// the monitor has run the command
+    pub fn commanded(&self, machine: &MachineId, command: &Command) {
+        let mut held = self.held.lock().unwrap();
+        match command {
+            Command::Lock => held.insert(machine.clone()),
+            Command::Unlock => held.remove(machine),
+            _ => false,
+        };
+    }
+
+    /// Have [`run_schedule`] check the timetable again, called when rooms change
+    pub fn changed(&self) {
+        self.changed.notify_one();
+    }
+}
+
+/// Lock or unlock a machine in the background, does nothing if its monitor isn't connected
+pub fn send_lock(state: &SharedState, machine: MachineId, lock: bool) {
+    let link = match state.registry.link(&machine) {
+        Ok(link) => link,
+        Err(_) => return,
+    };
+    let command = match lock {
+        true => Command::Lock,
+        false => Command::Unlock,
+    };
+
+    let state = state.clone();
+    tokio::spawn(async move {
+        match state.commands.run(&machine, &link, command).await {
+            Ok(()) => debug!("Set {machine} locked: {lock}"),
+            Err(ex) => warn!("Could not set {machine} locked: {lock}: {ex}"),
+        }
+    });
+}
+
+/// Switch rule sets and locks on and off at the start and end of every period, runs forever
+pub async fn run_schedule(state: SharedState) {
+    let mut previous: BTreeMap<RoomId, Vec<String>> = BTreeMap::new();
+
+    loop {
+        let now = state.schedule.now();
+        let mut running = BTreeMap::new();
+        let mut locked = BTreeSet::new();
+
+        let next = {
+            let timetable = state.schedule.timetable.read().unwrap();
+            for room in state.rooms.list() {
+                let periods = timetable.running(&room.id, now);
+                if timetable.is_locked(&periods) {
+                    locked.extend(room.machines);
+                }
+
+                let names: Vec<_> = periods.iter().map(|p| p.name().to_string()).collect();
+                if !names.is_empty() {
+                    running.insert(room.id, names);
+                }
+            }
+
+            timetable.next_change(now)
+        };
+
+        if running != previous {
+            info!("Periods running: {running:?}");
+            push_policies(&state);
+            previous = running;
+        }
+
+        let (lock, unlock) = {
+            let mut current = state.schedule.locked.lock().unwrap();
+            let held = state.schedule.held.lock().unwrap();
+            let lock: Vec<_> = locked.difference(&current).cloned().collect();
+            // Machines a teacher locked stay locked when the period ends
+            let unlock: Vec<_> = current
+                .difference(&locked)
+                .filter(|machine| !held.contains(machine))
+                .cloned()
+                .collect();
+            *current = locked;
+            (lock, unlock)
+        };
+        for machine in lock {
+            send_lock(&state, machine, true);
+        }
+        for machine in unlock {
+            send_lock(&state, machine, false);
+        }
+
+        // The next change is in local time, which can skip or repeat an hour
+        let wait = next
+            .and_then(|next| next.assume_timezone(state.schedule.timezone).take_first())
+            .map(|next| next - OffsetDateTime::now_utc())
+            // The next change may already have passed
+            .map(|wait| Duration::try_from(wait).unwrap_or(Duration::ZERO))
+            .map_or(MAX_WAIT, |wait| wait.min(MAX_WAIT));
+
+        let _ = timeout(wait, state.schedule.changed.notified()).await;
+    }
+}
@@ -0,0 +1,188 @@
+//! Serving over TLS, with optional client certificates for monitors
+//!
+//! warp can check client certificates but has no way to tell a route which certificate was used,
+//! so connections are accepted here instead. The identity from the client's certificate is added
+//! to every request made on the connection, where [`monitor_identity`] can pick it up.
+
+use crate::auth::{Forbidden, Unauthorized};
+use crate::ca::CertificateAuthority;
+use crate::config::ServerConfig;
+use crate::state::{with_state, SharedState};
+use birdseye_common::MachineId;
+use hyper::server::conn::Http;
+use hyper::service::{service_fn, Service};
+use hyper::{Body, Request, Response};
+use std::convert::Infallible;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio_rustls::rustls::internal::pemfile::{certs, pkcs8_private_keys, rsa_private_keys};
+use tokio_rustls::rustls::{
+    AllowAnyAnonymousOrAuthenticatedClient, Certificate, PrivateKey, RootCertStore,
+    ServerConfig as TlsConfig, Session,
+};
+use tokio_rustls::TlsAcceptor;
+use tracing::{debug, warn};
+use warp::{Filter, Rejection};
+use x509_parser::prelude::parse_x509_certificate;
+
+type TlsError = Box<dyn Error>;
+
+/// The machine a client certificate was issued to, taken from the certificate's common name
+#[derive(Debug, Clone)]
+pub struct PeerIdentity {
+    machine: MachineId,
+    /// Serial number of the certificate in hex
+    serial: String,
+}
+
+impl PeerIdentity {
+    fn from_certificate(cert: &Certificate) -> Option<Self> {
+        let (_, cert) = parse_x509_certificate(&cert.0).ok()?;
+        let common_name = cert.subject().iter_common_name().next()?.as_str().ok()?;
+
+        Some(Self {
+            machine: MachineId::new(common_name),
+            serial: cert.serial.to_str_radix(16),
+        })
+    }
+
+    pub fn machine(&self) -> &MachineId {
+        &self.machine
+    }
+
+    pub fn serial(&self) -> &str {
+        &self.serial
+    }
+}
+
+fn load_certs(path: &Path) -> Result<Vec<Certificate>, TlsError> {
+    let mut reader = BufReader::new(File::open(path)?);
+    certs(&mut reader)
+        .map_err(|()| format!("Could not read certificates from {}", path.display()).into())
+}
+
+/// Load a private key, in either PKCS8 or RSA format
+fn load_key(path: &Path) -> Result<PrivateKey, TlsError> {
+    let mut key = vec![];
+    File::open(path)?.read_to_end(&mut key)?;
+
+    let mut keys = pkcs8_private_keys(&mut key.as_slice()).unwrap_or_default();
+    if keys.is_empty() {
+        keys = rsa_private_keys(&mut key.as_slice()).unwrap_or_default();
+    }
+
+    keys.into_iter()
+        .next()
+        .ok_or_else(|| format!("Could not read a private key from {}", path.display()).into())
+}
+
+fn tls_config(config: &ServerConfig, ca: &CertificateAuthority) -> Result<TlsConfig, TlsError> {
+    let mut roots = RootCertStore::empty();
+    roots.add(&ca.certificate())?;
+    if let Some(client_ca) = &config.client_ca {
+        for cert in load_certs(client_ca)? {
+            roots.add(&cert)?;
+        }
+    }
+
+    // Client certificates are optional at the TLS level since browsers connect on the same port,
+    // routes that need one check for it themselves
+    let mut tls = TlsConfig::new(AllowAnyAnonymousOrAuthenticatedClient::new(roots));
+
+    tls.set_single_cert(load_certs(&config.cert)?, load_key(&config.key)?)?;
+    tls.set_protocols(&["h2".into(), "http/1.1".into()]);
+
+    Ok(tls)
+}
+
+/// Serve `service` over TLS until the server is stopped
+pub async fn serve<S>(
+    service: S,
+    config: &ServerConfig,
+    ca: &CertificateAuthority,
+    addr: SocketAddr,
+) -> Result<(), TlsError>
+where
+    S: Service<Request<Body>, Response = Response<Body>, Error = Infallible>
+        + Clone
+        + Send
+        + 'static,
+    S::Future: Send,
+{
+    let acceptor = TlsAcceptor::from(Arc::new(tls_config(config, ca)?));
+    let listener = TcpListener::bind(addr).await?;
+
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(ex) => {
+                warn!("Could not accept connection: {ex}");
+                continue;
+            }
+        };
+
+        let acceptor = acceptor.clone();
+        let service = service.clone();
+
+        tokio::spawn(async move {
+            let stream = match acceptor.accept(stream).await {
+                Ok(stream) => stream,
+                Err(ex) => {
+                    debug!("TLS handshake with {peer} failed: {ex}");
+                    return;
+                }
+            };
+
+            let session = stream.get_ref().1;
+            let http2 = session.get_alpn_protocol() == Some(b"h2".as_ref());
+            let identity = session
+                .get_peer_certificates()
+                .and_then(|certs| PeerIdentity::from_certificate(certs.first()?));
+
+            let service = service_fn(move |mut request: Request<Body>| {
+                if let Some(identity) = &identity {
+                    request.extensions_mut().insert(identity.clone());
+                }
+                service.clone().call(request)
+            });
+
+            if let Err(ex) = Http::new()
+                .http2_only(http2)
+                .serve_connection(stream, service)
+                .with_upgrades()
+                .await
+            {
+                debug!("Connection with {peer} failed: {ex}");
+            }
+        });
+    }
+}
+
+/// Filter that extracts the machine from the client's certificate, if `required` is set a
+/// connection without a certificate is rejected with [`Unauthorized`]. Revoked certificates are
+/// rejected with [`Forbidden`]. Monitors let in without a certificate are still turned away if they
+/// claim to be a machine that has been enrolled
+pub fn monitor_identity(
+    state: SharedState,
+    required: bool,
+) -> impl Filter<Extract = (Option<PeerIdentity>,), Error = Rejection> + Clone {
+    warp::ext::optional::<PeerIdentity>()
+        .and(with_state(state))
+        .and_then(
+            move |identity: Option<PeerIdentity>, state: SharedState| async move {
+                match identity {
+                    Some(identity) if state.enrollment.is_revoked(identity.serial()) => {
+                        warn!("Refusing revoked certificate for {}", identity.machine());
+                        Err(warp::reject::custom(Forbidden))
+                    }
+                    None if required => Err(warp::reject::custom(Unauthorized)),
+                    identity => Ok(identity),
+                }
+            },
+        )
+}
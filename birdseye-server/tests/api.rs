@@ -0,0 +1,94 @@
+use birdseye_common::api::RoomInfo;
+use birdseye_common::{MachineId, RoomId};
+use birdseye_server::api::ApiDoc;
+use birdseye_server::database::{Repository, SqliteRepository};
+use birdseye_server::rooms::Rooms;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use utoipa::OpenApi;
+
+/// Collect every `$ref` in a JSON document
+fn refs<'a>(value: &'a Value, found: &mut Vec<&'a str>) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                match (key.as_str(), value) {
+                    ("$ref", Value::String(reference)) => found.push(reference),
+                    _ => refs(value, found),
+                }
+            }
+        }
+        Value::Array(values) => values.iter().for_each(|value| refs(value, found)),
+        _ => {}
+    }
+}
+
+#[test]
+fn openapi_document_has_every_schema_it_uses() {
+    let doc = serde_json::to_value(ApiDoc::openapi()).unwrap();
+
+    let mut found = vec![];
+    refs(&doc, &mut found);
+    assert!(!found.is_empty());
+
+    for reference in found {
+        let name = reference.strip_prefix("#/components/schemas/").unwrap();
+        assert!(
+            doc["components"]["schemas"].get(name).is_some(),
+            "{reference} is missing from the document"
+        );
+    }
+
+    let paths = doc["paths"].as_object().unwrap();
+    for path in [
+        "/api/v1/machines",
+        "/api/v1/machines/{id}",
+        "/api/v1/machines/{id}/processes",
+        "/api/v1/machines/{id}/thumbnail",
+        "/api/v1/machines/{id}/commands",
+        "/api/v1/rooms",
+        "/api/v1/rooms/{id}",
+        "/api/v1/history",
+        "/api/v1/policies",
+        "/api/v1/policies/{id}",
+        "/api/v1/timetable",
+        "/api/v1/timetable/import",
+    ] {
+        assert!(
+            paths.contains_key(path),
+            "{path} is missing from the document"
+        );
+    }
+}
+
+#[test]
+fn rooms_can_be_changed_while_running() {
+    let db: Arc<dyn Repository> = Arc::new(SqliteRepository::in_memory().unwrap());
+    let config = BTreeMap::from([(
+        RoomId::new("Room 12"),
+        vec![MachineId::new("lab-02"), MachineId::new("lab-01")],
+    )]);
+    let rooms = Rooms::load(db.clone(), &config).unwrap();
+    let shared = rooms.clone();
+
+    rooms
+        .set_machines(&RoomId::new("Room 14"), &[MachineId::new("lab-02")])
+        .unwrap();
+
+    let expected = vec![
+        RoomInfo {
+            id: RoomId::new("Room 12"),
+            machines: vec![MachineId::new("lab-01")],
+        },
+        RoomInfo {
+            id: RoomId::new("Room 14"),
+            machines: vec![MachineId::new("lab-02")],
+        },
+    ];
+    assert_eq!(shared.list(), expected);
+
+    // The change was saved too
+    let reloaded = Rooms::load(db, &BTreeMap::new()).unwrap();
+    assert_eq!(reloaded.list(), expected);
+}
@@ -0,0 +1,46 @@
+use birdseye_common::backend::{CommandResult, ServerMessage};
+use birdseye_common::command::{Command, CommandError};
+use birdseye_common::MachineId;
+use birdseye_server::commands::Commands;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+#[tokio::test]
+async fn results_are_only_taken_from_the_machine_the_command_was_sent_to() {
+    let commands = Arc::new(Commands::new(Duration::from_millis(200)));
+    let machine = MachineId::new("lab-1");
+    let (link, mut monitor) = mpsc::channel(8);
+
+    let run = tokio::spawn({
+        let commands = commands.clone();
+        let machine = machine.clone();
+        async move { commands.run(&machine, &link, Command::Lock).await }
+    });
+
+    let id = match monitor.recv().await {
+        Some(ServerMessage::Command { id, .. }) => id,
+        msg => panic!("Expected a command, got {msg:?}"),
+    };
+
+    // Another monitor guessing the id can't answer for it
+    let other = MachineId::new("lab-2");
+    commands.resolve(&other, id, CommandResult::Ok);
+    assert_eq!(run.await.unwrap(), Err(CommandError::Timeout));
+
+    let run = tokio::spawn({
+        let commands = commands.clone();
+        let machine = machine.clone();
+        let (link, mut monitor) = mpsc::channel(8);
+        async move {
+            let run = commands.run(&machine, &link, Command::Unlock);
+            let reply = async {
+                if let Some(ServerMessage::Command { id, .. }) = monitor.recv().await {
+                    commands.resolve(&machine, id, CommandResult::Ok);
+                }
+            };
+            tokio::join!(run, reply).0
+        }
+    });
+    assert_eq!(run.await.unwrap(), Ok(()));
+}
@@ -0,0 +1,175 @@
+use birdseye_common::{Machine, MachineId, RoomId};
+use birdseye_server::accounts::{Account, Accounts};
+use birdseye_server::database::{Repository, SqliteRepository, StoredMachine};
+use birdseye_server::enrollment::Enrollment;
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+fn db() -> Arc<dyn Repository> {
+    Arc::new(SqliteRepository::in_memory().unwrap())
+}
+
+fn rooms(rooms: &[&str]) -> BTreeSet<RoomId> {
+    rooms.iter().map(|room| RoomId::new(room)).collect()
+}
+
+#[test]
+fn accounts_keep_their_rooms() {
+    let db = db();
+    let account = Account::new("alice", "hash", false, rooms(&["Room 12"]));
+
+    assert!(db.create_account(&account).unwrap());
+    assert!(!db.create_account(&account).unwrap());
+    assert_eq!(db.account("alice").unwrap(), Some(account));
+
+    assert!(db
+        .set_account_rooms("alice", &rooms(&["Room 12", "Room 14"]))
+        .unwrap());
+    assert!(!db.set_account_rooms("bob", &rooms(&["Room 12"])).unwrap());
+
+    let accounts = db.accounts().unwrap();
+    assert_eq!(accounts.len(), 1);
+    assert_eq!(accounts[0].rooms(), &rooms(&["Room 12", "Room 14"]));
+}
+
+#[test]
+fn passwords_are_hashed_and_checked() {
+    let accounts = Accounts::new(db()).unwrap();
+    accounts
+        .create("alice", "hunter2", true, BTreeSet::new())
+        .unwrap();
+
+    assert!(accounts
+        .create("alice", "other", false, BTreeSet::new())
+        .is_err());
+    assert_ne!(accounts.get("alice").unwrap().password_hash(), "hunter2");
+    assert!(accounts.verify("alice", "hunter2").is_some());
+    assert!(accounts.verify("alice", "wrong").is_none());
+    assert!(accounts.verify("bob", "hunter2").is_none());
+}
+
+#[test]
+fn machines_can_only_be_in_one_room() {
+    let db = db();
+    let machine = MachineId::new("lab-01");
+
+    for room in ["Room 12", "Room 14"] {
+        db.set_room_machines(&RoomId::new(room), std::slice::from_ref(&machine))
+            .unwrap();
+    }
+
+    assert_eq!(
+        db.room_machines().unwrap(),
+        vec![(RoomId::new("Room 14"), machine)]
+    );
+}
+
+#[test]
+fn machines_are_remembered() {
+    let db = db();
+    let stored = StoredMachine {
+        machine: Machine::new(MachineId::new("lab-01"), "lab-01", "birdseye-monitor/0.1.0"),
+        last_seen: 100,
+    };
+
+    db.save_machine(&stored).unwrap();
+    let updated = StoredMachine {
+        last_seen: 200,
+        ..stored
+    };
+    db.save_machine(&updated).unwrap();
+
+    assert_eq!(db.machines().unwrap(), vec![updated]);
+}
+
+#[test]
+fn tokens_can_only_be_used_once() {
+    let enrollment = Enrollment::new(db(), 60);
+    let token = enrollment.create_token().unwrap();
+    let machine = MachineId::new("lab-01");
+
+    let issued = enrollment
+        .redeem(&token.token, &machine, Ok::<_, ()>)
+        .unwrap();
+    assert!(matches!(issued, Some(Ok(_))));
+
+    let again = enrollment
+        .redeem(&token.token, &machine, Ok::<_, ()>)
+        .unwrap();
+    assert!(again.is_none());
+}
+
+#[test]
+fn failed_enrollment_keeps_the_token() {
+    let enrollment = Enrollment::new(db(), 60);
+    let token = enrollment.create_token().unwrap();
+    let machine = MachineId::new("lab-01");
+
+    let failed = enrollment
+        .redeem(&token.token, &machine, |_| Err::<u64, _>("bad request"))
+        .unwrap();
+    assert_eq!(failed, Some(Err("bad request")));
+
+    let retried = enrollment
+        .redeem(&token.token, &machine, Ok::<_, ()>)
+        .unwrap();
+    assert!(matches!(retried, Some(Ok(_))));
+}
+
+#[test]
+fn revoking_a_machine_revokes_its_certificates() {
+    let enrollment = Enrollment::new(db(), 60);
+    let machine = MachineId::new("lab-01");
+
+    let mut serials = vec![];
+    for _ in 0..2 {
+        let token = enrollment.create_token().unwrap();
+        let serial = enrollment
+            .redeem(&token.token, &machine, Ok::<_, ()>)
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        serials.push(format!("{serial:x}"));
+    }
+
+    assert!(!enrollment.is_revoked(&serials[0]));
+    assert_eq!(enrollment.revoke(&machine).unwrap(), 2);
+    assert!(serials.iter().all(|serial| enrollment.is_revoked(serial)));
+    assert_eq!(enrollment.revoke(&machine).unwrap(), 0);
+}
+
+#[test]
+fn reopening_a_database_keeps_its_data() {
+    let path = std::env::temp_dir().join(format!("birdseye-test-{}.db", std::process::id()));
+    let account = Account::new("alice", "hash", true, BTreeSet::new());
+
+    {
+        let db = SqliteRepository::open(&path).unwrap();
+        db.create_account(&account).unwrap();
+    }
+
+    // Migrations that have already run are skipped
+    let db = SqliteRepository::open(&path).unwrap();
+    assert_eq!(db.account("alice").unwrap(), Some(account));
+
+    drop(db);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn enrolled_machines_need_their_certificate_even_once_revoked() {
+    let enrollment = Enrollment::new(db(), 60);
+    let machine = MachineId::new("lab-01");
+    assert!(!enrollment.needs_certificate(&machine));
+
+    let token = enrollment.create_token().unwrap();
+    enrollment
+        .redeem(&token.token, &machine, Ok::<_, ()>)
+        .unwrap();
+    assert!(enrollment.needs_certificate(&machine));
+    assert!(!enrollment.needs_certificate(&MachineId::new("lab-02")));
+
+    // Leaving the certificate out is no way around revoking it
+    enrollment.revoke(&machine).unwrap();
+    assert!(enrollment.needs_certificate(&machine));
+}
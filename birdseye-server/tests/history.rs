@@ -0,0 +1,236 @@
+use birdseye_common::history::{Event, EventKind, HistoryQuery};
+use birdseye_common::sync::{ProcessList, ProcessStatus, ProcessSync};
+use birdseye_common::{MachineId, Process, RoomId, User};
+use birdseye_server::accounts::Account;
+use birdseye_server::database::{EventFilter, Repository, SqliteRepository};
+use birdseye_server::history::{History, HistoryError};
+use birdseye_server::processes::ProcessStore;
+use birdseye_server::rooms::Rooms;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+
+fn db() -> Arc<dyn Repository> {
+    Arc::new(SqliteRepository::in_memory().unwrap())
+}
+
+fn process(pid: u32, name: &str, user: &str, start_time: u64) -> Process {
+    Process::new(pid, name, &User::new(user)).with_start_time(start_time)
+}
+
+fn started(time: u64, machine: &str, process: Process) -> Event {
+    Event::new(
+        time,
+        &MachineId::new(machine),
+        EventKind::ProcessStarted(process),
+    )
+}
+
+fn stopped(time: u64, machine: &str, process: Process) -> Event {
+    Event::new(
+        time,
+        &MachineId::new(machine),
+        EventKind::ProcessStopped(process),
+    )
+}
+
+/// lab-01 is in Room 12 and lab-02 is in Room 14
+fn rooms(db: Arc<dyn Repository>) -> Rooms {
+    let config = BTreeMap::from([
+        (RoomId::new("Room 12"), vec![MachineId::new("lab-01")]),
+        (RoomId::new("Room 14"), vec![MachineId::new("lab-02")]),
+    ]);
+    Rooms::load(db, &config).unwrap()
+}
+
+fn teacher(rooms: &[&str]) -> Account {
+    let rooms = rooms.iter().map(|room| RoomId::new(room)).collect();
+    Account::new("alice", "hash", false, rooms)
+}
+
+fn admin() -> Account {
+    Account::new("root", "hash", true, BTreeSet::new())
+}
+
+fn names(filter: &EventFilter, db: &dyn Repository) -> Vec<String> {
+    db.events(filter, 100)
+        .unwrap()
+        .into_iter()
+        .map(|(_, event)| event.kind().process().unwrap().name().to_string())
+        .collect()
+}
+
+#[test]
+fn snapshots_only_report_what_changed() {
+    let store = ProcessStore::default();
+    let machine = MachineId::new("lab-01");
+
+    let mut source = ProcessList::new_source();
+    source.record(ProcessStatus::Start(process(1, "init", "root", 0)));
+    source.record(ProcessStatus::Start(process(2, "firefox", "student", 10)));
+    let changes = store
+        .apply(&machine, ProcessSync::Snapshot(source.snapshot()), |_| {})
+        .unwrap();
+    assert_eq!(changes.len(), 2);
+
+    // The pid was reused by a new process, so it counts as one stopping and another starting
+    source.record(ProcessStatus::Stop(process(2, "firefox", "student", 10)));
+    source.record(ProcessStatus::Start(process(2, "steam", "student", 20)));
+    let changes = store
+        .apply(&machine, ProcessSync::Snapshot(source.snapshot()), |_| {})
+        .unwrap();
+    assert_eq!(
+        changes,
+        vec![
+            ProcessStatus::Stop(process(2, "firefox", "student", 10)),
+            ProcessStatus::Start(process(2, "steam", "student", 20)),
+        ]
+    );
+
+    let update = source.record(ProcessStatus::Start(process(3, "code", "student", 30)));
+    let changes = store
+        .apply(&machine, ProcessSync::Update(update.clone()), |_| {})
+        .unwrap();
+    assert_eq!(changes.len(), 1);
+
+    // An update that was already applied is not reported again
+    let changes = store
+        .apply(&machine, ProcessSync::Update(update), |_| {})
+        .unwrap();
+    assert!(changes.is_empty());
+}
+
+#[test]
+fn events_can_be_filtered() {
+    let db = db();
+    db.record_events(&[
+        started(10, "lab-01", process(1, "Firefox", "student", 10)),
+        started(20, "lab-01", process(2, "steam", "student", 20)),
+        started(30, "lab-02", process(3, "firefox-bin", "other", 30)),
+        started(40, "lab-02", process(4, "50%_off", "other", 40)),
+    ])
+    .unwrap();
+
+    let filter = EventFilter {
+        process: Some("firefox".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(names(&filter, db.as_ref()), ["firefox-bin", "Firefox"]);
+
+    let filter = EventFilter {
+        user: Some("student".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(names(&filter, db.as_ref()), ["steam", "Firefox"]);
+
+    let filter = EventFilter {
+        machines: Some(vec![MachineId::new("lab-02")]),
+        process: Some("%_".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(names(&filter, db.as_ref()), ["50%_off"]);
+
+    let filter = EventFilter {
+        machines: Some(vec![]),
+        ..Default::default()
+    };
+    assert!(names(&filter, db.as_ref()).is_empty());
+}
+
+#[test]
+fn processes_still_running_at_the_start_of_a_range_are_included() {
+    let db = db();
+    db.record_events(&[
+        started(10, "lab-01", process(1, "stopped-early", "student", 10)),
+        started(20, "lab-01", process(2, "still-running", "student", 20)),
+        stopped(30, "lab-01", process(1, "stopped-early", "student", 10)),
+        started(50, "lab-01", process(3, "in-range", "student", 50)),
+        started(90, "lab-01", process(4, "too-late", "student", 90)),
+    ])
+    .unwrap();
+
+    let filter = EventFilter {
+        from: Some(40),
+        to: Some(60),
+        ..Default::default()
+    };
+    assert_eq!(names(&filter, db.as_ref()), ["in-range", "still-running"]);
+}
+
+#[test]
+fn pages_carry_on_from_the_last_one() {
+    let db = db();
+    let events: Vec<_> = (0..5)
+        .map(|pid| started(pid as u64, "lab-01", process(pid, "sh", "student", 0)))
+        .collect();
+    db.record_events(&events).unwrap();
+
+    let history = History::new(db.clone());
+    let rooms = rooms(db.clone());
+    let mut query = HistoryQuery {
+        limit: Some(2),
+        ..Default::default()
+    };
+
+    let mut pids = vec![];
+    loop {
+        let page = history.query(&admin(), &rooms, &query).unwrap();
+        pids.extend(
+            page.events
+                .iter()
+                .map(|entry| *entry.event.kind().process().unwrap().pid()),
+        );
+
+        match page.next {
+            Some(next) => query.before = Some(next),
+            None => break,
+        }
+    }
+
+    assert_eq!(pids, [4, 3, 2, 1, 0]);
+}
+
+#[test]
+fn teachers_only_see_their_rooms() {
+    let db = db();
+    db.record_events(&[
+        started(10, "lab-01", process(1, "room-12", "student", 10)),
+        started(20, "lab-02", process(2, "room-14", "student", 20)),
+        started(30, "lab-03", process(3, "no-room", "student", 30)),
+    ])
+    .unwrap();
+
+    let history = History::new(db.clone());
+    let rooms = rooms(db.clone());
+    let query = |machine: Option<&str>, room: Option<&str>| HistoryQuery {
+        machine: machine.map(MachineId::new),
+        room: room.map(RoomId::new),
+        ..Default::default()
+    };
+    let names = |account: &Account, query: HistoryQuery| -> Vec<String> {
+        history
+            .query(account, &rooms, &query)
+            .unwrap()
+            .events
+            .iter()
+            .map(|entry| entry.event.kind().process().unwrap().name().to_string())
+            .collect()
+    };
+
+    let teacher = teacher(&["Room 12"]);
+    assert_eq!(names(&teacher, query(None, None)), ["room-12"]);
+    assert_eq!(names(&teacher, query(None, Some("Room 12"))), ["room-12"]);
+    assert!(matches!(
+        history.query(&teacher, &rooms, &query(Some("lab-02"), None)),
+        Err(HistoryError::Forbidden)
+    ));
+    assert!(matches!(
+        history.query(&teacher, &rooms, &query(None, Some("Room 14"))),
+        Err(HistoryError::Forbidden)
+    ));
+
+    assert_eq!(
+        names(&admin(), query(None, None)),
+        ["no-room", "room-14", "room-12"]
+    );
+    assert!(names(&admin(), query(Some("lab-01"), Some("Room 14"))).is_empty());
+}
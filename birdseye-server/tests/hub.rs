@@ -0,0 +1,47 @@
+use birdseye_common::frontend::Topic;
+use birdseye_common::screen::Demand;
+use birdseye_common::{MachineId, RoomId};
+use birdseye_server::database::SqliteRepository;
+use birdseye_server::hub::Hub;
+use birdseye_server::rooms::Rooms;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+fn hub() -> Hub {
+    let config = BTreeMap::from([(RoomId::new("Room 12"), vec![MachineId::new("lab-1")])]);
+    let rooms = Rooms::load(Arc::new(SqliteRepository::in_memory().unwrap()), &config).unwrap();
+    Hub::new(8, rooms)
+}
+
+#[test]
+fn screens_are_only_demanded_while_someone_is_looking() {
+    let hub = hub();
+    let machine = MachineId::new("lab-1");
+    let thumbnails = Demand::Thumbnails { width: 320 };
+    assert_eq!(hub.demand(&machine, 320), Demand::None);
+
+    let mut changes = hub.changes();
+    let (teacher, _rx) = hub.connect("teacher", 13);
+    let (other, _other_rx) = hub.connect("other", 13);
+
+    // Subscribing to the room shows its thumbnails
+    hub.subscribe(teacher, Topic::Room(RoomId::new("Room 12")));
+    assert!(changes.has_changed().unwrap());
+    changes.borrow_and_update();
+    assert_eq!(hub.demand(&machine, 320), thumbnails);
+    assert_eq!(hub.demand(&MachineId::new("lab-2"), 320), Demand::None);
+
+    // Opening the machine needs every frame, until everyone has closed it
+    hub.watch_screen(teacher, machine.clone());
+    hub.watch_screen(other, machine.clone());
+    assert!(changes.has_changed().unwrap());
+    assert_eq!(hub.demand(&machine, 320), Demand::Full);
+
+    hub.unwatch_screen(teacher, &machine);
+    assert_eq!(hub.demand(&machine, 320), Demand::Full);
+    hub.disconnect(other);
+    assert_eq!(hub.demand(&machine, 320), thumbnails);
+
+    hub.unsubscribe(teacher, &Topic::Room(RoomId::new("Room 12")));
+    assert_eq!(hub.demand(&machine, 320), Demand::None);
+}
@@ -0,0 +1,90 @@
+use birdseye_common::policy::{Action, Pattern, PolicyError, Rule};
+use birdseye_common::RoomId;
+use birdseye_server::database::{Repository, SqliteRepository};
+use birdseye_server::policies::{Policies, RuleError};
+use std::sync::Arc;
+
+fn db() -> Arc<dyn Repository> {
+    Arc::new(SqliteRepository::in_memory().unwrap())
+}
+
+#[test]
+fn rules_are_saved_and_survive_a_restart() {
+    let db = db();
+    let policies = Policies::load(db.clone()).unwrap();
+
+    let games = policies
+        .create(Rule::new(0, "games", Action::Kill).with_process(Pattern::Glob("steam*".into())))
+        .unwrap();
+    let chat = policies
+        .create(
+            Rule::new(0, "chat", Action::Alert)
+                .with_exe(Pattern::Regex("discord".into()))
+                .with_rooms(vec![RoomId::new("Room 12")]),
+        )
+        .unwrap();
+    assert_ne!(games.id(), chat.id());
+
+    let chat = policies
+        .update(chat.id(), chat.clone().with_user("student"))
+        .unwrap();
+    policies.delete(games.id()).unwrap();
+
+    let reloaded = Policies::load(db).unwrap();
+    assert_eq!(reloaded.list(), vec![chat]);
+}
+
+#[test]
+fn invalid_and_missing_rules_are_refused() {
+    let policies = Policies::load(db()).unwrap();
+
+    assert!(matches!(
+        policies.create(Rule::new(0, "everything", Action::Kill)),
+        Err(RuleError::Invalid(PolicyError::NoPattern(_)))
+    ));
+    assert!(matches!(
+        policies
+            .create(Rule::new(0, "broken", Action::Kill).with_process(Pattern::Regex("(".into()))),
+        Err(RuleError::Invalid(PolicyError::InvalidPattern(_, _)))
+    ));
+    assert!(matches!(
+        policies.create(
+            Rule::new(0, "stop", Action::Throttle(0)).with_process(Pattern::Glob("*".into()))
+        ),
+        Err(RuleError::Invalid(PolicyError::InvalidThrottle(_)))
+    ));
+    assert!(matches!(
+        policies.update(
+            42,
+            Rule::new(0, "missing", Action::Alert).with_process(Pattern::Glob("*".into()))
+        ),
+        Err(RuleError::NotFound(42))
+    ));
+    assert!(matches!(policies.delete(42), Err(RuleError::NotFound(42))));
+    assert!(policies.list().is_empty());
+}
+
+#[test]
+fn machines_only_get_rules_for_their_room() {
+    let policies = Policies::load(db()).unwrap();
+    let everywhere = policies
+        .create(Rule::new(0, "games", Action::Kill).with_process(Pattern::Glob("steam*".into())))
+        .unwrap();
+    let room_12 = policies
+        .create(
+            Rule::new(0, "browsers", Action::Suspend)
+                .with_process(Pattern::Glob("firefox".into()))
+                .with_rooms(vec![RoomId::new("Room 12")]),
+        )
+        .unwrap();
+
+    assert_eq!(
+        policies.for_room(Some(&RoomId::new("Room 12"))),
+        vec![everywhere.clone(), room_12]
+    );
+    assert_eq!(
+        policies.for_room(Some(&RoomId::new("Room 14"))),
+        vec![everywhere.clone()]
+    );
+    assert_eq!(policies.for_room(None), vec![everywhere]);
+}
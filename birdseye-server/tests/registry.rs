@@ -0,0 +1,27 @@
+use birdseye_common::{Machine, MachineId};
+use birdseye_server::database::SqliteRepository;
+use birdseye_server::registry::Registry;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio::sync::oneshot::error::TryRecvError;
+
+#[test]
+fn reconnecting_closes_the_old_connection() {
+    let registry = Registry::load(Arc::new(SqliteRepository::in_memory().unwrap())).unwrap();
+    let machine = Machine::new(MachineId::new("lab-01"), "lab-01", "birdseye-monitor/0.1.0");
+    let (link, _rx) = mpsc::channel(1);
+
+    let (old, mut old_closed) = registry.connect(machine.clone(), 13, link.clone(), |_, _| {});
+    assert_eq!(old_closed.try_recv(), Err(TryRecvError::Empty));
+
+    let (new, mut new_closed) = registry.connect(machine.clone(), 13, link, |_, _| {});
+    assert_eq!(old_closed.try_recv(), Ok(()));
+    assert_eq!(new_closed.try_recv(), Err(TryRecvError::Empty));
+
+    // The old connection finishing doesn't take the machine offline
+    registry.disconnect(machine.id(), old, |_, _| panic!("Presence changed"));
+    assert!(registry.link(machine.id()).is_ok());
+
+    registry.disconnect(machine.id(), new, |_, _| {});
+    assert!(registry.link(machine.id()).is_err());
+}
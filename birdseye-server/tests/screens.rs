@@ -0,0 +1,106 @@
+use birdseye_common::screen::{Encoder, Encoding, Frame, Packet, TileEncoder, MAX_SIZE};
+use birdseye_common::MachineId;
+use birdseye_server::screens::Screens;
+
+fn frame(shade: u8) -> Packet {
+    Frame::new(2, 1, vec![shade; 8]).into()
+}
+
+#[tokio::test]
+async fn watchers_only_get_the_latest_frame() {
+    let screens = Screens::default();
+    let machine = MachineId::new("lab-1");
+    assert_eq!(screens.latest(&machine), None);
+
+    let mut screen = screens.watch(&machine);
+    screens.publish(&machine, frame(1));
+    screens.publish(&machine, frame(2));
+    screens.publish(&machine, frame(3));
+
+    // A watcher that fell behind skips straight to the newest frame
+    screen.changed().await.unwrap();
+    assert_eq!(*screen.borrow_and_update(), Some(frame(3)));
+    assert!(!screen.has_changed().unwrap());
+    assert_eq!(screens.latest(&machine), Some((3, frame(3))));
+
+    // Every watcher shares the same pixels
+    let other = screens.watch(&machine);
+    let (a, b) = (
+        screen.borrow().clone().unwrap(),
+        other.borrow().clone().unwrap(),
+    );
+    assert_eq!(a.data().as_ptr(), b.data().as_ptr());
+
+    assert_eq!(screens.latest(&MachineId::new("lab-2")), None);
+}
+
+#[tokio::test]
+async fn unchanged_frames_are_ignored() {
+    let screens = Screens::default();
+    let machine = MachineId::new("lab-1");
+
+    screens.publish(&machine, frame(1));
+    let screen = screens.watch(&machine);
+    screens.publish(&machine, frame(1));
+
+    assert!(!screen.has_changed().unwrap());
+    assert_eq!(screens.latest(&machine), Some((1, frame(1))));
+
+    screens.publish(&machine, frame(2));
+    assert!(screen.has_changed().unwrap());
+    assert_eq!(screens.latest(&machine), Some((2, frame(2))));
+}
+
+#[test]
+fn broken_frames_are_dropped() {
+    let screens = Screens::default();
+    let machine = MachineId::new("lab-1");
+
+    assert!(!screens.publish(&machine, Packet::new(Encoding::Raw, 2, 1, vec![0; 7])));
+    assert!(!screens.publish(&machine, Packet::new(Encoding::Jpeg, 2, 1, vec![])));
+    assert!(!screens.publish(&machine, Packet::new(Encoding::Jpeg, 0, 1, vec![0xff])));
+    // Too big to ever be decoded
+    let huge = Packet::new(Encoding::Tiles, MAX_SIZE + 1, MAX_SIZE, vec![1; 21]);
+    assert!(!screens.publish(&machine, huge));
+    assert!(!screens.publish(
+        &machine,
+        Packet::new(Encoding::Jpeg, 1, u32::MAX, vec![0xff])
+    ));
+    assert_eq!(screens.latest(&machine), None);
+
+    // Encoded frames are taken as they are
+    let jpeg = Packet::new(Encoding::Jpeg, 2, 1, vec![0xff, 0xd8]);
+    assert!(screens.publish(&machine, jpeg.clone()));
+    assert_eq!(screens.latest(&machine), Some((1, jpeg)));
+}
+
+#[test]
+fn tiled_screens_are_kept_whole() {
+    let screens = Screens::default();
+    let machine = MachineId::new("lab-1");
+    let mut encoder = TileEncoder::new();
+    let first = Frame::from_rgb(100, 80, &[10; 100 * 80 * 3]);
+    let second = Frame::from_rgb(100, 80, &[20; 100 * 80 * 3]);
+
+    let keyframe = encoder.encode(&first);
+    let delta = encoder.encode(&second);
+    let unchanged = encoder.encode(&second);
+
+    // A delta without the keyframe before it can't be shown
+    assert!(!screens.publish(&machine, delta.clone()));
+    assert_eq!(screens.latest(&machine), None);
+
+    assert!(screens.publish(&machine, keyframe));
+    let screen = screens.watch(&machine);
+    assert!(screens.publish(&machine, delta.clone()));
+    assert_eq!(*screen.borrow(), Some(delta));
+
+    // Watchers only get the delta, latest has the whole screen
+    let (generation, whole) = screens.latest(&machine).unwrap();
+    assert_eq!(generation, 2);
+    assert!(whole.is_keyframe());
+    assert_eq!(whole.decode(), Ok(second));
+
+    assert!(screens.publish(&machine, unchanged));
+    assert_eq!(screens.latest(&machine).unwrap().0, 2);
+}
@@ -0,0 +1,102 @@
+use birdseye_common::screen::{Encoder, Frame, JpegEncoder};
+use birdseye_common::MachineId;
+use birdseye_server::screens::Screens;
+use birdseye_server::thumbnails::{downscale, Thumbnails};
+
+/// A frame where every pixel is the same colour
+fn solid(width: u32, height: u32, bgr: [u8; 3]) -> Frame {
+    let pixel = [bgr[0], bgr[1], bgr[2], 0];
+    Frame::new(width, height, pixel.repeat((width * height) as usize))
+}
+
+/// The height and width from a JPEG's start of frame
+fn jpeg_size(jpeg: &[u8]) -> (u16, u16) {
+    let sof = jpeg
+        .windows(2)
+        .position(|bytes| bytes == [0xff, 0xc0])
+        .unwrap();
+    let size = &jpeg[sof + 5..sof + 9];
+    (
+        u16::from_be_bytes([size[2], size[3]]),
+        u16::from_be_bytes([size[0], size[1]]),
+    )
+}
+
+#[test]
+fn the_closest_configured_width_is_used() {
+    let thumbnails = Thumbnails::new(&[320, 0, 160, 320], 75);
+
+    assert_eq!(thumbnails.width(None), 160);
+    assert_eq!(thumbnails.width(Some(100)), 160);
+    assert_eq!(thumbnails.width(Some(161)), 320);
+    assert_eq!(thumbnails.width(Some(4000)), 320);
+    assert_eq!(thumbnails.largest(), 320);
+    assert_eq!(Thumbnails::new(&[], 75).width(None), 320);
+}
+
+#[test]
+fn frames_are_shrunk_by_averaging() {
+    // Two columns of black and white become grey
+    let mut data = vec![];
+    for _ in 0..4 {
+        data.extend_from_slice(&[0, 0, 0, 0, 255, 255, 255, 0]);
+    }
+    let frame = Frame::new(2, 4, data);
+
+    assert_eq!(downscale(&frame, 1), (1, 2, vec![127; 6]));
+    // Frames aren't made bigger
+    let (width, height, rgb) = downscale(&frame, 8);
+    assert_eq!((width, height, rgb.len()), (2, 4, 24));
+
+    // BGR0 becomes RGB
+    assert_eq!(
+        downscale(&solid(4, 4, [1, 2, 3]), 2),
+        (2, 2, [3, 2, 1].repeat(4))
+    );
+}
+
+#[test]
+fn thumbnails_are_only_made_again_when_the_screen_changes() {
+    let screens = Screens::default();
+    let thumbnails = Thumbnails::new(&[160, 320], 75);
+    let machine = MachineId::new("lab-1");
+    assert!(thumbnails.get(&screens, &machine, None).is_none());
+
+    screens.publish(&machine, solid(640, 360, [200, 100, 50]).into());
+    let first = thumbnails.get(&screens, &machine, Some(320)).unwrap();
+    assert_eq!(jpeg_size(first.jpeg()), (320, 180));
+    assert_eq!(
+        jpeg_size(thumbnails.get(&screens, &machine, None).unwrap().jpeg()),
+        (160, 90)
+    );
+
+    // The same frame again doesn't make a new thumbnail
+    screens.publish(&machine, solid(640, 360, [200, 100, 50]).into());
+    let again = thumbnails.get(&screens, &machine, Some(300)).unwrap();
+    assert_eq!(again.etag(), first.etag());
+    assert_eq!(again.jpeg().as_ptr(), first.jpeg().as_ptr());
+
+    screens.publish(&machine, solid(640, 360, [50, 100, 200]).into());
+    let changed = thumbnails.get(&screens, &machine, Some(320)).unwrap();
+    assert_ne!(changed.etag(), first.etag());
+
+    // A restarted server gives the same screen the same ETag, so browsers can keep their copy
+    let restarted = Screens::default();
+    restarted.publish(&machine, solid(640, 360, [50, 100, 200]).into());
+    let thumbnail = Thumbnails::new(&[160, 320], 75)
+        .get(&restarted, &machine, Some(320))
+        .unwrap();
+    assert_eq!(thumbnail.etag(), changed.etag());
+}
+
+#[test]
+fn thumbnails_are_made_from_encoded_frames() {
+    let screens = Screens::default();
+    let thumbnails = Thumbnails::new(&[160], 75);
+    let machine = MachineId::new("lab-1");
+
+    let mut encoder = JpegEncoder::new(2_000_000, 5);
+    screens.publish(&machine, encoder.encode(&solid(640, 360, [200, 100, 50])));
+    let thumbnail = thumbnails.get(&screens, &machine, None).unwrap();
+    assert_eq!(jpeg_size(thumbnail.jpeg()), (160, 90));
+}
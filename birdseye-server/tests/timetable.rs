@@ -0,0 +1,236 @@
+use birdseye_common::command::Command;
+use birdseye_common::timetable::{Holiday, Period, RuleSet, Timetable};
+use birdseye_common::{MachineId, RoomId};
+use birdseye_server::database::{Repository, SqliteRepository};
+use birdseye_server::timetable::{parse_ics, IcsError, Schedule};
+use std::sync::Arc;
+use time::macros::{date, time};
+use time::Weekday;
+use time_tz::{timezones, Tz};
+
+fn tz(name: &str) -> &'static Tz {
+    timezones::get_by_name(name).unwrap()
+}
+
+fn db() -> Arc<dyn Repository> {
+    Arc::new(SqliteRepository::in_memory().unwrap())
+}
+
+const CALENDAR: &str = "BEGIN:VCALENDAR\r
+VERSION:2.0\r
+BEGIN:VEVENT\r
+SUMMARY:Maths\r
+LOCATION:Room 12\r
+DTSTART:20240304T090000\r
+DTEND:20240304T100000\r
+RRULE:FREQ=WEEKLY;BYDAY=MO,WE;UNTIL=20241220T000000Z\r
+BEGIN:VALARM\r
+TRIGGER:-PT5M\r
+DESCRIPTION:Not a summary\r
+END:VALARM\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+SUMMARY:Science\\, year 10\r
+LOCATION:Room 12\r
+DTSTART;TZID=Pacific/Auckland:20240305T110000\r
+DTEND;TZID=Pacific/Auckland:20240305T120000\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+SUMMARY:Science\\, year 10\r
+LOCATION:Room 12\r
+DTSTART;TZID=Pacific/Auckland:20240312T110000\r
+DTEND;TZID=Pacific/Auckland:20240312T120000\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+SUMMARY:Assembly\r
+DTSTART:20240306T080000\r
+DTEND:20240306T083000\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+SUMMARY:Term break\r
+DTSTART;VALUE=DATE:20240413\r
+DTEND;VALUE=DATE:20240429\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+
+#[test]
+fn calendars_are_read_into_weekly_periods() {
+    let import = parse_ics(CALENDAR, tz("UTC"), None).unwrap();
+    let timetable = import.timetable;
+    let room_12 = RoomId::new("Room 12");
+
+    assert_eq!(
+        timetable.periods(),
+        &[
+            Period::new(
+                "Maths",
+                room_12.clone(),
+                Weekday::Monday,
+                time!(9:00),
+                time!(10:00)
+            ),
+            Period::new(
+                "Maths",
+                room_12.clone(),
+                Weekday::Wednesday,
+                time!(9:00),
+                time!(10:00)
+            ),
+            // Every week's lesson is folded into one period, and moved to UTC
+            Period::new(
+                "Science, year 10",
+                room_12,
+                Weekday::Monday,
+                time!(22:00),
+                time!(23:00)
+            ),
+        ]
+    );
+    // The end of an all day event is the day after
+    assert_eq!(
+        timetable.holidays(),
+        &[Holiday::new(
+            "Term break",
+            date!(2024 - 04 - 13),
+            date!(2024 - 04 - 28)
+        )]
+    );
+    // Assembly has no room
+    assert_eq!(import.skipped, 1);
+}
+
+#[test]
+fn a_room_can_be_given_for_every_period() {
+    let import = parse_ics(CALENDAR, tz("Pacific/Auckland"), Some(&RoomId::new("Hall"))).unwrap();
+
+    assert!(import
+        .timetable
+        .periods()
+        .iter()
+        .all(|period| period.room() == &RoomId::new("Hall")));
+    assert!(import.timetable.periods().contains(&Period::new(
+        "Science, year 10",
+        RoomId::new("Hall"),
+        Weekday::Tuesday,
+        time!(11:00),
+        time!(12:00)
+    )));
+    assert_eq!(import.skipped, 0);
+}
+
+#[test]
+fn folded_lines_prefixed_timezones_and_fortnightly_events_are_handled() {
+    let calendar = "BEGIN:VCALENDAR\r
+VERSION:2.0\r
+BEGIN:VEVENT\r
+SUMMARY:Digital techno\r
+ logy\r
+LOCATION:Room 12\r
+DTSTART;TZID=\"/mozilla.org/20050126_1/Pacific/Auckland\":20240304T090000\r
+DTEND;TZID=\"/mozilla.org/20050126_1/Pacific/Auckland\":20240304T100000\r
+RRULE:FREQ=WEEKLY\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+SUMMARY:Sport\r
+LOCATION:Gym\r
+DTSTART:20240305T130000\r
+DTEND:20240305T140000\r
+RRULE:FREQ=WEEKLY;INTERVAL=2;BYDAY=TU\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+
+    let import = parse_ics(calendar, tz("Pacific/Auckland"), None).unwrap();
+    assert_eq!(
+        import.timetable.periods(),
+        &[Period::new(
+            "Digital technology",
+            RoomId::new("Room 12"),
+            Weekday::Monday,
+            time!(09:00),
+            time!(10:00)
+        )]
+    );
+    assert_eq!(import.skipped, 1);
+}
+
+#[test]
+fn broken_calendars_are_refused() {
+    assert_eq!(
+        parse_ics("SUMMARY:Maths", tz("UTC"), None).unwrap_err(),
+        IcsError::NotACalendar
+    );
+    assert_eq!(
+        parse_ics(
+            "BEGIN:VCALENDAR\nBEGIN:VEVENT\nSUMMARY:Maths\nDTSTART:tomorrow\nEND:VEVENT\nEND:VCALENDAR",
+            tz("UTC"),
+            None
+        )
+        .unwrap_err(),
+        IcsError::InvalidDate("DTSTART".into(), "tomorrow".into())
+    );
+}
+
+#[test]
+fn imports_replace_rooms_in_the_file_and_keep_rule_sets() {
+    let db = db();
+    let schedule = Schedule::load(db.clone(), "UTC").unwrap();
+    schedule
+        .set(Timetable::new(
+            vec![
+                Period::new(
+                    "Old",
+                    RoomId::new("Room 12"),
+                    Weekday::Friday,
+                    time!(9:00),
+                    time!(10:00),
+                ),
+                Period::new(
+                    "Art",
+                    RoomId::new("Room 14"),
+                    Weekday::Friday,
+                    time!(9:00),
+                    time!(10:00),
+                ),
+            ],
+            vec![],
+            vec![RuleSet::new("Maths", vec![1], true)],
+        ))
+        .unwrap();
+
+    let (timetable, skipped) = schedule.import(CALENDAR, None).unwrap();
+    assert_eq!(skipped, 1);
+
+    let names: Vec<_> = timetable.periods().iter().map(|p| p.name()).collect();
+    assert_eq!(names, ["Art", "Maths", "Maths", "Science, year 10"]);
+    assert_eq!(
+        timetable.rule_sets(),
+        &[RuleSet::new("Maths", vec![1], true)]
+    );
+
+    // Importing again doesn't add the holiday twice, and it all survives a restart
+    schedule.import(CALENDAR, None).unwrap();
+    let reloaded = Schedule::load(db, "UTC").unwrap();
+    assert_eq!(reloaded.get(), timetable);
+}
+
+#[test]
+fn unknown_timezones_are_refused() {
+    assert!(Schedule::load(db(), "Middle/Earth").is_err());
+}
+
+#[test]
+fn teacher_locks_are_remembered_until_unlocked() {
+    let schedule = Schedule::load(db(), "Pacific/Auckland").unwrap();
+    let machine = MachineId::new("lab-1");
+    assert!(!schedule.is_locked(&machine));
+
+    schedule.commanded(&machine, &Command::Lock);
+    assert!(schedule.is_locked(&machine));
+    schedule.commanded(&machine, &Command::ShowMessage("Eyes up".to_string()));
+    assert!(schedule.is_locked(&machine));
+
+    schedule.commanded(&machine, &Command::Unlock);
+    assert!(!schedule.is_locked(&machine));
+}